@@ -0,0 +1,130 @@
+//! Measures how the router scales with the size of its route table.  For
+//! route tables of 10, 100, and 1000 generated routes - a mix of static
+//! paths, typed and untyped fragments, and trailing wildcards - this
+//! measures the time taken to look up a route alone (see
+//! [`under::Router::route_label`]), the time taken to handle a full request,
+//! and the throughput of handling requests concurrently on a multi-threaded
+//! runtime.
+//!
+//! Each measurement is taken over paths that hit the first, middle, and last
+//! routes of the table, as well as a path that matches no route, so that
+//! the cost of where a route sits in the table is visible.
+//!
+//! Run with `cargo bench --bench route_tables`.
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use std::sync::Arc;
+use under::{Request, Response};
+
+const SIZES: [usize; 3] = [10, 100, 1000];
+const CONCURRENCY: usize = 64;
+
+async fn endpoint(request: Request) -> Response {
+    std::hint::black_box(request.fragment_str(0));
+    Response::empty_204()
+}
+
+/// The pattern of the `i`th generated route, and a path that it matches.
+fn route(i: usize) -> (String, String) {
+    match i % 4 {
+        0 => (format!("/static/{i}/index"), format!("/static/{i}/index")),
+        1 => (format!("/users{i}/{{id}}"), format!("/users{i}/alice")),
+        2 => (
+            format!("/posts{i}/{{id:uint}}/comments"),
+            format!("/posts{i}/42/comments"),
+        ),
+        _ => (
+            format!("/files{i}/{{path:path}}"),
+            format!("/files{i}/a/b/c.txt"),
+        ),
+    }
+}
+
+fn router(size: usize) -> under::Router {
+    let mut http = under::http();
+    for i in 0..size {
+        http.at(route(i).0).get(endpoint);
+    }
+    http.fallback(under::endpoints::simple(Response::empty_404));
+    http.prepare();
+    http
+}
+
+/// The paths measured for a table of the given size, with their names.
+fn paths(size: usize) -> [(&'static str, String); 4] {
+    [
+        ("first", route(0).1),
+        ("middle", route(size / 2).1),
+        ("last", route(size - 1).1),
+        ("unmatched", "/missing/route".to_owned()),
+    ]
+}
+
+fn lookup(c: &mut Criterion) {
+    let method = http::Method::GET;
+    let mut group = c.benchmark_group("lookup");
+    for size in SIZES {
+        let http = router(size);
+        for (name, path) in paths(size) {
+            group.bench_with_input(BenchmarkId::new(name, size), &path, |b, path| {
+                b.iter(|| http.route_label(&method, std::hint::black_box(path)));
+            });
+        }
+    }
+    group.finish();
+}
+
+fn handle(c: &mut Criterion) {
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .build()
+        .unwrap();
+    let mut group = c.benchmark_group("handle");
+    for size in SIZES {
+        let http = router(size);
+        for (name, path) in paths(size) {
+            group.bench_with_input(BenchmarkId::new(name, size), &path, |b, path| {
+                b.to_async(&runtime).iter(|| async {
+                    let response = http.handle(Request::get(path).unwrap()).await.unwrap();
+                    std::hint::black_box(response.status());
+                });
+            });
+        }
+    }
+    group.finish();
+}
+
+/// Handles `CONCURRENCY` requests at once, spread over the worker threads of
+/// a multi-threaded runtime; the throughput is reported in requests.
+fn throughput(c: &mut Criterion) {
+    let runtime = tokio::runtime::Builder::new_multi_thread()
+        .enable_all()
+        .build()
+        .unwrap();
+    let mut group = c.benchmark_group("throughput");
+    group.throughput(Throughput::Elements(CONCURRENCY as u64));
+    for size in SIZES {
+        let http = Arc::new(router(size));
+        for (name, path) in paths(size) {
+            let path: Arc<str> = path.into();
+            group.bench_with_input(BenchmarkId::new(name, size), &path, |b, path| {
+                b.to_async(&runtime).iter(|| async {
+                    let tasks = (0..CONCURRENCY).map(|_| {
+                        let http = http.clone();
+                        let path = path.clone();
+                        tokio::spawn(async move {
+                            let response = http.handle(Request::get(&path[..]).unwrap()).await;
+                            std::hint::black_box(response.unwrap().status());
+                        })
+                    });
+                    for task in futures::future::join_all(tasks).await {
+                        task.unwrap();
+                    }
+                });
+            });
+        }
+    }
+    group.finish();
+}
+
+criterion_group!(route_tables, lookup, handle, throughput);
+criterion_main!(route_tables);
@@ -0,0 +1,158 @@
+//! Measures the allocations, and time, taken per request in the hot path of
+//! the router: routing, extracting fragments, and running middleware.  Each
+//! case is measured twice - once in wall time, and once in allocations, by
+//! counting them with a global allocator - so that allocation regressions
+//! show up as a change in a whole number, rather than in noisy timings.
+//!
+//! Run with `cargo bench --bench routing`.
+
+use criterion::measurement::{Measurement, ValueFormatter};
+use criterion::{criterion_group, criterion_main, Criterion, Throughput};
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use under::{Request, Response};
+
+/// Counts every allocation made by the process.
+struct Counting;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        // SAFETY: forwarded as-is.
+        unsafe { System.alloc(layout) }
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        // SAFETY: forwarded as-is.
+        unsafe { System.dealloc(ptr, layout) }
+    }
+}
+
+#[global_allocator]
+static GLOBAL: Counting = Counting;
+
+/// Measures the number of allocations made, instead of the time taken.
+struct Allocations;
+
+impl Measurement for Allocations {
+    type Intermediate = usize;
+    type Value = usize;
+
+    fn start(&self) -> usize {
+        ALLOCATIONS.load(Ordering::Relaxed)
+    }
+
+    fn end(&self, start: usize) -> usize {
+        ALLOCATIONS.load(Ordering::Relaxed) - start
+    }
+
+    fn add(&self, a: &usize, b: &usize) -> usize {
+        a + b
+    }
+
+    fn zero(&self) -> usize {
+        0
+    }
+
+    #[allow(clippy::cast_precision_loss)]
+    fn to_f64(&self, value: &usize) -> f64 {
+        *value as f64
+    }
+
+    fn formatter(&self) -> &dyn ValueFormatter {
+        &AllocationFormatter
+    }
+}
+
+struct AllocationFormatter;
+
+impl ValueFormatter for AllocationFormatter {
+    fn scale_values(&self, _: f64, _: &mut [f64]) -> &'static str {
+        "allocs"
+    }
+
+    fn scale_throughputs(&self, _: f64, _: &Throughput, _: &mut [f64]) -> &'static str {
+        "allocs"
+    }
+
+    fn scale_for_machines(&self, _: &mut [f64]) -> &'static str {
+        "allocs"
+    }
+}
+
+async fn endpoint(request: Request) -> Response {
+    let user = request.fragment_str("user").unwrap_or_default();
+    let post = request.fragment_str("post").unwrap_or_default();
+    std::hint::black_box((user, post));
+    Response::empty_204()
+}
+
+#[derive(Debug)]
+struct Passthrough;
+
+#[async_trait::async_trait]
+impl under::Middleware for Passthrough {
+    async fn apply(
+        self: std::pin::Pin<&Self>,
+        request: Request,
+        next: under::middleware::Next<'_>,
+    ) -> Result<Response, anyhow::Error> {
+        next.apply(request).await
+    }
+}
+
+fn router(middleware: usize) -> under::Router {
+    let mut http = under::http();
+    for _ in 0..middleware {
+        http.with(Passthrough);
+    }
+    http.at("/users/{user}/posts/{post}").get(endpoint);
+    http.at("/health")
+        .get(under::endpoints::simple(Response::empty_204));
+    http.fallback(under::endpoints::simple(Response::empty_404));
+    http.prepare();
+    http
+}
+
+fn routing<M: Measurement + 'static>(c: &mut Criterion<M>, group: &str) {
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .build()
+        .unwrap();
+    let plain = router(0);
+    let layered = router(3);
+
+    let mut group = c.benchmark_group(group);
+    for (name, http, path) in [
+        ("no fragments", &plain, "/health"),
+        ("two fragments", &plain, "/users/42/posts/hello-world"),
+        ("fallback", &plain, "/missing"),
+        ("3 middleware", &layered, "/health"),
+    ] {
+        group.bench_function(name, |b| {
+            b.to_async(&runtime).iter(|| async {
+                let request = Request::get(path).unwrap();
+                let response = http.handle(request).await.unwrap();
+                std::hint::black_box(response.status());
+            });
+        });
+    }
+    group.finish();
+}
+
+fn routing_time(c: &mut Criterion) {
+    routing(c, "routing");
+}
+
+fn routing_allocations(c: &mut Criterion<Allocations>) {
+    routing(c, "routing allocations");
+}
+
+criterion_group!(timing, routing_time);
+criterion_group! {
+    name = allocations;
+    config = Criterion::default().with_measurement(Allocations);
+    targets = routing_allocations
+}
+criterion_main!(timing, allocations);
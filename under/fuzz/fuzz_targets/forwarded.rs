@@ -0,0 +1,26 @@
+//! Reads the client address and scheme from arbitrary `Forwarded` and
+//! `X-Forwarded-*` headers.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use under::{HttpEntity, Request};
+
+fuzz_target!(|data: &[u8]| {
+    let Ok(value) = http::HeaderValue::from_bytes(data) else {
+        return;
+    };
+    let mut request = Request::get("/").unwrap();
+    for header in ["Forwarded", "X-Forwarded-For", "X-Forwarded-Proto"] {
+        request.headers_mut().append(header, value.clone());
+    }
+
+    for index in [0, 1, -1, -2, isize::MIN, isize::MAX] {
+        let mut remote = request.remote_address();
+        remote.trust_forwarded(index);
+        let _ = (remote.apply(), remote.scheme());
+        let mut remote = request.remote_address();
+        remote.trust_forwarded_for(index);
+        let _ = (remote.apply(), remote.scheme());
+    }
+});
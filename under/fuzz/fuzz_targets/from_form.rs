@@ -0,0 +1,28 @@
+//! Parses an arbitrary `application/x-www-form-urlencoded` body into a form
+//! with every kind of field.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use std::collections::HashMap;
+use under::{DataStream, FromForm};
+
+#[allow(dead_code)]
+#[derive(FromForm)]
+struct Form {
+    name: String,
+    #[form(alias = "years")]
+    age: Option<u8>,
+    #[form(multiple)]
+    tags: Vec<String>,
+    #[form(default)]
+    subscribe: bool,
+    address: Option<std::net::IpAddr>,
+}
+
+fuzz_target!(|data: &[u8]| {
+    let _ = futures::executor::block_on(DataStream::from(data.to_vec()).into_form::<Form>());
+    let _ = futures::executor::block_on(
+        DataStream::from(data.to_vec()).into_form::<HashMap<String, Vec<String>>>(),
+    );
+});
@@ -0,0 +1,23 @@
+//! Builds a router with the input as the pattern of a route, and routes the
+//! input through it.  Invalid patterns must be reported as errors (see
+//! `Router::try_prepare`), never panic.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use under::Response;
+
+fuzz_target!(|data: &[u8]| {
+    let Ok(pattern) = std::str::from_utf8(data) else {
+        return;
+    };
+    let (route, path) = pattern.split_once('\n').unwrap_or((pattern, pattern));
+
+    let mut http = under::http();
+    http.at(route)
+        .get(under::endpoints::simple(Response::empty_204));
+    if http.try_prepare().is_err() {
+        return;
+    }
+    let _ = http.route_label(&http::Method::GET, path);
+});
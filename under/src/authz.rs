@@ -0,0 +1,335 @@
+//! Authorization.
+//!
+//! Authentication middleware (such as [`crate::middleware::ApiKeyAuth`])
+//! decides _who_ made a request, storing the
+//! [`crate::middleware::Principal`] in the request; this decides what they
+//! are allowed to do.  An [`Authorizer`] is installed once, with
+//! [`AuthzMiddleware`], and decides whether a principal has a given
+//! permission.  Routes (or groups of routes, with a scope endpoint)
+//! then declare the permissions they need with [`requires`]; handlers can
+//! also check permissions themselves, with [`check`].
+//!
+//! Requests without a principal are rejected with a 401, and requests whose
+//! principal does not have the permission are rejected with a 403.  Both
+//! have a JSON body of the form `{"error":"Forbidden"}`.
+//!
+//! # Examples
+//! ```rust
+//! # use under::*;
+//! use under::authz::{requires, AuthzMiddleware};
+//! use under::middleware::{ApiKeyAuth, StaticKeys};
+//!
+//! #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+//! enum Role { Admin, Member }
+//!
+//! #[derive(Debug)]
+//! enum Permission { Admin, Read }
+//!
+//! fn authorize(role: &Role, permission: &Permission) -> bool {
+//!     match permission {
+//!         Permission::Admin => *role == Role::Admin,
+//!         Permission::Read => true,
+//!     }
+//! }
+//!
+//! # #[tokio::main] async fn main() -> Result<(), anyhow::Error> {
+//! let keys = StaticKeys::new()
+//!     .with_key("k-admin", Role::Admin)
+//!     .with_key("k-member", Role::Member);
+//! let mut http = under::http();
+//! http.with(ApiKeyAuth::new(keys))
+//!     .with(AuthzMiddleware::new(authorize));
+//! http.at("/posts").get(under::endpoints::scope()
+//!     .with(requires(Permission::Read))
+//!     .then(under::endpoints::simple(Response::empty_204)));
+//! http.at("/admin").get(under::endpoints::scope()
+//!     .with(requires(Permission::Admin))
+//!     .then(under::endpoints::simple(Response::empty_204)));
+//! http.prepare();
+//!
+//! let request = Request::get("/posts")?.with_header("X-API-Key", "k-member")?;
+//! let response = http.handle(request).await?;
+//! assert_eq!(response.status(), http::StatusCode::NO_CONTENT);
+//! let request = Request::get("/admin")?.with_header("X-API-Key", "k-member")?;
+//! let mut response = http.handle(request).await?;
+//! assert_eq!(response.status(), http::StatusCode::FORBIDDEN);
+//! assert_eq!(response.data(512).into_text().await?, r#"{"error":"Forbidden"}"#);
+//! let request = Request::get("/admin")?.with_header("X-API-Key", "k-admin")?;
+//! let response = http.handle(request).await?;
+//! assert_eq!(response.status(), http::StatusCode::NO_CONTENT);
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::middleware::{Middleware, Next};
+use crate::{HttpEntity, Request, Response};
+use std::marker::PhantomData;
+use std::pin::Pin;
+use std::sync::Arc;
+
+#[async_trait]
+/// Decides whether a principal has a permission.
+///
+/// `P` is the principal, as inserted by the authentication middleware (see
+/// [`crate::middleware::Principal`]); `Perm` is the permission, which is usually an enum
+/// defined by the application.  Since this is asynchronous, it may look the
+/// principal's permissions up in a database; however, as this is called for
+/// every permission check, it is suggested that such an authorizer caches
+/// its results.
+///
+/// This is implemented for any `Fn(&P, &Perm) -> bool`.
+pub trait Authorizer<P, Perm>: Send + Sync + 'static {
+    /// Returns whether or not the given principal has the given permission.
+    async fn authorize(&self, principal: &P, permission: &Perm) -> bool;
+}
+
+#[async_trait]
+impl<F, P, Perm> Authorizer<P, Perm> for F
+where
+    F: Fn(&P, &Perm) -> bool + Send + Sync + 'static,
+    P: Sync + 'static,
+    Perm: Sync + 'static,
+{
+    async fn authorize(&self, principal: &P, permission: &Perm) -> bool {
+        self(principal, permission)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, thiserror::Error)]
+#[non_exhaustive]
+/// The reason a request was denied by an authorization check.
+///
+/// If this is returned (as an error) from an endpoint or middleware, the
+/// router converts it into a response (see [`Denied::to_response`]).
+pub enum Denied {
+    /// The request did not have a principal; i.e., it was not
+    /// authenticated.
+    #[error("the request was not authenticated")]
+    Unauthenticated,
+    /// The request's principal does not have the required permission.
+    #[error("the request does not have the required permission")]
+    Forbidden,
+}
+
+impl Denied {
+    /// The status of the response generated for this denial: a 401 for
+    /// [`Denied::Unauthenticated`], and a 403 for [`Denied::Forbidden`].
+    #[must_use]
+    pub fn status(&self) -> http::StatusCode {
+        match self {
+            Denied::Unauthenticated => http::StatusCode::UNAUTHORIZED,
+            Denied::Forbidden => http::StatusCode::FORBIDDEN,
+        }
+    }
+
+    /// Generates the response for this denial.  This has the status of
+    /// [`Denied::status`], and a JSON body containing the reason phrase of
+    /// that status, e.g. `{"error":"Forbidden"}`.  The body never names the
+    /// permission that was required.
+    ///
+    /// # Examples
+    /// ```rust
+    /// # use under::*;
+    /// use under::authz::Denied;
+    /// # #[tokio::main] async fn main() -> Result<(), anyhow::Error> {
+    /// let mut response = Denied::Unauthenticated.to_response();
+    /// assert_eq!(response.status(), http::StatusCode::UNAUTHORIZED);
+    /// assert_eq!(response.data(512).into_text().await?, r#"{"error":"Unauthorized"}"#);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn to_response(&self) -> Response {
+        let status = self.status();
+        let body = format!(
+            r#"{{"error":"{}"}}"#,
+            status.canonical_reason().unwrap_or("error")
+        );
+        let response = Response::text(body).with_status(status);
+        response
+            .with_header(
+                http::header::CONTENT_TYPE,
+                "application/json; charset=utf-8",
+            )
+            .unwrap_or_else(|_| Response::empty(status))
+    }
+}
+
+#[async_trait]
+/// An [`Authorizer`] with its principal type erased, so that it can be
+/// looked up by the permission type alone.
+trait Check<Perm>: Send + Sync + 'static {
+    async fn check(&self, request: &Request, permission: &Perm) -> Result<(), Denied>;
+}
+
+struct Bound<A, P>(A, PhantomData<fn(&P)>);
+
+#[async_trait]
+impl<A, P, Perm> Check<Perm> for Bound<A, P>
+where
+    A: Authorizer<P, Perm>,
+    P: Send + Sync + 'static,
+    Perm: Sync + 'static,
+{
+    async fn check(&self, request: &Request, permission: &Perm) -> Result<(), Denied> {
+        let principal = request.principal::<P>().ok_or(Denied::Unauthenticated)?;
+        if self.0.authorize(principal, permission).await {
+            Ok(())
+        } else {
+            Err(Denied::Forbidden)
+        }
+    }
+}
+
+struct Installed<Perm>(Arc<dyn Check<Perm>>);
+
+impl<Perm> Clone for Installed<Perm> {
+    fn clone(&self) -> Self {
+        Installed(self.0.clone())
+    }
+}
+
+/// A middleware that installs an [`Authorizer`] for a permission type.
+///
+/// This must come after the authentication middleware in the stack, and
+/// before any use of [`requires`] or [`check`] with the same permission
+/// type.  Only one authorizer can be installed for each permission type;
+/// installing another replaces it for the rest of the stack.
+pub struct AuthzMiddleware<Perm> {
+    authorizer: Installed<Perm>,
+}
+
+impl<Perm: Sync + 'static> AuthzMiddleware<Perm> {
+    /// Creates a new authorization middleware, deciding permissions with
+    /// the given authorizer.
+    pub fn new<A, P>(authorizer: A) -> Self
+    where
+        A: Authorizer<P, Perm>,
+        P: Send + Sync + 'static,
+    {
+        AuthzMiddleware {
+            authorizer: Installed(Arc::new(Bound(authorizer, PhantomData))),
+        }
+    }
+}
+
+impl<Perm> std::fmt::Debug for AuthzMiddleware<Perm> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("AuthzMiddleware")
+            .field("permission", &std::any::type_name::<Perm>())
+            .finish_non_exhaustive()
+    }
+}
+
+#[async_trait]
+impl<Perm: Send + Sync + 'static> Middleware for AuthzMiddleware<Perm> {
+    async fn apply(
+        self: Pin<&Self>,
+        mut request: Request,
+        next: Next<'_>,
+    ) -> Result<Response, anyhow::Error> {
+        request.set_ext(self.authorizer.clone());
+        next.apply(request).await
+    }
+}
+
+/// Checks that the request has the given permission, using the
+/// [`Authorizer`] installed by [`AuthzMiddleware`].  If no authorizer is
+/// installed for the permission type, the request is always denied (and an
+/// error is logged), so that a misconfiguration does not grant access.
+///
+/// The returned error can be returned from an endpoint as-is, to respond
+/// with a 401 or 403.
+///
+/// # Examples
+/// ```rust
+/// # use under::*;
+/// use under::authz::{check, AuthzMiddleware, Denied};
+/// use under::middleware::Principal;
+///
+/// #[derive(Debug)]
+/// struct Edit(u32);
+///
+/// # #[tokio::main] async fn main() -> Result<(), anyhow::Error> {
+/// let mut http = under::http();
+/// http.with(AuthzMiddleware::new(|user: &u32, edit: &Edit| *user == edit.0));
+/// http.at("/users/{id:uint}").put(|request: Request| async move {
+///     let id = request.fragment_str("id").unwrap().parse()?;
+///     check(&request, &Edit(id)).await?;
+///     Ok::<_, anyhow::Error>(Response::empty_204())
+/// });
+/// http.prepare();
+///
+/// let mut request = Request::put("/users/1")?;
+/// request.set_ext(Principal(1u32));
+/// let response = http.handle(request).await?;
+/// assert_eq!(response.status(), http::StatusCode::NO_CONTENT);
+/// let mut request = Request::put("/users/2")?;
+/// request.set_ext(Principal(1u32));
+/// let response = http.handle(request).await?;
+/// assert_eq!(response.status(), http::StatusCode::FORBIDDEN);
+/// let response = http.handle(Request::put("/users/1")?).await?;
+/// assert_eq!(response.status(), http::StatusCode::UNAUTHORIZED);
+/// # Ok(())
+/// # }
+/// ```
+///
+/// # Errors
+/// This returns [`Denied::Unauthenticated`] if the request has no
+/// principal of the type the authorizer expects, and [`Denied::Forbidden`]
+/// if the authorizer denies the permission.
+pub async fn check<Perm: Send + Sync + 'static>(
+    request: &Request,
+    permission: &Perm,
+) -> Result<(), Denied> {
+    let Some(installed) = request.ext::<Installed<Perm>>() else {
+        log::error!(
+            "no authorizer is installed for {}; denying the request",
+            std::any::type_name::<Perm>()
+        );
+        return Err(Denied::Forbidden);
+    };
+
+    installed.0.check(request, permission).await
+}
+
+/// Creates a middleware that only passes on requests that have the given
+/// permission (see [`check`]); every other request is responded to with a
+/// 401 or 403.  To require a permission for a single route, or a group of
+/// routes, use this with a scope endpoint (see [`crate::endpoints::scope`]).
+///
+/// # Examples
+/// ```rust
+/// # use under::*;
+/// use under::authz::requires;
+/// #[derive(Debug)]
+/// enum Permission { Admin }
+///
+/// let endpoint = under::endpoints::scope()
+///     .with(requires(Permission::Admin))
+///     .then(under::endpoints::simple(Response::empty_204));
+/// ```
+pub fn requires<Perm: std::fmt::Debug + Send + Sync + 'static>(permission: Perm) -> Requires<Perm> {
+    Requires(permission)
+}
+
+#[derive(Debug)]
+/// A middleware that requires a permission.  See [`requires`].
+pub struct Requires<Perm>(Perm);
+
+#[async_trait]
+impl<Perm: std::fmt::Debug + Send + Sync + 'static> Middleware for Requires<Perm> {
+    async fn apply(
+        self: Pin<&Self>,
+        request: Request,
+        next: Next<'_>,
+    ) -> Result<Response, anyhow::Error> {
+        match check(&request, &self.0).await {
+            Ok(()) => next.apply(request).await,
+            Err(denied) => Ok(denied.to_response()),
+        }
+    }
+
+    fn describe(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "requires({:?})", self.0)
+    }
+}
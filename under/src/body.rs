@@ -0,0 +1,301 @@
+use bytes::Bytes;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+#[derive(Debug, Default)]
+/// The body of a request or response.
+///
+/// A body is either empty, a single buffer of bytes that is known up front,
+/// or a stream of bytes that is produced over time (e.g. a request body
+/// being received from the client, or a response body being generated by
+/// the application).  Bodies are usually created with `From`, from any of
+/// the common byte and string types; streaming bodies are created with
+/// [`Body::wrap_stream`], or [`Body::channel`].
+///
+/// Reading a body is usually done through [`crate::HttpEntity::data`].  A
+/// body is also an [`http_body::Body`], and a [`futures::Stream`] of its
+/// chunks.
+///
+/// # Examples
+/// ```rust
+/// # use under::*;
+/// # #[tokio::main] async fn main() -> Result<(), anyhow::Error> {
+/// let body = Body::from("hello, world");
+/// assert!(matches!(&body, Body::Full(bytes) if bytes == "hello, world"));
+/// let chunks = futures::stream::iter([Ok::<_, std::io::Error>("hello, "), Ok("world")]);
+/// let mut response = Response::empty_200().with_body(Body::wrap_stream(chunks));
+/// assert_eq!(response.data(512).into_text().await?, "hello, world");
+/// # Ok(())
+/// # }
+/// ```
+pub enum Body {
+    /// A body with no data.
+    #[default]
+    Empty,
+    /// A body with all of its data in a single buffer.
+    Full(Bytes),
+    /// A body whose data is streamed.
+    Stream(StreamBody),
+}
+
+/// The data of a streamed [`Body`].
+///
+/// This is opaque; it is read by reading the body it belongs to.
+pub struct StreamBody {
+    body: Pin<Box<dyn http_body::Body<Data = Bytes, Error = std::io::Error> + Send + Sync>>,
+    /// The trailers of the body, if they were reached while reading it as a
+    /// [`futures::Stream`], which only yields data.
+    trailers: Option<http::HeaderMap>,
+}
+
+impl std::fmt::Debug for StreamBody {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("StreamBody").finish_non_exhaustive()
+    }
+}
+
+/// The sending half of a [`Body::channel`].
+///
+/// The body ends when the sender is dropped.  If the receiving half of the
+/// channel is dropped (e.g. because the client disconnected), sending fails.
+pub struct BodySender(http_body_util::channel::Sender<Bytes, std::io::Error>);
+
+impl std::fmt::Debug for BodySender {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("BodySender").finish_non_exhaustive()
+    }
+}
+
+impl BodySender {
+    /// Sends a chunk of data, waiting until the body is ready for it.
+    ///
+    /// # Errors
+    /// Errors if the body was dropped.
+    pub async fn send_data(&mut self, chunk: Bytes) -> std::io::Result<()> {
+        self.0.send_data(chunk).await.map_err(map_send_error)
+    }
+
+    /// Sends the trailers of the body, after all of its data.
+    ///
+    /// # Errors
+    /// Errors if the body was dropped.
+    pub async fn send_trailers(&mut self, trailers: http::HeaderMap) -> std::io::Result<()> {
+        self.0.send_trailers(trailers).await.map_err(map_send_error)
+    }
+
+    /// Aborts the body, so that reading it errors, instead of ending
+    /// normally.  This lets the receiving side know that the body is
+    /// incomplete.
+    pub fn abort(self) {
+        self.0.abort(std::io::Error::new(
+            std::io::ErrorKind::UnexpectedEof,
+            "the body was aborted",
+        ));
+    }
+}
+
+impl Body {
+    /// Creates an empty body.
+    #[must_use]
+    pub fn empty() -> Self {
+        Body::Empty
+    }
+
+    /// Creates a body that streams the chunks of the given stream.  If the
+    /// stream errors, the body errors, and the request or response it
+    /// belongs to is aborted.
+    pub fn wrap_stream<S, O, E>(stream: S) -> Self
+    where
+        S: futures::Stream<Item = Result<O, E>> + Send + 'static,
+        O: Into<Bytes> + 'static,
+        E: Into<Box<dyn std::error::Error + Send + Sync>> + 'static,
+    {
+        use futures::TryStreamExt;
+        let frames = stream
+            .map_ok(|chunk| http_body::Frame::data(chunk.into()))
+            .map_err(std::io::Error::other);
+        Body::from_http_body(http_body_util::StreamBody::new(
+            sync_wrapper::SyncStream::new(frames),
+        ))
+    }
+
+    /// Creates a streaming body, and a sender that writes to it.
+    #[must_use]
+    pub fn channel() -> (BodySender, Self) {
+        let (sender, body) = http_body_util::channel::Channel::new(1);
+        (BodySender(sender), Body::from_http_body(body))
+    }
+
+    /// Wraps any other body, e.g. one that wraps this body, to count or
+    /// transform it.
+    pub(crate) fn from_http_body<B>(body: B) -> Self
+    where
+        B: http_body::Body<Data = Bytes, Error = std::io::Error> + Send + Sync + 'static,
+    {
+        Body::Stream(StreamBody {
+            body: Box::pin(body),
+            trailers: None,
+        })
+    }
+
+    /// Wraps a body received by hyper - either a request received by the
+    /// server, or a response received by the client.
+    pub(crate) fn from_incoming(body: hyper::body::Incoming) -> Self {
+        if http_body::Body::is_end_stream(&body) {
+            Body::Empty
+        } else {
+            Body::from_http_body(http_body_util::BodyExt::map_err(body, map_hyper_error))
+        }
+    }
+
+    /// Reads the body until its trailers, returning them; any data that has
+    /// not been read yet is discarded.  This returns `None` if the body has
+    /// no trailers.
+    pub(crate) async fn trailers(&mut self) -> std::io::Result<Option<http::HeaderMap>> {
+        while let Some(frame) = http_body_util::BodyExt::frame(self).await.transpose()? {
+            if let Ok(trailers) = frame.into_trailers() {
+                return Ok(Some(trailers));
+            }
+        }
+        Ok(None)
+    }
+}
+
+impl http_body::Body for Body {
+    type Data = Bytes;
+    type Error = std::io::Error;
+
+    fn poll_frame(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<http_body::Frame<Bytes>, std::io::Error>>> {
+        match &mut *self {
+            Body::Empty => Poll::Ready(None),
+            Body::Full(bytes) => {
+                let bytes = std::mem::take(bytes);
+                *self = Body::Empty;
+                Poll::Ready((!bytes.is_empty()).then(|| Ok(http_body::Frame::data(bytes))))
+            }
+            Body::Stream(stream) => match stream.trailers.take() {
+                Some(trailers) => Poll::Ready(Some(Ok(http_body::Frame::trailers(trailers)))),
+                None => stream.body.as_mut().poll_frame(cx),
+            },
+        }
+    }
+
+    fn is_end_stream(&self) -> bool {
+        match self {
+            Body::Empty => true,
+            Body::Full(bytes) => bytes.is_empty(),
+            Body::Stream(stream) => stream.trailers.is_none() && stream.body.is_end_stream(),
+        }
+    }
+
+    fn size_hint(&self) -> http_body::SizeHint {
+        match self {
+            Body::Empty => http_body::SizeHint::with_exact(0),
+            Body::Full(bytes) => http_body::SizeHint::with_exact(bytes.len() as u64),
+            Body::Stream(stream) => stream.body.size_hint(),
+        }
+    }
+}
+
+impl futures::Stream for Body {
+    type Item = Result<Bytes, std::io::Error>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        match &mut *self {
+            Body::Empty => Poll::Ready(None),
+            Body::Full(bytes) => {
+                let bytes = std::mem::take(bytes);
+                *self = Body::Empty;
+                Poll::Ready((!bytes.is_empty()).then_some(Ok(bytes)))
+            }
+            Body::Stream(stream) => loop {
+                let frame = match std::task::ready!(stream.body.as_mut().poll_frame(cx)) {
+                    Some(Ok(frame)) => frame,
+                    Some(Err(error)) => return Poll::Ready(Some(Err(error))),
+                    None => return Poll::Ready(None),
+                };
+                match frame.into_data() {
+                    Ok(data) => return Poll::Ready(Some(Ok(data))),
+                    // Keep the trailers, so that they can still be read once
+                    // all of the data has been.
+                    Err(frame) => stream.trailers = frame.into_trailers().ok(),
+                }
+            },
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        match self {
+            Body::Empty => (0, Some(0)),
+            Body::Full(bytes) => (usize::from(!bytes.is_empty()), Some(1)),
+            Body::Stream(_) => (0, None),
+        }
+    }
+}
+
+impl From<Bytes> for Body {
+    fn from(bytes: Bytes) -> Self {
+        Body::Full(bytes)
+    }
+}
+
+impl From<Vec<u8>> for Body {
+    fn from(bytes: Vec<u8>) -> Self {
+        Body::Full(bytes.into())
+    }
+}
+
+impl From<&'static [u8]> for Body {
+    fn from(bytes: &'static [u8]) -> Self {
+        Body::Full(Bytes::from_static(bytes))
+    }
+}
+
+impl From<String> for Body {
+    fn from(string: String) -> Self {
+        Body::Full(string.into())
+    }
+}
+
+impl From<&'static str> for Body {
+    fn from(string: &'static str) -> Self {
+        Body::Full(Bytes::from_static(string.as_bytes()))
+    }
+}
+
+impl From<std::borrow::Cow<'static, [u8]>> for Body {
+    fn from(bytes: std::borrow::Cow<'static, [u8]>) -> Self {
+        match bytes {
+            std::borrow::Cow::Borrowed(bytes) => bytes.into(),
+            std::borrow::Cow::Owned(bytes) => bytes.into(),
+        }
+    }
+}
+
+impl From<std::borrow::Cow<'static, str>> for Body {
+    fn from(string: std::borrow::Cow<'static, str>) -> Self {
+        match string {
+            std::borrow::Cow::Borrowed(string) => string.into(),
+            std::borrow::Cow::Owned(string) => string.into(),
+        }
+    }
+}
+
+/// Converts an error from sending on a [`Body::channel`] into an I/O error;
+/// sending only fails if the body was dropped.
+fn map_send_error(e: http_body_util::channel::SendError) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::BrokenPipe, e)
+}
+
+/// Converts an error from reading a hyper body into an I/O error, treating a
+/// body that ended early as the end of the file.
+fn map_hyper_error(e: hyper::Error) -> std::io::Error {
+    if e.is_closed() || e.is_incomplete_message() || e.is_canceled() {
+        std::io::Error::new(std::io::ErrorKind::UnexpectedEof, e)
+    } else {
+        std::io::Error::other(e)
+    }
+}
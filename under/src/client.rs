@@ -0,0 +1,217 @@
+//! An HTTP client, for making outbound requests.
+//!
+//! Most servers also make requests of their own, to other services.  This
+//! is a thin wrapper around hyper's client that uses the same [`Request`]
+//! and [`Response`] types as the rest of the framework, so that e.g. a
+//! request can be built with [`Request::post`] and its response read with
+//! [`crate::HttpEntity::data`].  The client supports both `http` and `https`
+//! (using the webpki roots).
+//!
+//! A [`Client`] holds a connection pool, which all of its clones share; so
+//! an application should create one, and share it between its endpoints,
+//! e.g. with [`crate::middleware::StateMiddleware`] (see
+//! [`Request::client`]).
+//!
+//! # Examples
+//! ```rust
+//! # use under::*;
+//! use under::client::Client;
+//! use under::middleware::StateMiddleware;
+//! # #[tokio::main] async fn main() -> Result<(), anyhow::Error> {
+//! # let listener = Listener::bind("127.0.0.1:0")?;
+//! # let address = listener.local_addr()?;
+//! # let mut upstream = under::http();
+//! # upstream.at("/greeting").get(under::endpoints::simple(|| Response::text("hello")));
+//! # tokio::spawn(upstream.listen_on(listener));
+//! # let upstream = format!("http://{address}/greeting");
+//! let mut http = under::http();
+//! http.with(StateMiddleware::new(Client::new()))
+//!     .at("/").get(move |request: Request| {
+//!         let upstream = upstream.clone();
+//!         async move {
+//!             let client = request.client().unwrap();
+//!             let mut response = client.send_for(&request, Request::get(&upstream)?).await?;
+//!             let greeting = response.data(512).into_text().await?;
+//!             Ok::<_, anyhow::Error>(Response::text(format!("{greeting}, world")))
+//!         }
+//!     });
+//! http.prepare();
+//! let mut response = http.handle(Request::get("/")?).await?;
+//! assert_eq!(response.data(512).into_text().await?, "hello, world");
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::middleware::Deadline;
+use crate::{Request, Response, UnderError};
+use hyper_util::client::legacy::connect::HttpConnector;
+use hyper_util::rt::TokioExecutor;
+
+type Connector = hyper_rustls::HttpsConnector<HttpConnector>;
+
+#[derive(Clone)]
+/// An HTTP client.  See the [module documentation](self) for more.
+///
+/// This is cheap to clone - all clones share the same connection pool.
+pub struct Client {
+    http: hyper_util::client::legacy::Client<Connector, crate::Body>,
+}
+
+impl Client {
+    /// Creates a new client, with its own connection pool.
+    #[must_use]
+    pub fn new() -> Self {
+        let connector = hyper_rustls::HttpsConnectorBuilder::new()
+            .with_webpki_roots()
+            .https_or_http()
+            .enable_all_versions()
+            .build();
+        Client {
+            http: hyper_util::client::legacy::Client::builder(TokioExecutor::new())
+                .build(connector),
+        }
+    }
+
+    /// Sends the given request, returning its response.  The URI of the
+    /// request must be absolute (e.g. `https://example.com/`).
+    ///
+    /// The request and its response are logged (using `log`) at the `debug`
+    /// level.
+    ///
+    /// If this is called while handling a request that has a deadline (see
+    /// [`Deadline::current`]), the outbound request is
+    /// abandoned once the deadline passes.
+    ///
+    /// # Errors
+    /// This errors with [`UnderError::DeadlineExceeded`] if the deadline of
+    /// the request being handled passes before the response is received;
+    /// and if the request could not be sent, or if no response was received
+    /// (e.g. because the connection failed).
+    ///
+    /// # Examples
+    /// ```rust
+    /// # use under::*;
+    /// use std::time::Duration;
+    /// use under::client::Client;
+    /// use under::middleware::Timeout;
+    /// # #[tokio::main] async fn main() -> Result<(), anyhow::Error> {
+    /// # let listener = Listener::bind("127.0.0.1:0")?;
+    /// # let upstream = format!("http://{}/", listener.local_addr()?);
+    /// # let mut slow = under::http();
+    /// # slow.at("/").get(|_: Request| async {
+    /// #     tokio::time::sleep(Duration::from_secs(5)).await;
+    /// #     Response::empty_204()
+    /// # });
+    /// # tokio::spawn(slow.listen_on(listener));
+    /// let mut http = under::http();
+    /// http.with(Timeout::new(Duration::from_millis(100)));
+    /// http.at("/").get(move |_: Request| {
+    ///     let upstream = upstream.clone();
+    ///     async move {
+    ///         // The upstream takes longer than the timeout.
+    ///         let response = Client::new().send(Request::get(&upstream)?).await?;
+    ///         Ok::<_, anyhow::Error>(response)
+    ///     }
+    /// });
+    /// http.prepare();
+    /// let response = http.handle(Request::get("/")?).await?;
+    /// assert_eq!(response.status(), http::StatusCode::GATEWAY_TIMEOUT);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn send(&self, request: Request) -> Result<Response, UnderError> {
+        self.send_until(request, Deadline::current()).await
+    }
+
+    /// Sends the given request, abandoning it once the deadline (if any)
+    /// passes.
+    async fn send_until(
+        &self,
+        request: Request,
+        deadline: Option<Deadline>,
+    ) -> Result<Response, UnderError> {
+        match deadline {
+            Some(deadline) => {
+                let until = tokio::time::Instant::from_std(deadline.instant());
+                tokio::time::timeout_at(until, self.request(request))
+                    .await
+                    .unwrap_or(Err(UnderError::DeadlineExceeded))
+            }
+            None => self.request(request).await,
+        }
+    }
+
+    async fn request(&self, request: Request) -> Result<Response, UnderError> {
+        let method = request.method().clone();
+        let uri = request.uri().clone();
+        log::debug!("client --> {method} {uri}");
+        let start = std::time::Instant::now();
+        let result = self.http.request(request.into()).await;
+        let elapsed = start.elapsed();
+        match &result {
+            Ok(response) => log::debug!(
+                "client <-- {method} {uri}: {} (in {}ms)",
+                response.status(),
+                elapsed.as_millis()
+            ),
+            Err(error) => log::debug!(
+                "client <-- {method} {uri}: {error} (in {}ms)",
+                elapsed.as_millis()
+            ),
+        }
+
+        result
+            .map(|response| Response::from(response.map(crate::Body::from_incoming)))
+            .map_err(|error| UnderError::Client(error.into()))
+    }
+
+    /// Sends the given request on behalf of an incoming one.  This behaves
+    /// the same as [`Client::send`], but also records how long the request
+    /// took into the timings of the incoming request (see
+    /// [`crate::Router::profile`]), as `client <host>`; so outbound calls
+    /// show up in e.g. [`crate::middleware::ServerTiming`].
+    ///
+    /// If the incoming request has a deadline (see [`Request::deadline`]),
+    /// the outbound request is abandoned once the deadline passes; so
+    /// downstream calls never outlive the request they were made for, even
+    /// from a task spawned to handle it.
+    ///
+    /// # Errors
+    /// This errors with [`UnderError::DeadlineExceeded`] if the deadline of
+    /// the incoming request passes before the response is received, or for
+    /// the same reasons as [`Client::send`].
+    pub async fn send_for(
+        &self,
+        incoming: &Request,
+        request: Request,
+    ) -> Result<Response, UnderError> {
+        let name = format!("client {}", request.uri().host().unwrap_or_default());
+        let start = std::time::Instant::now();
+        let deadline = incoming.deadline().copied();
+        let deadline = deadline.into_iter().chain(Deadline::current()).min();
+        let result = self.send_until(request, deadline).await;
+        incoming.timing(name, start.elapsed());
+        result
+    }
+
+    /// Sends a `GET` request to the given URI.
+    ///
+    /// # Errors
+    /// This errors if the URI is invalid, or for the same reasons as
+    /// [`Client::send`].
+    pub async fn get(&self, uri: &str) -> Result<Response, anyhow::Error> {
+        Ok(self.send(Request::get(uri)?).await?)
+    }
+}
+
+impl Default for Client {
+    fn default() -> Self {
+        Client::new()
+    }
+}
+
+impl std::fmt::Debug for Client {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Client").finish_non_exhaustive()
+    }
+}
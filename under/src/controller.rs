@@ -0,0 +1,109 @@
+use crate::{Endpoint, IntoResponse, Path, Request, Response};
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+
+/// A set of endpoints that share a single value.
+///
+/// Rather than writing each endpoint as a free function, and sharing state
+/// between them through [`crate::middleware::StateMiddleware`] (or a global),
+/// a controller is a struct whose methods are the endpoints, and whose fields
+/// are available to each of them.  [`Controller::routes`] describes where
+/// each method is routed to; the controller is then mounted all at once,
+/// with [`crate::Router::controller`] (or [`Path::controller`]).
+///
+/// Each method takes the controller as an `Arc<Self>`, so that the future it
+/// returns may hold on to it; [`Controller::action`] turns such a method
+/// into an endpoint.
+///
+/// # Examples
+/// ```rust
+/// # use under::*;
+/// use std::sync::atomic::{AtomicUsize, Ordering};
+/// use std::sync::Arc;
+///
+/// struct Counter {
+///     count: AtomicUsize,
+/// }
+///
+/// impl Counter {
+///     async fn show(self: Arc<Self>, _: Request) -> Response {
+///         Response::text(self.count.load(Ordering::SeqCst).to_string())
+///     }
+///
+///     async fn increment(self: Arc<Self>, _: Request) -> Response {
+///         self.count.fetch_add(1, Ordering::SeqCst);
+///         Response::empty_204()
+///     }
+/// }
+///
+/// impl Controller for Counter {
+///     fn routes(self: &Arc<Self>, path: &mut Path<'_>) {
+///         path.get(self.action(Self::show));
+///         path.at("/increment").post(self.action(Self::increment));
+///     }
+/// }
+///
+/// # #[tokio::main] async fn main() -> Result<(), anyhow::Error> {
+/// let mut http = under::http();
+/// http.controller("/counter", Counter { count: AtomicUsize::new(0) });
+/// http.prepare();
+/// http.handle(Request::post("/counter/increment")?).await?;
+/// let mut response = http.handle(Request::get("/counter")?).await?;
+/// assert_eq!(response.data(512).into_text().await?, "1");
+/// # Ok(())
+/// # }
+/// ```
+pub trait Controller: Send + Sync + Sized + 'static {
+    /// Declares the routes of the controller.  The given path is the one the
+    /// controller is mounted at.
+    fn routes(self: &Arc<Self>, path: &mut Path<'_>);
+
+    /// Creates an endpoint that calls the given method of this controller.
+    fn action<F, Fut, Res>(self: &Arc<Self>, method: F) -> Action<Self, F>
+    where
+        F: Fn(Arc<Self>, Request) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Res> + Send + 'static,
+        Res: IntoResponse + Send + 'static,
+    {
+        Action {
+            controller: self.clone(),
+            method,
+        }
+    }
+}
+
+/// An endpoint that calls a method of a controller.  See
+/// [`Controller::action`].
+pub struct Action<C, F> {
+    controller: Arc<C>,
+    method: F,
+}
+
+#[async_trait]
+impl<C, F, Fut, Res> Endpoint for Action<C, F>
+where
+    C: Controller,
+    F: Fn(Arc<C>, Request) -> Fut + Send + Sync + 'static,
+    Fut: Future<Output = Res> + Send + 'static,
+    Res: IntoResponse + Send + 'static,
+{
+    async fn apply(self: Pin<&Self>, request: Request) -> Result<Response, anyhow::Error> {
+        (self.method)(self.controller.clone(), request)
+            .await
+            .into_response()
+    }
+
+    fn describe(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", std::any::type_name::<F>())
+    }
+}
+
+impl<C, F> std::fmt::Debug for Action<C, F> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Action")
+            .field("controller", &std::any::type_name::<C>())
+            .field("method", &std::any::type_name::<F>())
+            .finish()
+    }
+}
@@ -1,4 +1,3 @@
-use futures::stream::MapErr;
 use futures::TryStreamExt;
 use tokio::io::{AsyncReadExt, AsyncWrite, Take};
 use tokio_util::io::StreamReader;
@@ -17,11 +16,11 @@ use crate::UnderError;
 #[must_use = "this consumes the body of the request regardless of whether it is used"]
 pub struct DataStream {
     /// The underlying stream.
-    stream: Take<StreamReader<HttpStream, hyper::body::Bytes>>,
+    stream: Take<StreamReader<crate::Body, bytes::Bytes>>,
+    /// The maximum amount of time reading the stream may take.
+    timeout: Option<std::time::Duration>,
 }
 
-type HttpStream = MapErr<hyper::Body, fn(hyper::Error) -> std::io::Error>;
-
 #[derive(Debug, Copy, Clone)]
 /// Information about a data transfer.  This is the result of
 /// [`DataStream::into`], and provides information about the state of the
@@ -37,13 +36,40 @@ pub struct DataTransfer {
 }
 
 impl DataStream {
-    /// Create a new data stream from a hyper body.
-    pub(crate) fn new(body: hyper::Body, limit: u64) -> Self {
+    /// Create a new data stream from a body.
+    pub(crate) fn new(body: crate::Body, limit: u64) -> Self {
         Self {
-            stream: StreamReader::new(body.map_err(map_hyper_error as fn(_) -> _)).take(limit + 1),
+            stream: StreamReader::new(body).take(limit + 1),
+            timeout: None,
         }
     }
 
+    /// Sets the maximum amount of time that reading the stream may take.
+    /// This applies to the entire read, and not to each individual chunk;
+    /// so a client cannot hold the stream open forever by sending the body
+    /// very slowly.  Requests handled by a router may also have a default
+    /// timeout (see [`crate::Router::body_timeout`]).
+    ///
+    /// # Examples
+    /// ```rust
+    /// # use under::*;
+    /// use std::time::Duration;
+    /// # #[tokio::main] async fn main() -> Result<(), anyhow::Error> {
+    /// let (_sender, body) = Body::channel();
+    /// let mut request = Request::post("/")?.with_body(body);
+    /// let result = request.data(512)
+    ///     .with_timeout(Duration::from_millis(10))
+    ///     .into_text()
+    ///     .await;
+    /// assert!(matches!(result, Err(UnderError::ReadTimeout(_))));
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn with_timeout(mut self, timeout: std::time::Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
     // note: this is destructive on the stream, so it should only be used once.
     fn limit_exceeded(&mut self) -> bool {
         self.stream.limit() <= 1
@@ -56,15 +82,28 @@ impl DataStream {
     ///
     /// # Errors
     /// This returns an error if the underlying stream cannot be written to the
-    /// given writer.  It does not return an error if the stream is incomplete,
-    /// as that is expected to be handled by the caller.
+    /// given writer, or if reading it takes longer than the timeout (see
+    /// [`DataStream::with_timeout`]).  It does not return an error if the
+    /// stream is incomplete, as that is expected to be handled by the caller.
     pub async fn into<W: AsyncWrite + Unpin>(
         mut self,
         writer: &mut W,
     ) -> Result<DataTransfer, UnderError> {
-        let written = tokio::io::copy(&mut self.stream, writer)
-            .await
-            .map_err(UnderError::ReadBody)?;
+        self.copy(writer).await
+    }
+
+    async fn copy<W: AsyncWrite + Unpin>(
+        &mut self,
+        writer: &mut W,
+    ) -> Result<DataTransfer, UnderError> {
+        let copy = tokio::io::copy(&mut self.stream, writer);
+        let written = match self.timeout {
+            Some(timeout) => tokio::time::timeout(timeout, copy)
+                .await
+                .map_err(UnderError::ReadTimeout)?,
+            None => copy.await,
+        }
+        .map_err(UnderError::ReadBody)?;
         let complete = !self.limit_exceeded();
         Ok(DataTransfer::new(written, complete))
     }
@@ -91,6 +130,113 @@ impl DataStream {
         }
     }
 
+    /// Read data from the stream into a shared byte buffer.
+    ///
+    /// This behaves the same as [`DataStream::into_bytes`], but if the body
+    /// arrives as a single chunk (as, e.g., a body created from a string or
+    /// byte buffer does), that chunk is returned as-is, without copying it.
+    /// This is useful for handing large bodies to APIs that accept
+    /// [`bytes::Bytes`].
+    ///
+    /// # Errors
+    /// Errors for the same reason as [`DataStream::into_bytes`].
+    ///
+    /// # Examples
+    /// ```rust
+    /// # use under::*;
+    /// # #[tokio::main] async fn main() -> Result<(), anyhow::Error> {
+    /// let body = bytes::Bytes::from_static(b"hello, world");
+    /// let mut request = Request::post("/")?.with_body(body.clone());
+    /// let data = request.data(512).into_bytes_shared().await?;
+    /// assert_eq!(data, body);
+    /// assert_eq!(data.as_ptr(), body.as_ptr());
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn into_bytes_shared(self) -> Result<bytes::Bytes, UnderError> {
+        let mut remaining = self.stream.limit();
+        // Nothing has been read from the stream yet, so the reader has no
+        // buffered data that would be lost here.
+        let mut body = self.stream.into_inner().into_inner();
+        let read = async move {
+            let mut chunks = Vec::<bytes::Bytes>::new();
+            while let Some(chunk) = body.try_next().await.map_err(UnderError::ReadBody)? {
+                remaining = remaining.saturating_sub(chunk.len() as u64);
+                if remaining <= 1 {
+                    return Err(UnderError::PayloadTooLarge(anyhow::anyhow!(
+                        "body too large"
+                    )));
+                }
+                chunks.push(chunk);
+            }
+
+            Ok(match chunks.len() {
+                0 => bytes::Bytes::new(),
+                1 => chunks.swap_remove(0),
+                _ => chunks.concat().into(),
+            })
+        };
+
+        match self.timeout {
+            Some(timeout) => tokio::time::timeout(timeout, read)
+                .await
+                .map_err(UnderError::ReadTimeout)?,
+            None => read.await,
+        }
+    }
+
+    /// Read data from the stream into a byte array, and then read the
+    /// trailers of the body, if it has any.  Trailers are only available
+    /// once the entire body has been read, and can only be sent over HTTP/2
+    /// (or in a body created with [`crate::Response::channel`]).
+    ///
+    /// # Errors
+    /// Errors for the same reason as [`DataStream::into_bytes`], and also
+    /// returns an error if the trailers could not be read.
+    ///
+    /// # Examples
+    /// ```rust
+    /// # use under::*;
+    /// # #[tokio::main] async fn main() -> Result<(), anyhow::Error> {
+    /// let (mut sender, body) = Body::channel();
+    /// let mut request = Request::post("/")?.with_body(body);
+    /// tokio::spawn(async move {
+    ///     sender.send_data("hello".into()).await?;
+    ///     let mut trailers = http::HeaderMap::new();
+    ///     trailers.insert("Row-Count", "1".parse()?);
+    ///     sender.send_trailers(trailers).await?;
+    ///     Ok::<_, anyhow::Error>(())
+    /// });
+    /// let (body, trailers) = request.data(512).into_bytes_with_trailers().await?;
+    /// assert_eq!(body, b"hello");
+    /// assert_eq!(trailers.unwrap()["Row-Count"], "1");
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn into_bytes_with_trailers(
+        mut self,
+    ) -> Result<(Vec<u8>, Option<http::HeaderMap>), UnderError> {
+        let mut buf = Vec::new();
+        let transfer = self.copy(&mut buf).await?;
+
+        if !transfer.complete {
+            return Err(UnderError::PayloadTooLarge(anyhow::anyhow!(
+                "body too large"
+            )));
+        }
+
+        let mut body = self.stream.into_inner().into_inner();
+        let trailers = body.trailers();
+        let trailers = match self.timeout {
+            Some(timeout) => tokio::time::timeout(timeout, trailers)
+                .await
+                .map_err(UnderError::ReadTimeout)?,
+            None => trailers.await,
+        }
+        .map_err(UnderError::ReadBody)?;
+        Ok((buf, trailers))
+    }
+
     /// Read data from the stream into a string.
     ///
     /// This streams from the body into the provided buffer, and returns the
@@ -126,7 +272,7 @@ impl DataStream {
     /// # }
     /// ```
     #[cfg(feature = "json")]
-    #[cfg_attr(nightly, doc(cfg(feature = "json")))]
+    #[cfg_attr(docsrs, doc(cfg(feature = "json")))]
     pub async fn into_json<T: serde::de::DeserializeOwned>(self) -> Result<T, UnderError> {
         let bytes = self.into_bytes().await?;
         serde_json::from_slice(&bytes[..]).map_err(crate::UnderError::JsonDeserialization)
@@ -148,7 +294,7 @@ impl DataStream {
     /// # Ok(())
     /// # }
     #[cfg(feature = "cbor")]
-    #[cfg_attr(nightly, doc(cfg(feature = "cbor")))]
+    #[cfg_attr(docsrs, doc(cfg(feature = "cbor")))]
     pub async fn into_cbor<T: serde::de::DeserializeOwned>(self) -> Result<T, UnderError> {
         let bytes = self.into_bytes().await?;
         ciborium::de::from_reader(&bytes[..])
@@ -172,12 +318,39 @@ impl DataStream {
     /// # }
     /// ```
     #[cfg(feature = "msgpack")]
-    #[cfg_attr(nightly, doc(cfg(feature = "msgpack")))]
+    #[cfg_attr(docsrs, doc(cfg(feature = "msgpack")))]
     pub async fn into_msgpack<T: serde::de::DeserializeOwned>(self) -> Result<T, UnderError> {
         let bytes = self.into_bytes().await?;
         rmp_serde::from_slice(&bytes[..]).map_err(crate::UnderError::MsgpackDeserialization)
     }
 
+    /// Parses the contents of the body as XML, deserializing it into the
+    /// given value.  This assumes that the body is UTF-8 (or a compatible
+    /// encoding), as declared by the XML prolog, if any.
+    ///
+    /// # Errors
+    /// Errors for the same reason as [`DataStream::into_bytes`], and also
+    /// returns an error if the body cannot be converted to an XML value.
+    ///
+    /// # Examples
+    /// ```rust
+    /// # use under::*;
+    /// #[derive(Debug, serde::Deserialize, PartialEq, Eq)]
+    /// struct Greeting { hello: String }
+    /// # #[tokio::main] async fn main() -> Result<(), anyhow::Error> {
+    /// let stream = DataStream::from("<greeting><hello>world</hello></greeting>");
+    /// let body = stream.into_xml::<Greeting>().await?;
+    /// assert_eq!(body, Greeting { hello: "world".to_string() });
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[cfg(feature = "xml")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "xml")))]
+    pub async fn into_xml<T: serde::de::DeserializeOwned>(self) -> Result<T, UnderError> {
+        let bytes = self.into_bytes().await?;
+        quick_xml::de::from_reader(&bytes[..]).map_err(crate::UnderError::XmlDeserialization)
+    }
+
     /// Parses the contents of the body as x-www-form-urlencoded,
     /// deserializing it into the given value.  This
     /// assumes that the request body is already UTF-8, or a UTF-8 compatible
@@ -203,20 +376,40 @@ impl DataStream {
     /// # }
     /// ```
     #[cfg(feature = "from_form")]
-    #[cfg_attr(nightly, doc(cfg(feature = "from_form")))]
+    #[cfg_attr(docsrs, doc(cfg(feature = "from_form")))]
     pub async fn into_form<T: crate::from_form::FromForm>(self) -> Result<T, UnderError> {
         let bytes = self.into_bytes().await?;
         let items = form_urlencoded::parse(&bytes);
         T::from_form(items).map_err(crate::UnderError::FormDeserialization)
     }
+
+    /// Parses the contents of the body as `multipart/form-data`, delimited
+    /// by the given boundary (see [`crate::multipart::Multipart::parse`]).
+    /// This does not check the content type; see
+    /// [`HttpEntity::as_multipart`](crate::HttpEntity::as_multipart) for a
+    /// version that does, and takes the boundary from it.
+    ///
+    /// # Errors
+    /// Errors for the same reason as [`DataStream::into_bytes`], and also
+    /// returns an error if the body is not valid multipart.
+    #[cfg(feature = "multipart")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "multipart")))]
+    pub async fn into_multipart(
+        self,
+        boundary: &str,
+    ) -> Result<crate::multipart::Multipart, UnderError> {
+        let bytes = self.into_bytes_shared().await?;
+        crate::multipart::Multipart::parse(boundary, &bytes)
+            .map_err(crate::UnderError::MultipartDeserialization)
+    }
 }
 
 impl<T> From<T> for DataStream
 where
-    T: Into<hyper::Body>,
+    T: Into<crate::Body>,
 {
     fn from(body: T) -> Self {
-        use hyper::body::HttpBody;
+        use http_body::Body;
         let body = body.into();
         let size_hint = body.size_hint();
         let limit = size_hint
@@ -233,11 +426,3 @@ impl DataTransfer {
         Self { count, complete }
     }
 }
-
-fn map_hyper_error(e: hyper::Error) -> std::io::Error {
-    if e.is_closed() || e.is_incomplete_message() || e.is_canceled() {
-        std::io::Error::new(std::io::ErrorKind::UnexpectedEof, e)
-    } else {
-        std::io::Error::new(std::io::ErrorKind::Other, e)
-    }
-}
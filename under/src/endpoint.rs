@@ -40,3 +40,85 @@ where
         self(request).await.into_response()
     }
 }
+
+/// Combinators for composing behavior around an [`Endpoint`], without
+/// writing a [`crate::Middleware`].
+///
+/// This is implemented for every endpoint.  It is not exported from the
+/// root of the crate, since [`crate::Router`] is also an endpoint, and has
+/// methods of its own with the same names (e.g.
+/// [`crate::Router::map_response`]); on a router, those are the ones you
+/// want.
+///
+/// # Examples
+/// ```rust
+/// # use under::*;
+/// use under::endpoints::EndpointExt;
+/// # #[tokio::main] async fn main() -> Result<(), anyhow::Error> {
+/// let endpoint = under::endpoints::simple(|| Response::text("hello"))
+///     .before(|request: Request| async move {
+///         anyhow::ensure!(request.header("X-Token").is_some(), "missing token");
+///         Ok(request)
+///     })
+///     .map_response(|response| response.with_header("X-Greeting", "1").unwrap())
+///     .map_err(|error| error.context("greeting failed"));
+/// let mut http = under::http();
+/// http.at("/").get(endpoint);
+/// http.prepare();
+/// let response = http.handle(Request::get("/")?.with_header("X-Token", "t")?).await?;
+/// assert_eq!(response.header("X-Greeting").unwrap(), "1");
+/// let error = http.handle(Request::get("/")?).await.unwrap_err();
+/// assert_eq!(format!("{error:#}"), "greeting failed: missing token");
+/// # Ok(())
+/// # }
+/// ```
+pub trait EndpointExt: Endpoint + Sized {
+    /// Maps the response of this endpoint, if it succeeds.
+    fn map_response<F>(self, map: F) -> crate::endpoints::MapResponse<Self, F>
+    where
+        F: Fn(Response) -> Response + Send + Sync + 'static,
+    {
+        crate::endpoints::MapResponse::new(self, map)
+    }
+
+    /// Maps the error of this endpoint, if it fails.  Errors that the router
+    /// turns into responses (e.g. [`crate::authz::Denied`]) may be returned
+    /// from here, to turn an error into a particular response.
+    fn map_err<F, E>(self, map: F) -> crate::endpoints::MapErr<Self, F>
+    where
+        F: Fn(anyhow::Error) -> E + Send + Sync + 'static,
+        E: Into<anyhow::Error>,
+    {
+        crate::endpoints::MapErr::new(self, map)
+    }
+
+    /// Runs the given function on each request before passing it to this
+    /// endpoint.  If the function errors, the endpoint is not run, and the
+    /// error is returned instead.
+    fn before<F, Fut>(self, before: F) -> crate::endpoints::Before<Self, F>
+    where
+        F: Fn(Request) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<Request, anyhow::Error>> + Send + 'static,
+    {
+        crate::endpoints::Before::new(self, before)
+    }
+
+    /// Erases the type of this endpoint; e.g., so that different endpoints
+    /// can be returned from the branches of an `if`.
+    ///
+    /// # Examples
+    /// ```rust
+    /// # use under::*;
+    /// use under::endpoints::EndpointExt;
+    /// let endpoint = if cfg!(debug_assertions) {
+    ///     under::endpoints::simple(|| Response::text("debug")).boxed()
+    /// } else {
+    ///     under::endpoints::simple(Response::empty_404).boxed()
+    /// };
+    /// ```
+    fn boxed(self) -> crate::endpoints::BoxedEndpoint {
+        crate::endpoints::BoxedEndpoint::new(self)
+    }
+}
+
+impl<E: Endpoint> EndpointExt for E {}
@@ -0,0 +1,190 @@
+use crate::{Endpoint, Request, Response};
+use std::future::Future;
+use std::pin::Pin;
+
+/// An endpoint that maps the response of another.  See
+/// [`crate::endpoints::EndpointExt::map_response`].
+pub struct MapResponse<E, F> {
+    endpoint: Pin<Box<E>>,
+    map: F,
+}
+
+impl<E, F> MapResponse<E, F> {
+    pub(crate) fn new(endpoint: E, map: F) -> Self {
+        MapResponse {
+            endpoint: Box::pin(endpoint),
+            map,
+        }
+    }
+}
+
+#[async_trait]
+impl<E, F> Endpoint for MapResponse<E, F>
+where
+    E: Endpoint,
+    F: Fn(Response) -> Response + Send + Sync + 'static,
+{
+    async fn apply(self: Pin<&Self>, request: Request) -> Result<Response, anyhow::Error> {
+        let response = self.endpoint.as_ref().apply(request).await?;
+        Ok((self.map)(response))
+    }
+
+    fn describe(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.endpoint.describe(f)
+    }
+}
+
+/// An endpoint that maps the error of another.  See
+/// [`crate::endpoints::EndpointExt::map_err`].
+pub struct MapErr<E, F> {
+    endpoint: Pin<Box<E>>,
+    map: F,
+}
+
+impl<E, F> MapErr<E, F> {
+    pub(crate) fn new(endpoint: E, map: F) -> Self {
+        MapErr {
+            endpoint: Box::pin(endpoint),
+            map,
+        }
+    }
+}
+
+#[async_trait]
+impl<E, F, Err> Endpoint for MapErr<E, F>
+where
+    E: Endpoint,
+    F: Fn(anyhow::Error) -> Err + Send + Sync + 'static,
+    Err: Into<anyhow::Error>,
+{
+    async fn apply(self: Pin<&Self>, request: Request) -> Result<Response, anyhow::Error> {
+        self.endpoint
+            .as_ref()
+            .apply(request)
+            .await
+            .map_err(|error| (self.map)(error).into())
+    }
+
+    fn describe(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.endpoint.describe(f)
+    }
+}
+
+/// An endpoint that runs a function on the request before passing it to
+/// another.  See [`crate::endpoints::EndpointExt::before`].
+pub struct Before<E, F> {
+    endpoint: Pin<Box<E>>,
+    before: F,
+}
+
+impl<E, F> Before<E, F> {
+    pub(crate) fn new(endpoint: E, before: F) -> Self {
+        Before {
+            endpoint: Box::pin(endpoint),
+            before,
+        }
+    }
+}
+
+#[async_trait]
+impl<E, F, Fut> Endpoint for Before<E, F>
+where
+    E: Endpoint,
+    F: Fn(Request) -> Fut + Send + Sync + 'static,
+    Fut: Future<Output = Result<Request, anyhow::Error>> + Send + 'static,
+{
+    async fn apply(self: Pin<&Self>, request: Request) -> Result<Response, anyhow::Error> {
+        let request = (self.before)(request).await?;
+        self.endpoint.as_ref().apply(request).await
+    }
+
+    fn describe(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.endpoint.describe(f)
+    }
+}
+
+/// An endpoint whose type has been erased.  See
+/// [`crate::endpoints::EndpointExt::boxed`].
+pub struct BoxedEndpoint(Pin<Box<dyn Endpoint>>);
+
+impl BoxedEndpoint {
+    pub(crate) fn new<E: Endpoint>(endpoint: E) -> Self {
+        BoxedEndpoint(Box::pin(endpoint))
+    }
+}
+
+#[async_trait]
+impl Endpoint for BoxedEndpoint {
+    async fn apply(self: Pin<&Self>, request: Request) -> Result<Response, anyhow::Error> {
+        self.0.as_ref().apply(request).await
+    }
+
+    fn describe(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.0.describe(f)
+    }
+}
+
+/// An endpoint that tries each of a list of endpoints in turn, until one
+/// of them does not decline the request.  See
+/// [`crate::Router::fallback_chain`].
+pub struct FallbackChain(Vec<BoxedEndpoint>);
+
+impl FallbackChain {
+    pub(crate) fn new(chain: Vec<BoxedEndpoint>) -> Self {
+        FallbackChain(chain)
+    }
+}
+
+#[async_trait]
+impl Endpoint for FallbackChain {
+    async fn apply(self: Pin<&Self>, mut request: Request) -> Result<Response, anyhow::Error> {
+        let mut response = Response::empty_404();
+        for endpoint in &self.0 {
+            response = Pin::new(endpoint).apply(request).await?;
+            let declined = response.remove_ext::<crate::response::Declined>();
+            match declined.and_then(|declined| declined.take()) {
+                Some(declined) => request = declined,
+                None => return Ok(response),
+            }
+        }
+        Ok(response)
+    }
+
+    fn describe(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (i, endpoint) in self.0.iter().enumerate() {
+            if i > 0 {
+                f.write_str(" -> ")?;
+            }
+            endpoint.describe(f)?;
+        }
+        Ok(())
+    }
+}
+
+macro_rules! debug {
+    ($($name:ident),*) => {
+        $(
+            impl<E: Endpoint, F> std::fmt::Debug for $name<E, F> {
+                fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+                    f.debug_struct(stringify!($name))
+                        .field("endpoint", &std::any::type_name::<E>())
+                        .finish_non_exhaustive()
+                }
+            }
+        )*
+    };
+}
+
+debug!(MapResponse, MapErr, Before);
+
+impl std::fmt::Debug for FallbackChain {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("FallbackChain").field(&self.0).finish()
+    }
+}
+
+impl std::fmt::Debug for BoxedEndpoint {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("BoxedEndpoint").field(&self.0).finish()
+    }
+}
@@ -1,5 +1,6 @@
 use super::Endpoint;
-use crate::{Request, Response};
+use crate::etag::ETag;
+use crate::{HttpEntity, Request, Response};
 use anyhow::Error;
 use std::path::{Path, PathBuf};
 use std::pin::Pin;
@@ -8,20 +9,83 @@ use tokio_util::io::ReaderStream;
 #[derive(Debug, Clone)]
 pub(super) struct DirEndpoint {
     base: PathBuf,
+    offload: Option<super::Offload>,
 }
 
 impl DirEndpoint {
-    pub(super) fn new<P: Into<PathBuf>>(path: P) -> Self {
-        DirEndpoint { base: path.into() }
+    pub(super) fn new<P: Into<PathBuf>>(path: P, offload: Option<super::Offload>) -> Self {
+        DirEndpoint {
+            base: path.into(),
+            offload,
+        }
+    }
+
+    /// The response that hands the file off to the front proxy, if the
+    /// endpoint is configured to.
+    fn offload(&self, path: &Path) -> Option<Result<Response, Error>> {
+        let response = match self.offload.as_ref()? {
+            super::Offload::AccelRedirect(prefix) => {
+                let relative = path.strip_prefix(&self.base).ok()?;
+                let mut uri = prefix.trim_end_matches('/').to_owned();
+                for segment in relative {
+                    uri.push('/');
+                    encode_segment(&mut uri, &segment.to_string_lossy());
+                }
+                Response::x_accel_redirect(uri).map_err(Error::from)
+            }
+            super::Offload::Sendfile => std::path::absolute(path)
+                .map_err(Error::from)
+                .and_then(|path| Response::x_sendfile(path).map_err(Error::from)),
+        };
+        let mime_type = mime_guess::MimeGuess::from_path(path).first_or_octet_stream();
+        Some(response.and_then(|response| {
+            Ok(response.with_header(http::header::CONTENT_TYPE, mime_type.as_ref())?)
+        }))
+    }
+
+    async fn resolve_file(&self, mut path: PathBuf, request: &Request) -> Result<Response, Error> {
+        let uri_path = request.uri().path();
+        let meta = match tokio::fs::metadata(&path).await {
+            Ok(meta) if meta.is_dir() && !uri_path.ends_with('/') => {
+                return Response::permanent_redirect(format!("{uri_path}/")).map_err(Error::from);
+            }
+            Ok(meta) if meta.is_dir() => {
+                path.push("index.html");
+                match tokio::fs::metadata(&path).await {
+                    Ok(meta) if meta.is_file() => meta,
+                    _ => return Ok(Response::empty_404()),
+                }
+            }
+            Ok(meta) => meta,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Response::empty_404()),
+            Err(e) => return Err(e.into()),
+        };
+
+        if let Some(response) = self.offload(&path) {
+            return response;
+        }
+
+        let etag = file_etag(&meta);
+        if let Some(etag) = etag.as_ref().filter(|etag| etag.not_modified(request)) {
+            return Ok(Response::empty(http::StatusCode::NOT_MODIFIED)
+                .with_header(http::header::ETAG, etag.to_string())?);
+        }
+        let file = tokio::fs::File::open(&path).await?;
+        let mime_type = mime_guess::MimeGuess::from_path(&path).first_or_octet_stream();
+        let response =
+            Response::seekable_stream(request, file, meta.len(), mime_type.as_ref()).await?;
+        match etag {
+            Some(etag) => Ok(response.with_header(http::header::ETAG, etag.to_string())?),
+            None => Ok(response),
+        }
     }
 }
 
 #[async_trait]
 impl Endpoint for DirEndpoint {
     async fn apply(self: Pin<&Self>, request: Request) -> Result<Response, Error> {
-        let uri_path = request.uri().path();
         match resolve_path(request.fragment::<String, _>(1), &self.base) {
-            Some(path) => resolve_file(path, uri_path).await,
+            Some(path) => self.resolve_file(path, &request).await,
             None => Ok(Response::empty_404()),
         }
     }
@@ -43,35 +107,38 @@ fn resolve_path(param: Option<String>, base: &Path) -> Option<PathBuf> {
     Some(buffer)
 }
 
-async fn resolve_file(mut path: PathBuf, request: &str) -> Result<Response, Error> {
-    match tokio::fs::metadata(&path).await {
-        Ok(meta) if meta.is_dir() && !request.ends_with('/') => {
-            return Response::permanent_redirect(format!("{request}/")).map_err(Error::from);
-        }
-        Ok(meta) if meta.is_dir() => {
-            path.push("index.html");
-            if !tokio::fs::metadata(&path)
-                .await
-                .map(|m| m.is_file())
-                .unwrap_or(false)
-            {
-                return Ok(Response::empty_404());
-            }
-        }
-        Ok(_) => {}
-        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Response::empty_404()),
-        Err(e) => return Err(e.into()),
-    }
-
-    load_file(tokio::fs::File::open(&path).await?, &path)
+/// A weak tag for the file, from its length and modification time, or
+/// `None` if the platform does not record when it was modified.  This is
+/// weak, since the file may change without either changing.
+fn file_etag(meta: &std::fs::Metadata) -> Option<ETag> {
+    let modified = meta.modified().ok()?;
+    let modified = modified.duration_since(std::time::UNIX_EPOCH).ok()?;
+    Some(ETag::weak(format!(
+        "{:x}-{:x}",
+        meta.len(),
+        modified.as_nanos()
+    )))
 }
 
-fn load_file(file: tokio::fs::File, path: &Path) -> Result<Response, Error> {
+pub(super) fn load_file(file: tokio::fs::File, path: &Path) -> Result<Response, Error> {
     let mime_type = mime_guess::MimeGuess::from_path(path).first_or_octet_stream();
-    hyper::Response::builder()
+    http::Response::builder()
         .header(http::header::CONTENT_TYPE, mime_type.to_string())
-        .status(hyper::StatusCode::OK)
-        .body(hyper::Body::wrap_stream(ReaderStream::new(file)))
+        .status(http::StatusCode::OK)
+        .body(crate::Body::wrap_stream(ReaderStream::new(file)))
         .map(Response::from)
         .map_err(Error::from)
 }
+
+/// Percent-encodes a segment of a path onto the given URI.
+fn encode_segment(uri: &mut String, segment: &str) {
+    use std::fmt::Write;
+    for byte in segment.bytes() {
+        if byte.is_ascii_alphanumeric() || matches!(byte, b'-' | b'.' | b'_' | b'~') {
+            uri.push(char::from(byte));
+        } else {
+            // Writing to a string never fails.
+            let _ = write!(uri, "%{byte:02X}");
+        }
+    }
+}
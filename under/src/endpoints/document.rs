@@ -0,0 +1,43 @@
+use super::Endpoint;
+use crate::etag::ETag;
+use crate::{HttpEntity, Request, Response};
+use anyhow::Error;
+use bytes::Bytes;
+use std::pin::Pin;
+
+/// An endpoint that serves a fixed, pre-serialized document.  The `ETag` of
+/// the document is computed once, when the endpoint is created, and requests
+/// with a matching `If-None-Match` receive a 304.
+#[derive(Debug, Clone)]
+pub(crate) struct DocumentEndpoint {
+    body: Bytes,
+    content_type: &'static str,
+    etag: ETag,
+}
+
+impl DocumentEndpoint {
+    pub(crate) fn new<B: Into<Bytes>>(body: B, content_type: &'static str) -> Self {
+        let body = body.into();
+        let etag = ETag::from_data(&body);
+        DocumentEndpoint {
+            body,
+            content_type,
+            etag,
+        }
+    }
+}
+
+#[async_trait]
+impl Endpoint for DocumentEndpoint {
+    async fn apply(self: Pin<&Self>, request: Request) -> Result<Response, Error> {
+        let response = if self.etag.not_modified(&request) {
+            Response::empty(http::StatusCode::NOT_MODIFIED)
+        } else {
+            Response::empty_200()
+                .with_header(http::header::CONTENT_TYPE, self.content_type)?
+                .with_body(self.body.clone())
+        };
+
+        Ok(response.with_header(http::header::ETAG, self.etag.to_string())?)
+    }
+}
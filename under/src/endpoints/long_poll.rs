@@ -0,0 +1,42 @@
+use super::Endpoint;
+use crate::{Request, Response};
+use anyhow::Error;
+use std::future::Future;
+use std::pin::Pin;
+use std::time::Duration;
+
+/// An endpoint that waits for a value from its source, for up to a timeout.
+pub(super) struct LongPollEndpoint<F> {
+    source: F,
+    timeout: Duration,
+}
+
+impl<F> LongPollEndpoint<F> {
+    pub(super) fn new(source: F, timeout: Duration) -> Self {
+        LongPollEndpoint { source, timeout }
+    }
+}
+
+#[async_trait]
+impl<F, Fut, T> Endpoint for LongPollEndpoint<F>
+where
+    F: Fn(Request) -> Fut + Send + Sync + 'static,
+    Fut: Future<Output = Option<T>> + Send + 'static,
+    T: serde::Serialize + Send + 'static,
+{
+    async fn apply(self: Pin<&Self>, request: Request) -> Result<Response, Error> {
+        // If the client disconnects while this is waiting, the connection
+        // drops this future, and the source's future with it.
+        match tokio::time::timeout(self.timeout, (self.source)(request)).await {
+            Ok(Some(value)) => Ok(Response::json(&value)?),
+            Ok(None) | Err(_) => Ok(Response::empty_204()),
+        }
+    }
+
+    fn describe(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("LongPollEndpoint")
+            .field("source", &std::any::type_name::<F>())
+            .field("timeout", &self.timeout)
+            .finish()
+    }
+}
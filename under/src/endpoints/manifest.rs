@@ -0,0 +1,198 @@
+use super::Endpoint;
+use crate::{HttpEntity, Request, Response};
+use anyhow::Error;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use std::sync::Arc;
+
+/// The `Cache-Control` for a fingerprinted file.  Since its name changes
+/// whenever its contents do, it can be cached forever.
+const IMMUTABLE: &str = "public, max-age=31536000, immutable";
+
+/// A manifest of fingerprinted static assets, for cache-busting.
+///
+/// When loaded, this hashes every file in a directory, and gives each a
+/// fingerprinted name that includes the hash (e.g. `app.js` becomes
+/// `app.0123456789abcdef.js`).  [`StaticManifest::url`] then maps the
+/// original name to the URL of the fingerprinted one, for use in e.g.
+/// templates; and, as an endpoint, the manifest serves the fingerprinted
+/// names with a `Cache-Control` that lets browsers cache them forever.  The
+/// original names are still served, but with `Cache-Control: no-cache`.
+///
+/// The endpoint serves files by the path of the request (rather than a
+/// fragment), and so it should be mounted at the prefix given to
+/// [`StaticManifest::load`], with a `{:path}` fragment.  Clones of the
+/// manifest share the same files.
+///
+/// Files added to the directory after the manifest is loaded are not
+/// served; and since the hashes are computed once, a file that changes
+/// afterwards is served with its new contents under its old hash.
+///
+/// # Examples
+/// ```rust
+/// # use under::*;
+/// use under::endpoints::StaticManifest;
+/// # #[tokio::main] async fn main() -> Result<(), anyhow::Error> {
+/// # let dir = std::env::temp_dir().join(format!("under-manifest-{}", std::process::id()));
+/// # std::fs::create_dir_all(dir.join("js"))?;
+/// # std::fs::write(dir.join("js/app.js"), "console.log(1);")?;
+/// // `dir` contains `js/app.js`, which is `console.log(1);`.
+/// let manifest = StaticManifest::load(&dir, "/assets")?;
+/// let url = manifest.url("js/app.js").unwrap();
+/// assert!(url.starts_with("/assets/js/app.") && url.ends_with(".js"));
+///
+/// let mut http = under::http();
+/// http.at("/assets/{:path}").get(manifest.clone());
+/// http.prepare();
+/// let mut response = http.handle(Request::get(&url)?).await?;
+/// assert_eq!(
+///     response.header("Cache-Control").unwrap(),
+///     "public, max-age=31536000, immutable"
+/// );
+/// assert_eq!(response.data(512).into_text().await?, "console.log(1);");
+/// let response = http.handle(Request::get("/assets/js/app.js")?).await?;
+/// assert_eq!(response.header("Cache-Control").unwrap(), "no-cache");
+/// # std::fs::remove_dir_all(&dir)?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone)]
+pub struct StaticManifest(Arc<Manifest>);
+
+struct Manifest {
+    base: PathBuf,
+    prefix: String,
+    /// The fingerprinted name of each file, by its original name.
+    urls: HashMap<String, String>,
+    /// Each file that is served, by the name that it is served as; along
+    /// with whether or not that name is fingerprinted.
+    files: HashMap<String, (String, bool)>,
+}
+
+impl StaticManifest {
+    /// Loads the manifest for the given directory, hashing every file in it
+    /// (and its subdirectories).  The prefix is the path that the manifest is
+    /// served at, e.g. `/assets`.
+    ///
+    /// This reads the files synchronously, and so it should be called while
+    /// setting up the router, rather than while serving requests.
+    ///
+    /// # Errors
+    /// This errors if the directory, or any file in it, cannot be read.
+    pub fn load<P: Into<PathBuf>, S: Into<String>>(path: P, prefix: S) -> std::io::Result<Self> {
+        let base = path.into();
+        let mut manifest = Manifest {
+            prefix: prefix.into().trim_end_matches('/').to_owned(),
+            urls: HashMap::new(),
+            files: HashMap::new(),
+            base,
+        };
+
+        let mut pending = vec![PathBuf::new()];
+        while let Some(relative) = pending.pop() {
+            for entry in std::fs::read_dir(manifest.base.join(&relative))? {
+                let entry = entry?;
+                let relative = relative.join(entry.file_name());
+                if entry.file_type()?.is_dir() {
+                    pending.push(relative);
+                    continue;
+                }
+                let Some(name) = logical_name(&relative) else {
+                    continue;
+                };
+                let hash = crate::etag::fnv1a(&std::fs::read(entry.path())?);
+                let fingerprinted = fingerprint(&name, hash);
+                manifest
+                    .files
+                    .insert(fingerprinted.clone(), (name.clone(), true));
+                manifest.files.insert(name.clone(), (name.clone(), false));
+                manifest.urls.insert(name, fingerprinted);
+            }
+        }
+
+        Ok(StaticManifest(Arc::new(manifest)))
+    }
+
+    /// The URL of the fingerprinted version of the given file, which is a
+    /// path relative to the directory of the manifest (e.g. `js/app.js`).
+    /// This returns `None` if the file was not in the directory when the
+    /// manifest was loaded.
+    #[must_use]
+    pub fn url(&self, name: &str) -> Option<String> {
+        let name = name.trim_start_matches('/');
+        let fingerprinted = self.0.urls.get(name)?;
+        Some(format!("{}/{fingerprinted}", self.0.prefix))
+    }
+
+    /// Iterates over the files of the manifest, as pairs of their original
+    /// name and their fingerprinted one.  This is useful for, e.g., writing
+    /// the manifest out for a frontend build tool.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> + '_ {
+        self.0.urls.iter().map(|(k, v)| (k.as_str(), v.as_str()))
+    }
+}
+
+impl std::fmt::Debug for StaticManifest {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("StaticManifest")
+            .field("base", &self.0.base)
+            .field("prefix", &self.0.prefix)
+            .field("files", &self.0.urls.len())
+            .finish()
+    }
+}
+
+#[async_trait]
+impl Endpoint for StaticManifest {
+    async fn apply(self: Pin<&Self>, request: Request) -> Result<Response, Error> {
+        let file = request
+            .uri()
+            .path()
+            .strip_prefix(&self.0.prefix)
+            .and_then(|path| path.strip_prefix('/'))
+            .and_then(|path| self.0.files.get(path));
+        let Some((name, fingerprinted)) = file else {
+            return Ok(Response::empty_404());
+        };
+
+        let path = self.0.base.join(name);
+        let file = match tokio::fs::File::open(&path).await {
+            Ok(file) => file,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Response::empty_404()),
+            Err(e) => return Err(e.into()),
+        };
+        let cache = if *fingerprinted {
+            IMMUTABLE
+        } else {
+            "no-cache"
+        };
+        Ok(super::dir::load_file(file, &path)?.with_header(http::header::CACHE_CONTROL, cache)?)
+    }
+}
+
+/// The name of the file at the given path, relative to the directory of the
+/// manifest, with `/` as the separator.  Files whose names are not valid
+/// UTF-8 are left out of the manifest.
+fn logical_name(relative: &Path) -> Option<String> {
+    let parts = relative
+        .components()
+        .map(|part| part.as_os_str().to_str())
+        .collect::<Option<Vec<_>>>()?;
+    Some(parts.join("/"))
+}
+
+/// Inserts the hash into the name of the file, before its extension (e.g.
+/// `js/app.js` becomes `js/app.<hash>.js`).
+fn fingerprint(name: &str, hash: u64) -> String {
+    let (dir, file) = name.rsplit_once('/').map_or(("", name), |(d, f)| (d, f));
+    let file = match file.rsplit_once('.') {
+        Some((stem, extension)) if !stem.is_empty() => format!("{stem}.{hash:016x}.{extension}"),
+        _ => format!("{file}.{hash:016x}"),
+    };
+    if dir.is_empty() {
+        file
+    } else {
+        format!("{dir}/{file}")
+    }
+}
@@ -14,12 +14,24 @@
 //! # }
 //! ```
 
+mod combinators;
 mod dir;
+mod document;
+#[cfg(feature = "json")]
+mod long_poll;
+mod manifest;
 mod scope;
+mod state;
 mod sync;
+mod tunnel;
 
+pub use self::combinators::{Before, BoxedEndpoint, FallbackChain, MapErr, MapResponse};
+pub(crate) use self::document::DocumentEndpoint;
+pub use self::manifest::StaticManifest;
 pub use self::scope::{ScopeEndpoint, ScopeEndpointBuilder};
 pub(crate) use self::sync::SyncEndpoint;
+pub use crate::controller::Action;
+pub use crate::endpoint::EndpointExt;
 use crate::response::IntoResponse;
 use crate::{Endpoint, Request};
 
@@ -48,6 +60,45 @@ where
     self::sync::SyncEndpoint(func)
 }
 
+/// Creates an endpoint that passes the given state to the handler, along
+/// with each request.  The state is cloned for each request, so it is
+/// usually an [`std::sync::Arc`], or a handle that is cheap to clone (such
+/// as a connection pool).  This lets a handler receive its dependencies
+/// directly, rather than looking them up in the extensions of the request
+/// (see [`crate::Request::state`]).  See also [`crate::Path::get_with_state`].
+///
+/// # Examples
+///
+/// ```rust
+/// # use under::*;
+/// # #[tokio::main] async fn main() -> Result<(), anyhow::Error> {
+/// use std::sync::Arc;
+/// use std::sync::atomic::{AtomicU32, Ordering};
+///
+/// async fn visit(_: Request, visits: Arc<AtomicU32>) -> Response {
+///     let count = visits.fetch_add(1, Ordering::Relaxed) + 1;
+///     Response::text(count.to_string())
+/// }
+///
+/// let mut http = under::http();
+/// http.at("/visits").get(under::endpoints::with_state(Arc::new(AtomicU32::new(0)), visit));
+/// http.prepare();
+/// http.handle(Request::get("/visits")?).await?;
+/// let mut response = http.handle(Request::get("/visits")?).await?;
+/// assert_eq!(response.data(512).into_text().await?, "2");
+/// # Ok(())
+/// # }
+/// ```
+pub fn with_state<S, F, Fut, Res>(state: S, handler: F) -> impl Endpoint
+where
+    S: Clone + Send + Sync + 'static,
+    F: Fn(Request, S) -> Fut + Send + Sync + 'static,
+    Fut: std::future::Future<Output = Res> + Send + 'static,
+    Res: IntoResponse + Send + 'static,
+{
+    self::state::StateEndpoint::new(state, handler)
+}
+
 /// Creates an endpoint that synchronously, infallibly generates a response.
 ///
 /// This is meant for a very basic operation that returns a specific response
@@ -75,6 +126,86 @@ where
     sync::<_, Res>(move |_| func())
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+/// The kind of redirect generated by [`redirect`].  All of these keep the
+/// method of the request, except for [`Redirect::SeeOther`], which always
+/// redirects to a `GET`.
+pub enum Redirect {
+    /// A 308 Permanent Redirect; see [`crate::Response::permanent_redirect`].
+    Permanent,
+    /// A 307 Temporary Redirect; see [`crate::Response::temporary_redirect`].
+    Temporary,
+    /// A 303 See Other; see [`crate::Response::see_other`].
+    SeeOther,
+}
+
+impl Redirect {
+    /// The status of the response for this kind of redirect.
+    #[must_use]
+    pub fn status(self) -> http::StatusCode {
+        match self {
+            Redirect::Permanent => http::StatusCode::PERMANENT_REDIRECT,
+            Redirect::Temporary => http::StatusCode::TEMPORARY_REDIRECT,
+            Redirect::SeeOther => http::StatusCode::SEE_OTHER,
+        }
+    }
+}
+
+/// Creates an endpoint that redirects every request to the given location.
+///
+/// # Panics
+/// Panics if the location is not a valid header value - e.g., if it
+/// contains a newline.  This happens when the endpoint is created, and not
+/// when a request is handled.
+///
+/// # Examples
+/// ```rust
+/// # use under::*;
+/// use under::endpoints::Redirect;
+/// # #[tokio::main] async fn main() -> Result<(), anyhow::Error> {
+/// let mut http = under::http();
+/// http.at("/old").get(under::endpoints::redirect("/new", Redirect::Permanent));
+/// http.prepare();
+/// let response = http.handle(Request::get("/old")?).await?;
+/// assert_eq!(response.status(), http::StatusCode::PERMANENT_REDIRECT);
+/// assert_eq!(response.header(http::header::LOCATION).unwrap(), "/new");
+/// # Ok(())
+/// # }
+/// ```
+#[must_use]
+pub fn redirect(location: &str, kind: Redirect) -> impl Endpoint {
+    let location = http::HeaderValue::from_str(location)
+        .unwrap_or_else(|_| panic!("invalid redirect location {location:?}"));
+    let status = kind.status();
+    simple(move || {
+        let mut response = crate::Response::empty(status);
+        response
+            .headers_mut()
+            .insert(http::header::LOCATION, location.clone());
+        response
+    })
+}
+
+/// Creates an endpoint that responds to every request with an empty response
+/// of the given status; e.g., a 410 Gone for a resource that was removed.
+///
+/// # Examples
+/// ```rust
+/// # use under::*;
+/// # #[tokio::main] async fn main() -> Result<(), anyhow::Error> {
+/// let mut http = under::http();
+/// http.at("/legacy").all(under::endpoints::status(http::StatusCode::GONE));
+/// http.prepare();
+/// let response = http.handle(Request::post("/legacy")?).await?;
+/// assert_eq!(response.status(), http::StatusCode::GONE);
+/// # Ok(())
+/// # }
+/// ```
+#[must_use]
+pub fn status(status: http::StatusCode) -> impl Endpoint {
+    simple(move || crate::Response::empty(status))
+}
+
 /// Creates an endpoint that serves files from the given directory.
 ///
 /// The endpoint expects the path to use to be a part of the request fragment
@@ -97,6 +228,11 @@ where
 /// it will 404; if it cannot read the file, it will 500; and finally, it will
 /// attempt to stream the file with a 200.
 ///
+/// The response has a weak [`crate::etag::ETag`], derived from the length
+/// and modification time of the file; a request whose `If-None-Match`
+/// matches it receives a 304 instead of the file.
+/// The `Range` header is honored (see [`crate::Response::seekable_stream`]).
+///
 /// # Examples
 ///
 /// ```rust
@@ -110,7 +246,231 @@ pub fn dir<P>(path: P) -> impl Endpoint
 where
     P: Into<std::path::PathBuf>,
 {
-    self::dir::DirEndpoint::new(path)
+    self::dir::DirEndpoint::new(path, None)
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+/// How [`dir_offload`] hands files off to the front proxy.
+pub enum Offload {
+    /// With `X-Accel-Redirect`, for nginx.  The path of the file, relative
+    /// to the directory, is appended to the given prefix; this should be an
+    /// `internal` location of nginx that serves the same directory.
+    AccelRedirect(String),
+    /// With `X-Sendfile`, for Apache (with `mod_xsendfile`) and lighttpd.
+    /// The header holds the absolute path of the file, so the proxy must be
+    /// able to read the same filesystem.
+    Sendfile,
+}
+
+/// Creates an endpoint that serves files from the given directory, like
+/// [`dir`], except that rather than streaming each file itself, it has the
+/// front proxy send it (see [`Offload`]).  The response is empty, with only
+/// the offload header and the content type of the file; the proxy takes
+/// care of conditional and range requests.  Paths are resolved, and
+/// rejected, the same way as with [`dir`], so the proxy is only ever asked
+/// to send files that exist in the directory.
+///
+/// # Examples
+///
+/// ```rust
+/// # use under::*;
+/// use under::endpoints::Offload;
+/// # #[tokio::main] async fn main() -> Result<(), anyhow::Error> {
+/// # let dir = std::env::temp_dir().join(format!("under-offload-{}", std::process::id()));
+/// # std::fs::create_dir_all(&dir)?;
+/// # std::fs::write(dir.join("app.js"), "console.log(1)")?;
+/// let mut http = under::http();
+/// http.at("/public/{:path}").get(under::endpoints::dir_offload(
+///     &dir,
+///     Offload::AccelRedirect("/protected".to_owned()),
+/// ));
+/// http.prepare();
+/// let response = http.handle(Request::get("/public/app.js")?).await?;
+/// assert_eq!(response.header("X-Accel-Redirect").unwrap(), "/protected/app.js");
+/// assert_eq!(response.header("Content-Type").unwrap(), "text/javascript");
+/// let response = http.handle(Request::get("/public/missing.js")?).await?;
+/// assert_eq!(response.status(), http::StatusCode::NOT_FOUND);
+/// # std::fs::remove_dir_all(&dir)?;
+/// # Ok(())
+/// # }
+/// ```
+pub fn dir_offload<P>(path: P, offload: Offload) -> impl Endpoint
+where
+    P: Into<std::path::PathBuf>,
+{
+    self::dir::DirEndpoint::new(path, Some(offload))
+}
+
+/// Creates an endpoint that serves the given JSON document.
+///
+/// The document is serialized once, when the endpoint is created, rather
+/// than on every request; and the response includes an `ETag` derived from
+/// its contents, so that requests with a matching `If-None-Match` receive an
+/// empty 304.  This is useful for `/.well-known/` documents, or for exposing
+/// runtime configuration to a frontend.
+///
+/// # Examples
+///
+/// ```rust
+/// # use under::*;
+/// # #[tokio::main] async fn main() -> Result<(), anyhow::Error> {
+/// let mut http = under::http();
+/// let config = serde_json::json!({ "api": "https://api.example.com" });
+/// http.at("/config.json").get(under::endpoints::json_value(&config));
+/// http.prepare();
+/// let response = http.handle(Request::get("/config.json")?).await?;
+/// assert_eq!(response.status(), http::StatusCode::OK);
+/// let etag = response.header(http::header::ETAG).unwrap().clone();
+/// let request = Request::get("/config.json")?
+///     .with_header(http::header::IF_NONE_MATCH, etag)?;
+/// let response = http.handle(request).await?;
+/// assert_eq!(response.status(), http::StatusCode::NOT_MODIFIED);
+/// # Ok(())
+/// # }
+/// ```
+#[cfg(feature = "json")]
+#[cfg_attr(docsrs, doc(cfg(feature = "json")))]
+#[must_use]
+pub fn json_value(value: &serde_json::Value) -> impl Endpoint {
+    // A `Value` always has string keys, so this cannot fail.
+    let body = serde_json::to_vec(value).unwrap_or_default();
+    self::document::DocumentEndpoint::new(body, "application/json; charset=utf-8")
+}
+
+/// Creates an endpoint that serves the given YAML document.
+///
+/// This behaves the same as [`json_value`], but with a YAML document.
+///
+/// # Examples
+///
+/// ```rust
+/// # use under::*;
+/// # #[tokio::main] async fn main() -> Result<(), anyhow::Error> {
+/// let mut http = under::http();
+/// let config: serde_yaml::Value = serde_yaml::from_str("api: https://api.example.com")?;
+/// http.at("/config.yaml").get(under::endpoints::yaml_value(&config));
+/// http.prepare();
+/// let mut response = http.handle(Request::get("/config.yaml")?).await?;
+/// assert_eq!(response.data(512).into_text().await?, "api: https://api.example.com\n");
+/// # Ok(())
+/// # }
+/// ```
+#[cfg(feature = "yaml")]
+#[cfg_attr(docsrs, doc(cfg(feature = "yaml")))]
+#[must_use]
+pub fn yaml_value(value: &serde_yaml::Value) -> impl Endpoint {
+    // Serializing a `Value` into a string cannot fail.
+    let body = serde_yaml::to_string(value).unwrap_or_default();
+    self::document::DocumentEndpoint::new(body, "application/yaml; charset=utf-8")
+}
+
+/// Creates an endpoint that tunnels `CONNECT` requests.
+///
+/// The endpoint responds to a `CONNECT` request with an empty 200; once that
+/// response has been sent, the connection is upgraded, and the handler is
+/// called with the target authority of the request (e.g. `example.com:443`)
+/// and the raw, bidirectional connection to the client.  From there, the
+/// handler can connect to the target and copy data in both directions,
+/// which is enough for a simple forward proxy.  Errors returned by the
+/// handler are logged.  Any request that is not a `CONNECT` receives a 405;
+/// and a `CONNECT` without an authority receives a 400.
+///
+/// Since the target of a `CONNECT` request is an authority, and not a path,
+/// it will not match any route - so this is best used as the router's
+/// [`crate::Router::fallback`].
+///
+/// # Examples
+/// ```rust
+/// # use under::*;
+/// use tokio::net::TcpStream;
+///
+/// async fn proxy(
+///     authority: http::uri::Authority,
+///     mut client: Upgraded,
+/// ) -> Result<(), anyhow::Error> {
+///     let mut server = TcpStream::connect(authority.as_str()).await?;
+///     tokio::io::copy_bidirectional(&mut client, &mut server).await?;
+///     Ok(())
+/// }
+///
+/// # #[tokio::main] async fn main() -> Result<(), anyhow::Error> {
+/// let mut http = under::http();
+/// http.fallback(under::endpoints::tunnel(proxy));
+/// http.prepare();
+/// let response = http.handle(Request::connect("example.com:443")?).await?;
+/// assert_eq!(response.status(), http::StatusCode::OK);
+/// let response = http.handle(Request::get("/")?).await?;
+/// assert_eq!(response.status(), http::StatusCode::METHOD_NOT_ALLOWED);
+/// # Ok(())
+/// # }
+/// ```
+pub fn tunnel<F, Fut>(handle: F) -> impl Endpoint
+where
+    F: Fn(http::uri::Authority, crate::Upgraded) -> Fut + Send + Sync + 'static,
+    Fut: std::future::Future<Output = Result<(), anyhow::Error>> + Send + 'static,
+{
+    self::tunnel::TunnelEndpoint::new(handle)
+}
+
+/// Creates an endpoint that long-polls the given source.
+///
+/// For each request, the source is called, and its future awaited for up to
+/// the given timeout.  If it produces a value in time, the value is
+/// responded with as JSON; if it times out, or produces `None` (e.g. the
+/// channel it was waiting on closed), the response is an empty 204, and the
+/// client is expected to poll again.  If the client disconnects while
+/// waiting, the source's future is dropped, and so any resources it holds
+/// (such as a channel subscription) are released.
+///
+/// This is a lighter-weight alternative to server-sent events (see
+/// `under::sse`) for simple notification APIs, where each request only
+/// needs a single value.  To wait for the next item of a stream, the source
+/// can return `stream.next()`.
+///
+/// # Examples
+/// ```rust
+/// # use under::*;
+/// use std::time::Duration;
+/// use tokio::sync::broadcast;
+///
+/// # #[tokio::main] async fn main() -> Result<(), anyhow::Error> {
+/// let (notify, _) = broadcast::channel::<String>(16);
+/// let mut http = under::http();
+/// let subscribe = notify.clone();
+/// http.at("/notifications").get(under::endpoints::long_poll(
+///     move |_| {
+///         let mut notifications = subscribe.subscribe();
+///         async move { notifications.recv().await.ok() }
+///     },
+///     Duration::from_millis(100),
+/// ));
+/// http.prepare();
+///
+/// let response = http.handle(Request::get("/notifications")?).await?;
+/// assert_eq!(response.status(), http::StatusCode::NO_CONTENT);
+///
+/// let waiting = http.handle(Request::get("/notifications")?);
+/// let sending = async {
+///     tokio::time::sleep(Duration::from_millis(20)).await;
+///     notify.send("new message".to_string()).ok();
+/// };
+/// let (response, ()) = tokio::join!(waiting, sending);
+/// let mut response = response?;
+/// assert_eq!(response.status(), http::StatusCode::OK);
+/// assert_eq!(response.data(512).into_text().await?, r#""new message""#);
+/// # Ok(())
+/// # }
+/// ```
+#[cfg(feature = "json")]
+#[cfg_attr(docsrs, doc(cfg(feature = "json")))]
+pub fn long_poll<F, Fut, T>(source: F, timeout: std::time::Duration) -> impl Endpoint
+where
+    F: Fn(Request) -> Fut + Send + Sync + 'static,
+    Fut: std::future::Future<Output = Option<T>> + Send + 'static,
+    T: serde::Serialize + Send + 'static,
+{
+    self::long_poll::LongPollEndpoint::new(source, timeout)
 }
 
 /// Creates a builder for a [`ScopeEndpoint`].
@@ -0,0 +1,37 @@
+use std::future::Future;
+use std::pin::Pin;
+
+use super::Endpoint;
+use crate::request::Request;
+use crate::response::{IntoResponse, Response};
+use anyhow::Error;
+
+pub struct StateEndpoint<S, F> {
+    state: S,
+    handler: F,
+}
+
+impl<S, F> StateEndpoint<S, F> {
+    pub const fn new(state: S, handler: F) -> Self {
+        StateEndpoint { state, handler }
+    }
+}
+
+#[async_trait]
+impl<S, F, Fut, Res> Endpoint for StateEndpoint<S, F>
+where
+    S: Clone + Send + Sync + 'static,
+    F: Fn(Request, S) -> Fut + Send + Sync + 'static,
+    Fut: Future<Output = Res> + Send + 'static,
+    Res: IntoResponse + Send + 'static,
+{
+    async fn apply(self: Pin<&Self>, request: Request) -> Result<Response, Error> {
+        (self.handler)(request, self.state.clone())
+            .await
+            .into_response()
+    }
+
+    fn describe(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", std::any::type_name::<F>())
+    }
+}
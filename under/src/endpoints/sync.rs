@@ -8,7 +8,7 @@ use anyhow::Error;
 pub struct SyncEndpoint<F>(pub(crate) F);
 
 impl<F> SyncEndpoint<F> {
-    pub fn new(f: F) -> Self {
+    pub const fn new(f: F) -> Self {
         SyncEndpoint(f)
     }
 }
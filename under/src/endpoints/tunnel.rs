@@ -0,0 +1,58 @@
+use super::Endpoint;
+use crate::{HttpEntity, Request, Response, Upgraded};
+use anyhow::Error;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+
+/// An endpoint that accepts `CONNECT` requests, and hands the upgraded
+/// connection to the handler once the response has been sent.
+pub(super) struct TunnelEndpoint<F>(Arc<F>);
+
+impl<F> TunnelEndpoint<F> {
+    pub(super) fn new(handle: F) -> Self {
+        TunnelEndpoint(Arc::new(handle))
+    }
+}
+
+#[async_trait]
+impl<F, Fut> Endpoint for TunnelEndpoint<F>
+where
+    F: Fn(http::uri::Authority, Upgraded) -> Fut + Send + Sync + 'static,
+    Fut: Future<Output = Result<(), Error>> + Send + 'static,
+{
+    async fn apply(self: Pin<&Self>, mut request: Request) -> Result<Response, Error> {
+        if request.method() != http::Method::CONNECT {
+            return Ok(Response::empty(http::StatusCode::METHOD_NOT_ALLOWED)
+                .with_header(http::header::ALLOW, "CONNECT")?);
+        }
+
+        let Some(authority) = request.uri().authority().cloned() else {
+            return Ok(Response::empty_400());
+        };
+
+        let upgrade = crate::upgrade::on(&mut request);
+        let handle = self.0.clone();
+        tokio::task::spawn(async move {
+            // The upgrade only completes once the response has been sent, and
+            // fails if the connection does not support it (e.g. the request
+            // did not come from a server).
+            match upgrade.await {
+                Ok(upgraded) => {
+                    if let Err(error) = handle(authority.clone(), upgraded).await {
+                        log::error!("error tunneling to {authority}: {error:?}");
+                    }
+                }
+                Err(error) => log::warn!("could not upgrade CONNECT to {authority}: {error}"),
+            }
+        });
+
+        Ok(Response::empty_200())
+    }
+
+    fn describe(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("TunnelEndpoint")
+            .field(&std::any::type_name::<F>())
+            .finish()
+    }
+}
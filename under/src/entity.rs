@@ -15,12 +15,12 @@ pub trait HttpEntity: Sized {
     /// # use under::*;
     /// # #[tokio::main] async fn main() -> Result<(), anyhow::Error> {
     /// let mut response = Response::text("hello");
-    /// let body = std::mem::replace(response.body_mut(), hyper::Body::empty());
-    /// let body = hyper::body::to_bytes(body).await?;
+    /// let body = std::mem::take(response.body_mut());
+    /// let body = DataStream::from(body).into_bytes().await?;
     /// assert_eq!(&body[..], b"hello");
     /// # Ok(())
     /// # }
-    fn body_mut(&mut self) -> &mut hyper::Body;
+    fn body_mut(&mut self) -> &mut crate::Body;
 
     /// Sets the body of the request to the given body.  This causes the
     /// previous body to be dropped in place.
@@ -31,13 +31,13 @@ pub trait HttpEntity: Sized {
     /// # #[tokio::main] async fn main() -> Result<(), anyhow::Error> {
     /// let mut response = Response::default();
     /// response.set_body("foo");
-    /// let body = hyper::body::to_bytes(response.take_body()).await?;
+    /// let body = DataStream::from(response.take_body()).into_bytes().await?;
     /// assert_eq!(&body[..], b"foo");
     /// # Ok(())
     /// # }
     /// ```
     ///
-    fn set_body<I: Into<hyper::Body>>(&mut self, body: I) -> &mut Self {
+    fn set_body<I: Into<crate::Body>>(&mut self, body: I) -> &mut Self {
         *self.body_mut() = body.into();
         self
     }
@@ -51,13 +51,13 @@ pub trait HttpEntity: Sized {
     /// # #[tokio::main] async fn main() -> Result<(), anyhow::Error> {
     /// let mut response = Response::default()
     ///     .with_body("foo");
-    /// let body = hyper::body::to_bytes(response.take_body()).await?;
+    /// let body = DataStream::from(response.take_body()).into_bytes().await?;
     /// assert_eq!(&body[..], b"foo");
     /// # Ok(())
     /// # }
     /// ```
     #[must_use]
-    fn with_body<I: Into<hyper::Body>>(mut self, body: I) -> Self {
+    fn with_body<I: Into<crate::Body>>(mut self, body: I) -> Self {
         *self.body_mut() = body.into();
         self
     }
@@ -71,15 +71,15 @@ pub trait HttpEntity: Sized {
     /// # use under::*;
     /// # #[tokio::main] async fn main() -> Result<(), anyhow::Error> {
     /// let mut response = Response::default().with_body("foo");
-    /// let body = hyper::body::to_bytes(response.take_body()).await?;
+    /// let body = DataStream::from(response.take_body()).into_bytes().await?;
     /// assert_eq!(&body[..], b"foo");
-    /// let body = hyper::body::to_bytes(response.take_body()).await?;
+    /// let body = DataStream::from(response.take_body()).into_bytes().await?;
     /// assert_eq!(&body[..], b"");
     /// # Ok(())
     /// # }
     /// ```
-    fn take_body(&mut self) -> hyper::Body {
-        std::mem::replace(self.body_mut(), hyper::Body::empty())
+    fn take_body(&mut self) -> crate::Body {
+        std::mem::take(self.body_mut())
     }
 
     /// Replaces the contents of the body with the given JSON body.  Note
@@ -101,7 +101,7 @@ pub trait HttpEntity: Sized {
     /// # }
     /// ```
     #[cfg(feature = "json")]
-    #[cfg_attr(nightly, doc(cfg(feature = "json")))]
+    #[cfg_attr(docsrs, doc(cfg(feature = "json")))]
     fn set_json<V: serde::Serialize>(
         &mut self,
         new_body: &V,
@@ -129,7 +129,7 @@ pub trait HttpEntity: Sized {
     /// # }
     /// ```
     #[cfg(feature = "json")]
-    #[cfg_attr(nightly, doc(cfg(feature = "json")))]
+    #[cfg_attr(docsrs, doc(cfg(feature = "json")))]
     fn with_json<V: serde::Serialize>(self, new_body: &V) -> Result<Self, serde_json::Error> {
         let value = serde_json::to_string(new_body)?;
         Ok(self.with_body(value))
@@ -155,7 +155,7 @@ pub trait HttpEntity: Sized {
     /// # }
     /// ```
     #[cfg(feature = "cbor")]
-    #[cfg_attr(nightly, doc(cfg(feature = "cbor")))]
+    #[cfg_attr(docsrs, doc(cfg(feature = "cbor")))]
     fn set_cbor<V: serde::Serialize>(
         &mut self,
         new_body: &V,
@@ -185,7 +185,7 @@ pub trait HttpEntity: Sized {
     /// # }
     /// ```
     #[cfg(feature = "cbor")]
-    #[cfg_attr(nightly, doc(cfg(feature = "cbor")))]
+    #[cfg_attr(docsrs, doc(cfg(feature = "cbor")))]
     fn with_cbor<V: serde::Serialize>(self, new_body: &V) -> Result<Self, anyhow::Error> {
         let mut out = vec![];
         ciborium::ser::into_writer(new_body, &mut out)?;
@@ -213,7 +213,7 @@ pub trait HttpEntity: Sized {
     /// # }
     /// ```
     #[cfg(feature = "msgpack")]
-    #[cfg_attr(nightly, doc(cfg(feature = "msgpack")))]
+    #[cfg_attr(docsrs, doc(cfg(feature = "msgpack")))]
     fn set_msgpack<V: serde::Serialize>(
         &mut self,
         new_body: &V,
@@ -244,7 +244,7 @@ pub trait HttpEntity: Sized {
     /// # }
     /// ```
     #[cfg(feature = "msgpack")]
-    #[cfg_attr(nightly, doc(cfg(feature = "msgpack")))]
+    #[cfg_attr(docsrs, doc(cfg(feature = "msgpack")))]
     fn with_msgpack<V: serde::Serialize>(
         self,
         new_body: &V,
@@ -286,6 +286,27 @@ pub trait HttpEntity: Sized {
         DataStream::new(self.take_body(), limit)
     }
 
+    /// Reads the body into a shared byte buffer, consuming the body.  This
+    /// is a shortcut for [`DataStream::into_bytes_shared`], which avoids
+    /// copying the body where possible.
+    ///
+    /// # Errors
+    /// Errors for the same reason as [`DataStream::into_bytes`].
+    ///
+    /// # Examples
+    /// ```rust
+    /// # use under::*;
+    /// # #[tokio::main] async fn main() -> Result<(), anyhow::Error> {
+    /// let mut response = Response::text("hello, world");
+    /// let data = response.as_bytes_shared(512).await?;
+    /// assert_eq!(&data[..], b"hello, world");
+    /// # Ok(())
+    /// # }
+    /// ```
+    async fn as_bytes_shared(&mut self, limit: u64) -> Result<bytes::Bytes, UnderError> {
+        self.data(limit).into_bytes_shared().await
+    }
+
     /// Returns a reference to the associated header field map.  This is used
     /// for all other methods in [`HttpEntity`].
     ///
@@ -362,6 +383,10 @@ pub trait HttpEntity: Sized {
     /// Sets the given header, consuming `self` and returning a new version
     /// with the given header.  This can be useful for builder patterns.
     /// Otherwise, this acts the same as [`Self::set_header`].
+    ///
+    /// # Errors
+    /// If the given value cannot be converted into a header value, this will
+    /// return an error.
     fn with_header<H, V>(mut self, key: H, value: V) -> Result<Self, http::Error>
     where
         H: http::header::IntoHeaderName,
@@ -405,6 +430,10 @@ pub trait HttpEntity: Sized {
     /// Sets the given header, consuming `self` and returning a new version
     /// with the given header.  This can be useful for builder patterns.
     /// Otherwise, this acts the same as [`Self::add_header`].
+    ///
+    /// # Errors
+    /// If the given value cannot be converted into a header value, this will
+    /// return an error.
     fn with_add_header<H, V>(mut self, key: H, value: V) -> Result<Self, http::Error>
     where
         H: http::header::IntoHeaderName,
@@ -441,9 +470,10 @@ pub trait HttpEntity: Sized {
 
     /// Attempts to parse the body based off of the content-type header;
     /// currently, it can sniff any activated serde features (e.g. `json`,
-    /// `cbor`, `msgpack`).  If the content-type is one of those, it forwards
-    /// the call to the respective functions ([`DataStream::into_json`],
-    /// [`DataStream::into_cbor`], [`DataStream::into_msgpack`]), thereby
+    /// `cbor`, `msgpack`, `xml`).  If the content-type is one of those, it
+    /// forwards the call to the respective functions
+    /// ([`DataStream::into_json`], [`DataStream::into_cbor`],
+    /// [`DataStream::into_msgpack`], [`DataStream::into_xml`]), thereby
     /// consuming the body.  If it cannot find the content type, or the content
     /// type is not one of those, it will return an error.
     ///
@@ -466,47 +496,152 @@ pub trait HttpEntity: Sized {
     /// # }
     /// ```
     #[cfg(feature = "serde")]
-    #[cfg_attr(nightly, doc(cfg(feature = "serde")))]
+    #[cfg_attr(docsrs, doc(cfg(feature = "serde")))]
     async fn as_sniff<T: serde::de::DeserializeOwned>(
         &mut self,
         limit: u64,
     ) -> Result<T, UnderError> {
-        sniff_serde(self, limit).await
+        sniff_serde(self, limit, sniff_types).await
     }
 
     /// Attempts to parse the body based off of the content type header;
     /// currently, it can sniff any activated serde features (e.g. `json`,
-    /// `cbor`, `msgpack`), or x-www-form-urlencoded.  If the content-type is
-    /// one of those, it forwards the call to the respective functions
-    /// ([`DataStream::into_json`], [`DataStream::into_cbor`],
-    /// [`DataStream::into_msgpack`], [`DataStream::into_form`]), thereby
+    /// `cbor`, `msgpack`, `xml`), or x-www-form-urlencoded.  If the
+    /// content-type is one of those, it forwards the call to the respective
+    /// functions ([`DataStream::into_json`], [`DataStream::into_cbor`],
+    /// [`DataStream::into_msgpack`], [`DataStream::into_xml`],
+    /// [`DataStream::into_form`]), thereby
     /// consuming the body.  If it cannot find the content type, or the content
     /// type is not one of those, it will return an error.
     ///
     /// This functions similarly to [`HttpEntity::as_sniff`], but it also can
-    /// parse `x-www-form-urlencoded` content types as well.
+    /// parse `x-www-form-urlencoded` content types as well; and, with the
+    /// `multipart` feature, `multipart/form-data`, whose text fields are
+    /// fed through [`crate::FromForm`] the same way (and whose files fill in
+    /// any `#[form(file)]` fields; see [`crate::FromForm::from_multipart`]).
+    /// This way, a single handler can accept either encoding of the same
+    /// HTML form.
+    ///
+    /// # Examples
+    /// ```rust
+    /// # use under::*;
+    /// # #[tokio::main] async fn main() -> Result<(), anyhow::Error> {
+    /// #[derive(Debug, serde::Deserialize, PartialEq, Eq, FromForm)]
+    /// struct Login {
+    ///     user: String,
+    /// }
+    ///
+    /// let mut request = Request::post("/")?
+    ///     .with_header("Content-Type", "application/x-www-form-urlencoded")?
+    ///     .with_body("user=alice");
+    /// let login = request.as_sniff_form::<Login>(512).await?;
+    /// assert_eq!(login, Login { user: "alice".to_string() });
+    ///
+    /// let mut request = Request::post("/")?
+    ///     .with_header("Content-Type", "multipart/form-data; boundary=x")?
+    ///     .with_body("--x\r\nContent-Disposition: form-data; name=\"user\"\r\n\r\nbob\r\n--x--");
+    /// let login = request.as_sniff_form::<Login>(512).await?;
+    /// assert_eq!(login, Login { user: "bob".to_string() });
+    /// # Ok(())
+    /// # }
+    /// ```
     #[cfg(all(feature = "serde", feature = "from_form"))]
-    #[cfg_attr(nightly, doc(cfg(all(feature = "serde", feature = "from_form"))))]
+    #[cfg_attr(docsrs, doc(cfg(all(feature = "serde", feature = "from_form"))))]
     async fn as_sniff_form<T: serde::de::DeserializeOwned + crate::FromForm>(
         &mut self,
         limit: u64,
     ) -> Result<T, UnderError> {
         let ctype = self.content_type();
-        if ctype.as_ref().map(mime_guess::Mime::essence_str)
-            == Some("application/x-www-form-urlencoded")
-        {
-            self.data(limit).into_form().await
-        } else {
-            sniff_serde(self, limit).await
+        match ctype.as_ref().map(mime_guess::Mime::essence_str) {
+            Some("application/x-www-form-urlencoded") => self.data(limit).into_form().await,
+            #[cfg(feature = "multipart")]
+            Some("multipart/form-data") => {
+                let multipart = self.as_multipart(limit).await?;
+                T::from_multipart(&multipart).map_err(UnderError::FormDeserialization)
+            }
+            _ => sniff_serde(self, limit, sniff_form_types).await,
         }
     }
+
+    /// Reads the body as `multipart/form-data`, with the boundary from the
+    /// content type (see [`crate::multipart`]).  This reads the whole body
+    /// (up to the limit) into memory.
+    ///
+    /// # Errors
+    /// Errors with [`UnderError::UnsupportedMediaType`] if the content type
+    /// is not `multipart/form-data`; with
+    /// [`UnderError::MultipartDeserialization`] if it has no valid boundary,
+    /// or the body is not valid multipart; and for the same reasons as
+    /// [`DataStream::into_bytes`].
+    #[cfg(feature = "multipart")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "multipart")))]
+    async fn as_multipart(
+        &mut self,
+        limit: u64,
+    ) -> Result<crate::multipart::Multipart, UnderError> {
+        let ctype = self.content_type();
+        match ctype.as_ref() {
+            Some(mime) if mime.essence_str() == "multipart/form-data" => {
+                let boundary = crate::multipart::Multipart::boundary(mime).ok_or(
+                    UnderError::MultipartDeserialization(
+                        crate::multipart::MultipartError::InvalidBoundary,
+                    ),
+                )?;
+                self.data(limit).into_multipart(boundary).await
+            }
+            _ => Err(UnderError::UnsupportedMediaType(
+                ctype,
+                vec!["multipart/form-data"],
+            )),
+        }
+    }
+}
+
+/// The media types that [`HttpEntity::as_sniff`] can parse, with the
+/// enabled features.
+#[cfg(feature = "serde")]
+fn sniff_types() -> Vec<&'static str> {
+    let types = [
+        ("application/json", cfg!(feature = "json")),
+        ("application/cbor", cfg!(feature = "cbor")),
+        ("application/msgpack", cfg!(feature = "msgpack")),
+        ("application/xml", cfg!(feature = "xml")),
+        ("text/xml", cfg!(feature = "xml")),
+    ];
+    types
+        .into_iter()
+        .filter(|(_, enabled)| *enabled)
+        .map(|(ctype, _)| ctype)
+        .collect()
+}
+
+/// The media types that [`HttpEntity::as_sniff_form`] can parse, with the
+/// enabled features.
+#[cfg(all(feature = "serde", feature = "from_form"))]
+fn sniff_form_types() -> Vec<&'static str> {
+    let mut types = vec!["application/x-www-form-urlencoded"];
+    if cfg!(feature = "multipart") {
+        types.push("multipart/form-data");
+    }
+    types.extend(sniff_types());
+    types
 }
 
 #[cfg(feature = "serde")]
-#[cfg_attr(nightly, doc(cfg(feature = "serde")))]
+#[cfg_attr(docsrs, doc(cfg(feature = "serde")))]
+#[cfg_attr(
+    not(any(
+        feature = "json",
+        feature = "cbor",
+        feature = "msgpack",
+        feature = "xml"
+    )),
+    allow(unused_variables)
+)]
 async fn sniff_serde<E: HttpEntity, T: serde::de::DeserializeOwned>(
     entity: &mut E,
     limit: u64,
+    accepted: fn() -> Vec<&'static str>,
 ) -> Result<T, UnderError> {
     let ctype = entity.content_type();
     let essence = ctype.as_ref().map(mime_guess::Mime::essence_str);
@@ -518,6 +653,8 @@ async fn sniff_serde<E: HttpEntity, T: serde::de::DeserializeOwned>(
         Some("application/cbor") => entity.data(limit).into_cbor().await,
         #[cfg(feature = "msgpack")]
         Some("application/msgpack") => entity.data(limit).into_msgpack().await,
-        _ => Err(UnderError::UnsupportedMediaType(ctype)),
+        #[cfg(feature = "xml")]
+        Some("application/xml" | "text/xml") => entity.data(limit).into_xml().await,
+        _ => Err(UnderError::UnsupportedMediaType(ctype, accepted())),
     }
 }
@@ -2,53 +2,274 @@
 #[non_exhaustive]
 /// Errors generated specifically from this library, and not its interactions
 /// user code.
+///
+/// Each error has a canonical status (see [`UnderError::status`]); errors
+/// caused by the request (such as a body that is too large, or could not be
+/// parsed) have a client error status, and all others are a
+/// `500 Internal Server Error`.  If an error with a status other than a 500
+/// is returned from an endpoint, the router responds with that status (see
+/// [`UnderError::to_response`]), rather than treating it as a failure of the
+/// server.
+///
+/// # Examples
+/// ```rust
+/// # use under::*;
+/// # #[tokio::main] async fn main() -> Result<(), anyhow::Error> {
+/// let mut http = under::http();
+/// http.at("/").get(|_| async {
+///     Err::<Response, _>(UnderError::BadRequest(anyhow::anyhow!("missing a name")))
+/// });
+/// http.at("/upload").get(|_| async {
+///     Err::<Response, _>(UnderError::MethodNotAllowed(vec![http::Method::POST]))
+/// });
+/// http.prepare();
+/// let response = http.handle(Request::get("/")?).await?;
+/// assert_eq!(response.status(), http::StatusCode::BAD_REQUEST);
+/// let response = http.handle(Request::get("/upload")?).await?;
+/// assert_eq!(response.status(), http::StatusCode::METHOD_NOT_ALLOWED);
+/// assert_eq!(response.header("Allow").unwrap(), "POST");
+/// # Ok(())
+/// # }
+/// ```
 pub enum UnderError {
     #[error("could not parse the given string ({:?}) as an address", .0)]
     /// Generated when attempting to parse an address (during
     /// [`crate::Router::listen`]), but the address was invalid.
     InvalidAddress(String),
-    #[error("could not serve server")]
-    /// Generated when attempting to bind and listen using hyper, but it failed
-    /// for some underlying reason.
-    HyperServer(#[source] hyper::Error),
+    #[error("could not create the listener")]
+    /// Generated when binding, or taking over, a [`crate::Listener`] fails.
+    Listener(#[source] std::io::Error),
+    #[error("the routes of the router are invalid")]
+    /// Generated when the router is used (or listens) while its routes are
+    /// invalid (see [`RouterBuildError`]).
+    InvalidRoutes(#[source] RouterBuildError),
+    #[cfg(feature = "fastcgi")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "fastcgi")))]
+    #[error("could not handle the CGI request")]
+    /// Generated when the request of a CGI program could not be read, or its
+    /// response could not be written (see [`crate::Router::serve_cgi`]).
+    Cgi(#[source] std::io::Error),
+    #[error("could not start a shard of the server")]
+    /// Generated when a shard of the server could not be started, or
+    /// panicked (see [`crate::Router::shards`]).
+    Shard(#[source] std::io::Error),
+    #[cfg(feature = "client")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "client")))]
+    #[error("could not send an outbound request")]
+    /// Generated when an outbound request (see [`crate::client::Client`])
+    /// could not be sent, or no response was received.
+    Client(#[source] Box<dyn std::error::Error + Send + Sync>),
+    #[cfg(feature = "client")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "client")))]
+    #[error("the outbound request did not complete before the deadline")]
+    /// Generated when an outbound request made on behalf of an incoming one
+    /// (see [`crate::client::Client::send_for`]) does not complete before
+    /// the deadline of the incoming request (see [`crate::Request::deadline`]).
+    DeadlineExceeded,
     /// Generated when attempting to read the body of a request, or response,
     /// and failing.
     #[error("could not read the body of a request or response")]
     ReadBody(#[source] std::io::Error),
+    /// Generated when reading the body of a request, or response, takes
+    /// longer than the timeout (see [`crate::DataStream::with_timeout`]).
+    #[error("timed out reading the body of a request or response")]
+    ReadTimeout(#[source] tokio::time::error::Elapsed),
     #[cfg(feature = "json")]
-    #[cfg_attr(nightly, doc(cfg(feature = "json")))]
+    #[cfg_attr(docsrs, doc(cfg(feature = "json")))]
     /// Generated when attempting to deserialize the body of a request or
     /// response from JSON.
     #[error("could not deserialize the body of a request or response from JSON")]
     JsonDeserialization(#[source] serde_json::Error),
     #[cfg(feature = "cbor")]
-    #[cfg_attr(nightly, doc(cfg(feature = "cbor")))]
+    #[cfg_attr(docsrs, doc(cfg(feature = "cbor")))]
     /// Generated when attempting to deserialize the body of a request or
     /// response from CBOR.
     #[error("could not deserialize the body of a request or response from CBOR")]
     CborDeserialization(#[source] anyhow::Error),
     #[cfg(feature = "msgpack")]
-    #[cfg_attr(nightly, doc(cfg(feature = "msgpack")))]
+    #[cfg_attr(docsrs, doc(cfg(feature = "msgpack")))]
     /// Generated when attempting to deserialize the body of a request or
     /// response from MessagePack.
     #[error("could not deserialize the body of a request or response from MessagePack")]
     MsgpackDeserialization(#[source] rmp_serde::decode::Error),
+    #[cfg(feature = "xml")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "xml")))]
+    /// Generated when attempting to deserialize the body of a request or
+    /// response from XML.
+    #[error("could not deserialize the body of a request or response from XML")]
+    XmlDeserialization(#[source] quick_xml::DeError),
     /// Generated when attempting to deserialize the body of a request or
     /// response from text.
     #[error("could not deserialize the body of a request or response from utf-8")]
     TextDeserialization(#[source] std::string::FromUtf8Error),
     #[cfg(feature = "from_form")]
-    #[cfg_attr(nightly, doc(cfg(feature = "from_form")))]
+    #[cfg_attr(docsrs, doc(cfg(feature = "from_form")))]
     /// Generated when attempting to deserialize the body of a request or
     /// response from x-www-form-urlencoded.
     #[error("could not deserialize the body of a request or response from urlencoded")]
     FormDeserialization(#[source] crate::from_form::FromFormError),
+    #[cfg(feature = "multipart")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "multipart")))]
+    /// Generated when attempting to parse the body of a request or response
+    /// as `multipart/form-data`.
+    #[error("could not parse the body of a request or response as multipart")]
+    MultipartDeserialization(#[source] crate::multipart::MultipartError),
     /// Generated when attempting to sniff the request or response of its
-    /// content type.
+    /// content type.  This carries the content type, if there was a valid
+    /// one, and the media types that would have been accepted instead (which
+    /// the router hints at; see [`crate::MediaTypeHint`]), if known.
     #[error("the content-type of the request was invalid")]
-    UnsupportedMediaType(Option<mime::Mime>),
+    UnsupportedMediaType(Option<mime::Mime>, Vec<&'static str>),
     /// Generated when the request body of the request (if not provided with
     /// a Content-Length header) is too large.
     #[error("the request body of the request was too long, and was cut off")]
     PayloadTooLarge(#[source] anyhow::Error),
+    /// Generated when handling a request takes longer than it is allowed
+    /// to (see [`crate::middleware::Timeout`]).
+    #[error("timed out handling the request")]
+    Timeout,
+    /// Generated when the request is malformed, e.g. because a part of it
+    /// could not be parsed.
+    #[error("the request was malformed")]
+    BadRequest(#[source] anyhow::Error),
+    /// Generated when the method of the request is not allowed for the
+    /// resource it targets; it contains the methods that are.  The router
+    /// generates this when the path of a request only matches routes for
+    /// other methods, and it has no fallback (see [`crate::Router::fallback`]).
+    #[error("the method of the request is not allowed")]
+    MethodNotAllowed(Vec<http::Method>),
+}
+
+impl UnderError {
+    /// The canonical status of the error: the status that a response caused
+    /// by it should have.
+    ///
+    /// # Examples
+    /// ```rust
+    /// # use under::*;
+    /// let error = UnderError::UnsupportedMediaType(None, vec![]);
+    /// assert_eq!(error.status(), http::StatusCode::UNSUPPORTED_MEDIA_TYPE);
+    /// let error = UnderError::InvalidAddress("nowhere".to_owned());
+    /// assert_eq!(error.status(), http::StatusCode::INTERNAL_SERVER_ERROR);
+    /// ```
+    #[must_use]
+    pub fn status(&self) -> http::StatusCode {
+        use http::StatusCode;
+        match self {
+            #[cfg(feature = "client")]
+            UnderError::DeadlineExceeded => StatusCode::GATEWAY_TIMEOUT,
+            UnderError::ReadTimeout(_) => StatusCode::REQUEST_TIMEOUT,
+            #[cfg(feature = "json")]
+            UnderError::JsonDeserialization(_) => StatusCode::BAD_REQUEST,
+            #[cfg(feature = "cbor")]
+            UnderError::CborDeserialization(_) => StatusCode::BAD_REQUEST,
+            #[cfg(feature = "msgpack")]
+            UnderError::MsgpackDeserialization(_) => StatusCode::BAD_REQUEST,
+            #[cfg(feature = "xml")]
+            UnderError::XmlDeserialization(_) => StatusCode::BAD_REQUEST,
+            #[cfg(feature = "from_form")]
+            UnderError::FormDeserialization(_) => StatusCode::BAD_REQUEST,
+            #[cfg(feature = "multipart")]
+            UnderError::MultipartDeserialization(_) => StatusCode::BAD_REQUEST,
+            UnderError::TextDeserialization(_) | UnderError::BadRequest(_) => {
+                StatusCode::BAD_REQUEST
+            }
+            UnderError::UnsupportedMediaType(..) => StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            UnderError::PayloadTooLarge(_) => StatusCode::PAYLOAD_TOO_LARGE,
+            UnderError::Timeout => StatusCode::SERVICE_UNAVAILABLE,
+            UnderError::MethodNotAllowed(_) => StatusCode::METHOD_NOT_ALLOWED,
+            _ => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    /// Creates an empty response with the status of the error (see
+    /// [`UnderError::status`]).  For [`UnderError::MethodNotAllowed`], this
+    /// includes the `Allow` header, listing the allowed methods.
+    pub fn to_response(&self) -> crate::Response {
+        let mut response = crate::Response::empty(self.status());
+        if let UnderError::MethodNotAllowed(allowed) = self {
+            let allowed = allowed
+                .iter()
+                .map(http::Method::as_str)
+                .collect::<Vec<_>>()
+                .join(", ");
+            if let Ok(allowed) = http::HeaderValue::try_from(allowed) {
+                response.headers_mut().insert(http::header::ALLOW, allowed);
+            }
+        }
+        response
+    }
+}
+
+#[derive(thiserror::Error, Debug, Clone)]
+#[non_exhaustive]
+/// Errors in the routes of a router, found when the routes are prepared
+/// (see [`crate::Router::try_prepare`]).
+///
+/// Where the error is in a single fragment of a route, the fragment is
+/// highlighted, e.g.:
+///
+/// ```text
+/// unknown fragment type "number" in route:
+///     /users/{id:number}
+///            ^^^^^^^^^^^
+/// ```
+pub enum RouterBuildError {
+    #[error("unknown fragment type {kind:?} in route:{}", Highlight(.path, .span))]
+    /// A fragment of the route has a type that does not exist (e.g.
+    /// `{id:number}`).  See [`crate::Path`] for the types that do.
+    UnknownFragmentType {
+        /// The path of the route.
+        path: String,
+        /// The location of the fragment in the path.
+        span: std::ops::Range<usize>,
+        /// The unknown type.
+        kind: String,
+    },
+    #[error("invalid fragment in route:{}", Highlight(.path, .span))]
+    /// A fragment of the route is not of the form `{[name][:<type>]}`, where
+    /// the name starts with a letter or underscore, and is followed by
+    /// letters, digits, or underscores (e.g. `{user-id}`, or `{1st}`).
+    InvalidFragment {
+        /// The path of the route.
+        path: String,
+        /// The location of the fragment in the path.
+        span: std::ops::Range<usize>,
+    },
+    #[error("duplicate fragment {name:?} in route:{}", Highlight(.path, .span))]
+    /// Two fragments of the route have the same name (e.g.
+    /// `/{id}/{id}`).  The second of them is highlighted.
+    DuplicateFragment {
+        /// The path of the route.
+        path: String,
+        /// The location of the second fragment in the path.
+        span: std::ops::Range<usize>,
+        /// The name of the fragments.
+        name: String,
+    },
+    #[error("could not compile the pattern of route {path:?}")]
+    /// The pattern of the route could not be compiled (e.g. because it is
+    /// too large).
+    InvalidPattern {
+        /// The path of the route.
+        path: String,
+        /// The underlying error.
+        source: regex::Error,
+    },
+    #[error("could not compile the routes of the router")]
+    /// The routes could not be compiled together (e.g. because there are too
+    /// many of them).
+    Compile(#[source] regex::Error),
+}
+
+/// Displays a path on its own line, with the given span of it underlined.
+struct Highlight<'a>(&'a str, &'a std::ops::Range<usize>);
+
+impl std::fmt::Display for Highlight<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let Highlight(path, span) = *self;
+        let offset = path[..span.start].chars().count();
+        let width = path[span.clone()].chars().count();
+        write!(f, "\n    {path}\n    {:offset$}{:^<width$}", "", "")
+    }
 }
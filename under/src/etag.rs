@@ -0,0 +1,238 @@
+//! Entity tags (the `ETag` header), and the conditional request headers
+//! that compare them.
+//!
+//! An [`ETag`] identifies a version of a resource; a client that has a
+//! version sends its tag back in `If-None-Match` (to have a 304 sent if it
+//! is still current), or in `If-Match` (to only change the resource if it
+//! has not changed since).  Tags are either strong, meaning that the content
+//! is byte-for-byte the same, or weak (`W/"..."`), meaning that it is only
+//! equivalent.  The two headers compare tags differently: `If-None-Match`
+//! uses the weak comparison, where only the tags themselves need to be the
+//! same, whereas `If-Match` uses the strong comparison, where neither may
+//! be weak.  See [RFC 9110].
+//!
+//! [RFC 9110]: https://www.rfc-editor.org/rfc/rfc9110#section-8.8.3
+//!
+//! # Examples
+//! ```rust
+//! # use under::*;
+//! use under::etag::ETag;
+//!
+//! let etag = ETag::from_data(b"hello, world");
+//! let request = Request::get("/")?.with_header("If-None-Match", etag.to_string())?;
+//! assert!(etag.not_modified(&request));
+//!
+//! let weak: ETag = "W/\"v1\"".parse()?;
+//! assert!(weak.is_weak());
+//! assert!(weak.weak_eq(&ETag::strong("v1")));
+//! assert!(!weak.strong_eq(&ETag::strong("v1")));
+//! # Ok::<(), anyhow::Error>(())
+//! ```
+
+use crate::{HttpEntity, Request};
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+/// An entity tag.  This displays as it appears in the header (e.g.
+/// `"abc"`, or `W/"abc"`), and parses from the same.
+///
+/// The derived equality compares both the tag and whether it is weak; for
+/// the comparisons of the conditional headers, use [`ETag::strong_eq`] or
+/// [`ETag::weak_eq`].
+pub struct ETag {
+    tag: String,
+    weak: bool,
+}
+
+#[derive(thiserror::Error, Debug, Clone, Copy, PartialEq, Eq)]
+#[error("the entity tag is invalid")]
+/// The error returned when parsing an entity tag that is not of the form
+/// `"tag"` or `W/"tag"`, or whose tag has characters that are not allowed
+/// (such as `"`).
+pub struct InvalidETag;
+
+impl ETag {
+    /// Creates a strong tag.
+    ///
+    /// # Panics
+    /// Panics if the tag has a character that is not allowed in a tag: a
+    /// `"`, a space, or a control character.
+    #[must_use]
+    pub fn strong<T: Into<String>>(tag: T) -> Self {
+        Self::new(tag.into(), false)
+    }
+
+    /// Creates a weak tag.
+    ///
+    /// # Panics
+    /// Panics if the tag has a character that is not allowed in a tag: a
+    /// `"`, a space, or a control character.
+    #[must_use]
+    pub fn weak<T: Into<String>>(tag: T) -> Self {
+        Self::new(tag.into(), true)
+    }
+
+    fn new(tag: String, weak: bool) -> Self {
+        assert!(is_valid(&tag), "invalid entity tag {tag:?}");
+        ETag { tag, weak }
+    }
+
+    /// Creates a strong tag from a hash of the given content.  The hash is
+    /// stable across processes, so every instance of a server gives the
+    /// same content the same tag.  It is not cryptographic, so it should
+    /// not be used where a client may choose the content to collide with
+    /// another's.
+    ///
+    /// # Examples
+    /// ```rust
+    /// use under::etag::ETag;
+    /// assert_eq!(ETag::from_data(b"").to_string(), "\"cbf29ce484222325\"");
+    /// ```
+    #[must_use]
+    pub fn from_data(data: &[u8]) -> Self {
+        ETag {
+            tag: format!("{:016x}", fnv1a(data)),
+            weak: false,
+        }
+    }
+
+    /// The tag, without its quotes or weak marker.
+    #[must_use]
+    pub fn tag(&self) -> &str {
+        &self.tag
+    }
+
+    /// Whether the tag is weak.
+    #[must_use]
+    pub fn is_weak(&self) -> bool {
+        self.weak
+    }
+
+    /// The strong comparison: whether both tags are strong, and the same.
+    /// This is the comparison used by `If-Match` (and `If-Range`).
+    #[must_use]
+    pub fn strong_eq(&self, other: &ETag) -> bool {
+        !self.weak && !other.weak && self.tag == other.tag
+    }
+
+    /// The weak comparison: whether the tags are the same, whether or not
+    /// either of them is weak.  This is the comparison used by
+    /// `If-None-Match`.
+    #[must_use]
+    pub fn weak_eq(&self, other: &ETag) -> bool {
+        self.tag == other.tag
+    }
+
+    /// Whether the `If-None-Match` header of the request matches this tag,
+    /// i.e. whether the client already has this version, and so should be
+    /// sent a `304 Not Modified` (for a `GET` or `HEAD`) instead of the
+    /// content.  This is `false` if the request has no such header.
+    ///
+    /// # Examples
+    /// ```rust
+    /// # use under::*;
+    /// use under::etag::ETag;
+    /// let etag = ETag::strong("v2");
+    /// let request = Request::get("/")?.with_header("If-None-Match", "\"v1\", W/\"v2\"")?;
+    /// assert!(etag.not_modified(&request));
+    /// let request = Request::get("/")?.with_header("If-None-Match", "\"v1\"")?;
+    /// assert!(!etag.not_modified(&request));
+    /// assert!(!etag.not_modified(&Request::get("/")?));
+    /// # Ok::<(), anyhow::Error>(())
+    /// ```
+    #[must_use]
+    pub fn not_modified(&self, request: &Request) -> bool {
+        matches(request, http::header::IF_NONE_MATCH, |tag| {
+            self.weak_eq(tag)
+        })
+        .unwrap_or(false)
+    }
+
+    /// Whether the `If-Match` header of the request allows the request to
+    /// proceed against this version, i.e. whether the client's version
+    /// is still current.  If it is not, the request should be answered with
+    /// a `412 Precondition Failed`.  This is `true` if the request has no
+    /// such header.
+    ///
+    /// # Examples
+    /// ```rust
+    /// # use under::*;
+    /// use under::etag::ETag;
+    /// let etag = ETag::strong("v2");
+    /// let request = Request::put("/")?.with_header("If-Match", "\"v2\"")?;
+    /// assert!(etag.precondition_holds(&request));
+    /// // Weak tags never match, under the strong comparison.
+    /// let request = Request::put("/")?.with_header("If-Match", "W/\"v2\"")?;
+    /// assert!(!etag.precondition_holds(&request));
+    /// assert!(etag.precondition_holds(&Request::put("/")?));
+    /// # Ok::<(), anyhow::Error>(())
+    /// ```
+    #[must_use]
+    pub fn precondition_holds(&self, request: &Request) -> bool {
+        matches(request, http::header::IF_MATCH, |tag| self.strong_eq(tag)).unwrap_or(true)
+    }
+}
+
+/// Whether any of the tags in the given header of the request is accepted
+/// by the comparison (or the header is `*`), or `None` if the request has
+/// no such header.  Tags in the list that are invalid are ignored.
+fn matches<F: Fn(&ETag) -> bool>(
+    request: &Request,
+    header: http::HeaderName,
+    compare: F,
+) -> Option<bool> {
+    let mut values = request.headers().get_all(header).iter().peekable();
+    values.peek()?;
+    Some(
+        values
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','))
+            .map(str::trim)
+            .any(|tag| tag == "*" || tag.parse::<ETag>().is_ok_and(|tag| compare(&tag))),
+    )
+}
+
+impl std::fmt::Display for ETag {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.weak {
+            f.write_str("W/")?;
+        }
+        write!(f, "\"{}\"", self.tag)
+    }
+}
+
+impl std::str::FromStr for ETag {
+    type Err = InvalidETag;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let (weak, value) = match value.strip_prefix("W/") {
+            Some(value) => (true, value),
+            None => (false, value),
+        };
+        let tag = value
+            .strip_prefix('"')
+            .and_then(|value| value.strip_suffix('"'))
+            .filter(|tag| is_valid(tag))
+            .ok_or(InvalidETag)?;
+        Ok(ETag {
+            tag: tag.to_owned(),
+            weak,
+        })
+    }
+}
+
+/// Whether the tag only has the characters allowed in a tag (`etagc`, in
+/// the RFC).
+fn is_valid(tag: &str) -> bool {
+    tag.bytes()
+        .all(|byte| byte == 0x21 || (0x23..=0x7e).contains(&byte) || byte >= 0x80)
+}
+
+/// The 64-bit FNV-1a hash.  This is used for [`ETag::from_data`], since it
+/// needs to be stable across processes (unlike
+/// [`std::collections::hash_map::DefaultHasher`]), so that every instance of
+/// a server produces the same tag.
+pub(crate) fn fnv1a(data: &[u8]) -> u64 {
+    data.iter().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+        (hash ^ u64::from(*byte)).wrapping_mul(0x0100_0000_01b3)
+    })
+}
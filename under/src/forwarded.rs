@@ -0,0 +1,218 @@
+//! Forwarding headers, for proxies.
+//!
+//! When an application passes requests on to another server, it should tell
+//! that server who the request is really from, with the `Forwarded` header
+//! ([RFC 7239]) and the older `X-Forwarded-*` headers.  [`Forwarded`] builds
+//! these headers for an outgoing request; and, at the edge of the
+//! infrastructure, [`strip`] (or [`crate::middleware::StripForwarded`])
+//! removes any that the client sent itself, since they cannot be trusted.
+//! See also [`crate::RemoteAddress`], which reads these headers.
+//!
+//! [RFC 7239]: https://www.rfc-editor.org/rfc/rfc7239
+//!
+//! # Examples
+//! ```rust
+//! # use under::*;
+//! use under::forwarded::Forwarded;
+//! let request = Request::get("https://example.com/")?.with_local_addr();
+//!
+//! let mut outgoing = http::HeaderMap::new();
+//! Forwarded::from_request(&request).apply(&mut outgoing);
+//! assert_eq!(outgoing["Forwarded"], "for=127.0.0.1;host=example.com;proto=https");
+//! assert_eq!(outgoing["X-Forwarded-For"], "127.0.0.1");
+//! assert_eq!(outgoing["X-Forwarded-Host"], "example.com");
+//! assert_eq!(outgoing["X-Forwarded-Proto"], "https");
+//! # Ok::<(), anyhow::Error>(())
+//! ```
+
+use crate::{HttpEntity, Request};
+use http::uri::Scheme;
+use http::{HeaderMap, HeaderName, HeaderValue};
+use std::net::IpAddr;
+
+/// The headers that carry forwarding information.
+const HEADERS: [&str; 5] = [
+    "Forwarded",
+    "X-Forwarded-For",
+    "X-Forwarded-Host",
+    "X-Forwarded-Proto",
+    "X-Real-IP",
+];
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[must_use]
+/// A single hop of forwarding information, i.e. a single entry of the
+/// `Forwarded` header.
+pub struct Forwarded {
+    for_addr: Option<IpAddr>,
+    by: Option<IpAddr>,
+    host: Option<String>,
+    proto: Option<Scheme>,
+}
+
+impl Forwarded {
+    /// Creates an entry with no information.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates the entry for passing on the given request: it is for the
+    /// peer address of the request, with the host and scheme that the request
+    /// was made to.
+    pub fn from_request(request: &Request) -> Self {
+        let host = request
+            .header(http::header::HOST)
+            .and_then(|v| v.to_str().ok())
+            .map(str::to_owned)
+            .or_else(|| request.uri().authority().map(|a| a.as_str().to_owned()));
+        Forwarded {
+            for_addr: request.peer_addr().map(|addr| addr.ip()),
+            by: None,
+            host,
+            proto: Some(request.scheme()),
+        }
+    }
+
+    /// Sets the address that the request is for, i.e. the client.
+    pub fn with_for(mut self, addr: IpAddr) -> Self {
+        self.for_addr = Some(addr);
+        self
+    }
+
+    /// Sets the address that the request was received by, i.e. the proxy.
+    pub fn with_by(mut self, addr: IpAddr) -> Self {
+        self.by = Some(addr);
+        self
+    }
+
+    /// Sets the host that the request was made to.
+    pub fn with_host<S: Into<String>>(mut self, host: S) -> Self {
+        self.host = Some(host.into());
+        self
+    }
+
+    /// Sets the scheme that the request was made with.
+    pub fn with_proto(mut self, proto: Scheme) -> Self {
+        self.proto = Some(proto);
+        self
+    }
+
+    /// Adds this entry to the given headers of an outgoing request.  It is
+    /// appended to the end of the `Forwarded` header, and its address to the
+    /// end of `X-Forwarded-For`, after any entries of earlier proxies.  Since
+    /// `X-Forwarded-Host` and `X-Forwarded-Proto` only hold one value, they
+    /// are only set if an earlier proxy has not already set them.
+    pub fn apply(&self, headers: &mut HeaderMap) {
+        let entry = self.to_string();
+        if !entry.is_empty() {
+            append(headers, "forwarded", &entry);
+        }
+        if let Some(addr) = self.for_addr {
+            append(headers, "x-forwarded-for", &addr.to_string());
+        }
+        if let Some(host) = self.host.as_deref() {
+            set_default(headers, "x-forwarded-host", host);
+        }
+        if let Some(proto) = &self.proto {
+            set_default(headers, "x-forwarded-proto", proto.as_str());
+        }
+    }
+}
+
+/// Formats the entry as it appears in the `Forwarded` header (e.g.
+/// `for=192.0.2.60;proto=http`).
+impl std::fmt::Display for Forwarded {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut pairs = vec![];
+        if let Some(addr) = self.for_addr {
+            pairs.push(("for", node(addr)));
+        }
+        if let Some(addr) = self.by {
+            pairs.push(("by", node(addr)));
+        }
+        if let Some(host) = &self.host {
+            pairs.push(("host", value(host)));
+        }
+        if let Some(proto) = &self.proto {
+            pairs.push(("proto", value(proto.as_str())));
+        }
+        for (i, (key, value)) in pairs.iter().enumerate() {
+            if i > 0 {
+                f.write_str(";")?;
+            }
+            write!(f, "{key}={value}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Removes all forwarding headers (`Forwarded`, `X-Forwarded-For`,
+/// `X-Forwarded-Host`, `X-Forwarded-Proto`, and `X-Real-IP`) from the given
+/// headers.  This should be done to requests from clients, at the edge of
+/// the infrastructure, so that the headers that later proxies see are only
+/// ever ones that a trusted proxy set.
+///
+/// # Examples
+/// ```rust
+/// # use under::*;
+/// let mut request = Request::get("/")?.with_header("X-Forwarded-For", "10.0.0.1")?;
+/// under::forwarded::strip(request.headers_mut());
+/// assert!(request.header("X-Forwarded-For").is_none());
+/// # Ok::<(), anyhow::Error>(())
+/// ```
+pub fn strip(headers: &mut HeaderMap) {
+    for header in HEADERS {
+        headers.remove(header);
+    }
+}
+
+/// Formats an address as a node of the `Forwarded` header; IPv6 addresses
+/// must be bracketed, and so quoted.
+fn node(addr: IpAddr) -> String {
+    match addr {
+        IpAddr::V4(addr) => addr.to_string(),
+        IpAddr::V6(addr) => format!("\"[{addr}]\""),
+    }
+}
+
+/// Formats a value of the `Forwarded` header, quoting it if it is not a
+/// token.
+fn value(value: &str) -> String {
+    let token = !value.is_empty()
+        && value
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b"!#$%&'*+-.^_`|~".contains(&b));
+    if token {
+        value.to_owned()
+    } else {
+        format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""))
+    }
+}
+
+/// Appends the value to the end of the comma-separated list in the header
+/// (whose name must be lowercase), joining any existing headers into one.
+fn append(headers: &mut HeaderMap, name: &'static str, value: &str) {
+    let existing = headers
+        .get_all(name)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .collect::<Vec<_>>();
+    let value = if existing.is_empty() {
+        value.to_owned()
+    } else {
+        format!("{}, {value}", existing.join(", "))
+    };
+    if let Ok(value) = HeaderValue::try_from(value) {
+        headers.insert(HeaderName::from_static(name), value);
+    }
+}
+
+/// Sets the header (whose name must be lowercase), unless it is already set.
+fn set_default(headers: &mut HeaderMap, name: &'static str, value: &str) {
+    let name = HeaderName::from_static(name);
+    if !headers.contains_key(&name) {
+        if let Ok(value) = HeaderValue::try_from(value) {
+            headers.insert(name, value);
+        }
+    }
+}
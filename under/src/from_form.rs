@@ -37,7 +37,51 @@ use unicase::UniCase;
 ///   ("password", "hunter2"),
 /// ].into_iter()).unwrap();
 /// ```
-#[cfg_attr(nightly, doc(cfg(feature = "from_form")))]
+///
+/// Fields can also be validated once they are parsed.  Every field is
+/// checked, and all of the errors are returned together:
+///
+/// ```rust
+/// # use under::{FromForm, FromFormError};
+/// fn not_empty(value: &String) -> Result<(), &'static str> {
+///     if value.is_empty() { Err("must not be empty") } else { Ok(()) }
+/// }
+///
+/// #[derive(FromForm)]
+/// struct SignupForm {
+///     #[form(validate = "not_empty")]
+///     username: String,
+///     age: u8,
+///     email: String,
+/// }
+///
+/// let error = SignupForm::from_form([
+///     ("username", ""),
+///     ("age", "old"),
+/// ].into_iter()).err().unwrap();
+/// let FromFormError::Multiple(errors) = error else { panic!() };
+/// let fields = errors.iter().map(|e| e.field().unwrap()).collect::<Vec<_>>();
+/// assert_eq!(fields, ["age", "email", "username"]);
+/// ```
+///
+/// Checkboxes are only sent when they are checked, so a `checkbox` field is
+/// `false` when it is missing, rather than an error:
+///
+/// ```rust
+/// # use under::FromForm;
+/// #[derive(FromForm)]
+/// struct Preferences {
+///     #[form(checkbox)]
+///     newsletter: bool,
+///     #[form(checkbox)]
+///     remember_me: bool,
+/// }
+///
+/// let form = Preferences::from_form([("newsletter", "on")].into_iter()).unwrap();
+/// assert!(form.newsletter);
+/// assert!(!form.remember_me);
+/// ```
+#[cfg_attr(docsrs, doc(cfg(feature = "from_form")))]
 pub trait FromForm: Sized {
     /// Takes in an iterator of key-values, and returns a `Result<Self,
     /// FromFormError>`.  The iterator is guaranteed to be in the order that the
@@ -48,6 +92,21 @@ pub trait FromForm: Sized {
         I: Iterator<Item = (K, V)>,
         K: AsRef<str> + 'f,
         V: AsRef<str> + 'f;
+
+    /// Takes in a `multipart/form-data` body, and returns a `Result<Self,
+    /// FromFormError>`.  By default, this passes the text fields of the body
+    /// (see [`crate::multipart::Multipart::fields`]) to
+    /// [`FromForm::from_form`]; the derived implementation also fills in the
+    /// fields marked `#[form(file)]` from the files of the body (see
+    /// [`crate::multipart::UploadedFile`]).
+    ///
+    /// # Errors
+    /// Errors for the same reasons as [`FromForm::from_form`].
+    #[cfg(feature = "multipart")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "multipart")))]
+    fn from_multipart(multipart: &crate::multipart::Multipart) -> Result<Self, FromFormError> {
+        Self::from_form(multipart.fields())
+    }
 }
 
 impl<V, S: std::hash::BuildHasher + Default> FromForm for std::collections::HashMap<String, V, S>
@@ -64,7 +123,8 @@ where
     {
         let mut map = <std::collections::HashMap<String, V, S> as Default>::default();
         for (key, value) in form {
-            let key = key.as_ref().to_string();
+            let key = key.as_ref();
+            let key = array_key(key).unwrap_or(key).to_string();
             let value = V::Item::from_form_value(value.as_ref()).map_err(|e| {
                 FromFormError::InvalidFormat("-", std::any::type_name::<V::Item>(), e.into())
             })?;
@@ -75,6 +135,46 @@ where
     }
 }
 
+/// The name of an array key of a form, i.e. `tags` for `tags[]` or
+/// `tags[0]` (as sent by PHP- and Rails-style form libraries), or `None` if
+/// the key is not an array key.  The derived [`FromForm`] pushes the values
+/// of array keys onto the `multiple` field of their name (if no field has
+/// the exact key), in the order they appear in the form - the index, if
+/// any, is ignored - and the [`FromForm`] implementation of `HashMap` files
+/// them under their name.
+///
+/// # Examples
+/// ```rust
+/// # use under::FromForm;
+/// use under::from_form::array_key;
+///
+/// assert_eq!(array_key("tags[]"), Some("tags"));
+/// assert_eq!(array_key("items[2]"), Some("items"));
+/// assert_eq!(array_key("tags"), None);
+/// assert_eq!(array_key("user[name]"), None);
+///
+/// #[derive(FromForm)]
+/// struct Post {
+///     title: String,
+///     #[form(multiple)]
+///     tags: Vec<String>,
+/// }
+///
+/// let post = Post::from_form([
+///     ("title", "Hello"),
+///     ("tags[]", "a"),
+///     ("tags[]", "b"),
+///     ("tags", "c"),
+/// ].into_iter()).unwrap();
+/// assert_eq!(post.tags, ["a", "b", "c"]);
+/// ```
+#[must_use]
+#[cfg_attr(docsrs, doc(cfg(feature = "from_form")))]
+pub fn array_key(key: &str) -> Option<&str> {
+    let (name, index) = key.strip_suffix(']')?.rsplit_once('[')?;
+    (!name.is_empty() && index.bytes().all(|byte| byte.is_ascii_digit())).then_some(name)
+}
+
 /// A trait for types that can be created from a form.
 ///
 /// This is for parsing from a HTML form, not a URL query string.  Specifically,
@@ -83,7 +183,7 @@ where
 /// `FromForm`, and returns a `Result<Self, Self::Error>`.  This is similar to
 /// `FromStr`, but allows for different parsings.  For example, `bool` parses
 /// from `1`, `true`, `on`, and `yes` (with other values defaulting to `false`).
-#[cfg_attr(nightly, doc(cfg(feature = "from_form")))]
+#[cfg_attr(docsrs, doc(cfg(feature = "from_form")))]
 pub trait FromFormValue<'f>: Sized {
     /// The error type that can be returned if parsing fails.  This is normally
     /// encapsulated into a [`anyhow::Error`] before being turned into a variant
@@ -104,7 +204,7 @@ pub trait FromFormValue<'f>: Sized {
 /// any type `T` such that `T: Default + Extend<V> + IntoIterator<Item = V>`.
 /// This should cover all cases, and you should not need to implement (or use)
 /// this.
-#[cfg_attr(nightly, doc(cfg(feature = "from_form")))]
+#[cfg_attr(docsrs, doc(cfg(feature = "from_form")))]
 pub trait FromFormMultiple<'f>: Sized {
     /// The item type that is being collected into `Self`.
     type Item;
@@ -119,7 +219,7 @@ pub trait FromFormMultiple<'f>: Sized {
     fn default() -> Self;
 }
 
-impl<'f, V, T> FromFormMultiple<'f> for T
+impl<V, T> FromFormMultiple<'_> for T
 where
     T: Default + Extend<V> + IntoIterator<Item = V>,
 {
@@ -204,7 +304,7 @@ forward_to_parse! {
 
 #[derive(Debug, thiserror::Error)]
 #[non_exhaustive]
-#[cfg_attr(nightly, doc(cfg(feature = "from_form")))]
+#[cfg_attr(docsrs, doc(cfg(feature = "from_form")))]
 /// The error type for parsing a form.
 ///
 /// This is returned by [`FromForm::from_form`].  You should not need to
@@ -224,4 +324,40 @@ pub enum FromFormError {
     /// value is the type that was expected.  The third value is the error
     /// returned by the parser.
     InvalidFormat(&'static str, &'static str, #[source] anyhow::Error),
+    #[error("the field `{0}' is invalid: {1}")]
+    /// A field was parsed, but did not pass validation (see the `validate`
+    /// parameter of `#[derive(FromForm)]`).  This includes the field's
+    /// primary name, and the message of the validation error.
+    Invalid(&'static str, String),
+    #[error("{} fields are invalid", .0.len())]
+    /// More than one field was missing, could not be parsed, or did not pass
+    /// validation.  The derived implementation checks every field before
+    /// returning, so that all of the errors can be reported at once.
+    Multiple(Vec<FromFormError>),
+}
+
+impl FromFormError {
+    /// The primary name of the field this error is for, if it is for a
+    /// single field.
+    #[must_use]
+    pub fn field(&self) -> Option<&'static str> {
+        match self {
+            FromFormError::MissingField(field)
+            | FromFormError::InvalidFormat(field, _, _)
+            | FromFormError::Invalid(field, _) => Some(field),
+            FromFormError::Multiple(_) => None,
+        }
+    }
+
+    #[doc(hidden)]
+    /// Combines the given errors into one, for the derived implementation.
+    /// A single error is returned as-is.
+    #[must_use]
+    pub fn aggregate(mut errors: Vec<FromFormError>) -> Self {
+        if errors.len() == 1 {
+            errors.remove(0)
+        } else {
+            FromFormError::Multiple(errors)
+        }
+    }
 }
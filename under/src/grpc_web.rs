@@ -0,0 +1,390 @@
+//! gRPC-Web.
+//!
+//! This implements the framing of the [gRPC-Web protocol], which allows
+//! browser gRPC clients to talk directly to an under server, without a
+//! translating proxy (such as Envoy) in front of it.  It provides both the
+//! framing codec itself ([`encode_message`], [`encode_trailers`], and
+//! [`decode`]), for those who want to bridge to their own gRPC service, and
+//! an endpoint for handling unary calls ([`endpoint`]).  Message bodies are
+//! passed through as bytes, so any protobuf library may be used to
+//! (de)serialize them.  Only the binary format (`application/grpc-web` and
+//! `application/grpc-web+proto`) is supported; the base64 text format is not.
+//! It is gated behind the `grpc_web` feature flag.
+//!
+//! [gRPC-Web protocol]: https://github.com/grpc/grpc/blob/master/doc/PROTOCOL-WEB.md
+
+use crate::{HttpEntity, Request, Response};
+use bytes::{BufMut, Bytes, BytesMut};
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+
+/// The flag of a frame containing a message.
+const MESSAGE_FLAG: u8 = 0x00;
+/// The flag bit of a frame that is compressed.
+const COMPRESSED_FLAG: u8 = 0x01;
+/// The flag bit of a frame containing the trailers.
+const TRAILERS_FLAG: u8 = 0x80;
+
+/// The default maximum size of a request body read by the endpoint.
+const DEFAULT_LIMIT: u64 = 4 * 1024 * 1024;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// A single gRPC-Web frame.
+pub enum Frame {
+    /// A frame containing a (serialized) message.
+    Message(Bytes),
+    /// A frame containing the trailers of a response.
+    Trailers(http::HeaderMap),
+}
+
+#[derive(Debug, thiserror::Error)]
+#[non_exhaustive]
+/// Errors that can occur while encoding or decoding gRPC-Web frames.
+pub enum GrpcWebError {
+    /// The body ended in the middle of a frame.
+    #[error("the gRPC-Web body ended in the middle of a frame")]
+    Truncated,
+    /// A frame was compressed, which is not supported.
+    #[error("compressed gRPC-Web frames are not supported")]
+    Compressed,
+    /// A trailers frame contained an invalid header.
+    #[error("the gRPC-Web trailers frame contained an invalid header")]
+    InvalidTrailers,
+    /// The contents of a frame were too large to encode; the length of a
+    /// frame must fit in 32 bits.
+    #[error("the gRPC-Web frame is too large to encode")]
+    TooLarge,
+}
+
+/// Encodes a message into a gRPC-Web frame.
+///
+/// # Errors
+/// Errors if the message is 4 GiB or larger, as the length of a frame must
+/// fit in 32 bits.
+///
+/// # Examples
+/// ```rust
+/// let frame = under::grpc_web::encode_message(b"hi").unwrap();
+/// assert_eq!(&frame[..], &[0, 0, 0, 0, 2, b'h', b'i'][..]);
+/// ```
+pub fn encode_message(message: &[u8]) -> Result<Bytes, GrpcWebError> {
+    encode_frame(MESSAGE_FLAG, message)
+}
+
+/// Encodes the given trailers into a gRPC-Web trailers frame.  Each trailer
+/// is written as `name: value\r\n`.
+///
+/// # Errors
+/// Errors if the encoded trailers are 4 GiB or larger, as the length of a
+/// frame must fit in 32 bits.
+///
+/// # Examples
+/// ```rust
+/// let mut trailers = http::HeaderMap::new();
+/// trailers.insert("grpc-status", "0".parse().unwrap());
+/// let frame = under::grpc_web::encode_trailers(&trailers).unwrap();
+/// assert_eq!(&frame[..], &b"\x80\x00\x00\x00\x10grpc-status: 0\r\n"[..]);
+/// ```
+pub fn encode_trailers(trailers: &http::HeaderMap) -> Result<Bytes, GrpcWebError> {
+    let mut block = BytesMut::new();
+    for (name, value) in trailers {
+        block.put_slice(name.as_str().as_bytes());
+        block.put_slice(b": ");
+        block.put_slice(value.as_bytes());
+        block.put_slice(b"\r\n");
+    }
+    encode_frame(TRAILERS_FLAG, &block)
+}
+
+/// Decodes a complete gRPC-Web body into its frames.
+///
+/// # Errors
+/// Errors if the body ends in the middle of a frame, if a frame is
+/// compressed, or if a trailers frame contains an invalid header.
+///
+/// # Examples
+/// ```rust
+/// use under::grpc_web::{decode, encode_message, Frame};
+/// let frames = decode(&encode_message(b"hi").unwrap()).unwrap();
+/// assert_eq!(frames, vec![Frame::Message("hi".into())]);
+/// assert!(decode(&[0, 0, 0, 0, 2, b'h']).is_err());
+/// ```
+pub fn decode(mut body: &[u8]) -> Result<Vec<Frame>, GrpcWebError> {
+    let mut frames = vec![];
+    while !body.is_empty() {
+        if body.len() < 5 {
+            return Err(GrpcWebError::Truncated);
+        }
+        let flag = body[0];
+        let length = u32::from_be_bytes([body[1], body[2], body[3], body[4]]);
+        let length = usize::try_from(length).map_err(|_| GrpcWebError::Truncated)?;
+        let rest = &body[5..];
+        if rest.len() < length {
+            return Err(GrpcWebError::Truncated);
+        }
+        let (data, rest) = rest.split_at(length);
+        body = rest;
+
+        if flag & COMPRESSED_FLAG != 0 {
+            return Err(GrpcWebError::Compressed);
+        } else if flag & TRAILERS_FLAG != 0 {
+            frames.push(Frame::Trailers(decode_trailers(data)?));
+        } else {
+            frames.push(Frame::Message(Bytes::copy_from_slice(data)));
+        }
+    }
+
+    Ok(frames)
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// The status of a gRPC call.  This is sent to the client in the trailers of
+/// the response, as `grpc-status` and `grpc-message`.
+pub struct Status {
+    code: u32,
+    message: String,
+}
+
+impl Status {
+    /// The gRPC status code for an invalid argument.
+    pub const INVALID_ARGUMENT: u32 = 3;
+    /// The gRPC status code for an exhausted resource (e.g., a message that
+    /// is too large).
+    pub const RESOURCE_EXHAUSTED: u32 = 8;
+    /// The gRPC status code for an unimplemented method.
+    pub const UNIMPLEMENTED: u32 = 12;
+    /// The gRPC status code for an internal error.
+    pub const INTERNAL: u32 = 13;
+
+    /// Creates a new status, with the given code and message.  See the
+    /// [gRPC status codes] for the possible codes.
+    ///
+    /// [gRPC status codes]: https://grpc.github.io/grpc/core/md_doc_statuscodes.html
+    pub fn new(code: u32, message: impl Into<String>) -> Self {
+        Status {
+            code,
+            message: message.into(),
+        }
+    }
+
+    /// Creates a status for an invalid argument.
+    pub fn invalid_argument(message: impl Into<String>) -> Self {
+        Status::new(Self::INVALID_ARGUMENT, message)
+    }
+
+    /// Creates a status for an exhausted resource.
+    pub fn resource_exhausted(message: impl Into<String>) -> Self {
+        Status::new(Self::RESOURCE_EXHAUSTED, message)
+    }
+
+    /// Creates a status for an unimplemented method.
+    pub fn unimplemented(message: impl Into<String>) -> Self {
+        Status::new(Self::UNIMPLEMENTED, message)
+    }
+
+    /// Creates a status for an internal error.
+    pub fn internal(message: impl Into<String>) -> Self {
+        Status::new(Self::INTERNAL, message)
+    }
+
+    /// The code of the status.
+    #[must_use]
+    pub fn code(&self) -> u32 {
+        self.code
+    }
+
+    /// The message of the status.
+    #[must_use]
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+
+    fn trailers(&self) -> http::HeaderMap {
+        let mut trailers = http::HeaderMap::new();
+        trailers.insert("grpc-status", self.code.into());
+        if !self.message.is_empty() {
+            if let Ok(message) = percent_encode(&self.message).try_into() {
+                trailers.insert("grpc-message", message);
+            }
+        }
+        trailers
+    }
+}
+
+/// Creates an endpoint that handles unary gRPC-Web calls.  The body of the
+/// request is decoded, and its message is passed to the handler, along with
+/// the request (whose body has been consumed); the path of the request
+/// (e.g. `/package.Service/Method`) determines the method being called.
+/// The message returned by the handler is then sent back to the client,
+/// followed by the trailers containing the status of the call.
+///
+/// Requests that are not `application/grpc-web` or
+/// `application/grpc-web+proto` receive an empty 415.  Requests that do not
+/// contain exactly one message are rejected with an `INVALID_ARGUMENT` status.
+///
+/// # Examples
+/// ```rust
+/// # use under::*;
+/// use under::grpc_web::{decode, encode_message, Frame, Status};
+///
+/// async fn echo(request: Request, message: bytes::Bytes) -> Result<bytes::Bytes, Status> {
+///     match request.uri().path() {
+///         "/echo.Echo/Echo" => Ok(message),
+///         _ => Err(Status::unimplemented("no such method")),
+///     }
+/// }
+///
+/// # #[tokio::main] async fn main() -> Result<(), anyhow::Error> {
+/// let mut http = under::http();
+/// http.at("/echo.Echo/{method}").post(under::grpc_web::endpoint(echo));
+/// http.prepare();
+/// let request = Request::post("/echo.Echo/Echo")?
+///     .with_header("Content-Type", "application/grpc-web+proto")?
+///     .with_body(encode_message(b"hello")?);
+/// let mut response = http.handle(request).await?;
+/// let body = response.data(512).into_bytes().await?;
+/// let frames = decode(&body)?;
+/// assert_eq!(frames[0], Frame::Message("hello".into()));
+/// match &frames[1] {
+///     Frame::Trailers(trailers) => assert_eq!(trailers["grpc-status"], "0"),
+///     _ => unreachable!(),
+/// }
+/// # Ok(())
+/// # }
+/// ```
+pub fn endpoint<F, Fut>(handle: F) -> GrpcWebEndpoint<F>
+where
+    F: Fn(Request, Bytes) -> Fut + Send + Sync + 'static,
+    Fut: Future<Output = Result<Bytes, Status>> + Send + 'static,
+{
+    GrpcWebEndpoint::new(handle)
+}
+
+#[derive(Debug, Clone)]
+/// An instance of a gRPC-Web endpoint.
+///
+/// This is created by [`endpoint`], and implements the [`crate::Endpoint`]
+/// trait.
+pub struct GrpcWebEndpoint<F> {
+    handle: Arc<F>,
+    limit: u64,
+}
+
+impl<F> GrpcWebEndpoint<F> {
+    fn new(f: F) -> Self {
+        GrpcWebEndpoint {
+            handle: Arc::new(f),
+            limit: DEFAULT_LIMIT,
+        }
+    }
+
+    #[must_use]
+    /// Sets the maximum size of the request body, in bytes.  Requests with a
+    /// larger body are rejected with a 413.  This defaults to 4 MiB.
+    pub fn with_limit(mut self, limit: u64) -> Self {
+        self.limit = limit;
+        self
+    }
+}
+
+#[async_trait]
+impl<F, Fut> crate::Endpoint for GrpcWebEndpoint<F>
+where
+    F: Fn(Request, Bytes) -> Fut + Send + Sync + 'static,
+    Fut: Future<Output = Result<Bytes, Status>> + Send + 'static,
+{
+    async fn apply(self: Pin<&Self>, mut request: Request) -> Result<Response, anyhow::Error> {
+        let ctype = match request.content_type() {
+            Some(ctype) if is_grpc_web(&ctype) => ctype,
+            _ => return Ok(Response::empty(http::StatusCode::UNSUPPORTED_MEDIA_TYPE)),
+        };
+
+        let body = match request.data(self.limit).into_bytes().await {
+            Ok(body) => body,
+            Err(crate::UnderError::PayloadTooLarge(_)) => {
+                return Ok(Response::empty(http::StatusCode::PAYLOAD_TOO_LARGE))
+            }
+            Err(e) => return Err(e.into()),
+        };
+
+        let result = match decode(&body).map(single_message) {
+            Ok(Some(message)) => (self.handle)(request, message).await,
+            Ok(None) => Err(Status::invalid_argument("expected exactly one message")),
+            Err(e) => Err(Status::invalid_argument(e.to_string())),
+        };
+
+        let mut out = BytesMut::new();
+        let status = match result.map(|message| encode_message(&message)) {
+            Ok(Ok(frame)) => {
+                out.put(frame);
+                Status::new(0, "")
+            }
+            Ok(Err(error)) => Status::resource_exhausted(error.to_string()),
+            Err(status) => status,
+        };
+        out.put(encode_trailers(&status.trailers())?);
+
+        Ok(Response::empty_200()
+            .with_header(http::header::CONTENT_TYPE, ctype.essence_str())?
+            .with_body(out.freeze()))
+    }
+
+    fn describe(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("GrpcWebEndpoint")
+            .field(&std::any::type_name::<F>())
+            .finish()
+    }
+}
+
+fn encode_frame(flag: u8, data: &[u8]) -> Result<Bytes, GrpcWebError> {
+    let length = u32::try_from(data.len()).map_err(|_| GrpcWebError::TooLarge)?;
+    let mut frame = BytesMut::with_capacity(5 + data.len());
+    frame.put_u8(flag);
+    frame.put_u32(length);
+    frame.put_slice(data);
+    Ok(frame.freeze())
+}
+
+fn decode_trailers(block: &[u8]) -> Result<http::HeaderMap, GrpcWebError> {
+    let mut trailers = http::HeaderMap::new();
+    let block = std::str::from_utf8(block).map_err(|_| GrpcWebError::InvalidTrailers)?;
+    for line in block.split("\r\n").filter(|line| !line.is_empty()) {
+        let (name, value) = line.split_once(':').ok_or(GrpcWebError::InvalidTrailers)?;
+        let name = http::header::HeaderName::from_bytes(name.trim().as_bytes())
+            .map_err(|_| GrpcWebError::InvalidTrailers)?;
+        let value =
+            http::HeaderValue::from_str(value.trim()).map_err(|_| GrpcWebError::InvalidTrailers)?;
+        trailers.append(name, value);
+    }
+    Ok(trailers)
+}
+
+fn single_message(frames: Vec<Frame>) -> Option<Bytes> {
+    let mut messages = frames.into_iter().filter_map(|frame| match frame {
+        Frame::Message(message) => Some(message),
+        Frame::Trailers(_) => None,
+    });
+    let message = messages.next()?;
+    messages.next().is_none().then_some(message)
+}
+
+fn is_grpc_web(ctype: &mime::Mime) -> bool {
+    matches!(
+        ctype.essence_str(),
+        "application/grpc-web" | "application/grpc-web+proto"
+    )
+}
+
+/// Percent-encodes a `grpc-message`, as required by the gRPC protocol.
+fn percent_encode(message: &str) -> String {
+    use std::fmt::Write;
+    message.bytes().fold(String::new(), |mut out, byte| {
+        if (0x20..0x7f).contains(&byte) && byte != b'%' {
+            out.push(char::from(byte));
+        } else {
+            write!(out, "%{byte:02X}").unwrap();
+        }
+        out
+    })
+}
@@ -0,0 +1,71 @@
+//! Header utilities.
+//!
+//! These are for applications that pass requests (or responses) on to
+//! another server, e.g. as a proxy; see also [`crate::forwarded`].
+
+use http::{HeaderMap, HeaderName};
+
+/// The headers that are always hop-by-hop ([RFC 9110, section 7.6.1]), along
+/// with the non-standard `Proxy-Connection`.
+///
+/// [RFC 9110, section 7.6.1]: https://www.rfc-editor.org/rfc/rfc9110#section-7.6.1
+const HOP_BY_HOP: [&str; 9] = [
+    "connection",
+    "keep-alive",
+    "proxy-authenticate",
+    "proxy-authorization",
+    "proxy-connection",
+    "te",
+    "trailer",
+    "transfer-encoding",
+    "upgrade",
+];
+
+/// Whether the given header is always hop-by-hop - that is, it only applies
+/// to a single connection, and must not be passed on by a proxy.  Note that
+/// the `Connection` header of a message can declare more headers to be
+/// hop-by-hop for that message; [`strip_hop_by_hop`] handles those too.
+///
+/// # Examples
+/// ```rust
+/// use under::headers::is_hop_by_hop;
+/// assert!(is_hop_by_hop(&http::header::TRANSFER_ENCODING));
+/// assert!(!is_hop_by_hop(&http::header::CONTENT_TYPE));
+/// ```
+#[must_use]
+pub fn is_hop_by_hop(name: &HeaderName) -> bool {
+    HOP_BY_HOP.contains(&name.as_str())
+}
+
+/// Removes the hop-by-hop headers from the given headers, so that the
+/// message they belong to can be passed on.  This removes both the headers
+/// that are always hop-by-hop (see [`is_hop_by_hop`]), and any others that
+/// the `Connection` header lists.
+///
+/// # Examples
+/// ```rust
+/// let mut headers = http::HeaderMap::new();
+/// headers.insert("Connection", "keep-alive, X-Session".parse().unwrap());
+/// headers.insert("Keep-Alive", "timeout=5".parse().unwrap());
+/// headers.insert("X-Session", "abc".parse().unwrap());
+/// headers.insert("Upgrade", "websocket".parse().unwrap());
+/// headers.insert("Content-Type", "text/plain".parse().unwrap());
+/// under::headers::strip_hop_by_hop(&mut headers);
+/// assert_eq!(headers.len(), 1);
+/// assert!(headers.contains_key("Content-Type"));
+/// ```
+pub fn strip_hop_by_hop(headers: &mut HeaderMap) {
+    let listed = headers
+        .get_all(http::header::CONNECTION)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(','))
+        .filter_map(|name| HeaderName::from_bytes(name.trim().as_bytes()).ok())
+        .collect::<Vec<_>>();
+    for name in listed {
+        headers.remove(name);
+    }
+    for name in HOP_BY_HOP {
+        headers.remove(name);
+    }
+}
@@ -0,0 +1,209 @@
+//! JSON request bodies.
+//!
+//! This provides [`Json`], which can be used to both extract a JSON body from
+//! a request (see [`Json::extract`] and [`Request::require_json`]), and to
+//! respond with a JSON body.  When extraction fails, the returned
+//! [`JsonRejection`] describes why - and, if it is returned from an endpoint,
+//! the router turns it into a 400, 413, 415, or 422 response (instead of a
+//! 500), with a JSON body describing the error.
+
+use crate::{HttpEntity, IntoResponse, Request, Response, UnderError};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+/// A JSON body.
+///
+/// This can be extracted from a request using [`Json::extract`], or used as
+/// a response, serializing the inner value as the body.
+///
+/// # Examples
+/// ```rust
+/// # use under::*;
+/// use under::json::Json;
+///
+/// #[derive(serde::Deserialize, serde::Serialize)]
+/// struct User { name: String }
+///
+/// async fn create(mut request: Request) -> Result<Json<User>, anyhow::Error> {
+///     let Json(user) = Json::<User>::extract(&mut request, 4096).await?;
+///     Ok(Json(user))
+/// }
+///
+/// # #[tokio::main] async fn main() -> Result<(), anyhow::Error> {
+/// let mut http = under::http();
+/// http.at("/users").post(create);
+/// http.prepare();
+/// let request = Request::post("/users")?
+///     .with_header("Content-Type", "application/json")?
+///     .with_body(r#"{"name": "hello"}"#);
+/// let mut response = http.handle(request).await?;
+/// assert_eq!(response.status(), http::StatusCode::OK);
+/// assert_eq!(response.data(512).into_text().await?, r#"{"name":"hello"}"#);
+///
+/// let request = Request::post("/users")?
+///     .with_header("Content-Type", "application/json")?
+///     .with_body(r#"{"name": 1}"#);
+/// let mut response = http.handle(request).await?;
+/// assert_eq!(response.status(), http::StatusCode::UNPROCESSABLE_ENTITY);
+/// let body = response.data(512).into_json::<serde_json::Value>().await?;
+/// assert_eq!(body["path"], "name");
+/// # Ok(())
+/// # }
+/// ```
+pub struct Json<T>(pub T);
+
+impl<T> Json<T> {
+    /// Returns the inner value.
+    pub fn into_inner(self) -> T {
+        self.0
+    }
+}
+
+impl<T: serde::de::DeserializeOwned> Json<T> {
+    /// Extracts a JSON body from the given request, reading at most `limit`
+    /// bytes.  The request must have a JSON content type - either
+    /// `application/json`, or any `application/*+json` type.
+    ///
+    /// # Errors
+    /// Errors if the content type is not JSON, if the body could not be read
+    /// (or was larger than the limit), or if the body could not be
+    /// deserialized into `T`.  See [`JsonRejection`].
+    pub async fn extract(request: &mut Request, limit: u64) -> Result<Self, JsonRejection> {
+        let ctype = request.content_type();
+        if !ctype.as_ref().is_some_and(is_json) {
+            return Err(JsonRejection::UnsupportedMediaType(ctype));
+        }
+
+        let bytes = request
+            .data(limit)
+            .into_bytes()
+            .await
+            .map_err(JsonRejection::Body)?;
+        let deserializer = &mut serde_json::Deserializer::from_slice(&bytes[..]);
+        serde_path_to_error::deserialize(deserializer)
+            .map(Json)
+            .map_err(|e| {
+                let path = e.path().to_string();
+                JsonRejection::Invalid {
+                    path: Some(path).filter(|p| p != "."),
+                    error: e.into_inner(),
+                }
+            })
+    }
+}
+
+impl<T: serde::Serialize> IntoResponse for Json<T> {
+    fn into_response(self) -> Result<Response, anyhow::Error> {
+        Ok(Response::json(&self.0)?)
+    }
+}
+
+impl<T> std::ops::Deref for Json<T> {
+    type Target = T;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl<T> std::ops::DerefMut for Json<T> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.0
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+#[non_exhaustive]
+/// The reason a JSON body could not be extracted from a request.
+///
+/// If this is returned (as an error) from an endpoint or middleware, the
+/// router converts it into a response with the appropriate status (see
+/// [`JsonRejection::status`]), and a JSON body describing the error.  Whether
+/// or not the description includes the details of the error can be
+/// configured with [`crate::Router::expose_error_details`].
+pub enum JsonRejection {
+    /// The request did not have a JSON content type.
+    #[error("expected a JSON content type")]
+    UnsupportedMediaType(Option<mime::Mime>),
+    /// The body of the request could not be read, or was too large.
+    #[error("could not read the request body")]
+    Body(#[source] UnderError),
+    /// The body of the request could not be deserialized.
+    #[error("could not deserialize the request body: {error}")]
+    Invalid {
+        /// The path to the value that could not be deserialized, e.g.
+        /// `users[0].name`, if it is known.
+        path: Option<String>,
+        /// The underlying error.  This contains the line and column of the
+        /// error.
+        #[source]
+        error: serde_json::Error,
+    },
+}
+
+impl JsonRejection {
+    /// The status of the response generated for this rejection.  A body that
+    /// is not valid JSON generates a 400; a body that is valid JSON, but does
+    /// not match the expected type, generates a 422; a body that is too large
+    /// generates a 413; a body that took too long to read generates a 408;
+    /// and a request without a JSON content type generates a 415.
+    #[must_use]
+    pub fn status(&self) -> http::StatusCode {
+        match self {
+            JsonRejection::UnsupportedMediaType(_) => http::StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            JsonRejection::Body(UnderError::PayloadTooLarge(_)) => {
+                http::StatusCode::PAYLOAD_TOO_LARGE
+            }
+            JsonRejection::Body(UnderError::ReadTimeout(_)) => http::StatusCode::REQUEST_TIMEOUT,
+            JsonRejection::Invalid { error, .. } if error.is_data() => {
+                http::StatusCode::UNPROCESSABLE_ENTITY
+            }
+            JsonRejection::Body(_) | JsonRejection::Invalid { .. } => http::StatusCode::BAD_REQUEST,
+        }
+    }
+
+    /// Generates the response for this rejection.  If `expose` is `true`, the
+    /// body contains the full error message, as well as the path, line, and
+    /// column of the error (if applicable); otherwise, it only contains the
+    /// reason phrase of the status.
+    ///
+    /// # Examples
+    /// ```rust
+    /// # use under::*;
+    /// use under::json::Json;
+    /// # #[tokio::main] async fn main() -> Result<(), anyhow::Error> {
+    /// let mut request = Request::post("/")?
+    ///     .with_header("Content-Type", "application/json")?
+    ///     .with_body("{");
+    /// let rejection = Json::<serde_json::Value>::extract(&mut request, 512).await.unwrap_err();
+    /// let mut response = rejection.to_response(true);
+    /// assert_eq!(response.status(), http::StatusCode::BAD_REQUEST);
+    /// let body = response.data(512).into_json::<serde_json::Value>().await?;
+    /// assert_eq!(body["line"], 1);
+    /// let mut response = rejection.to_response(false);
+    /// let body = response.data(512).into_json::<serde_json::Value>().await?;
+    /// assert_eq!(body, serde_json::json!({ "error": "Bad Request" }));
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn to_response(&self, expose: bool) -> Response {
+        let status = self.status();
+        let body = if expose {
+            let mut body = serde_json::json!({ "error": self.to_string() });
+            if let JsonRejection::Invalid { path, error } = self {
+                body["path"] = path.clone().into();
+                body["line"] = error.line().into();
+                body["column"] = error.column().into();
+            }
+            body
+        } else {
+            serde_json::json!({ "error": status.canonical_reason().unwrap_or("error") })
+        };
+
+        Response::json(&body).map_or_else(|_| Response::empty(status), |r| r.with_status(status))
+    }
+}
+
+fn is_json(mime: &mime::Mime) -> bool {
+    mime.type_() == mime::APPLICATION
+        && (mime.subtype() == mime::JSON || mime.suffix() == Some(mime::JSON))
+}
@@ -0,0 +1,334 @@
+//! An adapter for running a router on AWS Lambda.
+//!
+//! Lambda does not give the function an HTTP connection; instead, each
+//! request arrives as a JSON event - from API Gateway (REST APIs, and HTTP
+//! APIs with either payload format), or from an Application Load Balancer -
+//! and the response is returned as a JSON object.  This converts those
+//! events into a [`Request`], and the resulting [`Response`] back, so that
+//! the same router can be used both as a server and as a function.
+//!
+//! The Lambda runtime itself is not included; with the `lambda_runtime`
+//! crate, [`Router::handle_lambda`] can be used as the handler:
+//!
+//! ```rust,ignore
+//! let http = std::sync::Arc::new(http);
+//! lambda_runtime::run(lambda_runtime::service_fn(move |event: LambdaEvent<Value>| {
+//!     let http = http.clone();
+//!     async move { http.handle_lambda(event.payload).await }
+//! }))
+//! .await?;
+//! ```
+//!
+//! # Examples
+//! ```rust
+//! # use under::*;
+//! # #[tokio::main] async fn main() -> Result<(), anyhow::Error> {
+//! let mut http = under::http();
+//! http.at("/users/{id}").get(|request: Request| async move {
+//!     let id: u32 = request.fragment("id").unwrap();
+//!     Response::text(format!("user {id}"))
+//! });
+//! http.prepare();
+//!
+//! let event = serde_json::json!({
+//!     "version": "2.0",
+//!     "rawPath": "/users/1",
+//!     "rawQueryString": "",
+//!     "headers": { "accept": "text/plain" },
+//!     "requestContext": { "http": { "method": "GET", "sourceIp": "192.0.2.1" } },
+//!     "isBase64Encoded": false
+//! });
+//! let response = http.handle_lambda(event).await?;
+//! assert_eq!(response["statusCode"], 200);
+//! assert_eq!(response["body"], "user 1");
+//! assert_eq!(response["isBase64Encoded"], false);
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::{HttpEntity, Request, Response};
+use base64::Engine;
+use std::collections::HashMap;
+use std::fmt::Write;
+
+/// The maximum size of the body of a response that Lambda accepts.
+const MAX_RESPONSE_SIZE: u64 = 6 * 1024 * 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+/// The format of an event, and so of the response to it.
+pub enum PayloadFormat {
+    /// The format of API Gateway REST APIs, HTTP APIs with the `1.0`
+    /// payload, and Application Load Balancers, where each header has a
+    /// single value.
+    V1,
+    /// The same as [`PayloadFormat::V1`], but with multi-value headers (the
+    /// default for API Gateway, and an option for load balancers).
+    V1MultiValue,
+    /// The format of API Gateway HTTP APIs with the `2.0` payload.
+    V2,
+}
+
+#[derive(Debug, Clone, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+/// An HTTP event, as given to a Lambda function.  This accepts the events
+/// of API Gateway (with either payload format) and of Application Load
+/// Balancers; see [`LambdaRequest::format`].
+pub struct LambdaRequest {
+    version: Option<String>,
+    http_method: Option<String>,
+    path: Option<String>,
+    raw_path: Option<String>,
+    raw_query_string: Option<String>,
+    headers: Option<HashMap<String, String>>,
+    multi_value_headers: Option<HashMap<String, Vec<String>>>,
+    query_string_parameters: Option<HashMap<String, String>>,
+    multi_value_query_string_parameters: Option<HashMap<String, Vec<String>>>,
+    cookies: Option<Vec<String>>,
+    body: Option<String>,
+    #[serde(default)]
+    is_base64_encoded: bool,
+    #[serde(default)]
+    request_context: RequestContext,
+}
+
+#[derive(Debug, Clone, Default, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct RequestContext {
+    http: Option<ContextHttp>,
+    identity: Option<ContextIdentity>,
+    elb: Option<serde_json::Value>,
+}
+
+#[derive(Debug, Clone, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ContextHttp {
+    method: String,
+    source_ip: Option<String>,
+}
+
+#[derive(Debug, Clone, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ContextIdentity {
+    source_ip: Option<String>,
+}
+
+impl LambdaRequest {
+    /// The format of the event, which the response must be given in.
+    #[must_use]
+    pub fn format(&self) -> PayloadFormat {
+        if self.version.as_deref() == Some("2.0") {
+            PayloadFormat::V2
+        } else if self.multi_value_headers.is_some() {
+            PayloadFormat::V1MultiValue
+        } else {
+            PayloadFormat::V1
+        }
+    }
+
+    /// Converts the event into a request.  The address of the client, if
+    /// the event has one, is used as the peer address of the request (see
+    /// [`Request::peer_addr`]).
+    ///
+    /// # Errors
+    /// This errors if the event has no method, if its method, path, or
+    /// headers are invalid, or if its body is not valid base64 when it
+    /// claims to be.
+    pub fn into_request(self) -> Result<Request, anyhow::Error> {
+        let method = self
+            .request_context
+            .http
+            .as_ref()
+            .map(|http| http.method.as_str())
+            .or(self.http_method.as_deref())
+            .ok_or_else(|| anyhow::anyhow!("the event has no method"))?;
+        let method = http::Method::from_bytes(method.as_bytes())?;
+
+        let path = self
+            .raw_path
+            .as_deref()
+            .or(self.path.as_deref())
+            .unwrap_or("/");
+        let query = self.query();
+        let uri = if query.is_empty() {
+            path.to_owned()
+        } else {
+            format!("{path}?{query}")
+        };
+
+        let mut request = Request::from_method(uri.as_str(), method)?;
+        let headers: Vec<(&String, &String)> = if let Some(headers) = &self.multi_value_headers {
+            headers
+                .iter()
+                .flat_map(|(name, values)| values.iter().map(move |value| (name, value)))
+                .collect()
+        } else if let Some(headers) = &self.headers {
+            headers.iter().collect()
+        } else {
+            vec![]
+        };
+        for (name, value) in headers {
+            request.headers_mut().append(
+                http::HeaderName::from_bytes(name.as_bytes())?,
+                http::HeaderValue::from_str(value)?,
+            );
+        }
+        if let Some(cookies) = self.cookies.as_ref().filter(|c| !c.is_empty()) {
+            request.set_header(http::header::COOKIE, cookies.join("; "))?;
+        }
+
+        let source_ip = self
+            .request_context
+            .http
+            .as_ref()
+            .and_then(|http| http.source_ip.as_deref())
+            .or_else(|| {
+                let identity = self.request_context.identity.as_ref()?;
+                identity.source_ip.as_deref()
+            })
+            .and_then(|ip| ip.parse::<std::net::IpAddr>().ok());
+        if let Some(ip) = source_ip {
+            request.set_ext(crate::middleware::PeerAddress((ip, 0).into()));
+        }
+
+        if let Some(body) = self.body {
+            if self.is_base64_encoded {
+                let body = base64::engine::general_purpose::STANDARD.decode(body)?;
+                request.set_body(body);
+            } else {
+                request.set_body(body);
+            }
+        }
+
+        Ok(request)
+    }
+
+    /// The query string of the request.  Load balancers pass on the query
+    /// as it was received, while API Gateway decodes it, and so it must be
+    /// encoded again.
+    fn query(&self) -> String {
+        if let Some(query) = &self.raw_query_string {
+            return query.clone();
+        }
+
+        let encoded = self.request_context.elb.is_some();
+        let pairs: Vec<(&String, &String)> =
+            if let Some(params) = &self.multi_value_query_string_parameters {
+                params
+                    .iter()
+                    .flat_map(|(key, values)| values.iter().map(move |value| (key, value)))
+                    .collect()
+            } else if let Some(params) = &self.query_string_parameters {
+                params.iter().collect()
+            } else {
+                vec![]
+            };
+
+        pairs
+            .into_iter()
+            .map(|(key, value)| {
+                if encoded {
+                    format!("{key}={value}")
+                } else {
+                    format!("{}={}", encode(key), encode(value))
+                }
+            })
+            .collect::<Vec<_>>()
+            .join("&")
+    }
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+/// The response to an HTTP event, as returned from a Lambda function.
+pub struct LambdaResponse {
+    status_code: u16,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    headers: Option<HashMap<String, String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    multi_value_headers: Option<HashMap<String, Vec<String>>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    cookies: Option<Vec<String>>,
+    body: String,
+    is_base64_encoded: bool,
+}
+
+impl LambdaResponse {
+    /// Converts the response, in the given format.  The body is read in
+    /// full; it is sent as text if it is valid UTF-8, and as base64
+    /// otherwise.
+    ///
+    /// # Errors
+    /// This errors if the body could not be read, or is larger than Lambda
+    /// allows (6MB).
+    pub async fn from_response(
+        mut response: Response,
+        format: PayloadFormat,
+    ) -> Result<Self, anyhow::Error> {
+        let body = response.data(MAX_RESPONSE_SIZE).into_bytes().await?;
+        let (body, is_base64_encoded) = match String::from_utf8(body) {
+            Ok(body) => (body, false),
+            Err(e) => (
+                base64::engine::general_purpose::STANDARD.encode(e.into_bytes()),
+                true,
+            ),
+        };
+
+        let mut headers = HashMap::<String, Vec<String>>::new();
+        let mut cookies = vec![];
+        for (name, value) in response.headers() {
+            let Ok(value) = value.to_str() else { continue };
+            if format == PayloadFormat::V2 && name == http::header::SET_COOKIE {
+                cookies.push(value.to_owned());
+            } else {
+                headers
+                    .entry(name.as_str().to_owned())
+                    .or_default()
+                    .push(value.to_owned());
+            }
+        }
+
+        let mut lambda = LambdaResponse {
+            status_code: response.status().as_u16(),
+            headers: None,
+            multi_value_headers: None,
+            cookies: None,
+            body,
+            is_base64_encoded,
+        };
+        match format {
+            PayloadFormat::V1MultiValue => lambda.multi_value_headers = Some(headers),
+            PayloadFormat::V1 | PayloadFormat::V2 => {
+                let separator = if format == PayloadFormat::V2 {
+                    ","
+                } else {
+                    ", "
+                };
+                lambda.headers = Some(
+                    headers
+                        .into_iter()
+                        .map(|(name, values)| (name, values.join(separator)))
+                        .collect(),
+                );
+                if format == PayloadFormat::V2 {
+                    lambda.cookies = Some(cookies);
+                }
+            }
+        }
+        Ok(lambda)
+    }
+}
+
+/// Percent-encodes a component of a query string.
+fn encode(value: &str) -> String {
+    let mut encoded = String::with_capacity(value.len());
+    for byte in value.bytes() {
+        if byte.is_ascii_alphanumeric() || matches!(byte, b'-' | b'.' | b'_' | b'~') {
+            encoded.push(char::from(byte));
+        } else {
+            // Writing to a string never fails.
+            let _ = write!(encoded, "%{byte:02X}");
+        }
+    }
+    encoded
+}
@@ -33,34 +33,75 @@
 )]
 #![deny(clippy::correctness, unused_must_use)]
 #![allow(clippy::module_name_repetitions)]
-#![cfg_attr(nightly, feature(doc_cfg))]
+#![cfg_attr(docsrs, feature(doc_cfg))]
 
 #[macro_use]
 extern crate async_trait;
 
+pub mod authz;
+mod body;
+#[cfg(feature = "client")]
+#[cfg_attr(docsrs, doc(cfg(feature = "client")))]
+pub mod client;
+mod controller;
 mod endpoint;
 pub mod endpoints;
 mod entity;
 mod error;
+pub mod etag;
+pub mod forwarded;
+mod listener;
 
 mod data;
 #[cfg(feature = "from_form")]
 #[doc(hidden)]
 pub mod from_form;
+#[cfg(feature = "grpc_web")]
+#[cfg_attr(docsrs, doc(cfg(feature = "grpc_web")))]
+pub mod grpc_web;
+pub mod headers;
+#[cfg(feature = "json")]
+#[cfg_attr(docsrs, doc(cfg(feature = "json")))]
+pub mod json;
+#[cfg(feature = "lambda")]
+#[cfg_attr(docsrs, doc(cfg(feature = "lambda")))]
+pub mod lambda;
 pub mod middleware;
+#[cfg(feature = "multipart")]
+#[cfg_attr(docsrs, doc(cfg(feature = "multipart")))]
+pub mod multipart;
+#[cfg(feature = "oauth")]
+#[cfg_attr(docsrs, doc(cfg(feature = "oauth")))]
+pub mod oauth;
+pub mod range;
+pub mod redact;
 mod request;
 mod response;
+#[cfg(feature = "rng")]
+#[cfg_attr(docsrs, doc(cfg(feature = "rng")))]
+pub mod rng;
 mod router;
 #[cfg(feature = "sse")]
-#[cfg_attr(nightly, doc(cfg(feature = "sse")))]
+#[cfg_attr(docsrs, doc(cfg(feature = "sse")))]
 pub mod sse;
+mod upgrade;
+#[cfg(feature = "json")]
+#[cfg_attr(docsrs, doc(cfg(feature = "json")))]
+pub mod validation;
+pub mod well_known;
+#[cfg(feature = "websocket")]
+#[cfg_attr(docsrs, doc(cfg(feature = "websocket")))]
+pub mod ws;
 
+#[cfg(feature = "secure_cookies")]
+#[cfg_attr(docsrs, doc(cfg(feature = "secure_cookies")))]
+pub use cookie::Key;
 #[cfg(feature = "cookie")]
-#[cfg_attr(nightly, doc(cfg(feature = "cookie")))]
-pub use cookie::{Cookie, CookieBuilder, CookieJar};
+#[cfg_attr(docsrs, doc(cfg(feature = "cookie")))]
+pub use cookie::{Cookie, CookieBuilder, CookieJar, SameSite};
 
 #[cfg(feature = "from_form")]
-#[cfg_attr(nightly, doc(cfg(feature = "from_form")))]
+#[cfg_attr(docsrs, doc(cfg(feature = "from_form")))]
 pub use from_form::{FromForm, FromFormError, FromFormMultiple, FromFormValue};
 
 #[cfg(feature = "under_derive")]
@@ -70,18 +111,32 @@ extern crate under_derive;
 #[cfg(feature = "under_derive")]
 pub use under_derive::*;
 
+pub use self::body::{Body, BodySender, StreamBody};
+pub use self::controller::Controller;
 pub use self::data::{DataStream, DataTransfer};
 pub use self::endpoint::Endpoint;
 pub use self::entity::HttpEntity;
-pub use self::error::UnderError;
+pub use self::error::{RouterBuildError, UnderError};
+pub use self::listener::Listener;
 pub use self::middleware::Middleware;
 pub use self::request::fragment::FragmentSelect;
-pub use self::request::{RemoteAddress, Request};
-pub use self::response::{IntoResponse, Response};
-pub use self::router::{Path, Router};
+#[cfg(feature = "serde")]
+#[cfg_attr(docsrs, doc(cfg(feature = "serde")))]
+pub use self::request::QueryConfig;
+pub use self::request::{ExtKey, RemoteAddress, Request, RequestInfo};
+pub use self::response::{
+    CacheControl, CachedResponse, ContentDisposition, IntoResponse, Response, ResponseTemplate,
+};
+#[cfg(feature = "rng")]
+#[cfg_attr(docsrs, doc(cfg(feature = "rng")))]
+pub use self::rng::Rng;
+pub use self::router::{
+    Completion, ConnectionGauge, DecodePolicy, Group, MediaTypeHint, Path, PathDecoding, RouteInfo,
+    Router, RouterStats,
+};
+pub use self::upgrade::Upgraded;
 
 pub use ::http;
-pub use hyper::Body;
 
 /// A type alias for [`std::result::Result`].
 ///
@@ -0,0 +1,194 @@
+use crate::UnderError;
+use std::net::SocketAddr;
+
+#[derive(Debug)]
+/// A socket that the router can listen on (see [`crate::Router::listen_on`]).
+///
+/// Besides binding to an address, this can be created in ways that allow
+/// for zero-downtime restarts, without a load balancer in front of the
+/// server:
+///
+/// - with [`Listener::bind_reuse_port`], any number of processes can bind to
+///   the same address, and the kernel distributes connections between them;
+///   so a new process can be started before the old one stops.
+/// - with [`Listener::export`] and [`Listener::from_env`], an old process can
+///   hand its socket to a new one that it spawns, so that no connection is
+///   refused while the new process starts.
+///
+/// In both cases, once the new process is listening, the old one should be
+/// terminated gracefully (see [`crate::Router::termination_signal`]), so
+/// that it stops accepting connections, and drains the requests it is
+/// already handling.
+///
+/// # Examples
+/// ```rust,no_run
+/// # use under::*;
+/// # #[tokio::main] async fn main() -> Result<(), anyhow::Error> {
+/// let listener = match Listener::from_env("UNDER_LISTENER_FD")? {
+///     Some(listener) => listener,
+///     None => Listener::bind("0.0.0.0:8080")?,
+/// };
+///
+/// // When it is time to restart, hand the listener to the new process.
+/// let fd = listener.export()?;
+/// let mut command = std::process::Command::new(std::env::current_exe()?);
+/// command.env("UNDER_LISTENER_FD", fd.to_string());
+///
+/// let mut http = under::http();
+/// let terminate = http.termination_signal();
+/// http.at("/").get(|_| async { Response::text("hello, world!") });
+/// tokio::spawn(async move {
+///     // e.g. on SIGHUP:
+///     command.spawn()?;
+///     terminate.send(true)?;
+///     Ok::<_, anyhow::Error>(())
+/// });
+/// http.listen_on(listener).await?;
+/// # Ok(())
+/// # }
+/// ```
+pub struct Listener(std::net::TcpListener);
+
+impl Listener {
+    /// Binds to the given address.
+    ///
+    /// # Errors
+    /// Errors if the address is invalid, or if it could not be bound to
+    /// (e.g. because it is already in use).
+    pub fn bind(address: &str) -> Result<Self, UnderError> {
+        let address = parse(address)?;
+        std::net::TcpListener::bind(address)
+            .map(Listener)
+            .map_err(UnderError::Listener)
+    }
+
+    /// Binds to the given address, with `SO_REUSEPORT` set, so that other
+    /// processes (which also set it) can bind to the same address.
+    ///
+    /// # Errors
+    /// Errors if the address is invalid, or if it could not be bound to
+    /// (e.g. because it is already in use by a socket without
+    /// `SO_REUSEPORT`).
+    #[cfg(all(unix, not(any(target_os = "solaris", target_os = "illumos"))))]
+    #[cfg_attr(docsrs, doc(cfg(unix)))]
+    pub fn bind_reuse_port(address: &str) -> Result<Self, UnderError> {
+        use socket2::{Domain, Protocol, Socket, Type};
+
+        let address = parse(address)?;
+        let bind = || {
+            let socket = Socket::new(
+                Domain::for_address(address),
+                Type::STREAM,
+                Some(Protocol::TCP),
+            )?;
+            socket.set_reuse_address(true)?;
+            socket.set_reuse_port(true)?;
+            socket.bind(&address.into())?;
+            socket.listen(1024)?;
+            Ok(Listener(socket.into()))
+        };
+        bind().map_err(UnderError::Listener)
+    }
+
+    /// Takes over a listener that was exported by the parent process (see
+    /// [`Listener::export`]), whose file descriptor is in the given
+    /// environment variable.  Returns `None` if the variable is not set.
+    ///
+    /// The variable is expected to only ever be set by the parent process;
+    /// the file descriptor in it is assumed to be a listening socket that
+    /// nothing else in this process owns.
+    ///
+    /// # Errors
+    /// Errors if the variable does not contain a file descriptor, or if the
+    /// file descriptor is not a socket.
+    #[cfg(unix)]
+    #[cfg_attr(docsrs, doc(cfg(unix)))]
+    pub fn from_env(variable: &str) -> Result<Option<Self>, UnderError> {
+        use std::os::unix::io::{FromRawFd, RawFd};
+
+        let Ok(value) = std::env::var(variable) else {
+            return Ok(None);
+        };
+        let fd: RawFd = value.parse().map_err(|_| {
+            UnderError::Listener(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                format!("{variable} does not contain a file descriptor ({value:?})"),
+            ))
+        })?;
+
+        // SAFETY: the parent process passed us ownership of this descriptor,
+        // and nothing else in this process knows about it.
+        let socket = unsafe { socket2::Socket::from_raw_fd(fd) };
+        let check = || {
+            socket.local_addr()?;
+            // Don't pass the listener on to our own children, unless it is
+            // exported again.
+            socket.set_cloexec(true)
+        };
+        match check() {
+            Ok(()) => Ok(Some(Listener(socket.into()))),
+            Err(error) => {
+                // If it is not a socket, we don't own it after all.
+                std::mem::forget(socket);
+                Err(UnderError::Listener(error))
+            }
+        }
+    }
+
+    /// Makes the listener inheritable by child processes, returning its file
+    /// descriptor, which should be passed to the child (e.g. in an
+    /// environment variable; see [`Listener::from_env`]).  Note that _every_
+    /// child spawned after this inherits the listener.
+    ///
+    /// # Errors
+    /// Errors if the listener could not be made inheritable.
+    #[cfg(unix)]
+    #[cfg_attr(docsrs, doc(cfg(unix)))]
+    pub fn export(&self) -> Result<std::os::unix::io::RawFd, UnderError> {
+        use std::os::unix::io::AsRawFd;
+
+        socket2::SockRef::from(&self.0)
+            .set_cloexec(false)
+            .map_err(UnderError::Listener)?;
+        Ok(self.0.as_raw_fd())
+    }
+
+    /// The address the listener is bound to.
+    ///
+    /// # Errors
+    /// Errors if the address could not be retrieved.
+    pub fn local_addr(&self) -> Result<SocketAddr, UnderError> {
+        self.0.local_addr().map_err(UnderError::Listener)
+    }
+
+    pub(crate) fn try_clone(&self) -> Result<Self, UnderError> {
+        self.0
+            .try_clone()
+            .map(Listener)
+            .map_err(UnderError::Listener)
+    }
+
+    pub(crate) fn into_tokio(self) -> Result<tokio::net::TcpListener, UnderError> {
+        self.0.set_nonblocking(true).map_err(UnderError::Listener)?;
+        tokio::net::TcpListener::from_std(self.0).map_err(UnderError::Listener)
+    }
+}
+
+impl From<std::net::TcpListener> for Listener {
+    fn from(listener: std::net::TcpListener) -> Self {
+        Listener(listener)
+    }
+}
+
+#[cfg(unix)]
+impl std::os::unix::io::AsRawFd for Listener {
+    fn as_raw_fd(&self) -> std::os::unix::io::RawFd {
+        self.0.as_raw_fd()
+    }
+}
+
+fn parse(address: &str) -> Result<SocketAddr, UnderError> {
+    address
+        .parse()
+        .map_err(|_| UnderError::InvalidAddress(address.to_owned()))
+}
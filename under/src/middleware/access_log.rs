@@ -0,0 +1,520 @@
+use super::{Middleware, Next};
+use crate::redact::Redactor;
+use crate::{HttpEntity, Request, Response};
+use std::fmt::Write as _;
+use std::io::Write as _;
+use std::net::IpAddr;
+use std::path::PathBuf;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{mpsc, Arc, Mutex, PoisonError};
+use std::time::SystemTime;
+
+/// A middleware for writing an access log.
+///
+/// For every request, this writes a single line to the configured
+/// [`AccessLogSink`], in the configured [`AccessLogFormat`].  Each line
+/// contains the remote address of the client, the request line, the status
+/// of the response, the number of bytes sent, the `Referer` and `User-Agent`
+/// headers, and how long the request took (depending on the format).
+///
+/// By default, the remote address is the peer address of the connection; if
+/// the application is behind a reverse proxy, use
+/// [`AccessLog::with_remote_address`] to specify how to determine it.  The
+/// number of bytes sent is determined from the `Content-Length` header, or the
+/// size of the body if it is known ahead of time; if neither are available
+/// (e.g. the body is streamed), it is logged as unknown.
+///
+/// Sensitive query parameters in the URI and the `Referer` header are
+/// redacted, with [`Redactor::default`] unless another redactor is set (see
+/// [`AccessLog::with_redactor`]).
+///
+/// # Examples
+/// ```rust
+/// # use under::*;
+/// use under::middleware::{AccessLog, AccessLogFormat};
+/// use std::sync::{Arc, Mutex};
+/// # #[tokio::main] async fn main() -> Result<(), anyhow::Error> {
+/// let lines = Arc::new(Mutex::new(Vec::<String>::new()));
+/// let sink = lines.clone();
+/// let mut http = under::http();
+/// http.with(AccessLog::new(move |line: &str| sink.lock().unwrap().push(line.to_owned()))
+///         .with_format(AccessLogFormat::Combined))
+///     .at("/").get(under::endpoints::simple(|| Response::text("hello")));
+/// http.prepare();
+/// http.handle(Request::get("/")?.with_header("Referer", "/home")?.with_local_addr()).await?;
+/// let lines = lines.lock().unwrap();
+/// assert!(lines[0].starts_with("127.0.0.1 - - ["));
+/// assert!(lines[0].ends_with("] \"GET / HTTP/1.1\" 200 5 \"/home\" \"-\""));
+/// # Ok(())
+/// # }
+/// ```
+pub struct AccessLog {
+    sink: Box<dyn AccessLogSink>,
+    format: AccessLogFormat,
+    remote: fn(&Request) -> Option<IpAddr>,
+    redactor: Redactor,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Default)]
+#[non_exhaustive]
+/// The format of each line of the access log.
+pub enum AccessLogFormat {
+    /// The [Common Log Format], e.g.
+    /// `127.0.0.1 - - [10/Oct/2000:13:55:36 +0000] "GET / HTTP/1.1" 200 2326`.
+    ///
+    /// [Common Log Format]: https://httpd.apache.org/docs/current/logs.html#common
+    #[default]
+    Common,
+    /// The Combined Log Format, which is the Common Log Format, followed by
+    /// the `Referer` and `User-Agent` headers.
+    Combined,
+    /// A JSON object per line, containing all of the logged fields, including
+    /// the latency (in microseconds) of the request.
+    #[cfg(feature = "json")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "json")))]
+    Json,
+}
+
+/// A destination for access log lines.
+///
+/// This is implemented for [`StdoutSink`], [`FileSink`], and any
+/// `Fn(&str) + Send + Sync + 'static`, so custom destinations can usually
+/// just be a closure.
+pub trait AccessLogSink: Send + Sync + 'static {
+    /// Writes a single line to the sink.  The line does not contain a
+    /// trailing newline.  As this is called for every request, this should
+    /// not block for long.
+    fn write(&self, line: &str);
+}
+
+impl<F> AccessLogSink for F
+where
+    F: Fn(&str) + Send + Sync + 'static,
+{
+    fn write(&self, line: &str) {
+        self(line);
+    }
+}
+
+#[derive(Debug, Copy, Clone, Default)]
+/// An access log sink that writes each line to standard output.
+pub struct StdoutSink;
+
+impl AccessLogSink for StdoutSink {
+    fn write(&self, line: &str) {
+        let mut stdout = std::io::stdout().lock();
+        writeln!(stdout, "{line}").ok();
+    }
+}
+
+/// The default number of lines that may wait to be written by a
+/// [`FileSink`].
+const DEFAULT_CAPACITY: usize = 1024;
+
+#[derive(Debug, Clone)]
+/// An access log sink that appends each line to a file.
+///
+/// Lines are written to the file on a dedicated thread, so that a slow disk
+/// does not stall the request being logged; the thread exits once every
+/// clone of the sink has been dropped.  At most 1024 lines (see
+/// [`FileSink::with_capacity`]) wait to be written at a time; if the disk
+/// falls that far behind, further lines are dropped, and counted (see
+/// [`FileSink::dropped`]).
+///
+/// This is cheaply cloneable, with every clone writing to the same file; so,
+/// to support log rotation, keep a clone of the sink, and call
+/// [`FileSink::reopen`] after the file has been moved (for example, on
+/// `SIGHUP`).
+///
+/// # Examples
+/// ```rust,no_run
+/// # use under::*;
+/// use under::middleware::{AccessLog, FileSink};
+/// # fn main() -> Result<(), anyhow::Error> {
+/// let sink = FileSink::open("access.log")?;
+/// let handle = sink.clone();
+/// let mut http = under::http();
+/// http.with(AccessLog::new(sink));
+/// // ... once the log file has been rotated:
+/// handle.reopen()?;
+/// # Ok(())
+/// # }
+/// ```
+pub struct FileSink {
+    path: PathBuf,
+    sender: mpsc::SyncSender<String>,
+    /// The file to switch to, once [`FileSink::reopen`] has been called.
+    reopened: Arc<Mutex<Option<std::fs::File>>>,
+    dropped: Arc<AtomicU64>,
+}
+
+impl FileSink {
+    /// Opens the given file for appending, creating it if it does not exist.
+    ///
+    /// # Errors
+    /// Errors if the file could not be opened, or if the thread writing to
+    /// it could not be spawned.
+    pub fn open<P: Into<PathBuf>>(path: P) -> std::io::Result<Self> {
+        Self::with_capacity(path, DEFAULT_CAPACITY)
+    }
+
+    /// Opens the given file for appending, creating it if it does not exist,
+    /// with at most `capacity` lines waiting to be written at a time.
+    ///
+    /// # Errors
+    /// Errors if the file could not be opened, or if the thread writing to
+    /// it could not be spawned.
+    pub fn with_capacity<P: Into<PathBuf>>(path: P, capacity: usize) -> std::io::Result<Self> {
+        let path = path.into();
+        let file = Self::open_file(&path)?;
+        let (sender, receiver) = mpsc::sync_channel(capacity);
+        let reopened = Arc::new(Mutex::new(None));
+        let pending = reopened.clone();
+        std::thread::Builder::new()
+            .name("under-access-log".to_owned())
+            .spawn(move || write_lines(file, &receiver, &pending))?;
+        Ok(FileSink {
+            path,
+            sender,
+            reopened,
+            dropped: Arc::new(AtomicU64::new(0)),
+        })
+    }
+
+    /// The number of lines that were dropped, across every clone of this
+    /// sink, because too many lines were waiting to be written.
+    #[must_use]
+    pub fn dropped(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+
+    /// Reopens the file at the original path, for every clone of this sink.
+    /// This should be called after the log file has been rotated, so that
+    /// new lines are written to the new file.
+    ///
+    /// # Errors
+    /// Errors if the file could not be opened; if so, lines continue to be
+    /// written to the previous file.
+    pub fn reopen(&self) -> std::io::Result<()> {
+        let file = Self::open_file(&self.path)?;
+        *self.reopened.lock().unwrap_or_else(PoisonError::into_inner) = Some(file);
+        Ok(())
+    }
+
+    fn open_file(path: &std::path::Path) -> std::io::Result<std::fs::File> {
+        std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+    }
+}
+
+impl AccessLogSink for FileSink {
+    fn write(&self, line: &str) {
+        if let Err(mpsc::TrySendError::Full(_)) = self.sender.try_send(line.to_owned()) {
+            self.dropped.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+}
+
+fn write_lines(
+    mut file: std::fs::File,
+    receiver: &mpsc::Receiver<String>,
+    reopened: &Mutex<Option<std::fs::File>>,
+) {
+    for line in receiver {
+        if let Some(new) = reopened
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .take()
+        {
+            file = new;
+        }
+        writeln!(file, "{line}").ok();
+    }
+}
+
+impl AccessLog {
+    /// Creates a new access log middleware, writing to the given sink in the
+    /// [`AccessLogFormat::Common`] format.
+    pub fn new<S: AccessLogSink>(sink: S) -> Self {
+        AccessLog {
+            sink: Box::new(sink),
+            format: AccessLogFormat::default(),
+            remote: peer_ip,
+            redactor: Redactor::default(),
+        }
+    }
+
+    /// Creates a new access log middleware, writing to standard output.
+    #[must_use]
+    pub fn stdout() -> Self {
+        AccessLog::new(StdoutSink)
+    }
+
+    /// Sets the format of each line.
+    #[must_use]
+    pub fn with_format(mut self, format: AccessLogFormat) -> Self {
+        self.format = format;
+        self
+    }
+
+    /// Sets how to determine the remote address of the client.  See
+    /// [`Request::remote_address`] for more information.
+    ///
+    /// # Examples
+    /// ```rust
+    /// # use under::*;
+    /// use under::middleware::AccessLog;
+    /// let log = AccessLog::stdout().with_remote_address(|request| {
+    ///     request.remote_address()
+    ///         .trust_forwarded_for(-1)
+    ///         .trust_peer_address()
+    ///         .apply()
+    /// });
+    /// ```
+    #[must_use]
+    pub fn with_remote_address(mut self, remote: fn(&Request) -> Option<IpAddr>) -> Self {
+        self.remote = remote;
+        self
+    }
+
+    /// Sets the redactor for the URI and the `Referer` header of each line.
+    ///
+    /// # Examples
+    /// ```rust
+    /// # use under::*;
+    /// use under::middleware::AccessLog;
+    /// use under::redact::Redactor;
+    /// use std::sync::{Arc, Mutex};
+    /// # #[tokio::main] async fn main() -> Result<(), anyhow::Error> {
+    /// let lines = Arc::new(Mutex::new(Vec::<String>::new()));
+    /// let sink = lines.clone();
+    /// let mut http = under::http();
+    /// http.with(AccessLog::new(move |line: &str| sink.lock().unwrap().push(line.to_owned()))
+    ///         .with_redactor(Redactor::default().with_query_param("sig")))
+    ///     .at("/").get(under::endpoints::simple(Response::empty_204));
+    /// http.prepare();
+    /// http.handle(Request::get("/?sig=abc&token=def&page=2")?).await?;
+    /// let lines = lines.lock().unwrap();
+    /// assert!(lines[0].contains("\"GET /?sig=[REDACTED]&token=[REDACTED]&page=2 HTTP/1.1\""));
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[must_use]
+    pub fn with_redactor(mut self, redactor: Redactor) -> Self {
+        self.redactor = redactor;
+        self
+    }
+}
+
+#[async_trait]
+impl Middleware for AccessLog {
+    async fn apply(
+        self: Pin<&Self>,
+        request: Request,
+        next: Next<'_>,
+    ) -> Result<Response, anyhow::Error> {
+        let entry = Entry {
+            time: SystemTime::now(),
+            remote: (self.remote)(&request),
+            method: request.method().clone(),
+            uri: self
+                .redactor
+                .redact_uri(&request.uri().to_string())
+                .into_owned(),
+            version: request.version(),
+            referer: header_string(&request, http::header::REFERER)
+                .map(|referer| self.redactor.redact_uri(&referer).into_owned()),
+            user_agent: header_string(&request, http::header::USER_AGENT),
+            status: None,
+            bytes: None,
+            #[cfg(feature = "json")]
+            latency: std::time::Duration::ZERO,
+        };
+        #[cfg(feature = "json")]
+        let start = std::time::Instant::now();
+
+        let result = next.apply(request).await;
+        let entry = Entry {
+            status: result.as_ref().ok().map(|r| r.status().as_u16()),
+            bytes: result.as_ref().ok().and_then(body_length),
+            #[cfg(feature = "json")]
+            latency: start.elapsed(),
+            ..entry
+        };
+
+        self.sink.write(&entry.format(self.format));
+        result
+    }
+}
+
+impl std::fmt::Debug for AccessLog {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("AccessLog")
+            .field("format", &self.format)
+            .field("redactor", &self.redactor)
+            .finish_non_exhaustive()
+    }
+}
+
+struct Entry {
+    time: SystemTime,
+    remote: Option<IpAddr>,
+    method: http::Method,
+    uri: String,
+    version: http::Version,
+    referer: Option<String>,
+    user_agent: Option<String>,
+    status: Option<u16>,
+    bytes: Option<u64>,
+    #[cfg(feature = "json")]
+    latency: std::time::Duration,
+}
+
+impl Entry {
+    fn format(&self, format: AccessLogFormat) -> String {
+        match format {
+            AccessLogFormat::Common => self.common(),
+            AccessLogFormat::Combined => {
+                let mut line = self.common();
+                write!(
+                    line,
+                    " \"{}\" \"{}\"",
+                    quoted(self.referer.as_deref()),
+                    quoted(self.user_agent.as_deref())
+                )
+                .unwrap();
+                line
+            }
+            #[cfg(feature = "json")]
+            AccessLogFormat::Json => serde_json::json!({
+                "time": clf_time(self.time),
+                "remote": self.remote.map(|r| r.to_string()),
+                "method": self.method.as_str(),
+                "uri": self.uri,
+                "version": format!("{:?}", self.version),
+                "status": self.status,
+                "bytes": self.bytes,
+                "referer": self.referer,
+                "user_agent": self.user_agent,
+                "latency_us": u64::try_from(self.latency.as_micros()).unwrap_or(u64::MAX),
+            })
+            .to_string(),
+        }
+    }
+
+    fn common(&self) -> String {
+        let dash = || "-".to_string();
+        format!(
+            "{} - - [{}] \"{} {} {:?}\" {} {}",
+            self.remote.map_or_else(dash, |r| r.to_string()),
+            clf_time(self.time),
+            self.method,
+            self.uri,
+            self.version,
+            self.status.map_or_else(dash, |s| s.to_string()),
+            self.bytes.map_or_else(dash, |b| b.to_string()),
+        )
+    }
+}
+
+fn peer_ip(request: &Request) -> Option<IpAddr> {
+    request.peer_addr().map(|a| a.ip())
+}
+
+fn header_string(request: &Request, name: http::header::HeaderName) -> Option<String> {
+    request
+        .header(name)
+        .and_then(|v| v.to_str().ok())
+        .map(str::to_owned)
+}
+
+fn body_length(response: &Response) -> Option<u64> {
+    use http_body::Body;
+    let borrowed: &http::Response<crate::Body> = std::borrow::Borrow::borrow(response);
+    response
+        .header(http::header::CONTENT_LENGTH)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.parse().ok())
+        .or_else(|| borrowed.body().size_hint().exact())
+}
+
+fn quoted(value: Option<&str>) -> String {
+    value.map_or_else(
+        || "-".to_string(),
+        |v| v.replace('\\', "\\\\").replace('"', "\\\""),
+    )
+}
+
+/// Formats the given time in the format used by the Common Log Format (e.g.
+/// `10/Oct/2000:13:55:36 +0000`), in UTC.
+fn clf_time(time: SystemTime) -> String {
+    const MONTHS: [&str; 12] = [
+        "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+    ];
+    let secs = time
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs());
+    let (year, month, day) = civil_from_days(secs / 86_400);
+    let rem = secs % 86_400;
+    format!(
+        "{day:02}/{}/{year}:{:02}:{:02}:{:02} +0000",
+        MONTHS[usize::try_from(month - 1).unwrap_or(0)],
+        rem / 3600,
+        (rem % 3600) / 60,
+        rem % 60
+    )
+}
+
+/// Converts a number of days since the unix epoch into a (year, month, day)
+/// triple.  See <http://howardhinnant.github.io/date_algorithms.html>.
+pub(crate) fn civil_from_days(days: u64) -> (u64, u64, u64) {
+    let z = days + 719_468;
+    let era = z / 146_097;
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + u64::from(month <= 2);
+    (year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn test_clf_time() {
+        let time = SystemTime::UNIX_EPOCH + Duration::from_secs(971_186_136);
+        assert_eq!(clf_time(time), "10/Oct/2000:13:55:36 +0000");
+        assert_eq!(
+            clf_time(SystemTime::UNIX_EPOCH),
+            "01/Jan/1970:00:00:00 +0000"
+        );
+        let leap_day: u64 = 951_782_400;
+        let time = SystemTime::UNIX_EPOCH + Duration::from_secs(leap_day);
+        assert_eq!(clf_time(time), "29/Feb/2000:00:00:00 +0000");
+    }
+
+    #[test]
+    fn test_file_sink_writes_in_order() {
+        let path = std::env::temp_dir().join(format!("under-access-{}.log", std::process::id()));
+        std::fs::remove_file(&path).ok();
+        let sink = FileSink::open(&path).unwrap();
+        sink.write("first");
+        sink.write("second");
+        let start = std::time::Instant::now();
+        let mut contents = String::new();
+        while contents != "first\nsecond\n" && start.elapsed() < Duration::from_secs(5) {
+            std::thread::sleep(Duration::from_millis(10));
+            contents = std::fs::read_to_string(&path).unwrap();
+        }
+        std::fs::remove_file(&path).ok();
+        assert_eq!(contents, "first\nsecond\n");
+    }
+}
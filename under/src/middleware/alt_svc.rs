@@ -0,0 +1,168 @@
+use super::{Middleware, Next};
+use crate::{HttpEntity, Request, Response};
+use std::fmt::Write;
+use std::pin::Pin;
+use std::time::Duration;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Service {
+    protocol: String,
+    authority: String,
+}
+
+#[derive(Default, Debug, Clone)]
+/// A middleware for advertising other protocols the application is
+/// available over, through the [`Alt-Svc`] header.
+///
+/// Clients that support one of the advertised protocols (e.g. HTTP/3,
+/// served from a different listener, or by a proxy in front of the
+/// application) may switch to it for later requests.  The header is added to
+/// every response that does not already have one.  If no services are
+/// given, the middleware instead tells clients to forget any alternative
+/// services they know of (`Alt-Svc: clear`), which is useful when taking an
+/// alternative listener out of service.
+///
+/// [`Alt-Svc`]: https://www.rfc-editor.org/rfc/rfc7838
+///
+/// # Examples
+/// ```rust
+/// # use under::*;
+/// use under::middleware::AltSvc;
+/// use std::time::Duration;
+/// # #[tokio::main] async fn main() -> Result<(), anyhow::Error> {
+/// let mut http = under::http();
+/// http.with(
+///     AltSvc::new()
+///         .with_service("h3", ":443")
+///         .with_service("h2", "alt.example.com:443")
+///         .with_max_age(Duration::from_secs(3600)),
+/// );
+/// http.at("/").get(under::endpoints::simple(Response::empty_204));
+/// http.prepare();
+/// let response = http.handle(Request::get("/")?).await?;
+/// assert_eq!(
+///     response.header("Alt-Svc").unwrap(),
+///     "h3=\":443\"; ma=3600, h2=\"alt.example.com:443\"; ma=3600"
+/// );
+/// # Ok(())
+/// # }
+/// ```
+pub struct AltSvc {
+    services: Vec<Service>,
+    max_age: Option<Duration>,
+    persist: bool,
+}
+
+impl AltSvc {
+    #[must_use]
+    /// Creates a new middleware, with no services; on its own, this clears
+    /// the alternative services of clients.  This is provided as an
+    /// alternative to `Default`.
+    pub fn new() -> Self {
+        AltSvc::default()
+    }
+
+    #[must_use]
+    /// Creates a new middleware that clears the alternative services of
+    /// clients.  This is the same as [`AltSvc::new`], but clearer in
+    /// intent.
+    ///
+    /// # Examples
+    /// ```rust
+    /// # use under::*;
+    /// # #[tokio::main] async fn main() -> Result<(), anyhow::Error> {
+    /// let mut http = under::http();
+    /// http.with(under::middleware::AltSvc::clear());
+    /// http.at("/").get(under::endpoints::simple(Response::empty_204));
+    /// http.prepare();
+    /// let response = http.handle(Request::get("/")?).await?;
+    /// assert_eq!(response.header("Alt-Svc").unwrap(), "clear");
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn clear() -> Self {
+        AltSvc::default()
+    }
+
+    #[must_use]
+    /// Advertises the given protocol (its ALPN identifier, e.g. `h3` or
+    /// `h2`), at the given authority.  The host of the authority may be
+    /// left out (e.g. `:443`), in which case it is the host the request was
+    /// made to.
+    pub fn with_service<P: Into<String>, A: Into<String>>(
+        mut self,
+        protocol: P,
+        authority: A,
+    ) -> Self {
+        self.services.push(Service {
+            protocol: protocol.into(),
+            authority: authority.into(),
+        });
+        self
+    }
+
+    #[must_use]
+    /// Sets how long clients may remember the services for (the `ma`
+    /// parameter).  Without this, clients remember them for 24 hours.
+    pub fn with_max_age(mut self, max_age: Duration) -> Self {
+        self.max_age = Some(max_age);
+        self
+    }
+
+    #[must_use]
+    /// Sets whether clients should keep remembering the services when their
+    /// network changes (the `persist` parameter).  By default, they do not.
+    pub fn with_persist(mut self, persist: bool) -> Self {
+        self.persist = persist;
+        self
+    }
+
+    /// The value of the header.
+    fn header_value(&self) -> String {
+        if self.services.is_empty() {
+            return "clear".to_owned();
+        }
+
+        let mut value = String::new();
+        for (i, service) in self.services.iter().enumerate() {
+            if i > 0 {
+                value.push_str(", ");
+            }
+            // Writing to a string never fails.
+            let _ = write!(value, "{}=\"", service.protocol);
+            for c in service.authority.chars() {
+                if matches!(c, '"' | '\\') {
+                    value.push('\\');
+                }
+                value.push(c);
+            }
+            value.push('"');
+            if let Some(max_age) = self.max_age {
+                let _ = write!(value, "; ma={}", max_age.as_secs());
+            }
+            if self.persist {
+                value.push_str("; persist=1");
+            }
+        }
+        value
+    }
+}
+
+#[async_trait]
+impl Middleware for AltSvc {
+    async fn apply(
+        self: Pin<&Self>,
+        request: Request,
+        next: Next<'_>,
+    ) -> Result<Response, anyhow::Error> {
+        let mut response = next.apply(request).await?;
+        if !response.headers().contains_key(http::header::ALT_SVC) {
+            response.set_header(http::header::ALT_SVC, self.header_value())?;
+        }
+        Ok(response)
+    }
+
+    fn describe(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "alt_svc({})", self.header_value())
+    }
+}
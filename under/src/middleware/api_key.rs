@@ -0,0 +1,299 @@
+use super::{Middleware, Next};
+use crate::{HttpEntity, Request, Response};
+use std::pin::Pin;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+/// The authenticated principal of a request - i.e., who (or what) made the
+/// request, as resolved by an authentication middleware like
+/// [`ApiKeyAuth`].
+///
+/// This is the type that is inserted into the [`Request`] extensions; it
+/// can be retrieved using [`Request::principal`].
+pub struct Principal<P>(pub P);
+
+impl<P> Principal<P> {
+    /// Turns the given principal into its inner value, consuming the
+    /// principal.
+    pub fn into_inner(self) -> P {
+        self.0
+    }
+}
+
+impl<P> std::ops::Deref for Principal<P> {
+    type Target = P;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+#[async_trait]
+/// Resolves an API key into the principal it belongs to.
+///
+/// This is used by the [`ApiKeyAuth`] middleware.  Since it is asynchronous,
+/// it may look the key up in a database; however, as this is called on every
+/// request that passes through the middleware, it is suggested that such a
+/// validator caches its results.  Validators that compare the key against
+/// known keys should do so in constant time (see [`constant_time_eq`]), so
+/// that the time taken does not leak how much of the key was correct.
+///
+/// This is implemented for any `Fn(&str) -> Option<P>`, and for
+/// [`StaticKeys`].
+///
+/// # Examples
+/// ```rust
+/// use under::middleware::{constant_time_eq, ApiKeyAuth};
+/// let auth = ApiKeyAuth::new(|key: &str| {
+///     constant_time_eq(key.as_bytes(), b"k-1234").then_some(1u64)
+/// });
+/// ```
+pub trait KeyValidator: Send + Sync + 'static {
+    /// The principal that keys resolve to.
+    type Principal: Clone + Send + Sync + 'static;
+
+    /// Returns the principal the given key belongs to, or `None` if the key
+    /// is not valid.
+    async fn validate(&self, key: &str) -> Option<Self::Principal>;
+}
+
+#[async_trait]
+impl<F, P> KeyValidator for F
+where
+    F: Fn(&str) -> Option<P> + Send + Sync + 'static,
+    P: Clone + Send + Sync + 'static,
+{
+    type Principal = P;
+
+    async fn validate(&self, key: &str) -> Option<P> {
+        self(key)
+    }
+}
+
+#[derive(Clone)]
+/// A fixed set of API keys, each belonging to a principal.
+///
+/// Keys are compared in constant time, and every key is compared against,
+/// regardless of whether an earlier one matched.
+///
+/// # Examples
+/// ```rust
+/// use under::middleware::StaticKeys;
+/// let keys = StaticKeys::new()
+///     .with_key("k-1234", "billing-service")
+///     .with_key("k-5678", "reporting-service");
+/// ```
+pub struct StaticKeys<P> {
+    keys: Vec<(String, P)>,
+}
+
+impl<P> StaticKeys<P> {
+    /// Creates an empty set of keys.
+    #[must_use]
+    pub fn new() -> Self {
+        StaticKeys { keys: vec![] }
+    }
+
+    /// Adds the given key, belonging to the given principal.
+    #[must_use]
+    pub fn with_key<K: Into<String>>(mut self, key: K, principal: P) -> Self {
+        self.keys.push((key.into(), principal));
+        self
+    }
+}
+
+impl<P> Default for StaticKeys<P> {
+    fn default() -> Self {
+        StaticKeys::new()
+    }
+}
+
+impl<P, K: Into<String>> FromIterator<(K, P)> for StaticKeys<P> {
+    fn from_iter<I: IntoIterator<Item = (K, P)>>(iter: I) -> Self {
+        StaticKeys {
+            keys: iter.into_iter().map(|(k, p)| (k.into(), p)).collect(),
+        }
+    }
+}
+
+#[async_trait]
+impl<P: Clone + Send + Sync + 'static> KeyValidator for StaticKeys<P> {
+    type Principal = P;
+
+    async fn validate(&self, key: &str) -> Option<P> {
+        self.keys
+            .iter()
+            .fold(None, |found, (candidate, principal)| {
+                let matches = constant_time_eq(candidate.as_bytes(), key.as_bytes());
+                if matches && found.is_none() {
+                    Some(principal.clone())
+                } else {
+                    found
+                }
+            })
+    }
+}
+
+impl<P> std::fmt::Debug for StaticKeys<P> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("StaticKeys")
+            .field("keys", &self.keys.len())
+            .finish()
+    }
+}
+
+/// Compares the two byte strings in constant time - i.e., the time taken
+/// depends only on their lengths, and not on their contents.  This should be
+/// used to compare secrets, such as API keys.
+///
+/// # Examples
+/// ```rust
+/// use under::middleware::constant_time_eq;
+/// assert!(constant_time_eq(b"secret", b"secret"));
+/// assert!(!constant_time_eq(b"secret", b"secreT"));
+/// assert!(!constant_time_eq(b"secret", b"secrets"));
+/// ```
+#[must_use]
+pub fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+
+    let difference = a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y));
+    // Keep the compiler from turning this into an early-exit comparison.
+    std::hint::black_box(difference) == 0
+}
+
+/// A middleware for authenticating requests with an API key.
+///
+/// This reads the key from a header (`X-API-Key`, by default) or,
+/// optionally, a query parameter, and resolves it with the given
+/// [`KeyValidator`].  If the key is valid, the principal it belongs to is
+/// inserted into the request (see [`Request::principal`]); otherwise, the
+/// request receives an empty 401, and is not passed on.
+///
+/// # Examples
+/// ```rust
+/// # use under::*;
+/// use under::middleware::{ApiKeyAuth, StaticKeys};
+///
+/// async fn whoami(request: Request) -> Response {
+///     Response::text(*request.principal::<&'static str>().unwrap())
+/// }
+///
+/// # #[tokio::main] async fn main() -> Result<(), anyhow::Error> {
+/// let keys = StaticKeys::new().with_key("k-1234", "billing-service");
+/// let mut http = under::http();
+/// http.with(ApiKeyAuth::new(keys).with_query("api_key"))
+///     .at("/whoami").get(whoami);
+/// http.prepare();
+///
+/// let request = Request::get("/whoami")?.with_header("X-API-Key", "k-1234")?;
+/// let mut response = http.handle(request).await?;
+/// assert_eq!(response.data(512).into_text().await?, "billing-service");
+///
+/// let mut response = http.handle(Request::get("/whoami?api_key=k-1234")?).await?;
+/// assert_eq!(response.data(512).into_text().await?, "billing-service");
+///
+/// let request = Request::get("/whoami")?.with_header("X-API-Key", "wrong")?;
+/// let response = http.handle(request).await?;
+/// assert_eq!(response.status(), http::StatusCode::UNAUTHORIZED);
+/// let response = http.handle(Request::get("/whoami")?).await?;
+/// assert_eq!(response.status(), http::StatusCode::UNAUTHORIZED);
+/// # Ok(())
+/// # }
+/// ```
+pub struct ApiKeyAuth<V> {
+    validator: V,
+    header: http::HeaderName,
+    query: Option<String>,
+}
+
+impl<V: KeyValidator> ApiKeyAuth<V> {
+    /// Creates a new API key middleware, resolving keys with the given
+    /// validator.
+    pub fn new(validator: V) -> Self {
+        ApiKeyAuth {
+            validator,
+            header: http::HeaderName::from_static("x-api-key"),
+            query: None,
+        }
+    }
+
+    /// Sets the header the key is read from.
+    #[must_use]
+    pub fn with_header(mut self, header: http::HeaderName) -> Self {
+        self.header = header;
+        self
+    }
+
+    /// Also reads the key from the given query parameter, if it is not in
+    /// the header.  Note that keys in the query string are more likely to
+    /// end up in logs.
+    #[must_use]
+    pub fn with_query<Q: Into<String>>(mut self, parameter: Q) -> Self {
+        self.query = Some(parameter.into());
+        self
+    }
+
+    fn key(&self, request: &Request) -> Option<String> {
+        if let Some(key) = request.header(&self.header).and_then(|v| v.to_str().ok()) {
+            return Some(key.to_owned());
+        }
+
+        let parameter = self.query.as_ref()?;
+        request
+            .uri()
+            .query()?
+            .split('&')
+            .filter_map(|pair| pair.split_once('='))
+            .find(|(name, _)| crate::request::decode_query(name) == parameter.as_str())
+            .map(|(_, value)| crate::request::decode_query(value).into_owned())
+    }
+}
+
+#[async_trait]
+impl<V: KeyValidator> Middleware for ApiKeyAuth<V> {
+    async fn apply(
+        self: Pin<&Self>,
+        mut request: Request,
+        next: Next<'_>,
+    ) -> Result<Response, anyhow::Error> {
+        let principal = match self.key(&request) {
+            Some(key) => self.validator.validate(&key).await,
+            None => None,
+        };
+
+        match principal {
+            Some(principal) => {
+                request.extensions_mut().insert(Principal(principal));
+                next.apply(request).await
+            }
+            None => Ok(Response::empty(http::StatusCode::UNAUTHORIZED)),
+        }
+    }
+}
+
+impl<V> std::fmt::Debug for ApiKeyAuth<V> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ApiKeyAuth")
+            .field("validator", &std::any::type_name::<V>())
+            .field("header", &self.header)
+            .field("query", &self.query)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_query_key_decoding() {
+        let auth = ApiKeyAuth::new(StaticKeys::<()>::new()).with_query("api_key");
+        let key = |uri: &str| auth.key(&Request::get(uri).unwrap());
+        assert_eq!(key("/?api_key=a%2Bb+c").as_deref(), Some("a+b c"));
+        assert_eq!(key("/?api%5Fkey=abc").as_deref(), Some("abc"));
+        assert_eq!(key("/?api_key=%zzab").as_deref(), Some("%zzab"));
+        assert_eq!(key("/?api_key=%+f").as_deref(), Some("% f"));
+    }
+}
@@ -0,0 +1,235 @@
+use super::{Middleware, Next};
+use crate::{HttpEntity, Request, RequestInfo, Response};
+use http_body::Body as _;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::task::{Context, Poll};
+
+#[derive(Debug, Clone, Default)]
+/// The number of bytes written for the body of a response.
+///
+/// This is inserted into the extensions of every response that passes
+/// through the [`BodySize`] middleware.  Since the body of a response is
+/// only written after the response leaves the middleware stack, the count
+/// continues to go up as the body is written; [`BodyCounter::is_complete`]
+/// reports whether the body has been written in full.
+pub struct BodyCounter {
+    bytes: Arc<AtomicU64>,
+    complete: Arc<AtomicBool>,
+}
+
+impl BodyCounter {
+    #[must_use]
+    /// The number of bytes of the body written so far.
+    pub fn bytes(&self) -> u64 {
+        self.bytes.load(Ordering::Acquire)
+    }
+
+    #[must_use]
+    /// Whether or not the body has been written in full.  If the body was
+    /// dropped before then (e.g. because the client disconnected), this
+    /// remains `false`.
+    pub fn is_complete(&self) -> bool {
+        self.complete.load(Ordering::Acquire)
+    }
+}
+
+type Hook = Box<dyn Fn(&RequestInfo, http::StatusCode, &BodyCounter) + Send + Sync>;
+pub(crate) type OnComplete = Box<dyn FnOnce(&BodyCounter) + Send + Sync>;
+
+#[derive(Default)]
+/// A middleware for counting the bytes written for each response body.
+///
+/// This wraps the body of every response, counting the bytes as they are
+/// written, without buffering the body.  The count is available through the
+/// [`BodyCounter`] in the response's extensions, and is given to each hook
+/// (see [`BodySize::on_complete`]) once the body has been written, or
+/// dropped.  If the body has a known size, and the response does not
+/// already have a `Content-Length`, one is added, so that wrapping the body
+/// does not change how it is sent.
+///
+/// # Examples
+/// ```rust
+/// # use under::*;
+/// use under::middleware::{BodyCounter, BodySize};
+/// use std::sync::atomic::{AtomicU64, Ordering};
+/// use std::sync::Arc;
+/// # #[tokio::main] async fn main() -> Result<(), anyhow::Error> {
+/// let total = Arc::new(AtomicU64::new(0));
+/// let recorded = total.clone();
+/// let mut http = under::http();
+/// http.with(BodySize::new().on_complete(move |_, _, counter| {
+///     recorded.fetch_add(counter.bytes(), Ordering::SeqCst);
+/// }));
+/// http.at("/").get(under::endpoints::simple(|| Response::text("hello")));
+/// http.prepare();
+/// let mut response = http.handle(Request::get("/")?).await?;
+/// let counter = response.ext::<BodyCounter>().unwrap().clone();
+/// assert_eq!(counter.bytes(), 0);
+/// response.data(512).into_text().await?;
+/// assert!(counter.is_complete());
+/// assert_eq!(counter.bytes(), 5);
+/// assert_eq!(total.load(Ordering::SeqCst), 5);
+/// # Ok(())
+/// # }
+/// ```
+pub struct BodySize {
+    hooks: Arc<Vec<Hook>>,
+}
+
+impl BodySize {
+    #[must_use]
+    /// Creates a new body size middleware, with no hooks.
+    pub fn new() -> Self {
+        BodySize::default()
+    }
+
+    #[must_use]
+    /// Adds a hook that is called once the body of a response has been
+    /// written in full, or has been dropped.  The hook is given information
+    /// on the request, the status of the response, and the counter for the
+    /// body.  This is useful for e.g. recording response sizes into a
+    /// histogram.
+    #[allow(clippy::missing_panics_doc)]
+    pub fn on_complete<F>(mut self, hook: F) -> Self
+    where
+        F: Fn(&RequestInfo, http::StatusCode, &BodyCounter) + Send + Sync + 'static,
+    {
+        // The hooks are only shared once the middleware handles a request,
+        // and this consumes the middleware, so this will not panic.
+        Arc::get_mut(&mut self.hooks)
+            .expect("hooks are not yet shared")
+            .push(Box::new(hook));
+        self
+    }
+}
+
+impl std::fmt::Debug for BodySize {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("BodySize").finish_non_exhaustive()
+    }
+}
+
+#[async_trait]
+impl Middleware for BodySize {
+    async fn apply(
+        self: Pin<&Self>,
+        request: Request,
+        next: Next<'_>,
+    ) -> Result<Response, anyhow::Error> {
+        let info = (!self.hooks.is_empty()).then(|| RequestInfo::from(&request));
+        let mut response = next.apply(request).await?;
+        let status = response.status();
+        let on_complete = info.map(|info| {
+            let hooks = self.hooks.clone();
+            Box::new(move |counter: &BodyCounter| {
+                hooks.iter().for_each(|hook| hook(&info, status, counter));
+            }) as OnComplete
+        });
+        let counter = count_body(&mut response, on_complete);
+        response.extensions_mut().insert(counter);
+        Ok(response)
+    }
+}
+
+/// Wraps the body of the given response, counting the bytes written for it.
+/// The given closure (if any) is called once the body has been written in
+/// full, or dropped.  If the body has a known size, and the response does
+/// not already have a `Content-Length`, one is added.  The trailers of the
+/// body, if any, are passed through.
+pub(crate) fn count_body(response: &mut Response, on_complete: Option<OnComplete>) -> BodyCounter {
+    let counter = BodyCounter::default();
+
+    if !has_content_length(response) && allows_body(response.status()) {
+        let borrowed: &http::Response<crate::Body> = std::borrow::Borrow::borrow(&*response);
+        if let Some(length) = http_body::Body::size_hint(borrowed.body()).exact() {
+            response
+                .headers_mut()
+                .insert(http::header::CONTENT_LENGTH, length.into());
+        }
+    }
+
+    let body = std::mem::take(response.body_mut());
+    response.set_body(crate::Body::from_http_body(CountingBody {
+        body,
+        counter: counter.clone(),
+        on_complete,
+    }));
+    counter
+}
+
+fn has_content_length(response: &Response) -> bool {
+    response.header(http::header::CONTENT_LENGTH).is_some()
+}
+
+fn allows_body(status: http::StatusCode) -> bool {
+    !(status.is_informational()
+        || status == http::StatusCode::NO_CONTENT
+        || status == http::StatusCode::NOT_MODIFIED)
+}
+
+struct CountingBody {
+    body: crate::Body,
+    counter: BodyCounter,
+    on_complete: Option<OnComplete>,
+}
+
+impl CountingBody {
+    /// Calls the closure, if it has not been called yet.  The body is
+    /// complete if everything in it has been read; hyper stops reading a
+    /// body once it reports its end, so this is checked here, rather than
+    /// waiting for the body to return `None`.
+    fn complete(&mut self) {
+        if self.body.is_end_stream() {
+            self.counter.complete.store(true, Ordering::Release);
+        }
+        if let Some(on_complete) = self.on_complete.take() {
+            on_complete(&self.counter);
+        }
+    }
+}
+
+impl http_body::Body for CountingBody {
+    type Data = bytes::Bytes;
+    type Error = std::io::Error;
+
+    fn poll_frame(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<http_body::Frame<bytes::Bytes>, std::io::Error>>> {
+        let poll = Pin::new(&mut self.body).poll_frame(cx);
+        match &poll {
+            Poll::Ready(Some(Ok(frame))) => {
+                if let Some(chunk) = frame.data_ref() {
+                    self.counter
+                        .bytes
+                        .fetch_add(chunk.len() as u64, Ordering::AcqRel);
+                }
+                if self.body.is_end_stream() {
+                    self.complete();
+                }
+            }
+            Poll::Ready(None) => {
+                self.counter.complete.store(true, Ordering::Release);
+                self.complete();
+            }
+            _ => {}
+        }
+        poll
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.body.is_end_stream()
+    }
+
+    fn size_hint(&self) -> http_body::SizeHint {
+        self.body.size_hint()
+    }
+}
+
+impl Drop for CountingBody {
+    fn drop(&mut self) {
+        self.complete();
+    }
+}
@@ -0,0 +1,382 @@
+use super::{Middleware, Next};
+use crate::{Body, HttpEntity, Request, Response};
+use bytes::Bytes;
+use flate2::write::{DeflateEncoder, GzEncoder};
+use std::io::Write;
+use std::pin::Pin;
+
+/// The default size under which responses are not compressed.
+const DEFAULT_MIN_SIZE: u64 = 1024;
+
+type Exclude = Box<dyn Fn(&Response) -> bool + Send + Sync>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// The encodings the middleware can compress with.
+enum Encoding {
+    Gzip,
+    Deflate,
+}
+
+impl Encoding {
+    fn name(self) -> &'static str {
+        match self {
+            Encoding::Gzip => "gzip",
+            Encoding::Deflate => "deflate",
+        }
+    }
+
+    fn encoder(self, level: flate2::Compression) -> Encoder {
+        match self {
+            Encoding::Gzip => Encoder::Gzip(GzEncoder::new(vec![], level)),
+            Encoding::Deflate => Encoder::Deflate(DeflateEncoder::new(vec![], level)),
+        }
+    }
+}
+
+/// A middleware for compressing response bodies.
+///
+/// The encoding is negotiated with the `Accept-Encoding` header of the
+/// request; `gzip` and `deflate` are supported.  Bodies that are known up
+/// front are compressed all at once, while streamed bodies are compressed
+/// chunk by chunk, with each chunk flushed as it is written, so that the
+/// client can decode it as soon as it arrives.  Note that the trailers of a
+/// streamed body are not kept.
+///
+/// Some responses are never compressed:
+///
+/// - event streams (`text/event-stream`), since their events must reach the
+///   client without being held back by the compressor;
+/// - responses that already have a `Content-Encoding`;
+/// - partial responses (`206 Partial Content`), as the ranges refer to the
+///   uncompressed body;
+/// - responses smaller than the minimum size (1 KiB by default; see
+///   [`Compression::with_min_size`]), according to their `Content-Length`,
+///   or the size of their body if it is known ahead of time.
+///
+/// Further exclusions can be added with [`Compression::exclude`].  As with
+/// any middleware, compression can also be skipped for individual requests
+/// (see [`Request::skip`]).  This is gated behind the `compression` feature
+/// flag.
+///
+/// # Examples
+/// ```rust
+/// # use under::*;
+/// use under::middleware::Compression;
+/// # #[tokio::main] async fn main() -> Result<(), anyhow::Error> {
+/// let mut http = under::http();
+/// http.with(Compression::new().with_min_size(16));
+/// http.at("/").get(under::endpoints::simple(|| Response::text("hello, world! ".repeat(8))));
+/// http.prepare();
+/// let request = Request::get("/")?.with_header("Accept-Encoding", "gzip")?;
+/// let response = http.handle(request).await?;
+/// assert_eq!(response.header("Content-Encoding").unwrap(), "gzip");
+/// assert_eq!(response.header("Vary").unwrap(), "Accept-Encoding");
+/// let response = http.handle(Request::get("/")?).await?;
+/// assert!(response.header("Content-Encoding").is_none());
+/// # Ok(())
+/// # }
+/// ```
+pub struct Compression {
+    min_size: u64,
+    level: flate2::Compression,
+    exclude: Vec<Exclude>,
+}
+
+impl Default for Compression {
+    fn default() -> Self {
+        Compression {
+            min_size: DEFAULT_MIN_SIZE,
+            level: flate2::Compression::default(),
+            exclude: vec![],
+        }
+    }
+}
+
+impl Compression {
+    #[must_use]
+    /// Creates a new compression middleware.  This is provided as an
+    /// alternative to `Default`.
+    pub fn new() -> Self {
+        Compression::default()
+    }
+
+    #[must_use]
+    /// Sets the size, in bytes, under which responses are not compressed.
+    /// Responses whose size is not known ahead of time (i.e., streamed
+    /// responses without a `Content-Length`) are always compressed.
+    pub fn with_min_size(mut self, min_size: u64) -> Self {
+        self.min_size = min_size;
+        self
+    }
+
+    #[must_use]
+    /// Sets the compression level, from 0 (no compression) to 9 (best
+    /// compression).  Levels above 9 are treated as 9.  The default is 6.
+    pub fn with_level(mut self, level: u32) -> Self {
+        self.level = flate2::Compression::new(level.min(9));
+        self
+    }
+
+    #[must_use]
+    /// Adds a predicate that excludes responses from compression.  If any
+    /// predicate returns `true` for a response, it is sent as-is.  This is
+    /// checked in addition to the built-in exclusions.
+    ///
+    /// # Examples
+    /// ```rust
+    /// # use under::*;
+    /// use under::middleware::Compression;
+    /// # #[tokio::main] async fn main() -> Result<(), anyhow::Error> {
+    /// let mut http = under::http();
+    /// http.with(Compression::new().with_min_size(0).exclude(|response| {
+    ///     response.content_type().is_some_and(|t| t.type_() == mime::IMAGE)
+    /// }));
+    /// http.at("/").get(under::endpoints::simple(|| {
+    ///     Response::empty_200()
+    ///         .with_header("Content-Type", "image/png").unwrap()
+    ///         .with_body(vec![0u8; 64])
+    /// }));
+    /// http.prepare();
+    /// let request = Request::get("/")?.with_header("Accept-Encoding", "gzip")?;
+    /// let response = http.handle(request).await?;
+    /// assert!(response.header("Content-Encoding").is_none());
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn exclude<F>(mut self, predicate: F) -> Self
+    where
+        F: Fn(&Response) -> bool + Send + Sync + 'static,
+    {
+        self.exclude.push(Box::new(predicate));
+        self
+    }
+
+    /// Whether the response should be compressed, according to both the
+    /// built-in exclusions and those added with [`Compression::exclude`].
+    fn compresses(&self, response: &Response) -> bool {
+        let borrowed: &http::Response<Body> = std::borrow::Borrow::borrow(response);
+        let event_stream = response
+            .content_type()
+            .is_some_and(|t| t.essence_str() == mime::TEXT_EVENT_STREAM.essence_str());
+        let encoded = response.header(http::header::CONTENT_ENCODING).is_some();
+        let partial = response.status() == http::StatusCode::PARTIAL_CONTENT;
+        let small = response
+            .header(http::header::CONTENT_LENGTH)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.parse().ok())
+            .or_else(|| http_body::Body::size_hint(borrowed.body()).exact())
+            .is_some_and(|size| size == 0 || size < self.min_size);
+
+        let excluded = self.exclude.iter().any(|exclude| exclude(response));
+
+        !(event_stream || encoded || partial || small || excluded)
+    }
+}
+
+impl std::fmt::Debug for Compression {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Compression")
+            .field("min_size", &self.min_size)
+            .field("level", &self.level.level())
+            .field("exclude", &self.exclude.len())
+            .finish()
+    }
+}
+
+#[async_trait]
+impl Middleware for Compression {
+    async fn apply(
+        self: Pin<&Self>,
+        request: Request,
+        next: Next<'_>,
+    ) -> Result<Response, anyhow::Error> {
+        let encoding = request
+            .header(http::header::ACCEPT_ENCODING)
+            .and_then(|v| v.to_str().ok())
+            .and_then(negotiate);
+        let mut response = next.apply(request).await?;
+
+        if !self.compresses(&response) {
+            return Ok(response);
+        }
+
+        let headers = response.headers_mut();
+        headers.append(
+            http::header::VARY,
+            http::HeaderValue::from_static("Accept-Encoding"),
+        );
+        let Some(encoding) = encoding else {
+            return Ok(response);
+        };
+        headers.insert(
+            http::header::CONTENT_ENCODING,
+            http::HeaderValue::from_static(encoding.name()),
+        );
+        headers.remove(http::header::CONTENT_LENGTH);
+        // The compressed body is no longer byte-for-byte the same, so a
+        // strong validator of the original would be wrong.
+        if let Some(etag) = headers.get(http::header::ETAG) {
+            if !etag.as_bytes().starts_with(b"W/") {
+                let mut weak = b"W/".to_vec();
+                weak.extend_from_slice(etag.as_bytes());
+                if let Ok(weak) = http::HeaderValue::from_bytes(&weak) {
+                    headers.insert(http::header::ETAG, weak);
+                }
+            }
+        }
+
+        let body = response.take_body();
+        response.set_body(compress(body, encoding.encoder(self.level))?);
+
+        Ok(response)
+    }
+}
+
+/// Picks the encoding to use from the value of an `Accept-Encoding` header,
+/// preferring `gzip` over `deflate` when both are equally acceptable.
+fn negotiate(accept: &str) -> Option<Encoding> {
+    let mut wildcard = None;
+    let mut gzip = None;
+    let mut deflate = None;
+
+    for item in accept.split(',') {
+        let mut parts = item.split(';').map(str::trim);
+        let name = parts.next().unwrap_or("");
+        let quality = parts
+            .find_map(|p| p.strip_prefix("q=").or_else(|| p.strip_prefix("Q=")))
+            .map_or(Some(1.0), |q| q.parse::<f32>().ok())
+            .unwrap_or(0.0);
+        if name.eq_ignore_ascii_case("gzip") || name.eq_ignore_ascii_case("x-gzip") {
+            gzip = Some(quality);
+        } else if name.eq_ignore_ascii_case("deflate") {
+            deflate = Some(quality);
+        } else if name == "*" {
+            wildcard = Some(quality);
+        }
+    }
+
+    let gzip = gzip.or(wildcard).unwrap_or(0.0);
+    let deflate = deflate.or(wildcard).unwrap_or(0.0);
+    if gzip > 0.0 && gzip >= deflate {
+        Some(Encoding::Gzip)
+    } else if deflate > 0.0 {
+        Some(Encoding::Deflate)
+    } else {
+        None
+    }
+}
+
+enum Encoder {
+    Gzip(GzEncoder<Vec<u8>>),
+    Deflate(DeflateEncoder<Vec<u8>>),
+}
+
+impl Encoder {
+    /// Compresses the chunk, and flushes it, so that everything written so
+    /// far can be decoded by the client; returns the compressed output.
+    fn chunk(&mut self, chunk: &[u8]) -> std::io::Result<Bytes> {
+        let output = match self {
+            Encoder::Gzip(encoder) => {
+                encoder.write_all(chunk)?;
+                encoder.flush()?;
+                encoder.get_mut()
+            }
+            Encoder::Deflate(encoder) => {
+                encoder.write_all(chunk)?;
+                encoder.flush()?;
+                encoder.get_mut()
+            }
+        };
+        Ok(std::mem::take(output).into())
+    }
+
+    /// Finishes the compressed stream, returning the rest of its output.
+    fn finish(self) -> std::io::Result<Bytes> {
+        match self {
+            Encoder::Gzip(encoder) => encoder.finish().map(Bytes::from),
+            Encoder::Deflate(encoder) => encoder.finish().map(Bytes::from),
+        }
+    }
+}
+
+fn compress(body: Body, mut encoder: Encoder) -> std::io::Result<Body> {
+    use futures::StreamExt;
+
+    match body {
+        Body::Empty => Ok(Body::Empty),
+        Body::Full(bytes) => {
+            let mut output = encoder.chunk(&bytes)?.to_vec();
+            output.extend_from_slice(&encoder.finish()?);
+            Ok(Body::from(output))
+        }
+        stream @ Body::Stream(_) => {
+            let chunks = futures::stream::unfold(Some((stream, encoder)), |state| async move {
+                let (mut stream, mut encoder) = state?;
+                match stream.next().await {
+                    Some(Ok(chunk)) => {
+                        let output = encoder.chunk(&chunk);
+                        Some((output, Some((stream, encoder))))
+                    }
+                    Some(Err(error)) => Some((Err(error), None)),
+                    None => Some((encoder.finish(), None)),
+                }
+            });
+            Ok(Body::wrap_stream(chunks))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_negotiate() {
+        assert_eq!(negotiate("gzip"), Some(Encoding::Gzip));
+        assert_eq!(negotiate("deflate, gzip"), Some(Encoding::Gzip));
+        assert_eq!(negotiate("gzip;q=0.5, deflate"), Some(Encoding::Deflate));
+        assert_eq!(negotiate("gzip;q=0, *"), Some(Encoding::Deflate));
+        assert_eq!(negotiate("*"), Some(Encoding::Gzip));
+        assert_eq!(negotiate("br, identity"), None);
+        assert_eq!(negotiate("*;q=0"), None);
+        assert_eq!(negotiate(""), None);
+    }
+
+    #[test]
+    fn test_exclusions() {
+        let compression = Compression::new().with_min_size(8);
+        let body = || Response::empty_200().with_body("a long enough body");
+        assert!(compression.compresses(&body()));
+        let events = Response::empty_200()
+            .with_header("Content-Type", "text/event-stream")
+            .unwrap()
+            .with_body(Body::channel().1);
+        assert!(!compression.compresses(&events));
+        let encoded = body().with_header("Content-Encoding", "br").unwrap();
+        assert!(!compression.compresses(&encoded));
+        assert!(!compression.compresses(&Response::text("short")));
+        let excluded = Compression::new()
+            .with_min_size(8)
+            .exclude(|response| response.status() == http::StatusCode::OK);
+        assert!(!excluded.compresses(&body()));
+    }
+
+    #[tokio::test]
+    async fn test_streamed_chunks_decode_as_they_arrive() {
+        use flate2::write::GzDecoder;
+        use futures::StreamExt;
+
+        let chunks = futures::stream::iter([Ok::<_, std::io::Error>("data: one\n\n")]);
+        let body = compress(
+            Body::wrap_stream(chunks.chain(futures::stream::pending())),
+            Encoding::Gzip.encoder(flate2::Compression::default()),
+        )
+        .unwrap();
+        let mut body = Box::pin(body);
+        let first = body.next().await.unwrap().unwrap();
+        let mut decoder = GzDecoder::new(vec![]);
+        decoder.write_all(&first).unwrap();
+        decoder.flush().unwrap();
+        assert_eq!(decoder.get_ref(), b"data: one\n\n");
+    }
+}
@@ -0,0 +1,116 @@
+use super::{Middleware, Next};
+use crate::{HttpEntity, Request, Response};
+use std::borrow::Cow;
+use std::pin::Pin;
+
+#[derive(Debug, Clone)]
+/// A middleware for enforcing the content type of requests.
+///
+/// Any request with a body whose content type does not match one of the
+/// allowed types is rejected with an empty 415 (Unsupported Media Type), and
+/// is not passed down the stack.  Requests without a body (e.g. most `GET`
+/// requests) are always passed through.  The allowed types are matched the
+/// same way as [`Request::expect_content_type`] - so parameters are ignored,
+/// and wildcard subtypes (e.g. `text/*`) are allowed.
+///
+/// To only enforce this for a group of routes, use it with a scope endpoint
+/// (see [`crate::endpoints::scope`]).
+///
+/// # Examples
+/// ```rust
+/// # use under::*;
+/// # #[tokio::main] async fn main() -> Result<(), anyhow::Error> {
+/// let mut http = under::http();
+/// http.with(under::middleware::ContentTypeFilter::new(["application/json"]))
+///     .at("/users").post(under::endpoints::simple(Response::empty_204));
+/// http.prepare();
+///
+/// let request = Request::post("/users")?
+///     .with_header("Content-Type", "application/json")?
+///     .with_body("{}");
+/// let response = http.handle(request).await?;
+/// assert_eq!(response.status(), http::StatusCode::NO_CONTENT);
+///
+/// let request = Request::post("/users")?
+///     .with_header("Content-Type", "text/plain")?
+///     .with_body("hello");
+/// let response = http.handle(request).await?;
+/// assert_eq!(response.status(), http::StatusCode::UNSUPPORTED_MEDIA_TYPE);
+/// # Ok(())
+/// # }
+/// ```
+pub struct ContentTypeFilter {
+    allowed: Vec<Cow<'static, str>>,
+}
+
+impl ContentTypeFilter {
+    /// Creates a new content type filter, allowing the given types.
+    pub fn new<I, S>(allowed: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<Cow<'static, str>>,
+    {
+        ContentTypeFilter {
+            allowed: allowed.into_iter().map(Into::into).collect(),
+        }
+    }
+
+    #[must_use]
+    /// Allows the given content type, in addition to the existing ones.
+    ///
+    /// # Examples
+    /// ```rust
+    /// # use under::*;
+    /// let filter = under::middleware::ContentTypeFilter::new(["application/json"])
+    ///     .with_allowed("application/x-www-form-urlencoded");
+    /// ```
+    pub fn with_allowed(mut self, allowed: impl Into<Cow<'static, str>>) -> Self {
+        self.allowed.push(allowed.into());
+        self
+    }
+
+    fn allows(&self, request: &Request) -> bool {
+        if !has_body(request) {
+            return true;
+        }
+
+        request.content_type().is_some_and(|ctype| {
+            self.allowed
+                .iter()
+                .any(|allowed| crate::request::mime_matches(&ctype, allowed))
+        })
+    }
+}
+
+#[async_trait]
+impl Middleware for ContentTypeFilter {
+    async fn apply(
+        self: Pin<&Self>,
+        request: Request,
+        next: Next<'_>,
+    ) -> Result<Response, anyhow::Error> {
+        if self.allows(&request) {
+            next.apply(request).await
+        } else {
+            Ok(Response::empty(http::StatusCode::UNSUPPORTED_MEDIA_TYPE))
+        }
+    }
+}
+
+/// Whether or not the request has a body.  A request has a body if it has a
+/// `Transfer-Encoding`, or a non-zero `Content-Length`; if it has neither
+/// (e.g. it was constructed in memory), the body itself is checked.
+fn has_body(request: &Request) -> bool {
+    use http_body::Body;
+    if request.header(http::header::TRANSFER_ENCODING).is_some() {
+        return true;
+    }
+
+    let borrowed: &http::Request<crate::Body> = std::borrow::Borrow::borrow(request);
+    request
+        .header(http::header::CONTENT_LENGTH)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.parse::<u64>().ok())
+        .or_else(|| borrowed.body().size_hint().exact())
+        .map_or(true, |length| length > 0)
+}
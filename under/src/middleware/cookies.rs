@@ -1,6 +1,6 @@
 use super::{Middleware, Next};
 use crate::{HttpEntity, Request, Response};
-use cookie::{Cookie, CookieJar};
+use cookie::{Cookie, CookieJar, SameSite};
 use std::pin::Pin;
 
 /// Middleware for loading and setting cookies.
@@ -56,9 +56,47 @@ use std::pin::Pin;
 /// Ok(())
 /// }
 /// ```
+///
+/// The middleware can also apply default attributes to every cookie set on
+/// the response, so that cookies have safe settings unless they explicitly
+/// override them:
+///
+/// ```rust
+/// # use under::*;
+/// # use cookie::Cookie;
+/// use under::middleware::{CookieMiddleware, CookieExt};
+///
+/// # #[tokio::main] async fn main() -> Result<(), anyhow::Error> {
+/// async fn handler(req: Request) -> Response {
+///     let mut response = Response::empty_200();
+///     response.add_cookie(Cookie::new("session", "abc"));
+///     response.add_cookie(Cookie::build("theme", "dark").http_only(false).finish());
+///     response
+/// }
+/// let mut http = under::http();
+/// http
+///     .with(CookieMiddleware::new()
+///         .secure(true)
+///         .http_only(true)
+///         .with_same_site(SameSite::Lax)
+///         .with_path("/"))
+///     .at("/foo").get(handler);
+/// http.prepare();
+/// let response = http.handle(Request::get("/foo")?).await?;
+/// let mut cookies = response.headers().get_all("set-cookie").iter()
+///     .map(|v| v.to_str().unwrap())
+///     .collect::<Vec<_>>();
+/// cookies.sort();
+/// assert_eq!(cookies, [
+///     "session=abc; HttpOnly; SameSite=Lax; Secure; Path=/",
+///     "theme=dark; SameSite=Lax; Secure; Path=/",
+/// ]);
+/// # Ok(())
+/// # }
+/// ```
 #[derive(Default, Clone)]
 pub struct CookieMiddleware {
-    _v: (),
+    defaults: CookieOptions,
 }
 
 /// A trait for types that have cookies.  This allows interfacing with their
@@ -166,6 +204,312 @@ pub trait CookieExt: self::sealed::Sealed + Sized {
         self.add_cookie(cookie);
         self
     }
+
+    /// Returns the value of the cookie with the given name, deserialized
+    /// from JSON.  If no cookie jar is set, if no cookie with the given name
+    /// exists, or if its value cannot be deserialized into `T`, it returns
+    /// `None`.  This only reads plain cookies; for signed or private
+    /// cookies, see [`Self::typed_cookie_with`].
+    ///
+    /// # Examples
+    /// ```rust
+    /// # use under::*;
+    /// use under::middleware::{CookieExt, CookieOptions};
+    ///
+    /// #[derive(serde::Serialize, serde::Deserialize, Debug, PartialEq)]
+    /// struct Prefs { theme: String, page_size: u32 }
+    ///
+    /// let prefs = Prefs { theme: "dark".into(), page_size: 50 };
+    /// let mut request = Request::get("/").unwrap();
+    /// request.set_typed_cookie("prefs", &prefs, CookieOptions::new()).unwrap();
+    /// assert_eq!(request.typed_cookie::<Prefs>("prefs"), Some(prefs));
+    /// assert_eq!(request.typed_cookie::<u32>("prefs"), None);
+    /// ```
+    #[cfg(feature = "json")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "json")))]
+    fn typed_cookie<T: serde::de::DeserializeOwned>(&self, name: &str) -> Option<T> {
+        self.typed_cookie_with(name, &CookieOptions::default())
+    }
+
+    /// Returns the value of the cookie with the given name, deserialized
+    /// from JSON, and verified (or decrypted) with the key of the given
+    /// options - the same options the cookie was set with should be used
+    /// here.  A cookie that fails verification is treated as if it does not
+    /// exist.
+    ///
+    /// # Examples
+    /// ```rust
+    /// # use under::*;
+    /// use under::middleware::{CookieExt, CookieOptions};
+    /// # #[cfg(feature = "secure_cookies")] {
+    /// let options = CookieOptions::new().private(&under::Key::generate());
+    /// let mut response = Response::empty_200();
+    /// response.set_typed_cookie("cart", &vec![1, 2, 3], options.clone()).unwrap();
+    /// assert_eq!(response.typed_cookie_with::<Vec<u32>>("cart", &options), Some(vec![1, 2, 3]));
+    /// assert_eq!(response.typed_cookie::<Vec<u32>>("cart"), None);
+    /// # }
+    /// ```
+    #[cfg(feature = "json")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "json")))]
+    fn typed_cookie_with<T: serde::de::DeserializeOwned>(
+        &self,
+        name: &str,
+        options: &CookieOptions,
+    ) -> Option<T> {
+        let cookie = options.get(self.cookies()?, name)?;
+        serde_json::from_str(cookie.value()).ok()
+    }
+
+    /// Serializes the given value as JSON, and adds it to the cookie jar as
+    /// a cookie with the given name and options.  If the options have a key,
+    /// the cookie is signed or encrypted with it.
+    ///
+    /// # Errors
+    /// This errors if the value could not be serialized.
+    ///
+    /// # Examples
+    /// ```rust
+    /// # use under::*;
+    /// use under::middleware::{CookieExt, CookieMiddleware, CookieOptions};
+    /// use std::time::Duration;
+    ///
+    /// # #[tokio::main] async fn main() -> Result<(), anyhow::Error> {
+    /// async fn handler(_: Request) -> Result<Response, anyhow::Error> {
+    ///     let options = CookieOptions::new()
+    ///         .with_path("/")
+    ///         .with_max_age(Duration::from_secs(3600))
+    ///         .http_only(true);
+    ///     let mut response = Response::empty_204();
+    ///     response.set_typed_cookie("ids", &[1, 2], options)?;
+    ///     Ok(response)
+    /// }
+    /// let mut http = under::http();
+    /// http.with(CookieMiddleware::new()).at("/").get(handler);
+    /// http.prepare();
+    /// let response = http.handle(Request::get("/")?).await?;
+    /// assert_eq!(
+    ///     response.header("Set-Cookie").unwrap(),
+    ///     "ids=%5B1%2C2%5D; HttpOnly; Path=/; Max-Age=3600"
+    /// );
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[cfg(feature = "json")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "json")))]
+    fn set_typed_cookie<T: serde::Serialize + ?Sized>(
+        &mut self,
+        name: &str,
+        value: &T,
+        options: CookieOptions,
+    ) -> Result<(), serde_json::Error> {
+        let value = serde_json::to_string(value)?;
+        options.add(self.cookies_mut(), name.to_owned(), value);
+        Ok(())
+    }
+}
+
+#[derive(Clone, Default)]
+/// The attributes of a cookie set with [`CookieExt::set_typed_cookie`].
+///
+/// Attributes that are not set are left off of the cookie.  With the
+/// `secure_cookies` feature, the cookie can also be signed (so that the
+/// client cannot modify it) or made private (so that the client can neither
+/// read nor modify it) with a [`crate::Key`].
+///
+/// # Examples
+/// ```rust
+/// # use under::*;
+/// use under::middleware::CookieOptions;
+/// use std::time::Duration;
+/// let options = CookieOptions::new()
+///     .with_path("/")
+///     .with_domain("example.com")
+///     .with_max_age(Duration::from_secs(86_400))
+///     .with_same_site(SameSite::Lax)
+///     .secure(true)
+///     .http_only(true);
+/// ```
+pub struct CookieOptions {
+    path: Option<String>,
+    domain: Option<String>,
+    max_age: Option<std::time::Duration>,
+    same_site: Option<SameSite>,
+    secure: Option<bool>,
+    http_only: Option<bool>,
+    #[cfg(feature = "secure_cookies")]
+    protection: Option<(Protection, cookie::Key)>,
+}
+
+#[cfg(feature = "secure_cookies")]
+#[derive(Debug, Clone, Copy)]
+enum Protection {
+    Signed,
+    Private,
+}
+
+impl CookieOptions {
+    /// Creates a new set of options, without any attributes.  This is
+    /// provided as an alternative to `Default`.
+    #[must_use]
+    pub fn new() -> Self {
+        CookieOptions::default()
+    }
+
+    /// Sets the `Path` attribute of the cookie.
+    #[must_use]
+    pub fn with_path<P: Into<String>>(mut self, path: P) -> Self {
+        self.path = Some(path.into());
+        self
+    }
+
+    /// Sets the `Domain` attribute of the cookie.
+    #[must_use]
+    pub fn with_domain<D: Into<String>>(mut self, domain: D) -> Self {
+        self.domain = Some(domain.into());
+        self
+    }
+
+    /// Sets the `Max-Age` attribute of the cookie.
+    #[must_use]
+    pub fn with_max_age(mut self, max_age: std::time::Duration) -> Self {
+        self.max_age = Some(max_age);
+        self
+    }
+
+    /// Sets the `SameSite` attribute of the cookie.
+    #[must_use]
+    pub fn with_same_site(mut self, same_site: SameSite) -> Self {
+        self.same_site = Some(same_site);
+        self
+    }
+
+    /// Sets whether or not the cookie has the `Secure` attribute.
+    #[must_use]
+    pub fn secure(mut self, secure: bool) -> Self {
+        self.secure = Some(secure);
+        self
+    }
+
+    /// Sets whether or not the cookie has the `HttpOnly` attribute.
+    #[must_use]
+    pub fn http_only(mut self, http_only: bool) -> Self {
+        self.http_only = Some(http_only);
+        self
+    }
+
+    /// Signs the cookie with the given key, so that its value can be read,
+    /// but not modified, by the client.  This replaces [`Self::private`].
+    #[cfg(feature = "secure_cookies")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "secure_cookies")))]
+    #[must_use]
+    pub fn signed(mut self, key: &cookie::Key) -> Self {
+        self.protection = Some((Protection::Signed, key.clone()));
+        self
+    }
+
+    /// Encrypts the cookie with the given key, so that its value can be
+    /// neither read nor modified by the client.  This replaces
+    /// [`Self::signed`].
+    #[cfg(feature = "secure_cookies")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "secure_cookies")))]
+    #[must_use]
+    pub fn private(mut self, key: &cookie::Key) -> Self {
+        self.protection = Some((Protection::Private, key.clone()));
+        self
+    }
+
+    #[cfg_attr(not(feature = "json"), allow(dead_code))]
+    #[cfg_attr(not(feature = "secure_cookies"), allow(clippy::unused_self))]
+    fn get(&self, jar: &CookieJar, name: &str) -> Option<Cookie<'static>> {
+        #[cfg(feature = "secure_cookies")]
+        match &self.protection {
+            Some((Protection::Signed, key)) => return jar.signed(key).get(name),
+            Some((Protection::Private, key)) => return jar.private(key).get(name),
+            None => {}
+        }
+
+        jar.get(name).cloned()
+    }
+
+    #[cfg_attr(not(feature = "json"), allow(dead_code))]
+    fn add(&self, jar: &mut CookieJar, name: String, value: String) {
+        let cookie = self.build(name, value);
+
+        #[cfg(feature = "secure_cookies")]
+        match &self.protection {
+            Some((Protection::Signed, key)) => return jar.signed_mut(key).add(cookie),
+            Some((Protection::Private, key)) => return jar.private_mut(key).add(cookie),
+            None => {}
+        }
+
+        jar.add(cookie);
+    }
+
+    /// Builds a (plain) cookie with the given name and value, and the
+    /// attributes of these options.
+    pub(super) fn build(&self, name: String, value: String) -> Cookie<'static> {
+        let mut builder = Cookie::build(name, value);
+        if let Some(path) = &self.path {
+            builder = builder.path(path.clone());
+        }
+        if let Some(domain) = &self.domain {
+            builder = builder.domain(domain.clone());
+        }
+        if let Some(max_age) = self.max_age {
+            let max_age =
+                cookie::time::Duration::try_from(max_age).unwrap_or(cookie::time::Duration::MAX);
+            builder = builder.max_age(max_age);
+        }
+        if let Some(same_site) = self.same_site {
+            builder = builder.same_site(same_site);
+        }
+        if let Some(secure) = self.secure {
+            builder = builder.secure(secure);
+        }
+        if let Some(http_only) = self.http_only {
+            builder = builder.http_only(http_only);
+        }
+        builder.finish()
+    }
+}
+
+impl CookieOptions {
+    /// Sets the attributes of these options (other than `Max-Age`) on the
+    /// given cookie, for each attribute the cookie does not already have.
+    fn apply_defaults(&self, mut cookie: Cookie<'static>) -> Cookie<'static> {
+        if let (None, Some(path)) = (cookie.path(), &self.path) {
+            cookie.set_path(path.clone());
+        }
+        if let (None, Some(domain)) = (cookie.domain(), &self.domain) {
+            cookie.set_domain(domain.clone());
+        }
+        if cookie.same_site().is_none() {
+            cookie.set_same_site(self.same_site);
+        }
+        if cookie.secure().is_none() {
+            cookie.set_secure(self.secure);
+        }
+        if cookie.http_only().is_none() {
+            cookie.set_http_only(self.http_only);
+        }
+        cookie
+    }
+}
+
+impl std::fmt::Debug for CookieOptions {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut debug = f.debug_struct("CookieOptions");
+        debug
+            .field("path", &self.path)
+            .field("domain", &self.domain)
+            .field("max_age", &self.max_age)
+            .field("same_site", &self.same_site)
+            .field("secure", &self.secure)
+            .field("http_only", &self.http_only);
+        #[cfg(feature = "secure_cookies")]
+        debug.field("protection", &self.protection.as_ref().map(|(p, _)| p));
+        debug.finish()
+    }
 }
 
 impl self::sealed::Sealed for Request {}
@@ -195,7 +539,9 @@ mod sealed {
 
 impl std::fmt::Debug for CookieMiddleware {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        f.debug_struct("CookieMiddleware").finish()
+        f.debug_struct("CookieMiddleware")
+            .field("defaults", &self.defaults)
+            .finish()
     }
 }
 
@@ -204,7 +550,44 @@ impl CookieMiddleware {
     /// to `Default`.
     #[must_use]
     pub fn new() -> Self {
-        Self { _v: () }
+        Self::default()
+    }
+
+    /// Sets the default `Path` attribute of cookies set on responses.
+    #[must_use]
+    pub fn with_path<P: Into<String>>(mut self, path: P) -> Self {
+        self.defaults = self.defaults.with_path(path);
+        self
+    }
+
+    /// Sets the default `Domain` attribute of cookies set on responses.
+    #[must_use]
+    pub fn with_domain<D: Into<String>>(mut self, domain: D) -> Self {
+        self.defaults = self.defaults.with_domain(domain);
+        self
+    }
+
+    /// Sets the default `SameSite` attribute of cookies set on responses.
+    #[must_use]
+    pub fn with_same_site(mut self, same_site: SameSite) -> Self {
+        self.defaults = self.defaults.with_same_site(same_site);
+        self
+    }
+
+    /// Sets whether or not cookies set on responses have the `Secure`
+    /// attribute by default.
+    #[must_use]
+    pub fn secure(mut self, secure: bool) -> Self {
+        self.defaults = self.defaults.secure(secure);
+        self
+    }
+
+    /// Sets whether or not cookies set on responses have the `HttpOnly`
+    /// attribute by default.
+    #[must_use]
+    pub fn http_only(mut self, http_only: bool) -> Self {
+        self.defaults = self.defaults.http_only(http_only);
+        self
     }
 }
 
@@ -235,6 +618,7 @@ impl Middleware for CookieMiddleware {
         if let Some(jar) = result_jar {
             let headers = response.headers_mut();
             for cookie in jar.delta() {
+                let cookie = self.defaults.apply_defaults(cookie.clone());
                 if let Ok(cookie) = cookie.encoded().to_string().try_into() {
                     headers.append("Set-Cookie", cookie);
                 }
@@ -0,0 +1,168 @@
+use super::{Middleware, Next};
+use crate::{Request, Response};
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+#[async_trait]
+/// Decides whether a feature is enabled for a given request.
+///
+/// This is used by the [`FeatureGate`] middleware.  This can decide based on
+/// anything in the request - the path, a header, the tenant, etc.  Since it
+/// is asynchronous, it may also consult an external service; however, as
+/// this is called on every request that passes through the gate, it is
+/// suggested that such a provider caches its results.
+///
+/// This is implemented for any `Fn(&Request) -> bool`, and for
+/// [`AtomicFlag`].
+pub trait FlagProvider: Send + Sync + 'static {
+    /// Returns whether or not the feature is enabled for the given request.
+    async fn is_enabled(&self, request: &Request) -> bool;
+}
+
+#[async_trait]
+impl<F> FlagProvider for F
+where
+    F: Fn(&Request) -> bool + Send + Sync + 'static,
+{
+    async fn is_enabled(&self, request: &Request) -> bool {
+        self(request)
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+/// An in-process feature flag.
+///
+/// This is a flag that is either enabled or disabled for every request, and
+/// which can be toggled at runtime.  Clones of the flag share the same
+/// state, so a clone can be kept around to toggle the flag after the
+/// original has been given to a [`FeatureGate`].
+///
+/// # Examples
+/// ```rust
+/// use under::middleware::AtomicFlag;
+/// let flag = AtomicFlag::new(false);
+/// let clone = flag.clone();
+/// clone.enable();
+/// assert!(flag.is_set());
+/// ```
+pub struct AtomicFlag(Arc<AtomicBool>);
+
+impl AtomicFlag {
+    #[must_use]
+    /// Creates a new flag, with the given initial state.
+    pub fn new(enabled: bool) -> Self {
+        AtomicFlag(Arc::new(AtomicBool::new(enabled)))
+    }
+
+    /// Sets the state of the flag.
+    pub fn set(&self, enabled: bool) {
+        self.0.store(enabled, Ordering::Relaxed);
+    }
+
+    /// Enables the flag.
+    pub fn enable(&self) {
+        self.set(true);
+    }
+
+    /// Disables the flag.
+    pub fn disable(&self) {
+        self.set(false);
+    }
+
+    #[must_use]
+    /// Returns whether or not the flag is enabled.
+    pub fn is_set(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+#[async_trait]
+impl FlagProvider for AtomicFlag {
+    async fn is_enabled(&self, _: &Request) -> bool {
+        self.is_set()
+    }
+}
+
+/// A middleware for gating routes behind a feature flag.
+///
+/// Each request is checked against the [`FlagProvider`]; if the feature is
+/// disabled for that request, it is responded to with an empty
+/// response (a 404 by default, so that the feature cannot be discovered;
+/// see [`FeatureGate::with_status`]), and is not passed down the stack.
+/// To only gate a group of routes, use this with a scope endpoint (see
+/// [`crate::endpoints::scope`]).
+///
+/// # Examples
+/// ```rust
+/// # use under::*;
+/// use under::middleware::{AtomicFlag, FeatureGate};
+/// # #[tokio::main] async fn main() -> Result<(), anyhow::Error> {
+/// let flag = AtomicFlag::new(false);
+/// let mut http = under::http();
+/// http.at("/beta").get(under::endpoints::scope()
+///     .with(FeatureGate::new(flag.clone()))
+///     .then(under::endpoints::simple(Response::empty_204)));
+/// http.prepare();
+/// let response = http.handle(Request::get("/beta")?).await?;
+/// assert_eq!(response.status(), http::StatusCode::NOT_FOUND);
+/// flag.enable();
+/// let response = http.handle(Request::get("/beta")?).await?;
+/// assert_eq!(response.status(), http::StatusCode::NO_CONTENT);
+/// # Ok(())
+/// # }
+/// ```
+pub struct FeatureGate {
+    provider: Box<dyn FlagProvider>,
+    status: http::StatusCode,
+}
+
+impl FeatureGate {
+    /// Creates a new feature gate, using the given provider to decide
+    /// whether the feature is enabled.
+    ///
+    /// # Examples
+    /// ```rust
+    /// # use under::*;
+    /// use under::middleware::FeatureGate;
+    /// let gate = FeatureGate::new(|request: &Request| request.header("X-Beta").is_some());
+    /// ```
+    pub fn new<P: FlagProvider>(provider: P) -> Self {
+        FeatureGate {
+            provider: Box::new(provider),
+            status: http::StatusCode::NOT_FOUND,
+        }
+    }
+
+    #[must_use]
+    /// Sets the status of the response for requests for which the feature is
+    /// disabled.  This defaults to a 404; a 403 may be more appropriate for
+    /// features that are known to exist.
+    pub fn with_status<S: Into<http::StatusCode>>(mut self, status: S) -> Self {
+        self.status = status.into();
+        self
+    }
+}
+
+impl std::fmt::Debug for FeatureGate {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("FeatureGate")
+            .field("status", &self.status)
+            .finish_non_exhaustive()
+    }
+}
+
+#[async_trait]
+impl Middleware for FeatureGate {
+    async fn apply(
+        self: Pin<&Self>,
+        request: Request,
+        next: Next<'_>,
+    ) -> Result<Response, anyhow::Error> {
+        if self.provider.is_enabled(&request).await {
+            next.apply(request).await
+        } else {
+            Ok(Response::empty(self.status))
+        }
+    }
+}
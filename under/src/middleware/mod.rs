@@ -14,15 +14,51 @@
 //! # }
 //! ```
 
+mod access_log;
+mod alt_svc;
+mod api_key;
+mod body_size;
+#[cfg(feature = "compression")]
+mod compression;
+mod content_type;
 #[cfg(feature = "cookie")]
 mod cookies;
+mod feature_gate;
+mod server_timing;
+#[cfg(feature = "sessions")]
+mod session;
 mod state;
+mod strip_forwarded;
+mod tenant;
+mod timeout;
+mod timing;
 mod trace;
+pub(crate) use self::access_log::civil_from_days;
+pub use self::access_log::{AccessLog, AccessLogFormat, AccessLogSink, FileSink, StdoutSink};
+pub use self::alt_svc::AltSvc;
+pub use self::api_key::{constant_time_eq, ApiKeyAuth, KeyValidator, Principal, StaticKeys};
+pub(crate) use self::body_size::count_body;
+pub use self::body_size::{BodyCounter, BodySize};
+#[cfg(feature = "compression")]
+#[cfg_attr(docsrs, doc(cfg(feature = "compression")))]
+pub use self::compression::Compression;
+pub use self::content_type::ContentTypeFilter;
 #[cfg(feature = "cookie")]
-#[cfg_attr(nightly, doc(cfg(feature = "cookie")))]
-pub use self::cookies::{CookieExt, CookieMiddleware};
+#[cfg_attr(docsrs, doc(cfg(feature = "cookie")))]
+pub use self::cookies::{CookieExt, CookieMiddleware, CookieOptions};
+pub use self::feature_gate::{AtomicFlag, FeatureGate, FlagProvider};
+pub use self::server_timing::ServerTiming;
+#[cfg(feature = "sessions")]
+#[cfg_attr(docsrs, doc(cfg(feature = "sessions")))]
+pub use self::session::{
+    MemorySessionStore, Session, SessionMiddleware, SessionRecord, SessionStore,
+};
 pub use self::state::{State, StateMiddleware};
-pub use self::trace::TraceMiddleware;
+pub use self::strip_forwarded::StripForwarded;
+pub use self::tenant::{Tenant, TenantMiddleware};
+pub use self::timeout::{Deadline, Timeout};
+pub use self::timing::{Timing, Timings};
+pub use self::trace::{Sampler, TraceMiddleware};
 use crate::{Endpoint, Request, Response};
 use std::fmt::Debug;
 use std::pin::Pin;
@@ -53,7 +89,7 @@ pub struct Next<'a> {
 /// calling [`Next::apply`] with the modified request; then, take the resulting
 /// [`Response`], potentially modifying it, before returning.  However, since
 /// every layer of the stack is fallible, it must be able to handle errors.
-pub trait Middleware: Debug + Send + Sync + 'static {
+pub trait Middleware: std::any::Any + Debug + Send + Sync + 'static {
     #[must_use]
     /// Handles the given request, returning a response.  The next parameter
     /// contains the information on how to process everything after the current
@@ -63,6 +99,71 @@ pub trait Middleware: Debug + Send + Sync + 'static {
         request: Request,
         next: Next<'_>,
     ) -> Result<Response, anyhow::Error>;
+
+    /// Writes a short description of the middleware.  This is what the
+    /// middleware is shown as in the debug output of the router, and in the
+    /// [`Timings`] of a profiled request.  By default, this is the name of
+    /// the type.
+    ///
+    /// # Errors
+    /// This only fails if writing to the formatter fails.
+    ///
+    /// # Examples
+    /// ```rust
+    /// # use under::*;
+    /// # use std::pin::Pin;
+    /// #[derive(Debug)]
+    /// struct Limit(u32);
+    ///
+    /// #[async_trait::async_trait]
+    /// impl Middleware for Limit {
+    ///     async fn apply(
+    ///         self: Pin<&Self>,
+    ///         request: Request,
+    ///         next: under::middleware::Next<'_>,
+    ///     ) -> Result<Response, anyhow::Error> {
+    ///         next.apply(request).await
+    ///     }
+    ///
+    ///     fn describe(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    ///         write!(f, "limit({})", self.0)
+    ///     }
+    /// }
+    ///
+    /// let mut http = under::http();
+    /// http.with_named("limit", Limit(10));
+    /// assert!(format!("{http:?}").contains("middleware: [limit: limit(10)]"));
+    /// ```
+    fn describe(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", std::any::type_name::<Self>())
+    }
+}
+
+/// The middleware to skip for a request, by type (see [`Request::skip`]).
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Skipped(pub(crate) Vec<std::any::TypeId>);
+
+impl Skipped {
+    fn contains(&self, middleware: &dyn Middleware) -> bool {
+        // Through the `Any` supertrait's vtable, rather than by upcasting to
+        // `&dyn Any`, which needs a newer compiler.
+        self.0.contains(&std::any::Any::type_id(middleware))
+    }
+}
+
+/// Displays a middleware using its [`Middleware::describe`].
+pub(crate) struct Describe<'a>(pub(crate) &'a dyn Middleware);
+
+impl std::fmt::Display for Describe<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.0.describe(f)
+    }
+}
+
+impl Debug for Describe<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.0.describe(f)
+    }
 }
 
 impl<'a> Next<'a> {
@@ -89,18 +190,49 @@ impl<'a> Next<'a> {
     /// Note that it is entirely possible for this function to not return, if
     /// something down the line panics.
     ///
+    /// If the request contains [`Timings`] (see [`crate::Router::profile`]),
+    /// the duration of the next middleware (or the endpoint) is recorded into
+    /// it.
+    ///
+    /// Any middleware that the request has been marked to skip (see
+    /// [`Request::skip`]) is passed over.
+    ///
     /// # Errors
     /// This errors if any middleware below this one errors, or if the endpoint
     /// itself errors.
     pub async fn apply(self, request: Request) -> Result<Response, anyhow::Error> {
-        if let Some((current, next)) = self.middleware.split_first() {
+        let timings = request.ext::<Timings>().cloned();
+        let start = std::time::Instant::now();
+
+        let mut middleware = self.middleware;
+        if let Some(skipped) = request.ext::<Skipped>() {
+            while let Some((current, next)) = middleware.split_first() {
+                if !skipped.contains(&**current) {
+                    break;
+                }
+                middleware = next;
+            }
+        }
+
+        if let Some((current, next)) = middleware.split_first() {
             let new = Next {
                 middleware: next,
                 endpoint: self.endpoint,
             };
-            current.as_ref().apply(request, new).await
+            let result = current.as_ref().apply(request, new).await;
+            if let Some(timings) = timings {
+                let current: &dyn Middleware = &**current;
+                timings.record(Describe(current).to_string(), start.elapsed());
+            }
+            result
         } else {
-            self.endpoint.apply(request).await
+            let result = self.endpoint.apply(request).await;
+            if let Some(timings) = timings {
+                // Not the endpoint's `Debug`, which may expose its contents
+                // (and the timings may be sent to the client).
+                timings.record("endpoint", start.elapsed());
+            }
+            result
         }
     }
 }
@@ -0,0 +1,112 @@
+use super::{Middleware, Next, Timing, Timings};
+use crate::{Request, Response};
+use std::fmt::Write;
+use std::pin::Pin;
+
+#[derive(Default, Debug, Clone)]
+/// A middleware for exposing request timings through the [`Server-Timing`]
+/// header.
+///
+/// This takes all of the timings recorded into the request's [`Timings`] -
+/// both those automatically recorded for each middleware and the endpoint,
+/// and those added with [`Request::timing`] - and writes them to the
+/// `Server-Timing` header of the response, which is then shown in the
+/// developer tools of most browsers.  If the request does not have any
+/// [`Timings`] (i.e. the router is not profiling), then this inserts them,
+/// which enables profiling for all middleware after this one.
+///
+/// Since everything that comes before this middleware has not yet completed
+/// by the time the header is written, it is suggested to place this
+/// middleware as early in the stack as possible.
+///
+/// [`Server-Timing`]: https://www.w3.org/TR/server-timing/
+///
+/// # Examples
+/// ```rust
+/// # use under::*;
+/// use std::time::Duration;
+///
+/// async fn handler(request: Request) -> Response {
+///     request.timing("db", Duration::from_millis(12));
+///     Response::empty_204()
+/// }
+///
+/// # #[tokio::main] async fn main() -> Result<(), anyhow::Error> {
+/// let mut http = under::http();
+/// http.with(under::middleware::ServerTiming::new())
+///     .at("/").get(handler);
+/// http.prepare();
+/// let response = http.handle(Request::get("/")?).await?;
+/// let header = response.header("Server-Timing").unwrap().to_str()?;
+/// assert!(header.starts_with("db;dur=12.000, "));
+/// # Ok(())
+/// # }
+/// ```
+pub struct ServerTiming {
+    _v: (),
+}
+
+impl ServerTiming {
+    #[must_use]
+    /// Creates a new server timing middleware.  This is provided as an
+    /// alternative to `Default`.
+    pub fn new() -> Self {
+        ServerTiming::default()
+    }
+}
+
+#[async_trait]
+impl Middleware for ServerTiming {
+    async fn apply(
+        self: Pin<&Self>,
+        mut request: Request,
+        next: Next<'_>,
+    ) -> Result<Response, anyhow::Error> {
+        let timings = if let Some(timings) = request.ext::<Timings>() {
+            timings.clone()
+        } else {
+            let timings = Timings::default();
+            request.set_ext(timings.clone());
+            timings
+        };
+
+        let mut response = next.apply(request).await?;
+        let header = timings
+            .entries()
+            .iter()
+            .map(metric)
+            .collect::<Vec<_>>()
+            .join(", ");
+
+        if !header.is_empty() {
+            if let Ok(value) = header.try_into() {
+                response.headers_mut().append("Server-Timing", value);
+            }
+        }
+
+        Ok(response)
+    }
+}
+
+/// Formats a single timing as a `Server-Timing` metric.  Metric names must
+/// be tokens, so any character that is not allowed in a token is replaced;
+/// if that happens, the original name is included as the description.
+fn metric(timing: &Timing) -> String {
+    let name = timing
+        .name
+        .chars()
+        .map(|c| if is_token(c) { c } else { '_' })
+        .collect::<String>();
+    let mut buffer = format!("{name};dur={:.3}", timing.duration.as_secs_f64() * 1000.0);
+
+    if name != timing.name {
+        let desc = timing.name.replace('\\', "\\\\").replace('"', "\\\"");
+        write!(buffer, ";desc=\"{desc}\"").unwrap();
+    }
+
+    buffer
+}
+
+fn is_token(c: char) -> bool {
+    c.is_ascii_alphanumeric() || "!#$%&'*+-.^_`|~".contains(c)
+}
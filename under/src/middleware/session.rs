@@ -0,0 +1,572 @@
+use super::{CookieOptions, Middleware, Next};
+use crate::{HttpEntity, Request, Response};
+use cookie::{Cookie, SameSite};
+use std::collections::{HashMap, HashSet};
+use std::pin::Pin;
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::{Duration, SystemTime};
+
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+/// The stored data of a session.
+///
+/// This is what a [`SessionStore`] persists for each session ID.
+pub struct SessionRecord {
+    /// The values of the session, by key.
+    pub data: HashMap<String, serde_json::Value>,
+    /// When the session was created.  This is used for the absolute
+    /// timeout.
+    pub created_at: SystemTime,
+    /// When the session was last used.  This is used for the idle timeout.
+    pub accessed_at: SystemTime,
+}
+
+impl SessionRecord {
+    fn new(now: SystemTime) -> Self {
+        SessionRecord {
+            data: HashMap::new(),
+            created_at: now,
+            accessed_at: now,
+        }
+    }
+}
+
+#[async_trait]
+/// A storage backend for sessions.
+///
+/// A store maps session IDs to their [`SessionRecord`]s.  The
+/// [`SessionMiddleware`] handles generating IDs, expiring sessions, and
+/// deciding when a session needs to be saved; the store only has to load,
+/// save, and remove records.  [`MemorySessionStore`] is provided for
+/// development and single-process deployments.
+pub trait SessionStore: Send + Sync + 'static {
+    /// Loads the record for the given session ID, if it exists.
+    async fn load(&self, id: &str) -> Result<Option<SessionRecord>, anyhow::Error>;
+    /// Saves the record for the given session ID, replacing any existing
+    /// record.
+    async fn store(&self, id: &str, record: &SessionRecord) -> Result<(), anyhow::Error>;
+    /// Removes the record for the given session ID, if it exists.
+    async fn destroy(&self, id: &str) -> Result<(), anyhow::Error>;
+}
+
+#[derive(Debug, Clone, Default)]
+/// A session store that keeps sessions in memory.
+///
+/// All clones of the store share the same sessions.  Sessions are lost when
+/// the process exits, and are not shared between processes.
+pub struct MemorySessionStore {
+    sessions: Arc<Mutex<HashMap<String, SessionRecord>>>,
+}
+
+impl MemorySessionStore {
+    /// Creates a new, empty store.  This is provided as an alternative to
+    /// `Default`.
+    #[must_use]
+    pub fn new() -> Self {
+        MemorySessionStore::default()
+    }
+
+    /// Returns the number of sessions in the store.
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.lock().len()
+    }
+
+    /// Returns whether or not the store is empty.
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.lock().is_empty()
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<String, SessionRecord>> {
+        self.sessions.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+#[async_trait]
+impl SessionStore for MemorySessionStore {
+    async fn load(&self, id: &str) -> Result<Option<SessionRecord>, anyhow::Error> {
+        Ok(self.lock().get(id).cloned())
+    }
+
+    async fn store(&self, id: &str, record: &SessionRecord) -> Result<(), anyhow::Error> {
+        self.lock().insert(id.to_owned(), record.clone());
+        Ok(())
+    }
+
+    async fn destroy(&self, id: &str) -> Result<(), anyhow::Error> {
+        self.lock().remove(id);
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone)]
+/// The session of a request.
+///
+/// This is inserted into the request by the [`SessionMiddleware`], and can
+/// be retrieved with [`Request::session`].  All clones of a session refer to
+/// the same underlying session, so it can be modified through a shared
+/// reference; any changes are saved once the response has been generated.
+/// Values are stored as JSON.
+pub struct Session(Arc<Mutex<SessionState>>);
+
+#[derive(Debug)]
+struct SessionState {
+    id: Option<String>,
+    record: SessionRecord,
+    rotate_on: Arc<HashSet<String>>,
+    changed: bool,
+    regenerate: bool,
+    destroyed: bool,
+}
+
+impl Session {
+    fn new(id: Option<String>, record: SessionRecord, rotate_on: Arc<HashSet<String>>) -> Self {
+        Session(Arc::new(Mutex::new(SessionState {
+            id,
+            record,
+            rotate_on,
+            changed: false,
+            regenerate: false,
+            destroyed: false,
+        })))
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, SessionState> {
+        self.0.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Returns the ID of the session, as sent by the client.  This is `None`
+    /// for new sessions, which are only given an ID once the response has
+    /// been generated (and only if they have any values).
+    #[must_use]
+    pub fn id(&self) -> Option<String> {
+        self.lock().id.clone()
+    }
+
+    /// Returns whether or not this session is new - i.e., the client did not
+    /// send a valid, unexpired session ID.
+    #[must_use]
+    pub fn is_new(&self) -> bool {
+        self.lock().id.is_none()
+    }
+
+    /// Returns when the session was created.
+    #[must_use]
+    pub fn created_at(&self) -> SystemTime {
+        self.lock().record.created_at
+    }
+
+    /// Returns the value of the given key, deserialized from JSON.  If the
+    /// key does not exist, or its value cannot be deserialized into `T`, it
+    /// returns `None`.
+    #[must_use]
+    pub fn get<T: serde::de::DeserializeOwned>(&self, key: &str) -> Option<T> {
+        let value = self.lock().record.data.get(key).cloned()?;
+        serde_json::from_value(value).ok()
+    }
+
+    /// Sets the given key to the given value, serialized as JSON.
+    ///
+    /// If the key is one of the keys the session rotates on (see
+    /// [`SessionMiddleware::rotate_on`]), and the value differs from its
+    /// existing value, the session ID is regenerated.
+    ///
+    /// # Errors
+    /// This errors if the value could not be serialized.
+    pub fn insert<T: serde::Serialize + ?Sized>(
+        &self,
+        key: &str,
+        value: &T,
+    ) -> Result<(), serde_json::Error> {
+        let value = serde_json::to_value(value)?;
+        let mut state = self.lock();
+        let previous = state.record.data.insert(key.to_owned(), value.clone());
+        if previous.as_ref() != Some(&value) {
+            state.change(key);
+        }
+        Ok(())
+    }
+
+    /// Removes the given key from the session.  If the key is one of the
+    /// keys the session rotates on, and it existed, the session ID is
+    /// regenerated.
+    pub fn remove(&self, key: &str) {
+        let mut state = self.lock();
+        if state.record.data.remove(key).is_some() {
+            state.change(key);
+        }
+    }
+
+    /// Removes all values from the session, keeping its ID.
+    pub fn clear(&self) {
+        let mut state = self.lock();
+        if !state.record.data.is_empty() {
+            state.record.data.clear();
+            state.changed = true;
+        }
+    }
+
+    /// Regenerates the ID of the session, keeping its values.  The old ID is
+    /// invalidated once the response has been generated, and the client is
+    /// sent the new one.  This should be done whenever the privileges of the
+    /// session change (e.g. on login), so that an attacker who planted a
+    /// session ID on the client cannot use it afterwards.
+    pub fn regenerate_id(&self) {
+        self.lock().regenerate = true;
+    }
+
+    /// Destroys the session - removing it from the store, and removing the
+    /// cookie from the client.  This is useful for logging out.
+    pub fn destroy(&self) {
+        let mut state = self.lock();
+        state.destroyed = true;
+        state.record.data.clear();
+    }
+}
+
+impl SessionState {
+    fn change(&mut self, key: &str) {
+        self.changed = true;
+        if self.rotate_on.contains(key) {
+            self.regenerate = true;
+        }
+    }
+}
+
+/// Middleware for cookie-based sessions.
+///
+/// On every request, this loads the session identified by the session
+/// cookie from the store, and inserts it into the request as a [`Session`]
+/// (see [`Request::session`]).  Once the response has been generated, any
+/// changes to the session are saved, and a cookie is set if the session has
+/// a new ID.  A new session that never has any values set is never saved.
+///
+/// To protect against session fixation, session IDs are only ever
+/// generated by the server: a session ID that does not exist in the store
+/// is ignored, rather than adopted.  The ID is regenerated on request (see
+/// [`Session::regenerate_id`]), and automatically whenever one of the keys
+/// given to [`SessionMiddleware::rotate_on`] changes.  Sessions can also
+/// expire after a period of inactivity, or a period after they were
+/// created, regardless of activity.
+///
+/// The cookie defaults to `under.sid`, with the `HttpOnly` attribute, a
+/// `SameSite` of `Lax`, and a `Path` of `/`.
+///
+/// # Examples
+/// ```rust
+/// # use under::*;
+/// use under::middleware::{MemorySessionStore, SessionMiddleware};
+/// use std::time::Duration;
+///
+/// async fn login(request: Request) -> Result<Response, anyhow::Error> {
+///     let session = request.session().unwrap();
+///     session.insert("user_id", &42)?;
+///     Ok(Response::empty_204())
+/// }
+///
+/// async fn whoami(request: Request) -> Response {
+///     let user = request.session().and_then(|s| s.get::<u64>("user_id"));
+///     Response::text(format!("{user:?}"))
+/// }
+///
+/// # #[tokio::main] async fn main() -> Result<(), anyhow::Error> {
+/// let store = MemorySessionStore::new();
+/// let mut http = under::http();
+/// http.with(SessionMiddleware::new(store.clone())
+///         .rotate_on(["user_id"])
+///         .with_idle_timeout(Duration::from_secs(30 * 60))
+///         .with_absolute_timeout(Duration::from_secs(24 * 60 * 60)));
+/// http.at("/login").post(login);
+/// http.at("/whoami").get(whoami);
+/// http.prepare();
+///
+/// let response = http.handle(Request::post("/login")?).await?;
+/// let cookie = response.header("Set-Cookie").unwrap().to_str()?;
+/// assert!(cookie.starts_with("under.sid="));
+/// assert!(cookie.ends_with("; HttpOnly; SameSite=Lax; Path=/"));
+/// let cookie = cookie.split(';').next().unwrap().to_owned();
+/// assert_eq!(store.len(), 1);
+///
+/// let request = Request::get("/whoami")?.with_header("Cookie", &cookie)?;
+/// let mut response = http.handle(request).await?;
+/// assert_eq!(response.data(512).into_text().await?, "Some(42)");
+///
+/// // session IDs that the server did not generate are never used.
+/// let request = Request::get("/whoami")?.with_header("Cookie", "under.sid=planted")?;
+/// let mut response = http.handle(request).await?;
+/// assert_eq!(response.data(512).into_text().await?, "None");
+/// # Ok(())
+/// # }
+/// ```
+pub struct SessionMiddleware<S> {
+    store: S,
+    cookie_name: String,
+    cookie: CookieOptions,
+    idle_timeout: Option<Duration>,
+    absolute_timeout: Option<Duration>,
+    rotate_on: Arc<HashSet<String>>,
+}
+
+impl<S: SessionStore> SessionMiddleware<S> {
+    /// Creates a new session middleware, storing sessions in the given
+    /// store.
+    pub fn new(store: S) -> Self {
+        SessionMiddleware {
+            store,
+            cookie_name: "under.sid".to_owned(),
+            cookie: CookieOptions::new()
+                .with_path("/")
+                .with_same_site(SameSite::Lax)
+                .http_only(true),
+            idle_timeout: None,
+            absolute_timeout: None,
+            rotate_on: Arc::default(),
+        }
+    }
+
+    /// Sets the name of the session cookie.
+    #[must_use]
+    pub fn with_cookie_name<N: Into<String>>(mut self, name: N) -> Self {
+        self.cookie_name = name.into();
+        self
+    }
+
+    /// Sets the attributes of the session cookie.  This replaces the
+    /// defaults entirely; any key in the options is ignored, as session IDs
+    /// are random.
+    #[must_use]
+    pub fn with_cookie_options(mut self, options: CookieOptions) -> Self {
+        self.cookie = options;
+        self
+    }
+
+    /// Expires sessions that have not been used for the given duration.
+    /// Since this requires tracking when a session was last used, sessions
+    /// are saved on every request once this is set.
+    ///
+    /// # Examples
+    /// ```rust
+    /// # use under::*;
+    /// use under::middleware::{MemorySessionStore, SessionMiddleware};
+    /// use std::time::Duration;
+    ///
+    /// # #[tokio::main] async fn main() -> Result<(), anyhow::Error> {
+    /// let mut http = under::http();
+    /// http.with(SessionMiddleware::new(MemorySessionStore::new())
+    ///     .with_idle_timeout(Duration::from_millis(50)));
+    /// http.at("/").post(|request: Request| async move {
+    ///     let session = request.session().unwrap();
+    ///     let is_new = session.is_new();
+    ///     session.insert("seen", &true)?;
+    ///     Ok::<_, anyhow::Error>(Response::text(is_new.to_string()))
+    /// });
+    /// http.prepare();
+    ///
+    /// let response = http.handle(Request::post("/")?).await?;
+    /// let cookie = response.header("Set-Cookie").unwrap().to_str()?;
+    /// let cookie = cookie.split(';').next().unwrap().to_owned();
+    /// let request = Request::post("/")?.with_header("Cookie", &cookie)?;
+    /// let mut response = http.handle(request).await?;
+    /// assert_eq!(response.data(512).into_text().await?, "false");
+    /// tokio::time::sleep(Duration::from_millis(100)).await;
+    /// let request = Request::post("/")?.with_header("Cookie", &cookie)?;
+    /// let mut response = http.handle(request).await?;
+    /// assert_eq!(response.data(512).into_text().await?, "true");
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[must_use]
+    pub fn with_idle_timeout(mut self, timeout: Duration) -> Self {
+        self.idle_timeout = Some(timeout);
+        self
+    }
+
+    /// Expires sessions the given duration after they were created,
+    /// regardless of whether they are still being used.  Regenerating the
+    /// ID of a session does not reset this.
+    #[must_use]
+    pub fn with_absolute_timeout(mut self, timeout: Duration) -> Self {
+        self.absolute_timeout = Some(timeout);
+        self
+    }
+
+    /// Regenerates the session ID whenever a value of one of the given keys
+    /// is changed or removed.  These should be the keys that determine the
+    /// privileges of the session (e.g. the user ID, or roles), so that the
+    /// ID changes on login, logout, and privilege escalation.
+    ///
+    /// # Examples
+    /// ```rust
+    /// # use under::*;
+    /// use under::middleware::{MemorySessionStore, SessionMiddleware};
+    ///
+    /// # #[tokio::main] async fn main() -> Result<(), anyhow::Error> {
+    /// let store = MemorySessionStore::new();
+    /// let mut http = under::http();
+    /// http.with(SessionMiddleware::new(store.clone()).rotate_on(["role"]));
+    /// http.at("/visit").post(|request: Request| async move {
+    ///     request.session().unwrap().insert("visited", &true)?;
+    ///     Ok::<_, anyhow::Error>(Response::empty_204())
+    /// });
+    /// http.at("/sudo").post(|request: Request| async move {
+    ///     request.session().unwrap().insert("role", "admin")?;
+    ///     Ok::<_, anyhow::Error>(Response::empty_204())
+    /// });
+    /// http.prepare();
+    ///
+    /// let response = http.handle(Request::post("/visit")?).await?;
+    /// let first = response.header("Set-Cookie").unwrap().to_str()?;
+    /// let first = first.split(';').next().unwrap().to_owned();
+    /// let request = Request::post("/sudo")?.with_header("Cookie", &first)?;
+    /// let response = http.handle(request).await?;
+    /// let second = response.header("Set-Cookie").unwrap().to_str()?;
+    /// let second = second.split(';').next().unwrap().to_owned();
+    /// assert_ne!(first, second);
+    /// // the old ID no longer exists.
+    /// assert_eq!(store.len(), 1);
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[must_use]
+    pub fn rotate_on<I, K>(mut self, keys: I) -> Self
+    where
+        I: IntoIterator<Item = K>,
+        K: Into<String>,
+    {
+        self.rotate_on = Arc::new(keys.into_iter().map(Into::into).collect());
+        self
+    }
+
+    fn cookie_id(&self, request: &Request) -> Option<String> {
+        request
+            .headers()
+            .get_all(http::header::COOKIE)
+            .into_iter()
+            .filter_map(|h| h.to_str().ok())
+            .flat_map(|h| h.split(';'))
+            .filter_map(|h| Cookie::parse_encoded(h.trim()).ok())
+            .find(|cookie| cookie.name() == self.cookie_name)
+            .map(|cookie| cookie.value().to_owned())
+    }
+
+    fn is_expired(&self, record: &SessionRecord, now: SystemTime) -> bool {
+        let elapsed = |since: SystemTime| now.duration_since(since).unwrap_or_default();
+        self.idle_timeout
+            .is_some_and(|timeout| elapsed(record.accessed_at) > timeout)
+            || self
+                .absolute_timeout
+                .is_some_and(|timeout| elapsed(record.created_at) > timeout)
+    }
+
+    async fn load(&self, request: &Request, now: SystemTime) -> Result<Session, anyhow::Error> {
+        if let Some(id) = self.cookie_id(request) {
+            match self.store.load(&id).await? {
+                Some(record) if self.is_expired(&record, now) => self.store.destroy(&id).await?,
+                Some(mut record) => {
+                    record.accessed_at = now;
+                    return Ok(Session::new(Some(id), record, self.rotate_on.clone()));
+                }
+                None => {}
+            }
+        }
+
+        Ok(Session::new(
+            None,
+            SessionRecord::new(now),
+            self.rotate_on.clone(),
+        ))
+    }
+
+    async fn commit(
+        &self,
+        session: &Session,
+        had_cookie: bool,
+        response: &mut Response,
+    ) -> Result<(), anyhow::Error> {
+        let (mut id, record, changed, invalidate) = {
+            let mut state = session.lock();
+            (
+                state.id.clone(),
+                state.record.clone(),
+                std::mem::take(&mut state.changed),
+                state.destroyed || state.regenerate,
+            )
+        };
+
+        if invalidate {
+            if let Some(old) = id.take() {
+                self.store.destroy(&old).await?;
+            }
+        }
+
+        if id.is_none() && record.data.is_empty() {
+            // Nothing to save; make sure the client does not hold on to a
+            // session ID that no longer exists.
+            if had_cookie {
+                let mut cookie = self.cookie.build(self.cookie_name.clone(), String::new());
+                cookie.make_removal();
+                set_cookie(response, &cookie);
+            }
+            return Ok(());
+        }
+
+        let is_new = id.is_none();
+        let id = id.unwrap_or_else(generate_id);
+        if is_new || changed || self.idle_timeout.is_some() {
+            self.store.store(&id, &record).await?;
+        }
+        if is_new {
+            let cookie = self.cookie.build(self.cookie_name.clone(), id.clone());
+            set_cookie(response, &cookie);
+        }
+
+        let mut state = session.lock();
+        state.id = Some(id);
+        state.regenerate = false;
+        Ok(())
+    }
+}
+
+fn set_cookie(response: &mut Response, cookie: &Cookie<'static>) {
+    if let Ok(value) = cookie.encoded().to_string().try_into() {
+        response
+            .headers_mut()
+            .append(http::header::SET_COOKIE, value);
+    }
+}
+
+/// Generates a new, random session ID.  This is 32 alphanumeric characters,
+/// or about 190 bits of entropy.
+fn generate_id() -> String {
+    use rand::distributions::{Alphanumeric, DistString};
+    Alphanumeric.sample_string(&mut rand::thread_rng(), 32)
+}
+
+#[async_trait]
+impl<S: SessionStore> Middleware for SessionMiddleware<S> {
+    async fn apply(
+        self: Pin<&Self>,
+        mut request: Request,
+        next: Next<'_>,
+    ) -> Result<Response, anyhow::Error> {
+        let session = self.load(&request, SystemTime::now()).await?;
+        let had_cookie = self.cookie_id(&request).is_some();
+        request.extensions_mut().insert(session.clone());
+        let mut response = next.apply(request).await?;
+        self.commit(&session, had_cookie, &mut response).await?;
+        Ok(response)
+    }
+}
+
+impl<S> std::fmt::Debug for SessionMiddleware<S> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SessionMiddleware")
+            .field("store", &std::any::type_name::<S>())
+            .field("cookie_name", &self.cookie_name)
+            .field("cookie", &self.cookie)
+            .field("idle_timeout", &self.idle_timeout)
+            .field("absolute_timeout", &self.absolute_timeout)
+            .field("rotate_on", &self.rotate_on)
+            .finish()
+    }
+}
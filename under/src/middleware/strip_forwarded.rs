@@ -0,0 +1,55 @@
+use super::{Middleware, Next};
+use crate::{HttpEntity, Request, Response};
+use std::pin::Pin;
+
+#[derive(Debug, Clone, Default)]
+/// A middleware that removes the forwarding headers of requests (see
+/// [`crate::forwarded::strip`]).
+///
+/// This is for applications at the edge of the infrastructure - the ones
+/// that clients connect to directly.  Any forwarding header that reaches
+/// them was set by the client, and cannot be trusted; removing them means
+/// that everything after (including any server the request is passed on
+/// to) only ever sees forwarding headers from trusted proxies.
+///
+/// # Examples
+/// ```rust
+/// # use under::*;
+/// # #[tokio::main] async fn main() -> Result<(), anyhow::Error> {
+/// let mut http = under::http();
+/// http.with(under::middleware::StripForwarded::new())
+///     .at("/").get(|request: Request| async move {
+///         let ip = request.remote_address().trust_forwarded_for(-1).apply();
+///         Response::text(format!("{ip:?}"))
+///     });
+/// http.prepare();
+/// let request = Request::get("/")?.with_header("X-Forwarded-For", "10.0.0.1")?;
+/// let mut response = http.handle(request).await?;
+/// assert_eq!(response.data(512).into_text().await?, "None");
+/// # Ok(())
+/// # }
+/// ```
+pub struct StripForwarded {
+    _v: (),
+}
+
+impl StripForwarded {
+    #[must_use]
+    /// Creates a new middleware that strips forwarding headers.  This is
+    /// provided as an alternative to `Default`.
+    pub fn new() -> Self {
+        StripForwarded::default()
+    }
+}
+
+#[async_trait]
+impl Middleware for StripForwarded {
+    async fn apply(
+        self: Pin<&Self>,
+        mut request: Request,
+        next: Next<'_>,
+    ) -> Result<Response, anyhow::Error> {
+        crate::forwarded::strip(request.headers_mut());
+        next.apply(request).await
+    }
+}
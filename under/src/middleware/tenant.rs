@@ -0,0 +1,240 @@
+use super::{Middleware, Next};
+use crate::{HttpEntity, Request, Response};
+use std::pin::Pin;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+/// The tenant of a request, as derived by the [`TenantMiddleware`].
+///
+/// This is the type that is inserted into the [`Request`] extensions; it
+/// can be retrieved using [`Request::tenant`].
+pub struct Tenant<T>(pub T);
+
+impl<T> Tenant<T> {
+    /// Turns the given tenant into its inner value, consuming the tenant.
+    pub fn into_inner(self) -> T {
+        self.0
+    }
+}
+
+impl<T> std::ops::Deref for Tenant<T> {
+    type Target = T;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+type Extract<T> = Box<dyn Fn(&Request) -> Option<T> + Send + Sync>;
+
+/// A middleware for deriving the tenant of a request.
+///
+/// This runs the given extractor on each request, and, if it returns a
+/// tenant, inserts it into the request (see [`Request::tenant`]).  The
+/// tenant can be derived from anything in the request, but the most common
+/// sources have their own constructors: [`TenantMiddleware::from_host`],
+/// [`TenantMiddleware::from_header`], and [`TenantMiddleware::from_path`].
+/// By default, requests without a tenant are passed through as-is; see
+/// [`TenantMiddleware::required`].
+///
+/// # Examples
+/// ```rust
+/// # use under::*;
+/// use under::middleware::TenantMiddleware;
+///
+/// async fn handler(request: Request) -> Response {
+///     let tenant = request.tenant::<String>().unwrap();
+///     Response::text(format!("hello, {tenant}"))
+/// }
+///
+/// # #[tokio::main] async fn main() -> Result<(), anyhow::Error> {
+/// let mut http = under::http();
+/// http.with(TenantMiddleware::from_host(|host| {
+///     host.strip_suffix(".example.com").map(String::from)
+/// }).required());
+/// http.at("/").get(handler);
+/// http.prepare();
+/// let request = Request::get("/")?.with_header("Host", "acme.example.com")?;
+/// let mut response = http.handle(request).await?;
+/// assert_eq!(response.data(512).into_text().await?, "hello, acme");
+/// let request = Request::get("/")?.with_header("Host", "example.org")?;
+/// let response = http.handle(request).await?;
+/// assert_eq!(response.status(), http::StatusCode::NOT_FOUND);
+/// # Ok(())
+/// # }
+/// ```
+pub struct TenantMiddleware<T> {
+    extract: Extract<T>,
+    strip_path: bool,
+    required: bool,
+}
+
+impl<T: Clone + Send + Sync + 'static> TenantMiddleware<T> {
+    /// Creates a tenant middleware that derives the tenant of each request
+    /// using the given extractor.
+    pub fn new<F>(extract: F) -> Self
+    where
+        F: Fn(&Request) -> Option<T> + Send + Sync + 'static,
+    {
+        TenantMiddleware {
+            extract: Box::new(extract),
+            strip_path: false,
+            required: false,
+        }
+    }
+
+    /// Creates a tenant middleware that derives the tenant from the host of
+    /// the request (without the port).  This is taken from the URI, if it
+    /// is absolute, or otherwise the `Host` header.
+    pub fn from_host<F>(extract: F) -> Self
+    where
+        F: Fn(&str) -> Option<T> + Send + Sync + 'static,
+    {
+        TenantMiddleware::new(move |request| host(request).and_then(&extract))
+    }
+
+    /// Creates a tenant middleware that derives the tenant from the value of
+    /// the given header.
+    ///
+    /// # Examples
+    /// ```rust
+    /// # use under::*;
+    /// use under::middleware::TenantMiddleware;
+    /// let mut http = under::http();
+    /// http.with(TenantMiddleware::from_header("X-Tenant-Id", |id| id.parse::<u64>().ok()));
+    /// ```
+    pub fn from_header<F>(name: &'static str, extract: F) -> Self
+    where
+        F: Fn(&str) -> Option<T> + Send + Sync + 'static,
+    {
+        TenantMiddleware::new(move |request| {
+            request
+                .header(name)
+                .and_then(|v| v.to_str().ok())
+                .and_then(&extract)
+        })
+    }
+
+    /// Creates a tenant middleware that derives the tenant from the first
+    /// segment of the path of the request.  If a tenant is derived, the
+    /// segment is then removed from the path, so that e.g. `/acme/users`
+    /// becomes `/users`.
+    ///
+    /// Note that routing happens before any middleware is run, so the routes
+    /// of the router this middleware is on still see the original path.  To
+    /// route on the rewritten path, place the routes on a separate router,
+    /// and use that as the fallback of the router with the middleware.
+    ///
+    /// # Examples
+    /// ```rust
+    /// # use under::*;
+    /// use under::middleware::TenantMiddleware;
+    ///
+    /// async fn handler(request: Request) -> Response {
+    ///     let tenant = request.tenant::<String>().unwrap();
+    ///     Response::text(format!("{tenant}: {}", request.uri().path()))
+    /// }
+    ///
+    /// # #[tokio::main] async fn main() -> Result<(), anyhow::Error> {
+    /// let mut app = under::http();
+    /// app.at("/users").get(handler);
+    /// app.prepare();
+    ///
+    /// let mut http = under::http();
+    /// http.with(TenantMiddleware::from_path(|s| Some(s.to_string())))
+    ///     .fallback(app);
+    /// http.prepare();
+    /// let mut response = http.handle(Request::get("/acme/users?page=2")?).await?;
+    /// assert_eq!(response.data(512).into_text().await?, "acme: /users");
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn from_path<F>(extract: F) -> Self
+    where
+        F: Fn(&str) -> Option<T> + Send + Sync + 'static,
+    {
+        let mut middleware = TenantMiddleware::new(move |request| {
+            first_segment(request.uri().path()).and_then(|(segment, _)| extract(segment))
+        });
+        middleware.strip_path = true;
+        middleware
+    }
+
+    #[must_use]
+    /// Requires that every request has a tenant.  Requests that do not are
+    /// responded to with an empty 404, and are not passed down the stack.
+    pub fn required(mut self) -> Self {
+        self.required = true;
+        self
+    }
+}
+
+impl<T> std::fmt::Debug for TenantMiddleware<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("TenantMiddleware")
+            .field("tenant", &std::any::type_name::<T>())
+            .field("strip_path", &self.strip_path)
+            .field("required", &self.required)
+            .finish_non_exhaustive()
+    }
+}
+
+#[async_trait]
+impl<T: Clone + Send + Sync + 'static> Middleware for TenantMiddleware<T> {
+    async fn apply(
+        self: Pin<&Self>,
+        mut request: Request,
+        next: Next<'_>,
+    ) -> Result<Response, anyhow::Error> {
+        match (self.extract)(&request) {
+            Some(tenant) => {
+                if self.strip_path {
+                    strip_first_segment(&mut request)?;
+                }
+                request.set_ext(Tenant(tenant));
+            }
+            None if self.required => return Ok(Response::empty_404()),
+            None => {}
+        }
+
+        next.apply(request).await
+    }
+}
+
+fn host(request: &Request) -> Option<&str> {
+    if let Some(host) = request.uri().host() {
+        return Some(host);
+    }
+
+    let host = request.header(http::header::HOST)?.to_str().ok()?;
+    // IPv6 hosts are bracketed, and so may contain colons.
+    match host.rfind(':') {
+        Some(i) if !host[i..].contains(']') => Some(&host[..i]),
+        _ => Some(host),
+    }
+}
+
+/// Splits the first segment off of the given path, returning it and the
+/// remainder of the path (which always starts with a slash).
+fn first_segment(path: &str) -> Option<(&str, &str)> {
+    let path = path.strip_prefix('/')?;
+    let (segment, rest) = match path.find('/') {
+        Some(i) => path.split_at(i),
+        None => (path, "/"),
+    };
+    Some((segment, rest)).filter(|(segment, _)| !segment.is_empty())
+}
+
+fn strip_first_segment(request: &mut Request) -> Result<(), http::Error> {
+    let uri = request.uri();
+    let Some((_, rest)) = first_segment(uri.path()) else {
+        return Ok(());
+    };
+    let path_and_query = match uri.query() {
+        Some(query) => format!("{rest}?{query}"),
+        None => rest.to_string(),
+    };
+    let mut parts = uri.clone().into_parts();
+    parts.path_and_query = Some(path_and_query.parse()?);
+    *request.uri_mut() = http::Uri::from_parts(parts).map_err(http::Error::from)?;
+    Ok(())
+}
@@ -0,0 +1,153 @@
+use super::{Middleware, Next};
+use crate::{Request, Response};
+use std::pin::Pin;
+use std::time::{Duration, Instant};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+/// The point in time by which a request must be responded to.
+///
+/// This is inserted into the extensions of every request that passes
+/// through the [`Timeout`] middleware (see [`Request::deadline`]), and is
+/// available to the task handling it (see [`Deadline::current`]).  Work
+/// done on behalf of the request - in particular, outbound requests made
+/// with [`crate::client::Client`], which honor it automatically - should
+/// not outlive it.
+pub struct Deadline(Instant);
+
+tokio::task_local! {
+    static CURRENT: Deadline;
+}
+
+impl Deadline {
+    #[must_use]
+    /// Creates a deadline at the given instant.
+    pub fn at(instant: Instant) -> Self {
+        Deadline(instant)
+    }
+
+    #[must_use]
+    /// Creates a deadline the given duration from now.
+    pub fn after(duration: Duration) -> Self {
+        Deadline(Instant::now() + duration)
+    }
+
+    #[must_use]
+    /// The instant of the deadline.
+    pub fn instant(&self) -> Instant {
+        self.0
+    }
+
+    #[must_use]
+    /// The time left until the deadline, or zero if it has passed.
+    pub fn remaining(&self) -> Duration {
+        self.0.saturating_duration_since(Instant::now())
+    }
+
+    #[must_use]
+    /// Whether the deadline has passed.
+    pub fn is_expired(&self) -> bool {
+        self.remaining().is_zero()
+    }
+
+    #[must_use]
+    /// The deadline of the request that the current task is handling, if
+    /// it passed through the [`Timeout`] middleware.  This is the same as
+    /// [`Request::deadline`], without needing the request; tasks spawned
+    /// while handling the request do not inherit it, however.
+    ///
+    /// # Examples
+    /// ```rust
+    /// # use under::*;
+    /// use std::time::Duration;
+    /// use under::middleware::{Deadline, Timeout};
+    /// # #[tokio::main] async fn main() -> Result<(), anyhow::Error> {
+    /// let mut http = under::http();
+    /// http.with(Timeout::new(Duration::from_secs(1)));
+    /// http.at("/").get(|request: Request| async move {
+    ///     Response::text(format!("{}", Deadline::current() == request.deadline().copied()))
+    /// });
+    /// http.prepare();
+    /// let mut response = http.handle(Request::get("/")?).await?;
+    /// assert_eq!(response.data(512).into_text().await?, "true");
+    /// assert!(Deadline::current().is_none());
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn current() -> Option<Deadline> {
+        CURRENT.try_with(|deadline| *deadline).ok()
+    }
+}
+
+#[derive(Debug, Clone)]
+/// A middleware that limits how long the rest of the stack may take to
+/// respond to a request.
+///
+/// If the response is not ready before the timeout, the request is
+/// abandoned, and this errors with [`crate::UnderError::Timeout`] instead,
+/// which the router turns into an empty `503 Service Unavailable`.
+/// The deadline is also inserted into the request as a [`Deadline`], so that
+/// the endpoint can see how long it has left; if the request already has an
+/// earlier deadline (e.g. from a second timeout middleware further up the
+/// stack), that one is kept.
+///
+/// # Examples
+/// ```rust
+/// # use under::*;
+/// use std::time::Duration;
+/// use under::middleware::Timeout;
+/// # #[tokio::main] async fn main() -> Result<(), anyhow::Error> {
+/// let mut http = under::http();
+/// http.with(Timeout::new(Duration::from_millis(50)));
+/// http.at("/fast").get(|request: Request| async move {
+///     let remaining = request.deadline().unwrap().remaining();
+///     Response::text(format!("{}", remaining <= Duration::from_millis(50)))
+/// });
+/// http.at("/slow").get(|_: Request| async move {
+///     tokio::time::sleep(Duration::from_secs(5)).await;
+///     Response::empty_204()
+/// });
+/// http.prepare();
+/// let mut response = http.handle(Request::get("/fast")?).await?;
+/// assert_eq!(response.data(512).into_text().await?, "true");
+/// let response = http.handle(Request::get("/slow")?).await?;
+/// assert_eq!(response.status(), http::StatusCode::SERVICE_UNAVAILABLE);
+/// # Ok(())
+/// # }
+/// ```
+pub struct Timeout {
+    duration: Duration,
+}
+
+impl Timeout {
+    #[must_use]
+    /// Creates a new timeout middleware, with the given timeout.
+    pub fn new(duration: Duration) -> Self {
+        Timeout { duration }
+    }
+}
+
+#[async_trait]
+impl Middleware for Timeout {
+    async fn apply(
+        self: Pin<&Self>,
+        mut request: Request,
+        next: Next<'_>,
+    ) -> Result<Response, anyhow::Error> {
+        let deadline = Deadline::after(self.duration);
+        let deadline = request.deadline().map_or(deadline, |d| deadline.min(*d));
+        request.set_ext(deadline);
+
+        let until = tokio::time::Instant::from_std(deadline.instant());
+        let response = tokio::time::timeout_at(until, next.apply(request));
+        if let Ok(result) = CURRENT.scope(deadline, response).await {
+            result
+        } else {
+            log::warn!("request timed out after {:?}", self.duration);
+            Err(crate::UnderError::Timeout.into())
+        }
+    }
+
+    fn describe(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "timeout({:?})", self.duration)
+    }
+}
@@ -0,0 +1,81 @@
+use std::borrow::Cow;
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::Duration;
+
+#[derive(Debug, Clone, Default)]
+/// A collection of timings for a single request.
+///
+/// When profiling is enabled on the router (see [`crate::Router::profile`]),
+/// this is inserted into the request's extensions before any middleware is
+/// run, and every layer of the stack - each middleware, and the endpoint -
+/// records how long it took into it.  Once the request has been handled, the
+/// same timings are placed into the response's extensions.
+///
+/// This is cheaply cloneable; all clones refer to the same set of timings.
+/// Note that each middleware's duration includes the duration of everything
+/// that ran after it (i.e., all of the remaining middleware, and the
+/// endpoint).
+///
+/// # Examples
+/// ```rust
+/// # use under::*;
+/// use under::middleware::Timings;
+/// # #[tokio::main] async fn main() -> Result<(), anyhow::Error> {
+/// let mut http = under::http();
+/// http.profile(true)
+///     .with(under::middleware::TraceMiddleware::new())
+///     .at("/").get(under::endpoints::simple(Response::empty_204));
+/// http.prepare();
+/// let response = http.handle(Request::get("/")?).await?;
+/// let timings = response.ext::<Timings>().unwrap();
+/// assert_eq!(timings.entries().len(), 2);
+/// assert_eq!(timings.entries()[0].name, "endpoint");
+/// # Ok(())
+/// # }
+/// ```
+pub struct Timings(Arc<Mutex<Vec<Timing>>>);
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// A single timing entry, recorded into [`Timings`].
+pub struct Timing {
+    /// The name of the timing.  For middleware, this is their description
+    /// (see [`crate::Middleware::describe`]); for the endpoint, this is
+    /// `endpoint`.
+    pub name: Cow<'static, str>,
+    /// How long the timed operation took.
+    pub duration: Duration,
+}
+
+impl Timings {
+    /// Records a new timing entry.
+    ///
+    /// # Examples
+    /// ```rust
+    /// # use under::middleware::Timings;
+    /// # use std::time::Duration;
+    /// let timings = Timings::default();
+    /// timings.record("db", Duration::from_millis(5));
+    /// assert_eq!(timings.entries()[0].name, "db");
+    /// ```
+    pub fn record(&self, name: impl Into<Cow<'static, str>>, duration: Duration) {
+        let timing = Timing {
+            name: name.into(),
+            duration,
+        };
+        self.0
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .push(timing);
+    }
+
+    /// Returns a copy of all of the timings recorded so far, in the order
+    /// that they completed.  Since the innermost layer of the stack (the
+    /// endpoint) completes first, it will generally be first.
+    #[must_use]
+    pub fn entries(&self) -> Vec<Timing> {
+        self.0
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone()
+    }
+}
@@ -1,16 +1,116 @@
+use std::collections::HashMap;
 use std::fmt::Display;
 use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
 
-use super::{Middleware, Next};
-use crate::{Request, Response};
+use super::{Middleware, Next, Timings};
+use crate::redact::Redactor;
+use crate::{HttpEntity, Request, Response};
 
 #[derive(Default, Debug, Clone)]
 /// A middleware for tracing HTTP requests.
 ///
 /// This logs (using `log`) each request, as well as how long each request
 /// took.  The default log level is `info`.
+///
+/// If a slow threshold is set (see [`TraceMiddleware::with_slow_threshold`]),
+/// any request that takes longer than the threshold is additionally logged at
+/// the `warn` level, along with the timing breakdown of the request, if
+/// profiling is enabled on the router (see [`crate::Router::profile`]).
+///
+/// By default, every request is traced; to only trace some of them (e.g. so
+/// that a high volume of health checks doesn't flood the logs), set a
+/// [`Sampler`] with [`TraceMiddleware::with_sampler`], or for specific
+/// routes with [`TraceMiddleware::with_route_sampler`].  Slow requests are
+/// always logged, whether they were sampled or not.
+///
+/// Each request is logged with its path and query, and, at the `debug`
+/// level, its headers; sensitive query parameters and headers are redacted,
+/// with [`Redactor::default`] unless another redactor is set (see
+/// [`TraceMiddleware::with_redactor`]).
 pub struct TraceMiddleware {
-    _v: (),
+    slow: Option<Duration>,
+    redactor: Redactor,
+    sampler: Sampler,
+    routes: HashMap<String, Sampler>,
+    sequence: Arc<AtomicU64>,
+}
+
+#[derive(Default, Debug, Clone, PartialEq)]
+/// Decides which requests the [`TraceMiddleware`] traces.
+pub enum Sampler {
+    /// Traces every request.  This is the default.
+    #[default]
+    Always,
+    /// Traces no request.
+    Never,
+    /// Traces the given ratio of requests, from `0.0` (none of them) to
+    /// `1.0` (all of them).  Which requests are traced is spread evenly,
+    /// but is otherwise unpredictable.
+    Ratio(f64),
+    /// Follows the decision of the caller, if the request has a valid
+    /// `traceparent` header (see [W3C Trace Context]), tracing the request
+    /// if and only if its `sampled` flag is set; otherwise, the given
+    /// sampler decides.
+    ///
+    /// [W3C Trace Context]: https://www.w3.org/TR/trace-context/#traceparent-header
+    ParentBased(Box<Sampler>),
+}
+
+impl Sampler {
+    /// Whether to trace the request; `sequence` is a number that is
+    /// different for every request, used for ratio sampling.
+    fn sample(&self, request: &Request, sequence: u64) -> bool {
+        match self {
+            Sampler::Always => true,
+            Sampler::Never => false,
+            Sampler::Ratio(ratio) => *ratio >= 1.0 || spread(sequence) < *ratio,
+            Sampler::ParentBased(root) => {
+                parent_sampled(request).unwrap_or_else(|| root.sample(request, sequence))
+            }
+        }
+    }
+}
+
+/// Maps the sequence onto [0, 1), with a mixing function (splitmix64), so
+/// that the traced requests are spread out without following an obvious
+/// pattern.
+fn spread(sequence: u64) -> f64 {
+    let mut z = sequence.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^= z >> 31;
+    #[allow(clippy::cast_precision_loss)]
+    let position = (z >> 11) as f64 / (1u64 << 53) as f64;
+    position
+}
+
+/// The `sampled` flag of the `traceparent` header of the request, or `None`
+/// if it has no valid such header.
+fn parent_sampled(request: &Request) -> Option<bool> {
+    let header = request.header("traceparent")?.to_str().ok()?;
+    let mut parts = header.trim().split('-');
+    let version = parts.next()?;
+    let trace_id = parts.next()?;
+    let parent_id = parts.next()?;
+    let flags = parts.next()?;
+    let is_hex = |part: &str, len: usize| {
+        part.len() == len && part.bytes().all(|b| matches!(b, b'0'..=b'9' | b'a'..=b'f'))
+    };
+    let valid = is_hex(version, 2)
+        && version != "ff"
+        && is_hex(trace_id, 32)
+        && trace_id.bytes().any(|b| b != b'0')
+        && is_hex(parent_id, 16)
+        && parent_id.bytes().any(|b| b != b'0')
+        && is_hex(flags, 2)
+        && (version != "00" || parts.next().is_none());
+    if !valid {
+        return None;
+    }
+    Some(u8::from_str_radix(flags, 16).ok()? & 0x01 != 0)
 }
 
 impl TraceMiddleware {
@@ -20,6 +120,92 @@ impl TraceMiddleware {
     pub fn new() -> Self {
         TraceMiddleware::default()
     }
+
+    #[must_use]
+    /// Sets the threshold over which a request is considered slow.  Slow
+    /// requests are logged at the `warn` level, including the timing
+    /// breakdown of every middleware and endpoint below this one (if the
+    /// router is profiling).
+    ///
+    /// # Examples
+    /// ```rust
+    /// # use under::*;
+    /// use std::time::Duration;
+    /// let mut http = under::http();
+    /// http.profile(true)
+    ///     .with(under::middleware::TraceMiddleware::new()
+    ///         .with_slow_threshold(Duration::from_millis(500)));
+    /// ```
+    pub fn with_slow_threshold(mut self, threshold: Duration) -> Self {
+        self.slow = Some(threshold);
+        self
+    }
+
+    #[must_use]
+    /// Sets the sampler that decides which requests are traced (see
+    /// [`Sampler`]).  This applies to every request whose route has no
+    /// sampler of its own (see [`TraceMiddleware::with_route_sampler`]).
+    ///
+    /// # Examples
+    /// ```rust
+    /// # use under::*;
+    /// use under::middleware::{Sampler, TraceMiddleware};
+    /// let mut http = under::http();
+    /// http.with(TraceMiddleware::new()
+    ///     .with_sampler(Sampler::ParentBased(Box::new(Sampler::Ratio(0.1)))));
+    /// ```
+    pub fn with_sampler(mut self, sampler: Sampler) -> Self {
+        self.sampler = sampler;
+        self
+    }
+
+    #[must_use]
+    /// Sets the sampler for the requests routed to the given path, as it
+    /// was given to [`crate::Router::at`] (see
+    /// [`Request::matched_path`]), overriding the sampler of the
+    /// middleware.
+    ///
+    /// # Examples
+    /// ```rust
+    /// # use under::*;
+    /// use under::middleware::{Sampler, TraceMiddleware};
+    /// let mut http = under::http();
+    /// http.with(TraceMiddleware::new()
+    ///     .with_route_sampler("/healthz", Sampler::Never)
+    ///     .with_route_sampler("/users/{id}", Sampler::Ratio(0.5)));
+    /// http.at("/healthz").get(under::endpoints::simple(Response::empty_204));
+    /// ```
+    pub fn with_route_sampler<P: Into<String>>(mut self, path: P, sampler: Sampler) -> Self {
+        self.routes.insert(path.into(), sampler);
+        self
+    }
+
+    #[must_use]
+    /// Sets the redactor for the query and headers of each logged request.
+    ///
+    /// # Examples
+    /// ```rust
+    /// # use under::*;
+    /// use under::middleware::TraceMiddleware;
+    /// use under::redact::Redactor;
+    /// let mut http = under::http();
+    /// http.with(TraceMiddleware::new()
+    ///     .with_redactor(Redactor::default().with_header("x-upstream-token")));
+    /// ```
+    pub fn with_redactor(mut self, redactor: Redactor) -> Self {
+        self.redactor = redactor;
+        self
+    }
+
+    /// Whether to trace the request.
+    fn sampled(&self, request: &Request) -> bool {
+        let sampler = request
+            .matched_path()
+            .and_then(|path| self.routes.get(path))
+            .unwrap_or(&self.sampler);
+        let sequence = self.sequence.fetch_add(1, Ordering::Relaxed);
+        sampler.sample(request, sequence)
+    }
 }
 
 #[async_trait]
@@ -30,21 +216,48 @@ impl Middleware for TraceMiddleware {
         next: Next<'_>,
     ) -> Result<Response, anyhow::Error> {
         let method = request.method().clone();
-        let path = request.uri().path().to_string();
-        log::info!("--> {} {}", method, path);
+        let path = request
+            .uri()
+            .path_and_query()
+            .map_or("/", http::uri::PathAndQuery::as_str);
+        let path = self.redactor.redact_uri(path).into_owned();
+        let timings = request.ext::<Timings>().cloned();
+        let sampled = self.sampled(&request);
+        if sampled {
+            log::info!("--> {method} {path}");
+            if log::log_enabled!(log::Level::Debug) {
+                for (name, value) in request.headers() {
+                    let value = value.to_str().unwrap_or("(binary)");
+                    log::debug!("    {name}: {}", self.redactor.redact_header(name, value));
+                }
+            }
+        }
         let start = std::time::Instant::now();
 
         let result = next.apply(request).await;
         let elapse = start.elapsed();
         let status = StatusDisplay(&result);
 
-        log::info!(
-            "<-- {} {}: {} (in {}ms)",
-            method,
-            path,
-            status,
-            elapse.as_millis()
-        );
+        if sampled {
+            log::info!(
+                "<-- {} {}: {} (in {}ms)",
+                method,
+                path,
+                status,
+                elapse.as_millis()
+            );
+        }
+
+        if self.slow.is_some_and(|slow| elapse > slow) {
+            log::warn!(
+                "slow request {} {}: {} (in {}ms){}",
+                method,
+                path,
+                status,
+                elapse.as_millis(),
+                TimingsDisplay(timings.as_ref())
+            );
+        }
 
         result
     }
@@ -60,3 +273,24 @@ impl Display for StatusDisplay<'_> {
         }
     }
 }
+
+struct TimingsDisplay<'a>(Option<&'a Timings>);
+
+impl Display for TimingsDisplay<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let Some(timings) = self.0 else {
+            return Ok(());
+        };
+        // The timings are recorded innermost-first; display them in the
+        // order in which they were entered instead.
+        for timing in timings.entries().iter().rev() {
+            write!(
+                f,
+                "\n    {}: {}us",
+                timing.name,
+                timing.duration.as_micros()
+            )?;
+        }
+        Ok(())
+    }
+}
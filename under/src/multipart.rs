@@ -0,0 +1,462 @@
+//! `multipart/form-data` bodies.
+//!
+//! HTML forms that upload files are sent as `multipart/form-data` (see
+//! [RFC 7578]), where each field is a separate part of the body, with its
+//! own headers.  This parses such a body, once it has been read in full
+//! (up to a limit), into its [`Part`]s; text fields can then be fed through
+//! [`crate::FromForm`], the same as an `x-www-form-urlencoded` body (see
+//! [`crate::HttpEntity::as_sniff_form`]), and files read from their part,
+//! or into the `#[form(file)]` fields of a struct deriving `FromForm` (see
+//! [`UploadedFile`]).  It is gated behind the `multipart` feature flag.
+//!
+//! [RFC 7578]: https://www.rfc-editor.org/rfc/rfc7578
+//!
+//! # Examples
+//! ```rust
+//! # use under::*;
+//! # #[tokio::main] async fn main() -> Result<(), anyhow::Error> {
+//! let body = "--XYZ\r\n\
+//!     Content-Disposition: form-data; name=\"title\"\r\n\
+//!     \r\n\
+//!     Holiday\r\n\
+//!     --XYZ\r\n\
+//!     Content-Disposition: form-data; name=\"photo\"; filename=\"beach.jpg\"\r\n\
+//!     Content-Type: image/jpeg\r\n\
+//!     \r\n\
+//!     <jpeg>\r\n\
+//!     --XYZ--\r\n";
+//! let mut request = Request::post("/albums")?
+//!     .with_header("Content-Type", "multipart/form-data; boundary=XYZ")?
+//!     .with_body(body);
+//! let multipart = request.as_multipart(4096).await?;
+//! assert_eq!(multipart.fields().collect::<Vec<_>>(), [("title", "Holiday")]);
+//! let photo = multipart.part("photo").unwrap();
+//! assert_eq!(photo.filename(), Some("beach.jpg"));
+//! assert_eq!(photo.content_type().unwrap(), mime::IMAGE_JPEG);
+//! assert_eq!(&photo.data()[..], b"<jpeg>");
+//! # Ok(())
+//! # }
+//! ```
+
+use bytes::Bytes;
+
+#[derive(Debug, Clone, Default)]
+/// A parsed `multipart/form-data` body.
+pub struct Multipart {
+    parts: Vec<Part>,
+}
+
+#[derive(Debug, Clone)]
+/// A single part of a [`Multipart`] body, i.e. a single field of the form.
+pub struct Part {
+    headers: http::HeaderMap,
+    name: Option<String>,
+    filename: Option<String>,
+    data: Bytes,
+}
+
+#[derive(thiserror::Error, Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+/// The reason a `multipart/form-data` body could not be parsed.
+pub enum MultipartError {
+    /// The content type of the body does not have a `boundary` parameter,
+    /// or the boundary is invalid.
+    #[error("the multipart boundary is missing or invalid")]
+    InvalidBoundary,
+    /// The body does not contain the boundary, or ends before the closing
+    /// boundary.
+    #[error("the multipart body is incomplete")]
+    Incomplete,
+    /// The headers of a part are malformed.
+    #[error("the headers of a multipart part are malformed")]
+    InvalidHeaders,
+}
+
+/// The longest boundary allowed, per RFC 2046.
+const MAX_BOUNDARY: usize = 70;
+
+impl Multipart {
+    /// The boundary of a `multipart/*` content type, if it has a valid one.
+    ///
+    /// # Examples
+    /// ```rust
+    /// use under::multipart::Multipart;
+    /// let mime: mime::Mime = "multipart/form-data; boundary=\"a b\"".parse().unwrap();
+    /// assert_eq!(Multipart::boundary(&mime), Some("a b"));
+    /// assert_eq!(Multipart::boundary(&mime::TEXT_PLAIN), None);
+    /// ```
+    #[must_use]
+    pub fn boundary(mime: &mime::Mime) -> Option<&str> {
+        if mime.type_() != mime::MULTIPART {
+            return None;
+        }
+        let boundary = mime.get_param(mime::BOUNDARY)?.as_str();
+        let valid = (1..=MAX_BOUNDARY).contains(&boundary.len())
+            && !boundary.ends_with(' ')
+            && boundary
+                .bytes()
+                .all(|byte| byte.is_ascii_alphanumeric() || b"'()+_,-./:=? ".contains(&byte));
+        valid.then_some(boundary)
+    }
+
+    /// Parses the given body, delimited by the given boundary.  Anything
+    /// before the first boundary, or after the closing one, is ignored.
+    ///
+    /// # Errors
+    /// Errors if the boundary is invalid, if the body is not delimited by
+    /// it (or is missing the closing boundary), or if the headers of a part
+    /// are malformed.
+    ///
+    /// # Examples
+    /// ```rust
+    /// use under::multipart::{Multipart, MultipartError};
+    /// let body = b"--b\r\nContent-Disposition: form-data; name=\"a\"\r\n\r\n1\r\n--b--";
+    /// let multipart = Multipart::parse("b", &bytes::Bytes::from_static(body))?;
+    /// assert_eq!(multipart.parts().len(), 1);
+    /// assert_eq!(multipart.parts()[0].text(), Some("1"));
+    ///
+    /// let body = b"--b\r\nContent-Disposition: form-data; name=\"a\"\r\n\r\n1";
+    /// let error = Multipart::parse("b", &bytes::Bytes::from_static(body)).unwrap_err();
+    /// assert_eq!(error, MultipartError::Incomplete);
+    /// # Ok::<(), anyhow::Error>(())
+    /// ```
+    pub fn parse(boundary: &str, body: &Bytes) -> Result<Self, MultipartError> {
+        if boundary.is_empty() || boundary.len() > MAX_BOUNDARY {
+            return Err(MultipartError::InvalidBoundary);
+        }
+        let delimiter = format!("\r\n--{boundary}");
+        let delimiter = delimiter.as_bytes();
+
+        // The first delimiter may be at the very start of the body, in which
+        // case it has no preceding line break.
+        let mut position = if body.starts_with(&delimiter[2..]) {
+            delimiter.len() - 2
+        } else {
+            find(body, delimiter).ok_or(MultipartError::Incomplete)? + delimiter.len()
+        };
+
+        let mut parts = vec![];
+        loop {
+            let rest = &body[position..];
+            if rest.starts_with(b"--") {
+                break;
+            }
+            // The delimiter may be followed by whitespace, before the line
+            // break that ends it.
+            let padding = rest
+                .iter()
+                .take_while(|byte| matches!(byte, b' ' | b'\t'))
+                .count();
+            if !rest[padding..].starts_with(b"\r\n") {
+                return Err(MultipartError::Incomplete);
+            }
+            let start = position + padding + 2;
+            let length = find(&body[start..], delimiter).ok_or(MultipartError::Incomplete)?;
+            parts.push(Part::parse(&body.slice(start..start + length))?);
+            position = start + length + delimiter.len();
+        }
+
+        Ok(Multipart { parts })
+    }
+
+    /// The parts of the body, in the order in which they appeared.
+    #[must_use]
+    pub fn parts(&self) -> &[Part] {
+        &self.parts
+    }
+
+    /// The parts of the body, in the order in which they appeared.
+    #[must_use]
+    pub fn into_parts(self) -> Vec<Part> {
+        self.parts
+    }
+
+    /// The first part with the given name, if there is one.
+    #[must_use]
+    pub fn part(&self, name: &str) -> Option<&Part> {
+        self.parts.iter().find(|part| part.name() == Some(name))
+    }
+
+    /// Parses the form into the given type (see
+    /// [`crate::FromForm::from_multipart`]).
+    ///
+    /// # Errors
+    /// Errors if the form could not be parsed into the type.
+    #[cfg(feature = "from_form")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "from_form")))]
+    pub fn to_form<T: crate::FromForm>(&self) -> Result<T, crate::FromFormError> {
+        T::from_multipart(self)
+    }
+
+    /// The name and value of each text field of the form, i.e. each named
+    /// part that is not a file (see [`Part::is_file`]) and is valid UTF-8,
+    /// in the order in which they appeared.  This is what is fed to
+    /// [`crate::FromForm`].
+    pub fn fields(&self) -> impl Iterator<Item = (&str, &str)> + '_ {
+        self.parts
+            .iter()
+            .filter(|part| !part.is_file())
+            .filter_map(|part| Some((part.name()?, part.text()?)))
+    }
+}
+
+impl Part {
+    fn parse(part: &Bytes) -> Result<Self, MultipartError> {
+        let (head, data) = if part.starts_with(b"\r\n") {
+            (&b""[..], part.slice(2..))
+        } else {
+            let end = find(part, b"\r\n\r\n").ok_or(MultipartError::InvalidHeaders)?;
+            (&part[..end], part.slice(end + 4..))
+        };
+
+        let mut headers = http::HeaderMap::new();
+        for line in head.split(|byte| *byte == b'\n') {
+            let line = line.strip_suffix(b"\r").unwrap_or(line);
+            if line.is_empty() {
+                continue;
+            }
+            let colon = line
+                .iter()
+                .position(|byte| *byte == b':')
+                .ok_or(MultipartError::InvalidHeaders)?;
+            let name = http::HeaderName::from_bytes(&line[..colon])
+                .map_err(|_| MultipartError::InvalidHeaders)?;
+            let value = http::HeaderValue::from_bytes(line[colon + 1..].trim_ascii())
+                .map_err(|_| MultipartError::InvalidHeaders)?;
+            headers.append(name, value);
+        }
+
+        let disposition = headers
+            .get(http::header::CONTENT_DISPOSITION)
+            .and_then(|value| value.to_str().ok())
+            .map(disposition_params)
+            .unwrap_or_default();
+        let param = |key: &str| {
+            disposition
+                .iter()
+                .find(|(name, _)| name.eq_ignore_ascii_case(key))
+                .map(|(_, value)| value.clone())
+        };
+
+        Ok(Part {
+            name: param("name"),
+            filename: param("filename"),
+            headers,
+            data,
+        })
+    }
+
+    /// The name of the field, from the `Content-Disposition` header of the
+    /// part.
+    #[must_use]
+    pub fn name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
+
+    /// The name of the uploaded file, from the `Content-Disposition` header
+    /// of the part, if the part is a file.  This is given by the client,
+    /// and should not be trusted as a path.
+    #[must_use]
+    pub fn filename(&self) -> Option<&str> {
+        self.filename.as_deref()
+    }
+
+    /// Whether the part is a file, i.e. whether it has a filename.
+    #[must_use]
+    pub fn is_file(&self) -> bool {
+        self.filename.is_some()
+    }
+
+    /// The content type of the part, if it has a valid one.  Per RFC 7578,
+    /// a part without one is `text/plain`.
+    #[must_use]
+    pub fn content_type(&self) -> Option<mime::Mime> {
+        self.headers
+            .get(http::header::CONTENT_TYPE)?
+            .to_str()
+            .ok()?
+            .parse()
+            .ok()
+    }
+
+    /// The headers of the part.
+    #[must_use]
+    pub fn headers(&self) -> &http::HeaderMap {
+        &self.headers
+    }
+
+    /// The contents of the part.
+    #[must_use]
+    pub fn data(&self) -> &Bytes {
+        &self.data
+    }
+
+    /// The contents of the part, as text, if they are valid UTF-8.
+    #[must_use]
+    pub fn text(&self) -> Option<&str> {
+        std::str::from_utf8(&self.data).ok()
+    }
+
+    /// The contents of the part.
+    #[must_use]
+    pub fn into_data(self) -> Bytes {
+        self.data
+    }
+}
+
+#[derive(Debug, Clone)]
+/// A file uploaded through a `multipart/form-data` form.
+///
+/// This is the type of the fields of a struct deriving `FromForm` that are
+/// marked `#[form(file)]`, which are filled in from the part of the same
+/// name; it can also be wrapped in an `Option` (with `#[form(optional)]`),
+/// or collected into a `Vec` (with `#[form(multiple)]`).  A file input that
+/// was left empty (which browsers send as a part with an empty filename,
+/// and no content) counts as missing.  As the whole body is read into
+/// memory, so is the file; use [`UploadedFile::persist`] to write it out.
+///
+/// # Examples
+/// ```rust
+/// # use under::*;
+/// use under::multipart::UploadedFile;
+///
+/// #[derive(FromForm)]
+/// struct Album {
+///     title: String,
+///     #[form(file)]
+///     cover: UploadedFile,
+///     #[form(file, multiple)]
+///     photos: Vec<UploadedFile>,
+/// }
+///
+/// # #[tokio::main] async fn main() -> Result<(), anyhow::Error> {
+/// let body = "--x\r\n\
+///     Content-Disposition: form-data; name=\"title\"\r\n\r\nHoliday\r\n\
+///     --x\r\n\
+///     Content-Disposition: form-data; name=\"cover\"; filename=\"cover.png\"\r\n\
+///     Content-Type: image/png\r\n\r\n<png>\r\n\
+///     --x\r\n\
+///     Content-Disposition: form-data; name=\"photos\"; filename=\"a.jpg\"\r\n\r\n<a>\r\n\
+///     --x\r\n\
+///     Content-Disposition: form-data; name=\"photos\"; filename=\"b.jpg\"\r\n\r\n<b>\r\n\
+///     --x--";
+/// let mut request = Request::post("/albums")?
+///     .with_header("Content-Type", "multipart/form-data; boundary=x")?
+///     .with_body(body);
+/// let album: Album = request.as_multipart(4096).await?.to_form()?;
+/// assert_eq!(album.title, "Holiday");
+/// assert_eq!(album.cover.filename(), Some("cover.png"));
+/// assert_eq!(album.cover.content_type(), Some(&mime::IMAGE_PNG));
+/// assert_eq!(&album.cover.data()[..], b"<png>");
+/// assert_eq!(album.photos.len(), 2);
+/// # Ok(())
+/// # }
+/// ```
+pub struct UploadedFile {
+    filename: Option<String>,
+    content_type: Option<mime::Mime>,
+    data: Bytes,
+}
+
+impl UploadedFile {
+    /// Creates the file from the given part, or `None` if the part is an
+    /// empty file input (with an empty filename, and no content).
+    #[must_use]
+    pub fn from_part(part: &Part) -> Option<Self> {
+        if part.filename() == Some("") && part.data().is_empty() {
+            return None;
+        }
+        Some(UploadedFile {
+            filename: part.filename.clone(),
+            content_type: part.content_type(),
+            data: part.data.clone(),
+        })
+    }
+
+    /// The name of the file, as given by the client.  This should not be
+    /// trusted as a path.
+    #[must_use]
+    pub fn filename(&self) -> Option<&str> {
+        self.filename.as_deref()
+    }
+
+    /// The content type of the file, as given by the client.
+    #[must_use]
+    pub fn content_type(&self) -> Option<&mime::Mime> {
+        self.content_type.as_ref()
+    }
+
+    /// The contents of the file.
+    #[must_use]
+    pub fn data(&self) -> &Bytes {
+        &self.data
+    }
+
+    /// The contents of the file.
+    #[must_use]
+    pub fn into_data(self) -> Bytes {
+        self.data
+    }
+
+    /// The size of the file, in bytes.
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.data.len()
+    }
+
+    /// Whether the file is empty.
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    /// Writes the contents of the file to the given path, replacing any file
+    /// there.
+    ///
+    /// # Errors
+    /// Errors if the file could not be written.
+    pub async fn persist<P: AsRef<std::path::Path>>(&self, path: P) -> std::io::Result<()> {
+        tokio::fs::write(path, &self.data).await
+    }
+}
+
+/// The parameters of a `Content-Disposition` header (e.g. `name`, and
+/// `filename`), with quoted values unquoted.
+fn disposition_params(value: &str) -> Vec<(String, String)> {
+    let mut params = vec![];
+    let mut chars = value.chars().peekable();
+    // Skip the disposition type itself (e.g. `form-data`).
+    chars.by_ref().take_while(|c| *c != ';').for_each(drop);
+    loop {
+        let name = chars.by_ref().take_while(|c| *c != '=').collect::<String>();
+        let name = name.trim().trim_start_matches(';').trim();
+        if name.is_empty() {
+            break;
+        }
+        while chars.next_if(|c| c.is_whitespace()).is_some() {}
+        let value = if chars.next_if_eq(&'"').is_some() {
+            let mut value = String::new();
+            while let Some(c) = chars.next() {
+                match c {
+                    '"' => break,
+                    '\\' => value.extend(chars.next()),
+                    c => value.push(c),
+                }
+            }
+            chars.by_ref().take_while(|c| *c != ';').for_each(drop);
+            value
+        } else {
+            let value = chars.by_ref().take_while(|c| *c != ';').collect::<String>();
+            value.trim().to_owned()
+        };
+        params.push((name.to_owned(), value));
+    }
+    params
+}
+
+/// The position of the first occurrence of the needle in the haystack.
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack
+        .windows(needle.len())
+        .position(|window| window == needle)
+}
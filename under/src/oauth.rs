@@ -0,0 +1,545 @@
+//! OAuth 2.0 and OIDC clients.
+//!
+//! This provides an [`OAuthClient`] for the authorization code flow (with
+//! PKCE): it generates authorization URLs, exchanges codes for tokens, and
+//! refreshes tokens.  It also provides ready-made login and callback
+//! endpoints, which keep the flow's state in the session (see
+//! [`crate::middleware::SessionMiddleware`]), and stash the resulting tokens
+//! there too.  It is gated behind the `oauth` feature flag for those who do
+//! not want to use it.
+//!
+//! # Examples
+//! ```rust,no_run
+//! # use under::*;
+//! use under::middleware::{MemorySessionStore, SessionMiddleware};
+//! use under::oauth::OAuthClient;
+//!
+//! async fn home(request: Request) -> Response {
+//!     match under::oauth::tokens(request.session().unwrap()) {
+//!         Some(tokens) => Response::text(format!("logged in ({})", tokens.token_type)),
+//!         None => Response::text("not logged in"),
+//!     }
+//! }
+//!
+//! # #[tokio::main] async fn main() -> Result<(), anyhow::Error> {
+//! let client = OAuthClient::new(
+//!     "my-client-id",
+//!     "https://accounts.example.com/authorize",
+//!     "https://accounts.example.com/token",
+//! )?
+//! .with_client_secret("my-client-secret")
+//! .with_redirect_uri("https://app.example.com/auth/callback")
+//! .with_scopes(["openid", "email"]);
+//!
+//! let mut http = under::http();
+//! http.with(SessionMiddleware::new(MemorySessionStore::new()));
+//! http.at("/").get(home);
+//! http.at("/auth/login").get(client.login_endpoint());
+//! http.at("/auth/callback").get(client.callback_endpoint());
+//! http.listen("0.0.0.0:8080").await?;
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::client::Client;
+use crate::middleware::Session;
+use crate::{Endpoint, HttpEntity, Request, Response};
+use base64::Engine;
+use std::pin::Pin;
+use std::sync::Arc;
+
+/// The session key the tokens are stored under by the callback endpoint.
+pub const TOKEN_KEY: &str = "oauth.token";
+/// The session key the flow's state is stored under by the login endpoint.
+const FLOW_KEY: &str = "oauth.flow";
+/// The most bytes read from a response of the token endpoint.
+const TOKEN_RESPONSE_LIMIT: u64 = 64 * 1024;
+
+#[derive(Debug, thiserror::Error)]
+#[non_exhaustive]
+/// An error that occurred during an OAuth flow.
+pub enum OAuthError {
+    /// One of the URLs given to the client was invalid.
+    #[error("invalid url")]
+    InvalidUrl(#[from] http::uri::InvalidUri),
+    /// The request to the token endpoint failed.
+    #[error("could not reach the token endpoint")]
+    Request(#[source] crate::UnderError),
+    /// The token endpoint returned an error.
+    #[error("the token endpoint returned an error: {error}")]
+    Provider {
+        /// The error code, e.g. `invalid_grant`.
+        error: String,
+        /// The description of the error, if the provider gave one.
+        description: Option<String>,
+    },
+    /// The token endpoint returned a response that could not be understood.
+    #[error("the token endpoint returned an invalid response")]
+    InvalidResponse(#[source] anyhow::Error),
+    /// The `state` given to the callback did not match the one stored in the
+    /// session - either the flow was never started, or the callback was
+    /// forged.
+    #[error("the state of the callback did not match the session")]
+    StateMismatch,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+/// The tokens returned by the token endpoint.
+pub struct TokenResponse {
+    /// The access token.
+    pub access_token: String,
+    /// The type of the access token; usually `Bearer`.
+    pub token_type: String,
+    /// The number of seconds the access token is valid for, if the provider
+    /// gave one.
+    #[serde(default)]
+    pub expires_in: Option<u64>,
+    /// The refresh token, if the provider gave one.
+    #[serde(default)]
+    pub refresh_token: Option<String>,
+    /// The scopes of the access token, if they differ from the ones that
+    /// were requested.
+    #[serde(default)]
+    pub scope: Option<String>,
+    /// The OIDC ID token, if the `openid` scope was requested.
+    /// Its signature is not verified; since it was received directly from
+    /// the token endpoint, it is only as trustworthy as that connection -
+    /// so the token endpoint should be an `https` URL.
+    #[serde(default)]
+    pub id_token: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+/// The start of an authorization code flow.
+///
+/// This is generated by [`OAuthClient::authorize`].  The user should be
+/// redirected to the URL, and the state and verifier kept (e.g. in the
+/// session) until the callback, so that the code can be exchanged with
+/// [`OAuthClient::exchange_code`].
+pub struct AuthorizationRequest {
+    /// The URL to redirect the user to.
+    pub url: String,
+    /// The random `state` parameter, which the provider echoes back to the
+    /// callback.  It must be checked against the callback, to protect
+    /// against forged callbacks.
+    pub state: String,
+    /// The PKCE code verifier, which must be given to the token endpoint
+    /// with the code.
+    pub pkce_verifier: String,
+}
+
+#[derive(Clone)]
+/// A client for an OAuth 2.0 (or OIDC) provider.
+///
+/// This is cheap to clone - all clones share the same configuration and
+/// connection pool.  The client authenticates to the token endpoint by
+/// including its secret (if any) in the body of the request.
+pub struct OAuthClient {
+    config: Arc<Config>,
+    http: Client,
+}
+
+#[derive(Debug, Clone)]
+struct Config {
+    client_id: String,
+    client_secret: Option<String>,
+    authorize_url: http::Uri,
+    token_url: http::Uri,
+    redirect_uri: Option<String>,
+    scopes: Vec<String>,
+    after_login: String,
+}
+
+impl OAuthClient {
+    /// Creates a new client, with the given client ID, authorization
+    /// endpoint, and token endpoint.
+    ///
+    /// # Errors
+    /// This errors if either of the given URLs is invalid.
+    pub fn new(client_id: &str, authorize_url: &str, token_url: &str) -> Result<Self, OAuthError> {
+        Ok(OAuthClient {
+            config: Arc::new(Config {
+                client_id: client_id.to_owned(),
+                client_secret: None,
+                authorize_url: authorize_url.parse()?,
+                token_url: token_url.parse()?,
+                redirect_uri: None,
+                scopes: vec![],
+                after_login: "/".to_owned(),
+            }),
+            http: Client::new(),
+        })
+    }
+
+    /// Sets the client secret.  Public clients (e.g. those that cannot keep
+    /// a secret) should not set one, and rely on PKCE instead.
+    #[must_use]
+    pub fn with_client_secret(mut self, secret: &str) -> Self {
+        Arc::make_mut(&mut self.config).client_secret = Some(secret.to_owned());
+        self
+    }
+
+    /// Sets the redirect URI - the URL of the callback endpoint, as
+    /// registered with the provider.
+    #[must_use]
+    pub fn with_redirect_uri(mut self, uri: &str) -> Self {
+        Arc::make_mut(&mut self.config).redirect_uri = Some(uri.to_owned());
+        self
+    }
+
+    /// Sets the scopes to request.  For OIDC, this should include
+    /// `openid`.
+    #[must_use]
+    pub fn with_scopes<I, S>(mut self, scopes: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        Arc::make_mut(&mut self.config).scopes = scopes.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Sets where the callback endpoint redirects to once the user has
+    /// logged in.  This defaults to `/`.
+    #[must_use]
+    pub fn with_after_login(mut self, location: &str) -> Self {
+        location.clone_into(&mut Arc::make_mut(&mut self.config).after_login);
+        self
+    }
+
+    /// Starts an authorization code flow, generating a random state and PKCE
+    /// verifier (using the `S256` challenge method).
+    ///
+    /// # Examples
+    /// ```rust
+    /// use under::oauth::OAuthClient;
+    /// let client = OAuthClient::new(
+    ///     "client",
+    ///     "https://example.com/authorize",
+    ///     "https://example.com/token",
+    /// ).unwrap().with_scopes(["openid", "email"]);
+    /// let request = client.authorize();
+    /// assert!(request.url.starts_with(
+    ///     "https://example.com/authorize?response_type=code&client_id=client&scope=openid+email&state="
+    /// ));
+    /// assert!(request.url.ends_with("&code_challenge_method=S256"));
+    /// assert_ne!(request.state, client.authorize().state);
+    /// ```
+    #[must_use]
+    pub fn authorize(&self) -> AuthorizationRequest {
+        let state = random_token();
+        let pkce_verifier = random_token();
+        let challenge = base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(
+            <sha2::Sha256 as sha2::Digest>::digest(pkce_verifier.as_bytes()),
+        );
+
+        let mut query = form_urlencoded::Serializer::new(String::new());
+        query
+            .append_pair("response_type", "code")
+            .append_pair("client_id", &self.config.client_id);
+        if let Some(redirect_uri) = &self.config.redirect_uri {
+            query.append_pair("redirect_uri", redirect_uri);
+        }
+        if !self.config.scopes.is_empty() {
+            query.append_pair("scope", &self.config.scopes.join(" "));
+        }
+        query
+            .append_pair("state", &state)
+            .append_pair("code_challenge", &challenge)
+            .append_pair("code_challenge_method", "S256");
+
+        let base = self.config.authorize_url.to_string();
+        let separator = if self.config.authorize_url.query().is_some() {
+            '&'
+        } else {
+            '?'
+        };
+
+        AuthorizationRequest {
+            url: format!("{base}{separator}{}", query.finish()),
+            state,
+            pkce_verifier,
+        }
+    }
+
+    /// Exchanges the code given to the callback for tokens, using the PKCE
+    /// verifier of the flow.
+    ///
+    /// # Errors
+    /// This errors if the token endpoint could not be reached, if it returned
+    /// an error, or if its response could not be understood (including a
+    /// response larger than 64KiB).
+    pub async fn exchange_code(
+        &self,
+        code: &str,
+        pkce_verifier: &str,
+    ) -> Result<TokenResponse, OAuthError> {
+        let mut params = vec![
+            ("grant_type", "authorization_code"),
+            ("code", code),
+            ("code_verifier", pkce_verifier),
+        ];
+        if let Some(redirect_uri) = &self.config.redirect_uri {
+            params.push(("redirect_uri", redirect_uri));
+        }
+        self.token_request(params).await
+    }
+
+    /// Exchanges a refresh token for new tokens.  Note that the provider may
+    /// not return a new refresh token, in which case the old one should be
+    /// kept.
+    ///
+    /// # Errors
+    /// This errors for the same reasons as [`OAuthClient::exchange_code`].
+    pub async fn refresh(&self, refresh_token: &str) -> Result<TokenResponse, OAuthError> {
+        self.token_request(vec![
+            ("grant_type", "refresh_token"),
+            ("refresh_token", refresh_token),
+        ])
+        .await
+    }
+
+    async fn token_request(&self, params: Vec<(&str, &str)>) -> Result<TokenResponse, OAuthError> {
+        let body = {
+            let mut body = form_urlencoded::Serializer::new(String::new());
+            body.extend_pairs(params)
+                .append_pair("client_id", &self.config.client_id);
+            if let Some(secret) = &self.config.client_secret {
+                body.append_pair("client_secret", secret);
+            }
+            body.finish()
+        };
+
+        let request = http::Request::post(self.config.token_url.clone())
+            .header(
+                http::header::CONTENT_TYPE,
+                "application/x-www-form-urlencoded",
+            )
+            .header(http::header::ACCEPT, "application/json")
+            .body(crate::Body::from(body))
+            .map_err(|e| OAuthError::InvalidResponse(e.into()))?;
+        let mut response = self
+            .http
+            .send(request.into())
+            .await
+            .map_err(OAuthError::Request)?;
+        let status = response.status();
+        let bytes = response
+            .data(TOKEN_RESPONSE_LIMIT)
+            .into_bytes()
+            .await
+            .map_err(|e| OAuthError::InvalidResponse(e.into()))?;
+
+        if status.is_success() {
+            return serde_json::from_slice(&bytes)
+                .map_err(|e| OAuthError::InvalidResponse(e.into()));
+        }
+
+        match serde_json::from_slice::<ErrorResponse>(&bytes) {
+            Ok(e) => Err(OAuthError::Provider {
+                error: e.error,
+                description: e.error_description,
+            }),
+            Err(_) => Err(OAuthError::InvalidResponse(anyhow::anyhow!(
+                "the token endpoint responded with {status}"
+            ))),
+        }
+    }
+
+    /// Creates an endpoint that starts the flow.  It stores the state and
+    /// PKCE verifier of the flow in the session, and redirects the user to
+    /// the provider.  This requires the
+    /// [`crate::middleware::SessionMiddleware`].
+    #[must_use]
+    pub fn login_endpoint(&self) -> impl Endpoint {
+        LoginEndpoint(self.clone())
+    }
+
+    /// Creates an endpoint that completes the flow.  It checks the state of
+    /// the callback against the session, exchanges the code for tokens,
+    /// stores them in the session (see [`tokens`]), regenerates the session
+    /// ID, and then redirects the user (see [`OAuthClient::with_after_login`]).
+    /// A callback with a mismatched state receives a 400; a callback with an
+    /// error from the provider (e.g. the user denied access) receives a 401;
+    /// and a failed exchange receives a 502.  This requires the
+    /// [`crate::middleware::SessionMiddleware`].
+    ///
+    /// # Examples
+    /// ```rust
+    /// # use under::*;
+    /// use under::middleware::{MemorySessionStore, SessionMiddleware};
+    /// use under::oauth::OAuthClient;
+    ///
+    /// fn cookie(response: &Response) -> String {
+    ///     let header = response.header("Set-Cookie").unwrap().to_str().unwrap();
+    ///     header.split(';').next().unwrap().to_owned()
+    /// }
+    ///
+    /// # #[tokio::main] async fn main() -> Result<(), anyhow::Error> {
+    /// // a stand-in for the provider's token endpoint.
+    /// let mut provider = under::http();
+    /// provider.at("/token").post(|mut request: Request| async move {
+    ///     let body = request.data(4096).into_text().await?;
+    ///     assert!(body.starts_with("grant_type=authorization_code&code=abc&code_verifier="));
+    ///     Ok::<_, anyhow::Error>(Response::json(&serde_json::json!({
+    ///         "access_token": "token", "token_type": "Bearer", "expires_in": 3600
+    ///     }))?)
+    /// });
+    /// let listener = Listener::bind("127.0.0.1:0")?;
+    /// let address = listener.local_addr()?;
+    /// tokio::spawn(provider.listen_on(listener));
+    ///
+    /// let client = OAuthClient::new(
+    ///     "client",
+    ///     "https://provider.example.com/authorize",
+    ///     &format!("http://{address}/token"),
+    /// )?;
+    /// let mut http = under::http();
+    /// http.with(SessionMiddleware::new(MemorySessionStore::new()));
+    /// http.at("/login").get(client.login_endpoint());
+    /// http.at("/callback").get(client.callback_endpoint());
+    /// http.at("/").get(|request: Request| async move {
+    ///     let tokens = under::oauth::tokens(request.session().unwrap());
+    ///     Response::text(tokens.map_or("anonymous".to_owned(), |t| t.access_token))
+    /// });
+    /// http.prepare();
+    ///
+    /// let response = http.handle(Request::get("/login")?).await?;
+    /// assert_eq!(response.status(), http::StatusCode::SEE_OTHER);
+    /// let location = response.header("Location").unwrap().to_str()?;
+    /// let state = location.split("state=").nth(1).unwrap().split('&').next().unwrap();
+    /// let session = cookie(&response);
+    ///
+    /// let forged = Request::get("/callback?code=abc&state=forged")?
+    ///     .with_header("Cookie", &session)?;
+    /// assert_eq!(http.handle(forged).await?.status(), http::StatusCode::BAD_REQUEST);
+    ///
+    /// // the forged callback ended the flow, so start it again.
+    /// let request = Request::get("/login")?.with_header("Cookie", &session)?;
+    /// let response = http.handle(request).await?;
+    /// let location = response.header("Location").unwrap().to_str()?;
+    /// let state = location.split("state=").nth(1).unwrap().split('&').next().unwrap();
+    /// let request = Request::get(format!("/callback?code=abc&state={state}"))?
+    ///     .with_header("Cookie", &session)?;
+    /// let response = http.handle(request).await?;
+    /// assert_eq!(response.status(), http::StatusCode::SEE_OTHER);
+    /// let session = cookie(&response);
+    ///
+    /// let request = Request::get("/")?.with_header("Cookie", &session)?;
+    /// let mut response = http.handle(request).await?;
+    /// assert_eq!(response.data(512).into_text().await?, "token");
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[must_use]
+    pub fn callback_endpoint(&self) -> impl Endpoint {
+        CallbackEndpoint(self.clone())
+    }
+}
+
+/// Returns the tokens stored in the session by the callback endpoint, if
+/// any.
+#[must_use]
+pub fn tokens(session: &Session) -> Option<TokenResponse> {
+    session.get(TOKEN_KEY)
+}
+
+/// Generates a random, URL-safe token, with 256 bits of entropy.
+fn random_token() -> String {
+    let mut bytes = [0u8; 32];
+    rand::RngCore::fill_bytes(&mut rand::thread_rng(), &mut bytes);
+    base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(bytes)
+}
+
+fn session(request: &Request) -> Result<&Session, anyhow::Error> {
+    request
+        .session()
+        .ok_or_else(|| anyhow::anyhow!("the oauth endpoints require the session middleware"))
+}
+
+/// An error response from the token endpoint.
+#[derive(serde::Deserialize)]
+struct ErrorResponse {
+    error: String,
+    error_description: Option<String>,
+}
+
+struct LoginEndpoint(OAuthClient);
+
+#[async_trait]
+impl Endpoint for LoginEndpoint {
+    async fn apply(self: Pin<&Self>, request: Request) -> Result<Response, anyhow::Error> {
+        let authorization = self.0.authorize();
+        session(&request)?.insert(FLOW_KEY, &authorization)?;
+        Ok(Response::see_other(authorization.url)?)
+    }
+
+    fn describe(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("LoginEndpoint").field(&self.0).finish()
+    }
+}
+
+struct CallbackEndpoint(OAuthClient);
+
+#[derive(serde::Deserialize)]
+struct CallbackQuery {
+    code: Option<String>,
+    state: Option<String>,
+    error: Option<String>,
+}
+
+#[async_trait]
+impl Endpoint for CallbackEndpoint {
+    async fn apply(self: Pin<&Self>, request: Request) -> Result<Response, anyhow::Error> {
+        // This is cloned, so that the request isn't borrowed across the
+        // exchange.
+        let session = session(&request)?.clone();
+        let flow = session.get::<AuthorizationRequest>(FLOW_KEY);
+        session.remove(FLOW_KEY);
+        let query = request.query::<CallbackQuery>().unwrap_or(CallbackQuery {
+            code: None,
+            state: None,
+            error: None,
+        });
+
+        let Some(flow) = flow.filter(|flow| query.state.as_ref() == Some(&flow.state)) else {
+            log::warn!("oauth callback: {}", OAuthError::StateMismatch);
+            return Ok(Response::empty_400());
+        };
+        if let Some(error) = query.error {
+            log::info!("oauth callback: the provider returned {error}");
+            return Ok(Response::empty(http::StatusCode::UNAUTHORIZED));
+        }
+        let Some(code) = query.code else {
+            return Ok(Response::empty_400());
+        };
+
+        match self.0.exchange_code(&code, &flow.pkce_verifier).await {
+            Ok(tokens) => {
+                session.insert(TOKEN_KEY, &tokens)?;
+                session.regenerate_id();
+                Ok(Response::see_other(self.0.config.after_login.as_str())?)
+            }
+            Err(error) => {
+                log::warn!("oauth callback: {error}");
+                Ok(Response::empty(http::StatusCode::BAD_GATEWAY))
+            }
+        }
+    }
+
+    fn describe(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("CallbackEndpoint").field(&self.0).finish()
+    }
+}
+
+impl std::fmt::Debug for OAuthClient {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("OAuthClient")
+            .field("client_id", &self.config.client_id)
+            .field("authorize_url", &self.config.authorize_url)
+            .field("token_url", &self.config.token_url)
+            .field("redirect_uri", &self.config.redirect_uri)
+            .field("scopes", &self.config.scopes)
+            .finish_non_exhaustive()
+    }
+}
@@ -0,0 +1,242 @@
+//! Parsing of the `Range` header.
+//!
+//! This is for endpoints that serve partial content (e.g. files, or blobs
+//! from a store): [`parse`] turns the `Range` header of a request into the
+//! validated byte ranges that should be sent, given the length of the
+//! content.
+//!
+//! # Examples
+//! ```rust
+//! # use under::*;
+//! use under::range::{self, RangeError};
+//!
+//! let content = b"hello, world";
+//! let response = match range::parse("bytes=0-4", content.len() as u64) {
+//!     Ok(ranges) if ranges.len() == 1 => {
+//!         let range = ranges[0];
+//!         Response::empty(http::StatusCode::PARTIAL_CONTENT)
+//!             .with_header("Content-Range", range.content_range(content.len() as u64))?
+//!             .with_body(content[range.start as usize..=range.end as usize].to_vec())
+//!     }
+//!     Err(err @ RangeError::Unsatisfiable) => err.to_response(content.len() as u64),
+//!     // Invalid headers (and, here, multiple ranges) are ignored, and the
+//!     // whole content is sent.
+//!     _ => Response::empty_200().with_body(content.to_vec()),
+//! };
+//! assert_eq!(response.status(), http::StatusCode::PARTIAL_CONTENT);
+//! assert_eq!(response.header("Content-Range").unwrap(), "bytes 0-4/12");
+//! # Ok::<(), anyhow::Error>(())
+//! ```
+
+use crate::Response;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+/// A range of bytes of some content.  Both ends are inclusive, as they are
+/// in the header; so `bytes=0-0` is the range with only the first byte.
+pub struct ByteRange {
+    /// The offset of the first byte of the range.
+    pub start: u64,
+    /// The offset of the last byte of the range.
+    pub end: u64,
+}
+
+impl ByteRange {
+    /// The number of bytes in the range.  This is never zero.
+    #[must_use]
+    #[allow(clippy::len_without_is_empty)]
+    pub fn len(&self) -> u64 {
+        self.end - self.start + 1
+    }
+
+    /// The value of the `Content-Range` header for this range of content of
+    /// the given length (e.g. `bytes 0-499/1234`).
+    ///
+    /// # Examples
+    /// ```rust
+    /// # use under::range::ByteRange;
+    /// let range = ByteRange { start: 0, end: 499 };
+    /// assert_eq!(range.content_range(1234), "bytes 0-499/1234");
+    /// ```
+    #[must_use]
+    pub fn content_range(&self, total_len: u64) -> String {
+        format!("bytes {}-{}/{total_len}", self.start, self.end)
+    }
+}
+
+#[derive(thiserror::Error, Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+/// Errors from parsing a `Range` header.
+pub enum RangeError {
+    #[error("the range header is invalid")]
+    /// The header is not a valid byte range header.  These headers should be
+    /// ignored, and the whole content sent instead.
+    Invalid,
+    #[error("none of the ranges of the range header can be satisfied")]
+    /// The header is valid, but none of its ranges overlap the content (e.g.
+    /// `bytes=500-` for content of 100 bytes).  This should be responded to
+    /// with a 416 (see [`RangeError::to_response`]).
+    Unsatisfiable,
+}
+
+impl RangeError {
+    /// The response for this error, for content of the given length.  For
+    /// [`RangeError::Unsatisfiable`], this is a 416, with the
+    /// `Content-Range` header that the RFC requires; for
+    /// [`RangeError::Invalid`], this is a 400 - although it is usually
+    /// better to ignore the header, and send the whole content.
+    pub fn to_response(&self, total_len: u64) -> Response {
+        match self {
+            RangeError::Invalid => Response::empty_400(),
+            RangeError::Unsatisfiable => {
+                let mut response = Response::empty(http::StatusCode::RANGE_NOT_SATISFIABLE);
+                if let Ok(value) = http::HeaderValue::try_from(format!("bytes */{total_len}")) {
+                    response
+                        .headers_mut()
+                        .insert(http::header::CONTENT_RANGE, value);
+                }
+                response
+            }
+        }
+    }
+}
+
+/// Parses the value of a `Range` header, for content of the given length.
+///
+/// This supports multiple ranges (`bytes=0-99,200-299`), open-ended ranges
+/// (`bytes=100-`), and suffix ranges (`bytes=-100`, the last 100 bytes).
+/// Ranges that extend past the end of the content are cut off at the end;
+/// and ranges that start past the end of it are left out, unless all of them
+/// are, in which case this returns [`RangeError::Unsatisfiable`].  The
+/// ranges are returned in the order they were requested, and are not merged
+/// even if they overlap.
+///
+/// # Errors
+/// This returns [`RangeError::Invalid`] if the header is not a valid byte
+/// range header, and [`RangeError::Unsatisfiable`] if none of its ranges
+/// overlap the content.
+///
+/// # Examples
+/// ```rust
+/// use under::range::{parse, ByteRange, RangeError};
+///
+/// assert_eq!(
+///     parse("bytes=0-99, -10", 1000),
+///     Ok(vec![ByteRange { start: 0, end: 99 }, ByteRange { start: 990, end: 999 }])
+/// );
+/// assert_eq!(parse("bytes=900-", 500), Err(RangeError::Unsatisfiable));
+/// assert_eq!(parse("items=0-1", 500), Err(RangeError::Invalid));
+/// ```
+pub fn parse(header: &str, total_len: u64) -> Result<Vec<ByteRange>, RangeError> {
+    let (unit, specs) = header.split_once('=').ok_or(RangeError::Invalid)?;
+    if !unit.trim().eq_ignore_ascii_case("bytes") {
+        return Err(RangeError::Invalid);
+    }
+
+    let mut ranges = vec![];
+    let mut any = false;
+    // Empty elements of the list are allowed, and ignored.
+    for spec in specs.split(',').map(str::trim).filter(|s| !s.is_empty()) {
+        any = true;
+        if let Some(range) = parse_spec(spec, total_len)? {
+            ranges.push(range);
+        }
+    }
+
+    if !any {
+        Err(RangeError::Invalid)
+    } else if ranges.is_empty() {
+        Err(RangeError::Unsatisfiable)
+    } else {
+        Ok(ranges)
+    }
+}
+
+/// Parses a single range of the header, returning `None` if it is valid but
+/// does not overlap the content.
+fn parse_spec(spec: &str, total_len: u64) -> Result<Option<ByteRange>, RangeError> {
+    fn number(value: &str) -> Result<u64, RangeError> {
+        if value.is_empty() || !value.bytes().all(|b| b.is_ascii_digit()) {
+            return Err(RangeError::Invalid);
+        }
+        // Numbers too large to fit are still valid, they are just past the
+        // end of any content.
+        Ok(value.parse().unwrap_or(u64::MAX))
+    }
+
+    let (start, end) = spec.split_once('-').ok_or(RangeError::Invalid)?;
+    let (start, end) = (start.trim(), end.trim());
+    if start.is_empty() {
+        let suffix = number(end)?;
+        if suffix == 0 || total_len == 0 {
+            return Ok(None);
+        }
+        return Ok(Some(ByteRange {
+            start: total_len.saturating_sub(suffix),
+            end: total_len - 1,
+        }));
+    }
+
+    let start = number(start)?;
+    let end = if end.is_empty() {
+        None
+    } else {
+        Some(number(end)?)
+    };
+    if end.is_some_and(|end| end < start) {
+        return Err(RangeError::Invalid);
+    }
+    if start >= total_len {
+        return Ok(None);
+    }
+    Ok(Some(ByteRange {
+        start,
+        end: end.map_or(total_len - 1, |end| end.min(total_len - 1)),
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn range(start: u64, end: u64) -> ByteRange {
+        ByteRange { start, end }
+    }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(parse("bytes=0-0", 10), Ok(vec![range(0, 0)]));
+        assert_eq!(parse("bytes=5-100", 10), Ok(vec![range(5, 9)]));
+        assert_eq!(parse("bytes=5-", 10), Ok(vec![range(5, 9)]));
+        assert_eq!(parse("bytes=-3", 10), Ok(vec![range(7, 9)]));
+        assert_eq!(parse("bytes=-30", 10), Ok(vec![range(0, 9)]));
+        assert_eq!(
+            parse("Bytes = 1-2, ,3-4", 10),
+            Ok(vec![range(1, 2), range(3, 4)])
+        );
+        assert_eq!(parse("bytes=20-30,1-2", 10), Ok(vec![range(1, 2)]));
+        assert_eq!(
+            parse("bytes=99999999999999999999999-", 10),
+            Err(RangeError::Unsatisfiable)
+        );
+        assert_eq!(parse("bytes=10-", 10), Err(RangeError::Unsatisfiable));
+        assert_eq!(parse("bytes=-0", 10), Err(RangeError::Unsatisfiable));
+        assert_eq!(parse("bytes=0-", 0), Err(RangeError::Unsatisfiable));
+    }
+
+    #[test]
+    fn test_parse_invalid() {
+        for header in [
+            "bytes",
+            "bytes=",
+            "bytes=,",
+            "bytes=a-b",
+            "bytes=5-1",
+            "bytes=1",
+            "bytes=-",
+            "bytes=+1-2",
+            "lines=1-2",
+        ] {
+            assert_eq!(parse(header, 10), Err(RangeError::Invalid), "{header}");
+        }
+    }
+}
@@ -0,0 +1,323 @@
+//! Redaction of secrets from what is logged about requests.
+//!
+//! Headers such as `Authorization`, query parameters such as `access_token`,
+//! and session cookies carry secrets that should never be written to a log.
+//! A [`Redactor`] knows which of them are sensitive, and replaces their
+//! values with [`REDACTED`] before they are logged; it is shared by the
+//! [`crate::middleware::TraceMiddleware`], the
+//! [`crate::middleware::AccessLog`], and the errors logged by the router
+//! (see [`crate::Router::redactor`]).
+//!
+//! # Examples
+//! ```rust
+//! use under::redact::Redactor;
+//!
+//! let redactor = Redactor::default().with_query_param("signature");
+//! assert_eq!(
+//!     redactor.redact_uri("/download?file=a.txt&signature=abc123"),
+//!     "/download?file=a.txt&signature=[REDACTED]"
+//! );
+//! assert_eq!(
+//!     redactor.redact_header(&http::header::AUTHORIZATION, "Bearer abc123"),
+//!     "[REDACTED]"
+//! );
+//! ```
+
+use std::borrow::Cow;
+use std::sync::Arc;
+
+/// What a redacted value is replaced with.
+pub const REDACTED: &str = "[REDACTED]";
+
+/// The headers that [`Redactor::default`] redacts.
+const DEFAULT_HEADERS: &[&str] = &[
+    "authorization",
+    "proxy-authorization",
+    "cookie",
+    "set-cookie",
+    "x-api-key",
+];
+
+/// The query parameters that [`Redactor::default`] redacts.
+const DEFAULT_QUERY: &[&str] = &[
+    "access_token",
+    "api_key",
+    "client_secret",
+    "password",
+    "refresh_token",
+    "secret",
+    "token",
+];
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+/// The kind of value that a custom rule (see [`Redactor::with_rule`]) is
+/// asked about.
+pub enum Field {
+    /// A header; the name is lowercase.
+    Header,
+    /// A query parameter, as it appears in the URI (i.e. not decoded).
+    Query,
+    /// A cookie, from a `Cookie` or `Set-Cookie` header.
+    Cookie,
+}
+
+type Rule = Arc<dyn Fn(Field, &str, &str) -> bool + Send + Sync>;
+
+#[derive(Clone)]
+/// Decides which headers, query parameters, and cookies are sensitive, and
+/// redacts their values.
+///
+/// Headers and query parameters are matched case-insensitively, and cookies
+/// case-sensitively (as cookie names are).  Cookies are only looked at
+/// individually if the `Cookie` or `Set-Cookie` header is not itself
+/// redacted; as [`Redactor::default`] redacts both, to only redact some
+/// cookies, start from [`Redactor::new`].  Custom rules (see
+/// [`Redactor::with_rule`]) are consulted for every value that is not
+/// already redacted by name.
+///
+/// This is cheaply cloneable.
+///
+/// # Examples
+/// ```rust
+/// use under::redact::{Field, Redactor};
+///
+/// let redactor = Redactor::new()
+///     .with_header("x-internal-token")
+///     .with_cookie("session")
+///     // Anything that looks like a JWT is redacted, wherever it is.
+///     .with_rule(|_, _, value| value.starts_with("eyJ"));
+/// assert_eq!(
+///     redactor.redact_header(&http::header::COOKIE, "session=abc; theme=dark"),
+///     "session=[REDACTED]; theme=dark"
+/// );
+/// assert_eq!(redactor.redact_uri("/?id=eyJhbGci.e30.sig"), "/?id=[REDACTED]");
+/// assert_eq!(redactor.redact_uri("/?id=42"), "/?id=42");
+/// ```
+pub struct Redactor {
+    headers: Vec<http::HeaderName>,
+    query: Vec<String>,
+    cookies: Vec<String>,
+    rules: Vec<Rule>,
+}
+
+impl Redactor {
+    /// Creates a redactor that redacts nothing, to be configured from
+    /// scratch.  See [`Redactor::default`] for one that redacts common
+    /// secrets.
+    #[must_use]
+    pub fn new() -> Self {
+        Redactor {
+            headers: vec![],
+            query: vec![],
+            cookies: vec![],
+            rules: vec![],
+        }
+    }
+
+    /// Adds a header to redact.
+    ///
+    /// # Panics
+    /// Panics if the name is not a valid header name.
+    #[must_use]
+    pub fn with_header<N: AsRef<str>>(mut self, name: N) -> Self {
+        let name = http::HeaderName::try_from(name.as_ref()).expect("invalid header name");
+        self.headers.push(name);
+        self
+    }
+
+    /// Adds a query parameter to redact.
+    #[must_use]
+    pub fn with_query_param<N: Into<String>>(mut self, name: N) -> Self {
+        self.query.push(name.into());
+        self
+    }
+
+    /// Adds a cookie to redact.
+    #[must_use]
+    pub fn with_cookie<N: Into<String>>(mut self, name: N) -> Self {
+        self.cookies.push(name.into());
+        self
+    }
+
+    /// Adds a custom rule, which is given the kind, the name, and the value
+    /// of every header, query parameter, and cookie that is not already
+    /// redacted, and returns whether to redact it.
+    #[must_use]
+    pub fn with_rule<F>(mut self, rule: F) -> Self
+    where
+        F: Fn(Field, &str, &str) -> bool + Send + Sync + 'static,
+    {
+        self.rules.push(Arc::new(rule));
+        self
+    }
+
+    /// Whether the given header is sensitive.
+    #[must_use]
+    pub fn is_sensitive_header(&self, name: &http::HeaderName) -> bool {
+        self.headers.contains(name)
+    }
+
+    /// Whether the given query parameter is sensitive, by name.
+    #[must_use]
+    pub fn is_sensitive_query_param(&self, name: &str) -> bool {
+        self.query
+            .iter()
+            .any(|param| param.eq_ignore_ascii_case(name))
+    }
+
+    /// Whether the given cookie is sensitive, by name.
+    #[must_use]
+    pub fn is_sensitive_cookie(&self, name: &str) -> bool {
+        self.cookies.iter().any(|cookie| cookie == name)
+    }
+
+    fn matches_rule(&self, field: Field, name: &str, value: &str) -> bool {
+        self.rules.iter().any(|rule| rule(field, name, value))
+    }
+
+    /// Redacts the value of the given header, if it is sensitive.  For the
+    /// `Cookie` and `Set-Cookie` headers, if they are not sensitive
+    /// themselves, this redacts the value of each sensitive cookie instead.
+    ///
+    /// # Examples
+    /// ```rust
+    /// use under::redact::Redactor;
+    /// let redactor = Redactor::default();
+    /// assert_eq!(redactor.redact_header(&http::header::ACCEPT, "*/*"), "*/*");
+    /// assert_eq!(redactor.redact_header(&http::header::COOKIE, "a=b"), "[REDACTED]");
+    /// ```
+    #[must_use]
+    pub fn redact_header<'v>(&self, name: &http::HeaderName, value: &'v str) -> Cow<'v, str> {
+        if self.is_sensitive_header(name) || self.matches_rule(Field::Header, name.as_str(), value)
+        {
+            Cow::Borrowed(REDACTED)
+        } else if name == http::header::COOKIE {
+            self.redact_pairs(value, "; ", Field::Cookie)
+        } else if name == http::header::SET_COOKIE {
+            // Only the first pair is the cookie; the rest are its attributes.
+            match value.split_once(';') {
+                Some((cookie, attributes)) => {
+                    match self.redact_pairs(cookie, "; ", Field::Cookie) {
+                        Cow::Borrowed(_) => Cow::Borrowed(value),
+                        Cow::Owned(cookie) => Cow::Owned(format!("{cookie};{attributes}")),
+                    }
+                }
+                None => self.redact_pairs(value, "; ", Field::Cookie),
+            }
+        } else {
+            Cow::Borrowed(value)
+        }
+    }
+
+    /// Redacts the values of the sensitive query parameters in the given
+    /// URI (or any string with a query, such as a `Referer` header).
+    ///
+    /// # Examples
+    /// ```rust
+    /// use under::redact::Redactor;
+    /// let redactor = Redactor::default();
+    /// assert_eq!(redactor.redact_uri("/a?Token=1&b=2"), "/a?Token=[REDACTED]&b=2");
+    /// assert_eq!(redactor.redact_uri("/a?b=2"), "/a?b=2");
+    /// assert_eq!(
+    ///     redactor.redact_uri("/a?pass%77ord=1"),
+    ///     "/a?pass%77ord=[REDACTED]"
+    /// );
+    /// ```
+    #[must_use]
+    pub fn redact_uri<'u>(&self, uri: &'u str) -> Cow<'u, str> {
+        let Some((path, rest)) = uri.split_once('?') else {
+            return Cow::Borrowed(uri);
+        };
+        let (query, fragment) = match rest.split_once('#') {
+            Some((query, fragment)) => (query, Some(fragment)),
+            None => (rest, None),
+        };
+        match self.redact_pairs(query, "&", Field::Query) {
+            Cow::Borrowed(_) => Cow::Borrowed(uri),
+            Cow::Owned(query) => match fragment {
+                Some(fragment) => Cow::Owned(format!("{path}?{query}#{fragment}")),
+                None => Cow::Owned(format!("{path}?{query}")),
+            },
+        }
+    }
+
+    /// Redacts the sensitive values of the `name=value` pairs of the list,
+    /// separated by the given separator (where any whitespace around each
+    /// pair is ignored).
+    fn redact_pairs<'v>(&self, list: &'v str, separator: &str, field: Field) -> Cow<'v, str> {
+        let split = separator.trim();
+        let sensitive = |name: &str, value: &str| {
+            // Names in a query may be percent-encoded, which would otherwise
+            // hide them from the rules.
+            let name = match field {
+                Field::Query => crate::request::decode_query(name),
+                Field::Cookie | Field::Header => Cow::Borrowed(name),
+            };
+            let name = name.as_ref();
+            let by_name = match field {
+                Field::Query => self.is_sensitive_query_param(name),
+                Field::Cookie => self.is_sensitive_cookie(name),
+                Field::Header => false,
+            };
+            by_name || self.matches_rule(field, name, value)
+        };
+
+        if !list
+            .split(split)
+            .map(split_pair)
+            .any(|(name, value)| sensitive(name, value))
+        {
+            return Cow::Borrowed(list);
+        }
+
+        let pairs = list
+            .split(split)
+            .map(split_pair)
+            .map(|(name, value)| {
+                if sensitive(name, value) {
+                    format!("{name}={REDACTED}")
+                } else if value.is_empty() {
+                    name.to_owned()
+                } else {
+                    format!("{name}={value}")
+                }
+            })
+            .collect::<Vec<_>>();
+        Cow::Owned(pairs.join(separator))
+    }
+}
+
+/// Splits a `name=value` pair, ignoring any whitespace around it.
+fn split_pair(pair: &str) -> (&str, &str) {
+    let pair = pair.trim();
+    pair.split_once('=').unwrap_or((pair, ""))
+}
+
+impl Default for Redactor {
+    /// A redactor for common secrets: the `Authorization`,
+    /// `Proxy-Authorization`, `Cookie`, `Set-Cookie`, and `X-Api-Key`
+    /// headers, and the `access_token`, `api_key`, `client_secret`,
+    /// `password`, `refresh_token`, `secret`, and `token` query parameters.
+    fn default() -> Self {
+        DEFAULT_QUERY.iter().fold(
+            DEFAULT_HEADERS
+                .iter()
+                .fold(Redactor::new(), |redactor, header| {
+                    redactor.with_header(header)
+                }),
+            |redactor, param| redactor.with_query_param(*param),
+        )
+    }
+}
+
+impl std::fmt::Debug for Redactor {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Redactor")
+            .field("headers", &self.headers)
+            .field("query", &self.query)
+            .field("cookies", &self.cookies)
+            .field("rules", &self.rules.len())
+            .finish()
+    }
+}
@@ -1,46 +1,47 @@
 use crate::router::Route;
-use std::collections::HashMap;
-use std::ops::Range;
 use std::sync::Arc;
 
 /// Contains all of the fragment information from the route definition.
 ///
 /// This is meant to act as an extension on a request, and never used publicly.
-#[derive(Debug)]
+/// The fragments are stored as offsets into the path, and looked up by name
+/// using the keys of the matched route's pattern, so that extracting them
+/// only requires the one allocation for the offsets.  This is also how the
+/// request knows which route it matched.
+#[derive(Debug, Clone)]
 pub struct Fragment {
     base: String,
-    fragments_index: Vec<Option<Range<usize>>>,
-    fragments_hash: HashMap<Arc<str>, Option<Range<usize>>>,
+    locations: regex::CaptureLocations,
+    route: Arc<Route>,
+    /// Whether the path was already percent-decoded by the router.
+    decoded: bool,
 }
 
 impl Fragment {
-    pub(crate) fn new(path: impl Into<String>, route: &Route) -> Option<Self> {
-        let path = path.into();
-        let captures = route.pattern.regex().captures(&path)?;
-        let fragments_index = captures
-            .iter()
-            .map(|v| v.map(|v| v.range()))
-            .collect::<Vec<_>>();
-        let fragments_hash = route
-            .pattern
-            .match_keys()
-            .iter()
-            .enumerate()
-            .filter_map(|(i, n)| n.clone().map(|nn| (nn, fragments_index[i].clone())))
-            .collect::<HashMap<_, _>>();
+    pub(crate) fn new(path: impl Into<String>, route: Arc<Route>, decoded: bool) -> Self {
+        let base = path.into();
+        let regex = route.pattern.regex();
+        let mut locations = regex.capture_locations();
+        // The path always matches, since the route was picked by matching
+        // against it; if it somehow did not, there would be no fragments.
+        regex.captures_read(&mut locations, &base);
+
+        Fragment {
+            base,
+            locations,
+            route,
+            decoded,
+        }
+    }
 
-        Some(Fragment {
-            base: path,
-            fragments_index,
-            fragments_hash,
-        })
+    pub(crate) fn route(&self) -> &Arc<Route> {
+        &self.route
     }
 
     pub(crate) fn get(&self, i: usize) -> Option<&str> {
-        self.fragments_index
+        self.locations
             .get(i)
-            .and_then(Option::as_ref)
-            .map(|r| &self.base[r.clone()])
+            .map(|(start, end)| &self.base[start..end])
     }
 
     pub(crate) fn name<Q>(&self, n: &Q) -> Option<&str>
@@ -49,10 +50,20 @@ impl Fragment {
         Arc<str>: std::borrow::Borrow<Q>,
         Q: std::hash::Hash + Eq,
     {
-        self.fragments_hash
-            .get(n)
-            .and_then(Option::as_ref)
-            .map(|r| &self.base[r.clone()])
+        use std::borrow::Borrow;
+        // Patterns only have a handful of keys, so a linear search is
+        // cheaper than building a map for every request.
+        let index = self
+            .route
+            .pattern
+            .match_keys()
+            .iter()
+            .position(|key| key.as_ref().is_some_and(|key| key.borrow() == n))?;
+        self.get(index)
+    }
+
+    pub(crate) fn is_decoded(&self) -> bool {
+        self.decoded
     }
 
     pub(crate) fn select<K>(&self, key: K) -> Option<&str>
@@ -83,7 +94,7 @@ impl sealed::FragmentSelectSealed for usize {
 
 impl FragmentSelect for usize {}
 
-impl<'v, Q> sealed::FragmentSelectSealed for &'v Q
+impl<Q> sealed::FragmentSelectSealed for &Q
 where
     Q: ?Sized,
     Arc<str>: std::borrow::Borrow<Q>,
@@ -94,7 +105,7 @@ where
     }
 }
 
-impl<'v, Q> FragmentSelect for &'v Q
+impl<Q> FragmentSelect for &Q
 where
     Q: ?Sized,
     Arc<str>: std::borrow::Borrow<Q>,
@@ -0,0 +1,84 @@
+use crate::HttpEntity;
+
+#[derive(Debug, Clone)]
+/// A snapshot of the head of a request.
+///
+/// Since a [`Request`](super::Request) is consumed by the middleware and
+/// endpoint handling it, hooks that run after the request has been handled
+/// (such as [`crate::Router::map_response`]) are instead given this, which
+/// contains the method, URI, version, and headers of the request as it first
+/// entered the router.
+pub struct RequestInfo {
+    method: http::Method,
+    uri: http::Uri,
+    version: http::Version,
+    headers: http::HeaderMap,
+}
+
+impl RequestInfo {
+    /// Returns the method of the request.
+    ///
+    /// # Examples
+    /// ```rust
+    /// # use under::*;
+    /// let request = Request::post("/").unwrap();
+    /// let info = RequestInfo::from(&request);
+    /// assert_eq!(info.method(), http::Method::POST);
+    /// ```
+    #[must_use]
+    pub fn method(&self) -> &http::Method {
+        &self.method
+    }
+
+    /// Returns the URI of the request.
+    ///
+    /// # Examples
+    /// ```rust
+    /// # use under::*;
+    /// let request = Request::get("/foo").unwrap();
+    /// let info = RequestInfo::from(&request);
+    /// assert_eq!(info.uri(), "/foo");
+    /// ```
+    #[must_use]
+    pub fn uri(&self) -> &http::Uri {
+        &self.uri
+    }
+
+    /// Returns the HTTP version of the request.
+    #[must_use]
+    pub fn version(&self) -> http::Version {
+        self.version
+    }
+
+    /// Returns the headers of the request.
+    #[must_use]
+    pub fn headers(&self) -> &http::HeaderMap {
+        &self.headers
+    }
+
+    /// Retrieves the given header from the request.
+    ///
+    /// # Examples
+    /// ```rust
+    /// # use under::*;
+    /// let request = Request::get("/").unwrap()
+    ///     .with_header("User-Agent", "under").unwrap();
+    /// let info = RequestInfo::from(&request);
+    /// assert_eq!(info.header("User-Agent").unwrap(), "under");
+    /// ```
+    #[must_use]
+    pub fn header<H: http::header::AsHeaderName>(&self, key: H) -> Option<&http::HeaderValue> {
+        self.headers.get(key)
+    }
+}
+
+impl From<&super::Request> for RequestInfo {
+    fn from(request: &super::Request) -> Self {
+        RequestInfo {
+            method: request.method().clone(),
+            uri: request.uri().clone(),
+            version: request.version(),
+            headers: request.headers().clone(),
+        }
+    }
+}
@@ -0,0 +1,138 @@
+use std::any::{Any, TypeId};
+use std::collections::HashMap;
+use std::marker::PhantomData;
+
+/// A key for an extension of a request, for values whose type alone does
+/// not identify them.
+///
+/// The extensions map of a request holds at most one value of each type,
+/// so two middleware that both store, say, a `String` would overwrite each
+/// other's value.  Instead, each can define a key for its value, and use the
+/// keyed extension methods of the request (e.g. [`Request::ext_keyed`]);
+/// values stored under different keys never collide, even if they are of
+/// the same type.  Keys are identified by their name and the type of their
+/// value, so the name should be namespaced, e.g. by the name of the crate
+/// or module defining it.
+///
+/// [`Request::ext_keyed`]: super::Request::ext_keyed
+///
+/// # Examples
+/// ```rust
+/// # use under::*;
+/// const USER: ExtKey<String> = ExtKey::new("my_auth::user");
+/// const TENANT: ExtKey<String> = ExtKey::new("my_tenancy::tenant");
+///
+/// let mut request = Request::get("/").unwrap();
+/// request.set_ext_keyed(USER, "alice".to_owned());
+/// request.set_ext_keyed(TENANT, "acme".to_owned());
+/// assert_eq!(request.ext_keyed(USER).unwrap(), "alice");
+/// assert_eq!(request.ext_keyed(TENANT).unwrap(), "acme");
+/// // The keyed values are kept apart from the type-keyed ones.
+/// assert_eq!(request.ext::<String>(), None);
+/// ```
+pub struct ExtKey<T> {
+    name: &'static str,
+    _value: PhantomData<fn() -> T>,
+}
+
+impl<T> ExtKey<T> {
+    /// Creates a key with the given name.
+    #[must_use]
+    pub const fn new(name: &'static str) -> Self {
+        ExtKey {
+            name,
+            _value: PhantomData,
+        }
+    }
+
+    /// The name of the key.
+    #[must_use]
+    pub const fn name(&self) -> &'static str {
+        self.name
+    }
+}
+
+impl<T: 'static> ExtKey<T> {
+    fn id(self) -> (&'static str, TypeId) {
+        (self.name, TypeId::of::<T>())
+    }
+}
+
+impl<T> Clone for ExtKey<T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T> Copy for ExtKey<T> {}
+
+impl<T> std::fmt::Debug for ExtKey<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("ExtKey")
+            .field(&self.name)
+            .field(&std::any::type_name::<T>())
+            .finish()
+    }
+}
+
+/// The values stored under an [`ExtKey`], which is itself stored in the
+/// extensions map of the request.
+#[derive(Default)]
+pub(super) struct KeyedExtensions(HashMap<(&'static str, TypeId), Box<dyn Value>>);
+
+impl KeyedExtensions {
+    pub(super) fn get<T: Send + Sync + 'static>(&self, key: ExtKey<T>) -> Option<&T> {
+        (**self.0.get(&key.id())?).as_any().downcast_ref()
+    }
+
+    pub(super) fn get_mut<T: Send + Sync + 'static>(&mut self, key: ExtKey<T>) -> Option<&mut T> {
+        (**self.0.get_mut(&key.id())?).as_any_mut().downcast_mut()
+    }
+
+    pub(super) fn insert<T: Clone + Send + Sync + 'static>(&mut self, key: ExtKey<T>, value: T) {
+        self.0.insert(key.id(), Box::new(value));
+    }
+
+    pub(super) fn remove<T: Send + Sync + 'static>(&mut self, key: ExtKey<T>) -> Option<T> {
+        let value = self.0.remove(&key.id())?;
+        value.into_any().downcast().ok().map(|value| *value)
+    }
+}
+
+impl Clone for KeyedExtensions {
+    fn clone(&self) -> Self {
+        KeyedExtensions(
+            self.0
+                .iter()
+                .map(|(&id, value)| (id, (**value).clone_value()))
+                .collect(),
+        )
+    }
+}
+
+/// A value stored under an [`ExtKey`].  Like every other extension, these
+/// must be cloneable, so that the extensions of the request can be.
+trait Value: Send + Sync {
+    fn clone_value(&self) -> Box<dyn Value>;
+    fn as_any(&self) -> &dyn Any;
+    fn as_any_mut(&mut self) -> &mut dyn Any;
+    fn into_any(self: Box<Self>) -> Box<dyn Any>;
+}
+
+impl<T: Clone + Send + Sync + 'static> Value for T {
+    fn clone_value(&self) -> Box<dyn Value> {
+        Box::new(self.clone())
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+
+    fn into_any(self: Box<Self>) -> Box<dyn Any> {
+        self
+    }
+}
@@ -1,9 +1,20 @@
 pub(crate) mod fragment;
+mod info;
+mod keyed;
+#[cfg(feature = "serde")]
+mod query;
 mod remote;
 
 use self::fragment::{Fragment, FragmentSelect};
+pub use self::info::RequestInfo;
+pub use self::keyed::ExtKey;
+use self::keyed::KeyedExtensions;
+#[cfg(feature = "serde")]
+pub use self::query::QueryConfig;
 pub use self::remote::RemoteAddress;
+use crate::middleware::Skipped;
 use crate::HttpEntity;
+use std::borrow::Cow;
 use std::convert::TryFrom;
 use std::str::FromStr;
 
@@ -35,7 +46,9 @@ macro_rules! forward {
 macro_rules! construct {
     () => {};
     ($($(#[$m:meta])* $v:vis fn $method:ident = $action:expr;)+) => {
-        $($(#[$m])* $v fn $method<U>(uri: U) -> Result<Self, http::Error>
+        $($(#[$m])*
+        #[allow(clippy::missing_errors_doc)]
+        $v fn $method<U>(uri: U) -> Result<Self, http::Error>
         where
             http::Uri: TryFrom<U>,
             <http::Uri as TryFrom<U>>::Error: Into<http::Error>
@@ -43,7 +56,7 @@ macro_rules! construct {
             http::request::Builder::new()
                 .method($action)
                 .uri(uri)
-                .body(hyper::Body::empty())
+                .body(crate::Body::empty())
                 .map(Request)
         })+
     };
@@ -73,7 +86,7 @@ macro_rules! construct {
 /// ```
 ///
 ///
-pub struct Request(http::Request<hyper::Body>);
+pub struct Request(http::Request<crate::Body>);
 
 impl Request {
     construct! {
@@ -191,15 +204,20 @@ impl Request {
         http::request::Builder::new()
             .method(method)
             .uri(uri)
-            .body(hyper::Body::empty())
+            .body(crate::Body::empty())
             .map(Request)
     }
 
     /// Retrieves a path fragment from the request, then attempts to parse it.
-    /// The key can either be a number, or a string.
+    /// The key can either be a number, or a string.  The fragment is
+    /// percent-decoded before it is parsed (see [`Request::fragment_decoded`]).
+    ///
+    /// If the fragment's type in the route is the type it is parsed as (e.g.
+    /// `{amount:u32}`, parsed as a `u32`), the route only matches requests
+    /// where the fragment parses; so, this never returns `None` for it.
     ///
     /// # Examples
-    /// ```rust,no_run
+    /// ```rust
     /// # use under::*;
     ///
     /// async fn point(request: Request) -> Response {
@@ -209,22 +227,28 @@ impl Request {
     ///
     /// # #[tokio::main] async fn main() -> Result<(), anyhow::Error> {
     /// let mut http = under::http();
-    /// http.at("/buy/{amount:uint}").get(point);
+    /// http.at("/buy/{amount:u32}").get(point);
     /// http.prepare();
     /// let mut response = http.handle(Request::get("/buy/3")?).await?;
     /// assert_eq!(response.status(), http::StatusCode::OK);
     /// let body = response.data(512).into_text().await?;
     /// assert_eq!(body, "you bought 3 coconuts");
+    /// let response = http.handle(Request::get("/buy/99999999999")?).await?;
+    /// assert_eq!(response.status(), http::StatusCode::INTERNAL_SERVER_ERROR);
     /// # Ok(())
     /// # }
     /// ```
     pub fn fragment<I: FromStr, K: FragmentSelect>(&self, key: K) -> Option<I> {
-        self.fragment_str(key).and_then(|s| s.parse().ok())
+        self.fragment_decoded(key).and_then(|s| s.parse().ok())
     }
 
     /// Retrieves a path fragment from the request.  The key can either be
     /// a number, or a string.
     ///
+    /// This returns the fragment as it appears in the URI; so, for a route
+    /// of `/files/{name}`, a request for `/files/my%20file` has a fragment of
+    /// `my%20file`.  To decode it, see [`Request::fragment_decoded`].
+    ///
     /// # Examples
     /// ```rust
     /// # use under::*;
@@ -249,14 +273,189 @@ impl Request {
         self.fragment_ext()?.select(key)
     }
 
+    /// Retrieves a path fragment from the request, percent-decoding it.  The
+    /// key can either be a number, or a string.
+    ///
+    /// The fragment is decoded according to these rules:
+    ///
+    /// - Every `%XX` sequence (where `XX` are two hex digits) is decoded into
+    ///   the byte it represents - including `%2F`, so that a fragment can
+    ///   contain a slash.
+    /// - Decoded bytes that are not valid UTF-8 are replaced with `U+FFFD`.
+    /// - A `%` that is not followed by two hex digits is kept as-is.
+    /// - `+` is kept as-is; it only means a space in a query string or a
+    ///   form, not in a path.
+    ///
+    /// If the router already percent-decodes paths (see
+    /// [`crate::Router::path_decoding`]), the fragment is returned as the
+    /// router decoded it, so that it is not decoded twice.
+    ///
+    /// # Examples
+    /// ```rust
+    /// # use under::*;
+    /// # #[tokio::main] async fn main() -> Result<(), anyhow::Error> {
+    /// let mut http = under::http();
+    /// http.at("/files/{name}").get(|request: Request| async move {
+    ///     let raw = request.fragment_str("name").unwrap();
+    ///     let decoded = request.fragment_decoded("name").unwrap();
+    ///     Response::text(format!("{raw} -> {decoded}"))
+    /// });
+    /// http.prepare();
+    /// let mut response = http.handle(Request::get("/files/my%20file%2Ftxt")?).await?;
+    /// let body = response.data(512).into_text().await?;
+    /// assert_eq!(body, "my%20file%2Ftxt -> my file/txt");
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn fragment_decoded<K: FragmentSelect>(&self, key: K) -> Option<Cow<'_, str>> {
+        let fragment = self.fragment_ext()?;
+        let value = fragment.select(key)?;
+        if fragment.is_decoded() {
+            Some(value.into())
+        } else {
+            Some(crate::router::percent_decode(value))
+        }
+    }
+
+    /// Returns the pattern of the route that the request was routed to
+    /// (e.g. `/users/{id}`), if any.  This is only set once the router has
+    /// routed the request, so it is available to middleware and endpoints;
+    /// it is `None` for requests handled by the fallback endpoint.  See also
+    /// [`crate::Router::route_label`].
+    ///
+    /// # Examples
+    /// ```rust
+    /// # use under::*;
+    /// # #[tokio::main] async fn main() -> Result<(), anyhow::Error> {
+    /// let mut http = under::http();
+    /// http.at("/users/{id}").get(|request: Request| async move {
+    ///     Response::text(request.matched_path().unwrap().to_owned())
+    /// });
+    /// http.prepare();
+    /// let mut response = http.handle(Request::get("/users/1")?).await?;
+    /// assert_eq!(response.data(512).into_text().await?, "/users/{id}");
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn matched_path(&self) -> Option<&str> {
+        self.fragment_ext()
+            .map(|fragment| &fragment.route().path[..])
+    }
+
+    /// The tags of the route that the request was routed to (see
+    /// [`crate::Group::tag`]).  This is empty if the request did not match
+    /// a route, or if the route has no tags.
+    #[must_use]
+    pub fn route_tags(&self) -> &[Cow<'static, str>] {
+        self.fragment_ext()
+            .map_or(&[], |fragment| fragment.route().tags())
+    }
+
+    /// Marks the given middleware to be skipped for this request.  Any
+    /// middleware of that type that has not run yet is passed over, as if it
+    /// were not in the stack; this is useful for, e.g., a middleware that
+    /// disables another for some requests, without having to restructure
+    /// the stack.  Middleware that is already running is not affected.  See
+    /// also [`crate::Group::skip`].
+    ///
+    /// # Examples
+    /// ```rust
+    /// # use under::*;
+    /// use under::middleware::ServerTiming;
+    /// # #[tokio::main] async fn main() -> Result<(), anyhow::Error> {
+    /// let mut http = under::http();
+    /// http.with(ServerTiming::new()).at("/").get(under::endpoints::simple(Response::empty_204));
+    /// http.prepare();
+    ///
+    /// let mut request = Request::get("/")?;
+    /// request.skip::<ServerTiming>();
+    /// assert!(request.is_skipped::<ServerTiming>());
+    /// let response = http.handle(request).await?;
+    /// assert!(response.header("Server-Timing").is_none());
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn skip<M: crate::Middleware>(&mut self) -> &mut Self {
+        self.skip_types(&[std::any::TypeId::of::<M>()])
+    }
+
+    pub(crate) fn skip_types(&mut self, types: &[std::any::TypeId]) -> &mut Self {
+        let extensions = self.extensions_mut();
+        if extensions.get::<Skipped>().is_none() {
+            extensions.insert(Skipped::default());
+        }
+        if let Some(skipped) = extensions.get_mut::<Skipped>() {
+            skipped.0.extend_from_slice(types);
+        }
+        self
+    }
+
+    /// Whether the given middleware has been marked to be skipped for this
+    /// request (see [`Request::skip`]).
+    #[must_use]
+    pub fn is_skipped<M: crate::Middleware>(&self) -> bool {
+        self.ext::<Skipped>()
+            .is_some_and(|skipped| skipped.0.contains(&std::any::TypeId::of::<M>()))
+    }
+
     fn fragment_ext(&self) -> Option<&Fragment> {
         self.extensions().get::<Fragment>()
     }
 
+    /// The key-value pairs of the query string, decoded (as
+    /// `application/x-www-form-urlencoded`, so `+` is a space), in the order
+    /// they appear.  A key without a value (`?debug`) has an empty value,
+    /// and a request without a query string has no pairs.  For parsing the
+    /// query string into a type, see [`Request::query`].
+    ///
+    /// # Examples
+    /// ```rust
+    /// # use under::*;
+    /// let request = Request::get("/search?q=hello+world&tag=a&tag=b%26c&debug").unwrap();
+    /// let pairs = request.query_pairs().collect::<Vec<_>>();
+    /// assert_eq!(pairs, [
+    ///     ("q".into(), "hello world".into()),
+    ///     ("tag".into(), "a".into()),
+    ///     ("tag".into(), "b&c".into()),
+    ///     ("debug".into(), "".into()),
+    /// ]);
+    /// ```
+    pub fn query_pairs(&self) -> impl Iterator<Item = (Cow<'_, str>, Cow<'_, str>)> {
+        self.uri()
+            .query()
+            .unwrap_or("")
+            .split('&')
+            .filter(|pair| !pair.is_empty())
+            .map(|pair| {
+                let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+                (decode_query(key), decode_query(value))
+            })
+    }
+
+    /// The decoded value of the first pair of the query string with the
+    /// given (decoded) key, or `None` if there is no such pair (see
+    /// [`Request::query_pairs`]).
+    ///
+    /// # Examples
+    /// ```rust
+    /// # use under::*;
+    /// let request = Request::get("/search?q=hello+world&page=2&page=3").unwrap();
+    /// assert_eq!(request.query_get("q").unwrap(), "hello world");
+    /// assert_eq!(request.query_get("page").unwrap(), "2");
+    /// assert!(request.query_get("sort").is_none());
+    /// ```
+    #[must_use]
+    pub fn query_get(&self, key: &str) -> Option<Cow<'_, str>> {
+        self.query_pairs()
+            .find(|(name, _)| name == key)
+            .map(|(_, value)| value)
+    }
+
     /// Parses the query string from the request into the provided type.  If
     /// there is no query string, then `None` is returned; or, if the query
     /// string cannot be parsed into the given type, then `None` is also
-    /// returned.
+    /// returned.  The query string is parsed with the router's
+    /// [`QueryConfig`] (see [`crate::Router::query_config`]).
     ///
     /// # Examples
     /// ```rust
@@ -268,11 +467,141 @@ impl Request {
     /// assert_eq!(user.id, 1);
     /// ```
     #[cfg(feature = "serde")]
-    #[cfg_attr(nightly, doc(cfg(feature = "serde")))]
+    #[cfg_attr(docsrs, doc(cfg(feature = "serde")))]
     pub fn query<'q, S: serde::Deserialize<'q>>(&'q self) -> Option<S> {
         self.uri()
             .query()
-            .and_then(|s| serde_qs::from_str::<S>(s).ok())
+            .and_then(|s| self.query_config().parse::<S>(s).ok())
+    }
+
+    /// The configuration for parsing the query string, as set by
+    /// [`crate::Router::query_config`].
+    #[cfg(feature = "serde")]
+    fn query_config(&self) -> QueryConfig {
+        self.ext::<QueryConfig>().copied().unwrap_or_default()
+    }
+
+    /// Parses the query string from the request into the provided type.
+    /// Unlike [`Request::query`], a missing query string is treated as
+    /// empty, and if the query string cannot be parsed, the error says which
+    /// field was invalid; if it is returned from the endpoint, the router
+    /// responds with a 422 (see [`crate::validation::Validation`]).  Like
+    /// [`Request::query`], this uses the router's [`QueryConfig`].
+    ///
+    /// # Errors
+    /// Errors if the query string cannot be parsed into the given type.
+    ///
+    /// # Examples
+    /// ```rust
+    /// # use under::*;
+    /// #[derive(serde::Deserialize, Debug)]
+    /// struct Search { q: String, page: u32 }
+    /// let request = Request::get("/search?q=under&page=2").unwrap();
+    /// let search: Search = request.require_query().unwrap();
+    /// assert_eq!(search.page, 2);
+    ///
+    /// let request = Request::get("/search?page=2").unwrap();
+    /// let validation = request.require_query::<Search>().unwrap_err();
+    /// assert_eq!(validation.errors()[0].field, "q");
+    /// assert_eq!(validation.errors()[0].message, "is required");
+    /// ```
+    #[cfg(feature = "json")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "json")))]
+    pub fn require_query<T: serde::de::DeserializeOwned>(
+        &self,
+    ) -> Result<T, crate::validation::Validation> {
+        crate::validation::query(self.uri().query(), self.query_config())
+    }
+
+    /// Reads the body of the request as JSON, deserializing it into `T`.  This
+    /// is a shortcut for [`crate::json::Json::extract`]; if it fails, and the
+    /// error is returned from the endpoint, the router responds with a 400,
+    /// 413, 415, or 422 (see [`crate::json::JsonRejection`]), rather than a
+    /// 500.
+    ///
+    /// # Errors
+    /// Errors if the request does not have a JSON content type, if the body
+    /// could not be read (or is larger than `limit`), or if the body could not
+    /// be deserialized.
+    ///
+    /// # Examples
+    /// ```rust
+    /// # use under::*;
+    /// # #[tokio::main] async fn main() -> Result<(), anyhow::Error> {
+    /// #[derive(serde::Deserialize)]
+    /// struct User { id: u32 }
+    /// let mut request = Request::post("/users")?
+    ///     .with_header("Content-Type", "application/json")?
+    ///     .with_body(r#"{"id": 1}"#);
+    /// let user: User = request.require_json(512).await?;
+    /// assert_eq!(user.id, 1);
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[cfg(feature = "json")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "json")))]
+    pub async fn require_json<T: serde::de::DeserializeOwned>(
+        &mut self,
+        limit: u64,
+    ) -> Result<T, crate::json::JsonRejection> {
+        crate::json::Json::extract(self, limit)
+            .await
+            .map(crate::json::Json::into_inner)
+    }
+
+    /// Checks that the content type of the request matches the given mime
+    /// type, returning the parsed content type if it does.  Only the essence
+    /// of the content type is compared (so parameters, such as the charset,
+    /// are ignored), case-insensitively; the expected type may use a wildcard
+    /// subtype (e.g. `text/*`), and `*/*` matches any (valid) content type.
+    ///
+    /// If the returned error is passed back up from the endpoint, the router
+    /// responds with an empty 415, rather than a 500.  To check every request
+    /// for a group of routes, see [`crate::middleware::ContentTypeFilter`].
+    ///
+    /// # Errors
+    /// Errors with [`crate::UnderError::UnsupportedMediaType`] if the request
+    /// has no content type, if its content type is not a valid mime type, or
+    /// if it does not match.
+    ///
+    /// # Examples
+    /// ```rust
+    /// # use under::*;
+    /// let request = Request::post("/")?
+    ///     .with_header("Content-Type", "application/json; charset=utf-8")?;
+    /// let ctype = request.expect_content_type("application/json")?;
+    /// assert_eq!(ctype.get_param("charset").unwrap(), "utf-8");
+    /// assert!(request.expect_content_type("application/*").is_ok());
+    /// assert!(request.expect_content_type("text/plain").is_err());
+    /// assert!(Request::post("/")?.expect_content_type("*/*").is_err());
+    /// # Ok::<(), anyhow::Error>(())
+    /// ```
+    pub fn expect_content_type(&self, expected: &str) -> Result<mime::Mime, crate::UnderError> {
+        match self.content_type() {
+            Some(ctype) if mime_matches(&ctype, expected) => Ok(ctype),
+            ctype => Err(crate::UnderError::UnsupportedMediaType(ctype, vec![])),
+        }
+    }
+
+    /// Returns whether or not the client expects a `100 Continue` before it
+    /// sends the body of the request (i.e., the request has an
+    /// `Expect: 100-continue` header).  The interim response is sent
+    /// automatically once the body starts being read; so, to reject such a
+    /// request without the client sending the body, respond without reading
+    /// it.  See [`crate::Router::expect_continue`].
+    ///
+    /// # Examples
+    /// ```rust
+    /// # use under::*;
+    /// let request = Request::post("/upload")?.with_header("Expect", "100-Continue")?;
+    /// assert!(request.expects_continue());
+    /// assert!(!Request::post("/upload")?.expects_continue());
+    /// # Ok::<(), anyhow::Error>(())
+    /// ```
+    pub fn expects_continue(&self) -> bool {
+        self.header(http::header::EXPECT)
+            .and_then(|v| v.to_str().ok())
+            .is_some_and(|v| v.trim().eq_ignore_ascii_case("100-continue"))
     }
 
     /// Attempts to load the peer address of the request.  This is only
@@ -412,6 +741,39 @@ impl Request {
         RemoteAddress::new(self)
     }
 
+    /// The scheme of the connection that the request was made over.  This is
+    /// the scheme of the request's URI, if it has one (e.g. over HTTP/2), and
+    /// `http` otherwise, since the server itself does not terminate TLS.
+    ///
+    /// If the application is behind a proxy that terminates TLS, this is the
+    /// scheme between the proxy and the application; to use the scheme that
+    /// the proxy reports, use [`RemoteAddress::scheme`], e.g.
+    /// `request.remote_address().trust_forwarded_for(-1).scheme()`.
+    ///
+    /// # Examples
+    /// ```rust
+    /// # use under::*;
+    /// let request = Request::get("/").unwrap();
+    /// assert_eq!(request.scheme(), http::uri::Scheme::HTTP);
+    /// let request = Request::get("https://example.com/").unwrap();
+    /// assert_eq!(request.scheme(), http::uri::Scheme::HTTPS);
+    /// assert!(request.is_secure());
+    /// ```
+    #[must_use]
+    pub fn scheme(&self) -> http::uri::Scheme {
+        self.uri()
+            .scheme()
+            .cloned()
+            .unwrap_or(http::uri::Scheme::HTTP)
+    }
+
+    /// Whether the connection that the request was made over is secure.
+    /// See [`Request::scheme`].
+    #[must_use]
+    pub fn is_secure(&self) -> bool {
+        self::remote::is_secure(&self.scheme())
+    }
+
     /// Returns state information provided by the
     /// [`crate::middleware::StateMiddleware`] middleware.  This is a
     /// shortcut to retrieving the [`crate::middleware::State`]
@@ -429,6 +791,138 @@ impl Request {
         self.ext::<crate::middleware::State<T>>().map(|v| &v.0)
     }
 
+    /// The deadline of the request, if any, as set by
+    /// [`crate::middleware::Timeout`].  Work done on behalf of the request
+    /// should not outlive it.
+    ///
+    /// # Examples
+    /// ```rust
+    /// # use under::*;
+    /// use under::middleware::Deadline;
+    /// use std::time::Duration;
+    /// let request = Request::get("/")?.with_ext(Deadline::after(Duration::from_secs(1)));
+    /// assert!(!request.deadline().unwrap().is_expired());
+    /// # Ok::<(), anyhow::Error>(())
+    /// ```
+    #[must_use]
+    pub fn deadline(&self) -> Option<&crate::middleware::Deadline> {
+        self.ext::<crate::middleware::Deadline>()
+    }
+
+    /// The client shared with the request, if any, through
+    /// [`crate::middleware::StateMiddleware`].  This is a shortcut for
+    /// `request.state::<Client>()`; see [`crate::client`].
+    #[cfg(feature = "client")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "client")))]
+    #[must_use]
+    pub fn client(&self) -> Option<&crate::client::Client> {
+        self.state::<crate::client::Client>()
+    }
+
+    /// Returns the authenticated principal of the request, as resolved by an
+    /// authentication middleware like [`crate::middleware::ApiKeyAuth`].
+    /// This is a shortcut to retrieving the [`crate::middleware::Principal`]
+    /// extension from the request.
+    ///
+    /// # Examples
+    /// ```rust
+    /// # use under::*;
+    /// use under::middleware::Principal;
+    /// let request = Request::get("/").unwrap()
+    ///     .with_ext(Principal(42u64));
+    /// assert_eq!(request.principal::<u64>(), Some(&42));
+    /// ```
+    pub fn principal<P: Send + Sync + 'static>(&self) -> Option<&P> {
+        self.ext::<crate::middleware::Principal<P>>().map(|v| &v.0)
+    }
+
+    /// Returns the tenant of the request, as derived by the
+    /// [`crate::middleware::TenantMiddleware`] middleware.  This is a
+    /// shortcut to retrieving the [`crate::middleware::Tenant`] extension
+    /// from the request.
+    ///
+    /// # Examples
+    /// ```rust
+    /// # use under::*;
+    /// use under::middleware::Tenant;
+    /// let request = Request::get("/").unwrap()
+    ///     .with_ext(Tenant("acme".to_string()));
+    /// assert_eq!(request.tenant::<String>().map(String::as_str), Some("acme"));
+    /// ```
+    pub fn tenant<T: Send + Sync + 'static>(&self) -> Option<&T> {
+        self.ext::<crate::middleware::Tenant<T>>().map(|v| &v.0)
+    }
+
+    /// Returns the session of the request, as loaded by the
+    /// [`crate::middleware::SessionMiddleware`] middleware.  This is a
+    /// shortcut to retrieving the [`crate::middleware::Session`] extension
+    /// from the request.
+    ///
+    /// # Examples
+    /// ```rust
+    /// # use under::*;
+    /// let request = Request::get("/").unwrap();
+    /// assert!(request.session().is_none());
+    /// ```
+    #[cfg(feature = "sessions")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "sessions")))]
+    pub fn session(&self) -> Option<&crate::middleware::Session> {
+        self.ext::<crate::middleware::Session>()
+    }
+
+    /// Returns the random number generator of the request.  This is inserted
+    /// by the router for every request it handles (see
+    /// [`crate::Router::seed_rng`]), and so is only absent if the request
+    /// has not (yet) been handled by a router.
+    ///
+    /// # Examples
+    /// ```rust
+    /// # use under::*;
+    /// # #[tokio::main] async fn main() -> Result<(), anyhow::Error> {
+    /// let mut http = under::http();
+    /// http.at("/").get(|request: Request| async move {
+    ///     Response::text(request.rng().unwrap().token(8))
+    /// });
+    /// http.prepare();
+    /// let mut response = http.handle(Request::get("/")?).await?;
+    /// assert_eq!(response.data(512).into_text().await?.len(), 8);
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[cfg(feature = "rng")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "rng")))]
+    pub fn rng(&self) -> Option<&crate::Rng> {
+        self.ext::<crate::Rng>()
+    }
+
+    /// Records a timing for this request, under the given name.  This is
+    /// recorded into the [`crate::middleware::Timings`] of the request, which
+    /// is only present if the router is profiling (see
+    /// [`crate::Router::profile`]), or the
+    /// [`crate::middleware::ServerTiming`] middleware is used; otherwise,
+    /// this does nothing.
+    ///
+    /// # Examples
+    /// ```rust
+    /// # use under::*;
+    /// use under::middleware::Timings;
+    /// use std::time::Duration;
+    /// let request = Request::get("/").unwrap()
+    ///     .with_ext(Timings::default());
+    /// request.timing("db", Duration::from_millis(5));
+    /// let timings = request.ext::<Timings>().unwrap().entries();
+    /// assert_eq!(timings[0].name, "db");
+    /// ```
+    pub fn timing(
+        &self,
+        name: impl Into<std::borrow::Cow<'static, str>>,
+        duration: std::time::Duration,
+    ) {
+        if let Some(timings) = self.ext::<crate::middleware::Timings>() {
+            timings.record(name, duration);
+        }
+    }
+
     /// Retrieves a specific extension from the extensions map.  This is
     /// the same as calling [`Self::extensions`].`get` wit the given
     /// type parameter.
@@ -469,7 +963,7 @@ impl Request {
     /// request.set_ext(123u32);
     /// assert_eq!(request.ext::<u32>(), Some(&123u32));
     /// ```
-    pub fn set_ext<T: Send + Sync + 'static>(&mut self, value: T) -> &mut Self {
+    pub fn set_ext<T: Clone + Send + Sync + 'static>(&mut self, value: T) -> &mut Self {
         self.extensions_mut().insert(value);
         self
     }
@@ -486,7 +980,7 @@ impl Request {
     /// assert_eq!(request.ext::<u32>(), Some(&123u32));
     /// ```
     #[must_use]
-    pub fn with_ext<T: Send + Sync + 'static>(mut self, value: T) -> Self {
+    pub fn with_ext<T: Clone + Send + Sync + 'static>(mut self, value: T) -> Self {
         self.set_ext(value);
         self
     }
@@ -528,6 +1022,98 @@ impl Request {
         self
     }
 
+    /// Retrieves the extension stored under the given key (see
+    /// [`ExtKey`]).  Unlike [`Self::ext`], values of the same type stored
+    /// under different keys do not collide.
+    ///
+    /// # Examples
+    /// ```rust
+    /// # use under::*;
+    /// const USER: ExtKey<String> = ExtKey::new("docs::user");
+    /// let request = Request::get("/").unwrap();
+    /// assert_eq!(request.ext_keyed(USER), None);
+    /// ```
+    pub fn ext_keyed<T: Send + Sync + 'static>(&self, key: ExtKey<T>) -> Option<&T> {
+        self.ext::<KeyedExtensions>()?.get(key)
+    }
+
+    /// Retrieves a mutable reference to the extension stored under the
+    /// given key (see [`ExtKey`]).
+    ///
+    /// # Examples
+    /// ```rust
+    /// # use under::*;
+    /// const VISITS: ExtKey<u32> = ExtKey::new("docs::visits");
+    /// let mut request = Request::get("/").unwrap().with_ext_keyed(VISITS, 1);
+    /// *request.ext_keyed_mut(VISITS).unwrap() += 1;
+    /// assert_eq!(request.ext_keyed(VISITS), Some(&2));
+    /// ```
+    pub fn ext_keyed_mut<T: Send + Sync + 'static>(&mut self, key: ExtKey<T>) -> Option<&mut T> {
+        self.ext_mut::<KeyedExtensions>()?.get_mut(key)
+    }
+
+    /// Stores the value under the given key (see [`ExtKey`]), replacing
+    /// any value already stored under it.
+    ///
+    /// # Examples
+    /// ```rust
+    /// # use under::*;
+    /// const USER: ExtKey<String> = ExtKey::new("docs::user");
+    /// let mut request = Request::get("/").unwrap();
+    /// request.set_ext_keyed(USER, "alice".to_owned());
+    /// assert_eq!(request.ext_keyed(USER).unwrap(), "alice");
+    /// ```
+    pub fn set_ext_keyed<T: Clone + Send + Sync + 'static>(
+        &mut self,
+        key: ExtKey<T>,
+        value: T,
+    ) -> &mut Self {
+        let extensions = self.extensions_mut();
+        if extensions.get::<KeyedExtensions>().is_none() {
+            extensions.insert(KeyedExtensions::default());
+        }
+        if let Some(keyed) = extensions.get_mut::<KeyedExtensions>() {
+            keyed.insert(key, value);
+        }
+        self
+    }
+
+    /// Stores the value under the given key, consuming `self`, and then
+    /// returning the new value.  This is the same as calling
+    /// [`Self::set_ext_keyed`], but it consumes `self`.
+    ///
+    /// # Examples
+    /// ```rust
+    /// # use under::*;
+    /// const USER: ExtKey<String> = ExtKey::new("docs::user");
+    /// let request = Request::get("/").unwrap().with_ext_keyed(USER, "alice".to_owned());
+    /// assert_eq!(request.ext_keyed(USER).unwrap(), "alice");
+    /// ```
+    #[must_use]
+    pub fn with_ext_keyed<T: Clone + Send + Sync + 'static>(
+        mut self,
+        key: ExtKey<T>,
+        value: T,
+    ) -> Self {
+        self.set_ext_keyed(key, value);
+        self
+    }
+
+    /// Removes the value stored under the given key (see [`ExtKey`]),
+    /// returning it.
+    ///
+    /// # Examples
+    /// ```rust
+    /// # use under::*;
+    /// const USER: ExtKey<String> = ExtKey::new("docs::user");
+    /// let mut request = Request::get("/").unwrap().with_ext_keyed(USER, "alice".to_owned());
+    /// assert_eq!(request.remove_ext_keyed(USER).unwrap(), "alice");
+    /// assert_eq!(request.ext_keyed(USER), None);
+    /// ```
+    pub fn remove_ext_keyed<T: Send + Sync + 'static>(&mut self, key: ExtKey<T>) -> Option<T> {
+        self.ext_mut::<KeyedExtensions>()?.remove(key)
+    }
+
     forward! {
         /// Returns a reference to the associated URI.
         ///
@@ -539,6 +1125,19 @@ impl Request {
         /// ```
         #[inline]
         pub fn uri(&self) -> &http::Uri;
+        /// Returns a mutable reference to the associated URI.  Note that
+        /// changing the URI from a middleware does not change the route that
+        /// the request was routed to.
+        ///
+        /// # Examples
+        /// ```rust
+        /// # use under::*;
+        /// let mut request: Request = Request::get("/").unwrap();
+        /// *request.uri_mut() = "/users".parse().unwrap();
+        /// assert_eq!(request.uri(), "/users");
+        /// ```
+        #[inline]
+        pub fn uri_mut(&mut self) -> &mut http::Uri;
         /// Returns a reference to the associated HTTP method.
         ///
         /// # Examples
@@ -549,6 +1148,16 @@ impl Request {
         /// ```
         #[inline]
         pub fn method(&self) -> &http::Method;
+        /// Returns the associated HTTP version.
+        ///
+        /// # Examples
+        /// ```rust
+        /// # use under::*;
+        /// let request: Request = Request::get("/").unwrap();
+        /// assert_eq!(request.version(), http::Version::HTTP_11);
+        /// ```
+        #[inline]
+        pub fn version(&self) -> http::Version;
         /// Returns a reference to the associated extensions.
         ///
         /// # Examples
@@ -575,7 +1184,7 @@ impl Request {
 
 impl crate::HttpEntity for Request {
     #[inline]
-    fn body_mut(&mut self) -> &mut hyper::Body {
+    fn body_mut(&mut self) -> &mut crate::Body {
         self.0.body_mut()
     }
     #[inline]
@@ -587,28 +1196,64 @@ impl crate::HttpEntity for Request {
     fn headers_mut(&mut self) -> &mut http::HeaderMap {
         self.0.headers_mut()
     }
+
+    fn data(&mut self, limit: u64) -> crate::DataStream {
+        let stream = crate::DataStream::new(self.take_body(), limit);
+        match self.ext::<BodyTimeout>() {
+            Some(BodyTimeout(timeout)) => stream.with_timeout(*timeout),
+            None => stream,
+        }
+    }
+}
+
+/// Decodes a key or value of a query string, where, unlike in a path, `+`
+/// is a space.
+pub(crate) fn decode_query(component: &str) -> Cow<'_, str> {
+    if component.contains('+') {
+        crate::router::percent_decode(&component.replace('+', " "))
+            .into_owned()
+            .into()
+    } else {
+        crate::router::percent_decode(component)
+    }
 }
 
-impl From<http::Request<hyper::Body>> for Request {
-    fn from(r: http::Request<hyper::Body>) -> Self {
+#[derive(Debug, Copy, Clone)]
+/// The default timeout for reading the body of a request, as set by
+/// [`crate::Router::body_timeout`].
+pub(crate) struct BodyTimeout(pub(crate) std::time::Duration);
+
+impl From<http::Request<crate::Body>> for Request {
+    fn from(r: http::Request<crate::Body>) -> Self {
         Request(r)
     }
 }
 
-impl From<Request> for http::Request<hyper::Body> {
+impl From<Request> for http::Request<crate::Body> {
     fn from(r: Request) -> Self {
         r.0
     }
 }
 
-impl std::borrow::Borrow<http::Request<hyper::Body>> for Request {
-    fn borrow(&self) -> &http::Request<hyper::Body> {
+impl std::borrow::Borrow<http::Request<crate::Body>> for Request {
+    fn borrow(&self) -> &http::Request<crate::Body> {
         &self.0
     }
 }
 
-impl std::borrow::BorrowMut<http::Request<hyper::Body>> for Request {
-    fn borrow_mut(&mut self) -> &mut http::Request<hyper::Body> {
+impl std::borrow::BorrowMut<http::Request<crate::Body>> for Request {
+    fn borrow_mut(&mut self) -> &mut http::Request<crate::Body> {
         &mut self.0
     }
 }
+
+/// Checks that the essence of the given mime type matches the given pattern,
+/// which is either a full essence (`type/subtype`), a wildcard subtype
+/// (`type/*`), or `*/*`.
+pub(crate) fn mime_matches(mime: &mime::Mime, pattern: &str) -> bool {
+    match pattern.split_once('/') {
+        Some(("*", "*")) => true,
+        Some((ty, "*")) => mime.type_().as_str().eq_ignore_ascii_case(ty),
+        _ => mime.essence_str().eq_ignore_ascii_case(pattern),
+    }
+}
@@ -0,0 +1,61 @@
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+/// How query strings are parsed into types, by [`crate::Request::query`] and
+/// [`crate::Request::require_query`].  This is set for every request the
+/// router handles with [`crate::Router::query_config`].
+///
+/// Nested keys use brackets: `filter[name]=x` is the field `name` of the
+/// field `filter`.  `max_depth` is how deeply keys may nest before the rest
+/// of the key is taken as is (with `0` meaning that there is no nesting at
+/// all); `strict` is whether brackets must be sent as they are, or may also
+/// be percent-encoded (`filter%5Bname%5D=x`), as some clients do.  Strict
+/// parsing is the default, as it is less ambiguous about what a key means.
+/// See [`serde_qs`](https://docs.rs/serde_qs/0.9/serde_qs/#strict-vs-non-strict-modes)
+/// for the details.
+///
+/// # Examples
+/// ```rust
+/// # use under::*;
+/// # #[tokio::main] async fn main() -> Result<(), anyhow::Error> {
+/// #[derive(serde::Deserialize)]
+/// struct Search { filter: Filter }
+/// #[derive(serde::Deserialize)]
+/// struct Filter { name: String }
+///
+/// let mut http = under::http();
+/// http.query_config(QueryConfig { max_depth: 5, strict: false });
+/// http.at("/search").get(|request: Request| async move {
+///     let search = request.query::<Search>().ok_or_else(|| anyhow::anyhow!("no query"))?;
+///     Ok::<_, anyhow::Error>(Response::text(search.filter.name))
+/// });
+/// http.prepare();
+/// let mut response = http.handle(Request::get("/search?filter%5Bname%5D=under")?).await?;
+/// assert_eq!(response.data(512).into_text().await?, "under");
+/// # Ok(())
+/// # }
+/// ```
+pub struct QueryConfig {
+    /// How deeply keys may be nested.  This is `5` by default.
+    pub max_depth: usize,
+    /// Whether brackets in keys must not be percent-encoded.  This is `true`
+    /// by default.
+    pub strict: bool,
+}
+
+impl QueryConfig {
+    /// Parses the query string into the given type, with this configuration.
+    pub(crate) fn parse<'q, T: serde::Deserialize<'q>>(
+        self,
+        query: &'q str,
+    ) -> Result<T, serde_qs::Error> {
+        serde_qs::Config::new(self.max_depth, self.strict).deserialize_str(query)
+    }
+}
+
+impl Default for QueryConfig {
+    fn default() -> Self {
+        QueryConfig {
+            max_depth: 5,
+            strict: true,
+        }
+    }
+}
@@ -1,6 +1,8 @@
 use crate::HttpEntity;
+use http::uri::Scheme;
 use std::borrow::Cow;
 use std::net::IpAddr;
+use std::sync::LazyLock;
 
 /// A type that helps retrieve the remote address of a request.
 ///
@@ -106,6 +108,13 @@ impl RemoteAddress<'_> {
     ///   .trust_forwarded(-1)
     ///   .apply();
     /// assert_eq!(ip, Some(IpAddr::from([3, 3, 3, 3])));
+    ///
+    /// // Ports, and bracketed IPv6 addresses, are understood too.
+    /// request.set_header("Forwarded", r#"for="[2001:db8::1]:4711", for="1.1.1.1:80""#);
+    /// let ip = request.remote_address().trust_forwarded(0).apply();
+    /// assert_eq!(ip, Some("2001:db8::1".parse().unwrap()));
+    /// let ip = request.remote_address().trust_forwarded(1).apply();
+    /// assert_eq!(ip, Some(IpAddr::from([1, 1, 1, 1])));
     /// ```
     pub fn trust_forwarded(&mut self, index: isize) -> &mut Self {
         self.trusted_sources
@@ -203,6 +212,54 @@ impl RemoteAddress<'_> {
         }
         None
     }
+
+    /// Determines the scheme that the client used to make the request, using
+    /// the same sources that are trusted for the IP address.  The sources
+    /// are evaluated in order, like [`Self::apply`]:
+    ///
+    /// - for [`Self::trust_forwarded`], the `proto` of the same entry of the
+    ///   `Forwarded` header;
+    /// - for [`Self::trust_forwarded_for`], the same entry of the
+    ///   `X-Forwarded-Proto` header (which usually only has one);
+    /// - for [`Self::trust_peer_address`], the scheme of the connection
+    ///   itself (see [`super::Request::scheme`]).
+    ///
+    /// Sources that only carry an IP address (e.g. [`Self::trust_header`])
+    /// are passed over.  If none of the sources has a scheme, this is the
+    /// scheme of the connection.
+    ///
+    /// # Examples
+    /// ```rust
+    /// # use under::*;
+    /// # let mut request = Request::get("/").unwrap().with_local_addr();
+    /// request.set_header("X-Forwarded-For", "1.1.1.1");
+    /// request.set_header("X-Forwarded-Proto", "https");
+    /// let mut remote = request.remote_address();
+    /// remote.trust_forwarded_for(-1).trust_peer_address();
+    /// assert_eq!(remote.scheme(), http::uri::Scheme::HTTPS);
+    /// assert!(remote.is_secure());
+    /// // Without trusting the proxy, the header is ignored.
+    /// assert!(!request.remote_address().trust_peer_address().is_secure());
+    /// ```
+    #[must_use]
+    pub fn scheme(&self) -> Scheme {
+        self.trusted_sources
+            .iter()
+            .find_map(|source| source.scheme(self.request))
+            .unwrap_or_else(|| self.request.scheme())
+    }
+
+    /// Whether the client used a secure scheme (`https` or `wss`) to make
+    /// the request.  See [`Self::scheme`].
+    #[must_use]
+    pub fn is_secure(&self) -> bool {
+        is_secure(&self.scheme())
+    }
+}
+
+/// Whether the given scheme is a secure one.
+pub(super) fn is_secure(scheme: &Scheme) -> bool {
+    *scheme == Scheme::HTTPS || scheme.as_str().eq_ignore_ascii_case("wss")
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
@@ -249,52 +306,92 @@ impl RemoteAddressSource {
             RemoteAddressSource::PeerAddress => request.peer_addr().map(|v| v.ip()),
         }
     }
+
+    fn scheme(&self, request: &super::Request) -> Option<Scheme> {
+        match self {
+            RemoteAddressSource::XForwardedFor(index) => {
+                let proto = request
+                    .header_all("X-Forwarded-Proto")
+                    .into_iter()
+                    .filter_map(|s| s.to_str().ok())
+                    .flat_map(|s| s.split(','))
+                    .map(str::trim);
+                select(proto, *index).and_then(|s| s.parse().ok())
+            }
+            RemoteAddressSource::Forwarded(index) => forwarded_element(request, *index)?
+                .iter()
+                .find(|(k, _)| k.trim().eq_ignore_ascii_case("proto"))
+                .and_then(|(_, v)| v.trim().trim_matches('"').parse().ok()),
+            RemoteAddressSource::Header(_) => None,
+            RemoteAddressSource::PeerAddress => Some(request.scheme()),
+        }
+    }
+}
+
+/// Selects the item at the given index of the iterator; negative indices
+/// count from the end, so -1 is the last item.
+fn select<I: DoubleEndedIterator>(mut iter: I, index: isize) -> Option<I::Item> {
+    if index < 0 {
+        #[allow(clippy::cast_sign_loss)]
+        let index = (index.checked_abs()? as usize).checked_sub(1)?;
+        iter.nth_back(index)
+    } else {
+        #[allow(clippy::cast_sign_loss)]
+        iter.nth(index as usize)
+    }
 }
 
 fn x_forwarded_for_header(request: &super::Request, index: isize) -> Option<IpAddr> {
-    let mut ip = request
+    let ip = request
         .header_all("X-Forwarded-For")
         .into_iter()
         .filter_map(|s| s.to_str().ok())
         .flat_map(|s| s.split(','))
         .map(str::trim);
 
-    if index < 0 {
-        #[allow(clippy::cast_sign_loss)]
-        let index = (index.checked_abs()? as usize).checked_sub(1)?;
-        ip.nth_back(index).and_then(|s| s.parse().ok())
-    } else if index >= 0 {
-        #[allow(clippy::cast_sign_loss)]
-        ip.nth(index as usize).and_then(|s| s.parse().ok())
-    } else {
-        None
-    }
+    select(ip, index).and_then(|s| s.parse().ok())
 }
 
-lazy_static::lazy_static! {
-    static ref FOR_WORD: regex::Regex = regex::Regex::new(r"(?i)^for$").unwrap();
-    static ref SPECIAL_TOKEN: regex::Regex = regex::Regex::new(r#"^"[(.+)]"$"#).unwrap();
-}
+static FOR_WORD: LazyLock<regex::Regex> =
+    LazyLock::new(|| regex::Regex::new(r"(?i)^for$").unwrap());
 
 // How is this even more unreliable than x-forwarded-for?  If it's not utf-8,
 // or doesn't match key-value parsing pairs, than it'll ignore whole sections.
 // Not sure this is a good thing.
 fn forwarded_header(request: &super::Request, index: isize) -> Option<IpAddr> {
-    fn parse_key_value(s: &str) -> Option<(&str, &str)> {
-        let (key, value) = s.split_once('=')?;
-        Some((key, value))
-    }
-
+    // A node is either an address, an IPv6 address in brackets, or either
+    // of those with a port; all but a bare IPv4 address must be quoted.
     fn parse_ip(s: &str) -> Option<IpAddr> {
         let s = s.trim();
-        if let Some(cap) = SPECIAL_TOKEN.captures(s) {
-            cap[1].parse().ok()
-        } else {
-            s.parse().ok()
+        let s = s
+            .strip_prefix('"')
+            .and_then(|s| s.strip_suffix('"'))
+            .unwrap_or(s);
+        if let Some(rest) = s.strip_prefix('[') {
+            let (ip, _) = rest.split_once(']')?;
+            return ip.parse::<std::net::Ipv6Addr>().ok().map(IpAddr::V6);
         }
+        s.parse().ok().or_else(|| {
+            let (ip, _) = s.split_once(':')?;
+            ip.parse::<std::net::Ipv4Addr>().ok().map(IpAddr::V4)
+        })
     }
 
-    let ip = request
+    forwarded_element(request, index)?
+        .iter()
+        .find(|(k, _)| FOR_WORD.is_match(k))
+        .and_then(|(_, v)| parse_ip(v))
+}
+
+/// The pairs of the entry of the `Forwarded` header at the given index,
+/// counting only the entries that have a `for` key.
+fn forwarded_element(request: &super::Request, index: isize) -> Option<Vec<(&str, &str)>> {
+    fn parse_key_value(s: &str) -> Option<(&str, &str)> {
+        let (key, value) = s.split_once('=')?;
+        Some((key, value))
+    }
+
+    let elements = request
         .header_all("Forwarded")
         .into_iter()
         .filter_map(|s| s.to_str().ok())
@@ -308,20 +405,6 @@ fn forwarded_header(request: &super::Request, index: isize) -> Option<IpAddr> {
 
     // FOR_WORD is a requirement here because the standard says `for` is
     // case insensitive.  We _could_ try to lowercase it, but...
-    let mut ffor = ip.filter_map(|v| {
-        v.iter()
-            .find(|(k, _)| FOR_WORD.is_match(k))
-            .map(|(_, v)| *v)
-    });
-
-    if index < 0 {
-        #[allow(clippy::cast_sign_loss)]
-        let index = (index.checked_abs()? as usize).checked_sub(1)?;
-        ffor.nth_back(index).and_then(parse_ip)
-    } else if index >= 0 {
-        #[allow(clippy::cast_sign_loss)]
-        ffor.nth(index as usize).and_then(parse_ip)
-    } else {
-        None
-    }
+    let ffor = elements.filter(|v| v.iter().any(|(k, _)| FOR_WORD.is_match(k)));
+    select(ffor, index)
 }
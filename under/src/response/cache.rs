@@ -0,0 +1,160 @@
+use super::Response;
+use std::time::SystemTime;
+
+#[must_use]
+/// A builder for the `Cache-Control` header of a response.
+///
+/// This is created by [`Response::cache_control`].  Each directive is
+/// written to the header as soon as it is added, so the builder can simply
+/// be dropped once it is done.
+///
+/// # Examples
+/// ```rust
+/// # use under::*;
+/// let mut response = Response::text("hello");
+/// response.cache_control().max_age(3600).public().immutable();
+/// assert_eq!(
+///     response.header("Cache-Control").unwrap(),
+///     "max-age=3600, public, immutable"
+/// );
+/// ```
+pub struct CacheControl<'r> {
+    response: &'r mut Response,
+    directives: Vec<String>,
+}
+
+impl<'r> CacheControl<'r> {
+    pub(super) fn new(response: &'r mut Response) -> Self {
+        CacheControl {
+            response,
+            directives: vec![],
+        }
+    }
+
+    /// Adds the given directive, replacing any earlier directive with the
+    /// same name, and rewrites the header.
+    fn directive(mut self, name: &str, value: Option<u64>) -> Self {
+        self.directives
+            .retain(|d| d.split('=').next() != Some(name));
+        self.directives.push(match value {
+            Some(value) => format!("{name}={value}"),
+            None => name.to_owned(),
+        });
+        let header = self.directives.join(", ");
+        if let Ok(header) = http::HeaderValue::try_from(header) {
+            self.response
+                .headers_mut()
+                .insert(http::header::CACHE_CONTROL, header);
+        }
+        self
+    }
+
+    /// Sets `max-age`, the number of seconds that the response stays fresh
+    /// for.
+    pub fn max_age(self, seconds: u64) -> Self {
+        self.directive("max-age", Some(seconds))
+    }
+
+    /// Sets `s-maxage`, which is [`CacheControl::max_age`], but only for
+    /// shared caches (e.g. CDNs).
+    pub fn s_maxage(self, seconds: u64) -> Self {
+        self.directive("s-maxage", Some(seconds))
+    }
+
+    /// Sets `stale-while-revalidate`, the number of seconds after the
+    /// response becomes stale that a cache may still use it, while it
+    /// revalidates it in the background.
+    pub fn stale_while_revalidate(self, seconds: u64) -> Self {
+        self.directive("stale-while-revalidate", Some(seconds))
+    }
+
+    /// Adds `public`, which lets shared caches store the response, even if
+    /// it would normally not be (e.g. because the request was authorized).
+    pub fn public(self) -> Self {
+        self.directive("public", None)
+    }
+
+    /// Adds `private`, which only lets the browser store the response, and
+    /// not any shared cache.
+    pub fn private(self) -> Self {
+        self.directive("private", None)
+    }
+
+    /// Adds `no-cache`, which requires caches to revalidate the response
+    /// before every use.
+    pub fn no_cache(self) -> Self {
+        self.directive("no-cache", None)
+    }
+
+    /// Adds `no-store`, which forbids any cache from storing the response.
+    pub fn no_store(self) -> Self {
+        self.directive("no-store", None)
+    }
+
+    /// Adds `must-revalidate`, which forbids caches from using the response
+    /// once it is stale, without revalidating it first.
+    pub fn must_revalidate(self) -> Self {
+        self.directive("must-revalidate", None)
+    }
+
+    /// Adds `no-transform`, which forbids intermediaries from changing the
+    /// response (e.g. recompressing images).
+    pub fn no_transform(self) -> Self {
+        self.directive("no-transform", None)
+    }
+
+    /// Adds `immutable`, which tells the browser that the response never
+    /// changes while it is fresh, so it does not need to revalidate it even
+    /// when the page is reloaded.  This is useful for fingerprinted assets
+    /// (see [`crate::endpoints::StaticManifest`]).
+    pub fn immutable(self) -> Self {
+        self.directive("immutable", None)
+    }
+}
+
+impl std::fmt::Debug for CacheControl<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("CacheControl")
+            .field("directives", &self.directives)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Formats the given time as an HTTP date (e.g.
+/// `Sun, 06 Nov 1994 08:49:37 GMT`), as used by e.g. the `Expires` header.
+pub(crate) fn http_date(time: SystemTime) -> String {
+    const DAYS: [&str; 7] = ["Thu", "Fri", "Sat", "Sun", "Mon", "Tue", "Wed"];
+    const MONTHS: [&str; 12] = [
+        "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+    ];
+    let secs = time
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs());
+    let days = secs / 86_400;
+    let (year, month, day) = crate::middleware::civil_from_days(days);
+    let rem = secs % 86_400;
+    format!(
+        "{}, {day:02} {} {year} {:02}:{:02}:{:02} GMT",
+        DAYS[usize::try_from(days % 7).unwrap_or(0)],
+        MONTHS[usize::try_from(month - 1).unwrap_or(0)],
+        rem / 3600,
+        (rem % 3600) / 60,
+        rem % 60
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn test_http_date() {
+        let time = SystemTime::UNIX_EPOCH + Duration::from_secs(784_111_777);
+        assert_eq!(http_date(time), "Sun, 06 Nov 1994 08:49:37 GMT");
+        assert_eq!(
+            http_date(SystemTime::UNIX_EPOCH),
+            "Thu, 01 Jan 1970 00:00:00 GMT"
+        );
+    }
+}
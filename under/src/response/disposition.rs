@@ -0,0 +1,108 @@
+use std::fmt::Write;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// A `Content-Disposition` header, as described by [RFC 6266].
+///
+/// The filename is encoded as both a plain `filename` parameter - with any
+/// character that cannot be sent as-is replaced - and, if it needs it, a
+/// UTF-8 `filename*` parameter, which clients that support it prefer.
+///
+/// [RFC 6266]: https://www.rfc-editor.org/rfc/rfc6266
+///
+/// # Examples
+/// ```rust
+/// # use under::*;
+/// let disposition = ContentDisposition::attachment("résumé.pdf");
+/// assert_eq!(
+///     disposition.to_string(),
+///     "attachment; filename=\"r_sum_.pdf\"; filename*=UTF-8''r%C3%A9sum%C3%A9.pdf"
+/// );
+/// assert_eq!(ContentDisposition::inline().to_string(), "inline");
+/// ```
+pub struct ContentDisposition {
+    attachment: bool,
+    filename: Option<String>,
+}
+
+impl ContentDisposition {
+    /// A disposition for content that should be downloaded, and saved with
+    /// the given filename.
+    pub fn attachment<S: Into<String>>(filename: S) -> Self {
+        ContentDisposition {
+            attachment: true,
+            filename: Some(filename.into()),
+        }
+    }
+
+    /// A disposition for content that should be shown in the browser.
+    #[must_use]
+    pub fn inline() -> Self {
+        ContentDisposition {
+            attachment: false,
+            filename: None,
+        }
+    }
+
+    /// Sets the filename, used if the content is saved.
+    #[must_use]
+    pub fn with_filename<S: Into<String>>(mut self, filename: S) -> Self {
+        self.filename = Some(filename.into());
+        self
+    }
+
+    /// The header value for the disposition.
+    #[must_use]
+    pub fn to_header_value(&self) -> http::HeaderValue {
+        // Everything that could not be sent as-is has been replaced, or
+        // percent-encoded, so this never falls back.
+        http::HeaderValue::try_from(self.to_string())
+            .unwrap_or_else(|_| http::HeaderValue::from_static("attachment"))
+    }
+}
+
+impl std::fmt::Display for ContentDisposition {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(if self.attachment {
+            "attachment"
+        } else {
+            "inline"
+        })?;
+        let Some(filename) = &self.filename else {
+            return Ok(());
+        };
+
+        f.write_str("; filename=\"")?;
+        for c in filename.chars() {
+            match c {
+                '"' | '\\' => write!(f, "\\{c}")?,
+                ' '..='~' => f.write_char(c)?,
+                _ => f.write_char('_')?,
+            }
+        }
+        f.write_char('"')?;
+
+        if !filename.chars().all(|c| matches!(c, ' '..='~')) {
+            f.write_str("; filename*=UTF-8''")?;
+            for byte in filename.bytes() {
+                if is_attr_char(byte) {
+                    f.write_char(char::from(byte))?;
+                } else {
+                    write!(f, "%{byte:02X}")?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Whether the byte may appear as-is in an extended parameter value (the
+/// `attr-char` of [RFC 8187]).
+///
+/// [RFC 8187]: https://www.rfc-editor.org/rfc/rfc8187
+fn is_attr_char(byte: u8) -> bool {
+    byte.is_ascii_alphanumeric()
+        || matches!(
+            byte,
+            b'!' | b'#' | b'$' | b'&' | b'+' | b'-' | b'.' | b'^' | b'_' | b'`' | b'|' | b'~'
+        )
+}
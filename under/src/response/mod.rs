@@ -0,0 +1,1241 @@
+mod cache;
+mod disposition;
+mod seekable;
+
+pub use self::cache::CacheControl;
+pub use self::disposition::ContentDisposition;
+use std::convert::TryFrom;
+use std::sync::{Arc, Mutex, PoisonError};
+
+/// The request that a response declined to respond to (see
+/// [`Response::decline`]).  Extensions must be cloneable, but requests are
+/// not, so the request is shared between the clones, and can be taken from
+/// any one of them, once.
+#[derive(Clone)]
+pub(crate) struct Declined(Arc<Mutex<Option<crate::Request>>>);
+
+impl Declined {
+    /// Takes the declined request, if it has not already been taken.
+    pub(crate) fn take(&self) -> Option<crate::Request> {
+        self.0.lock().unwrap_or_else(PoisonError::into_inner).take()
+    }
+}
+
+#[derive(Debug)]
+#[must_use]
+/// An HTTP response.
+///
+/// An HTTP Response consists of a head (a status code and some headers), and
+/// a body (which may be empty).  This type offers convenient helpers for
+/// constructing HTTP responses for you for common use-cases.
+///
+/// # Examples
+///
+/// ```rust
+/// use under::{Request, Response, HttpEntity};
+///
+/// // Here, we're defining an endpoint for our server.
+/// async fn handle_get(request: Request) -> Result<Response, anyhow::Error> {
+///     let target = request.fragment_str("target").unwrap_or("world");
+///     Ok(Response::text(format!("hello, {}", target)))
+/// }
+///
+/// # #[tokio::main]
+/// # async fn main() -> Result<(), anyhow::Error> {
+/// let mut http = under::http();
+/// http
+///     .at("/hello").get(handle_get)
+///     .at("/hello/{target}").get(handle_get);
+/// http.prepare();
+/// let mut response = http.handle(Request::get("/hello")?).await?;
+/// assert_eq!(response.status(), http::StatusCode::OK);
+/// let body = response.data(512).into_text().await?;
+/// assert_eq!(body, "hello, world");
+/// # Ok(())
+/// # }
+/// ```
+pub struct Response(http::Response<crate::Body>);
+
+macro_rules! forward {
+    () => {};
+    (
+        $(#[$m:meta])* $v:vis fn $name:ident(&self $(, $pn:ident: $pt:ty)*) -> $ret:ty;
+        $($tail:tt)*
+    ) => {
+        $(#[$m])* $v fn $name(&self $(, $pn: $pt)*) -> $ret {
+            (self.0).$name($($pn),*)
+        }
+
+        forward! { $($tail)* }
+    };
+
+    (
+        $(#[$m:meta])* $v:vis fn $name:ident(&mut self $(, $pn:ident: $pt:ty)*) -> $ret:ty;
+        $($tail:tt)*
+    ) => {
+        $(#[$m])* $v fn $name(&mut self $(, $pn: $pt)*) -> $ret {
+            (self.0).$name($($pn),*)
+        }
+
+        forward! { $($tail)* }
+    }
+}
+
+impl Response {
+    /// Creates an empty response with a status code of 200.
+    ///
+    /// See [`Response::empty_status`] for more information.
+    ///
+    /// # Example
+    /// ```rust
+    /// # use under::*;
+    /// let response = Response::empty_200();
+    /// assert_eq!(response.status(), http::StatusCode::OK);
+    /// ```
+    pub fn empty_200() -> Self {
+        Self::empty_status(http::StatusCode::OK)
+    }
+
+    /// Creates an empty response with a status code of 204.
+    ///
+    /// See [`Response::empty_status`] for more information.
+    ///
+    /// # Examples
+    /// ```rust
+    /// # use under::*;
+    /// let response = Response::empty_204();
+    /// assert_eq!(response.status(), http::StatusCode::NO_CONTENT);
+    /// ```
+    pub fn empty_204() -> Self {
+        Response::empty_status(http::StatusCode::NO_CONTENT)
+    }
+
+    /// Creates an empty response with a status code of 400.
+    ///
+    /// See [`Response::empty_status`] for more information.
+    ///
+    /// # Examples
+    /// ```rust
+    /// # use under::*;
+    /// let response = Response::empty_400();
+    /// assert_eq!(response.status(), http::StatusCode::BAD_REQUEST);
+    /// ```
+    pub fn empty_400() -> Self {
+        Response::empty_status(http::StatusCode::BAD_REQUEST)
+    }
+
+    /// Creates an empty response with a status code of 401.
+    ///
+    /// See [`Response::empty_status`] for more information.
+    ///
+    /// # Examples
+    /// ```rust
+    /// # use under::*;
+    /// let response = Response::empty_401();
+    /// assert_eq!(response.status(), http::StatusCode::UNAUTHORIZED);
+    /// ```
+    pub fn empty_401() -> Self {
+        Response::empty_status(http::StatusCode::UNAUTHORIZED)
+    }
+
+    /// Creates an empty response with a status code of 403.
+    ///
+    /// See [`Response::empty_status`] for more information.
+    ///
+    /// # Examples
+    /// ```rust
+    /// # use under::*;
+    /// let response = Response::empty_403();
+    /// assert_eq!(response.status(), http::StatusCode::FORBIDDEN);
+    /// ```
+    pub fn empty_403() -> Self {
+        Response::empty_status(http::StatusCode::FORBIDDEN)
+    }
+
+    /// Creates an empty response with a status code of 404.
+    ///
+    /// See [`Response::empty_status`] for more information.
+    ///
+    /// # Examples
+    /// ```rust
+    /// # use under::*;
+    /// let response = Response::empty_404();
+    /// assert_eq!(response.status(), http::StatusCode::NOT_FOUND);
+    /// ```
+    pub fn empty_404() -> Self {
+        Response::empty_status(http::StatusCode::NOT_FOUND)
+    }
+
+    /// Creates an empty response with a status code of 422.
+    ///
+    /// See [`Response::empty_status`] for more information.
+    ///
+    /// # Examples
+    /// ```rust
+    /// # use under::*;
+    /// let response = Response::empty_422();
+    /// assert_eq!(response.status(), http::StatusCode::UNPROCESSABLE_ENTITY);
+    /// ```
+    pub fn empty_422() -> Self {
+        Response::empty_status(http::StatusCode::UNPROCESSABLE_ENTITY)
+    }
+
+    /// Creates an empty response with a status code of 500.
+    ///
+    /// See [`Response::empty_status`] for more information.
+    ///
+    /// # Examples
+    /// ```rust
+    /// # use under::*;
+    /// let response = Response::empty_500();
+    /// assert_eq!(response.status(), http::StatusCode::INTERNAL_SERVER_ERROR);
+    /// ```
+    pub fn empty_500() -> Self {
+        Response::empty_status(http::StatusCode::INTERNAL_SERVER_ERROR)
+    }
+
+    /// Creates an empty response with a status code of 503.
+    ///
+    /// See [`Response::empty_status`] for more information.
+    ///
+    /// # Examples
+    /// ```rust
+    /// # use under::*;
+    /// let response = Response::empty_503();
+    /// assert_eq!(response.status(), http::StatusCode::SERVICE_UNAVAILABLE);
+    /// ```
+    pub fn empty_503() -> Self {
+        Response::empty_status(http::StatusCode::SERVICE_UNAVAILABLE)
+    }
+
+    /// Creates a response that declines to respond to the request, handing
+    /// it back so that the next fallback of a chain can respond to it
+    /// instead (see [`crate::Router::fallback_chain`]).  When there is no
+    /// next fallback, this is an empty 404.
+    ///
+    /// # Examples
+    /// ```rust
+    /// # use under::*;
+    /// let response = Response::decline(Request::get("/").unwrap());
+    /// assert!(response.is_declined());
+    /// assert_eq!(response.status(), http::StatusCode::NOT_FOUND);
+    /// ```
+    pub fn decline(request: crate::Request) -> Self {
+        let mut response = Response::empty_404();
+        response.set_ext(Declined(Arc::new(Mutex::new(Some(request)))));
+        response
+    }
+
+    /// Whether this response declined to respond to its request (see
+    /// [`Response::decline`]).
+    #[must_use]
+    pub fn is_declined(&self) -> bool {
+        self.ext::<Declined>().is_some()
+    }
+
+    /// Creates a redirect (using See Other) to the given location.
+    ///
+    /// # Errors
+    /// This attempts to convert the location into a [`http::HeaderValue`];
+    /// however, the conversion may fail (for reasons specified on
+    /// [`http::HeaderValue::from_str`]).  It may also fail to construct the
+    /// underlying response.
+    ///
+    /// # Examples
+    /// ```rust
+    /// # use under::*;
+    /// let response = Response::see_other("/foo").unwrap();
+    /// assert_eq!(response.status(), http::StatusCode::SEE_OTHER);
+    /// ```
+    pub fn see_other<T>(location: T) -> Result<Self, http::Error>
+    where
+        http::HeaderValue: TryFrom<T>,
+        <http::HeaderValue as TryFrom<T>>::Error: Into<http::Error>,
+    {
+        Ok(Response(
+            http::Response::builder()
+                .status(http::StatusCode::SEE_OTHER)
+                .header(http::header::LOCATION, location)
+                .body(crate::Body::empty())?,
+        ))
+    }
+
+    /// Creates an empty response that has nginx send the given URI in its
+    /// place, through the `X-Accel-Redirect` header.  The URI is usually
+    /// that of an `internal` location, so that clients cannot request it
+    /// directly; this lets the application check access to a file, and then
+    /// leave sending it to the proxy.
+    ///
+    /// # Errors
+    /// This errors if the URI is not a valid header value.
+    ///
+    /// # Examples
+    /// ```rust
+    /// # use under::*;
+    /// let response = Response::x_accel_redirect("/protected/report.pdf").unwrap();
+    /// assert_eq!(response.header("X-Accel-Redirect").unwrap(), "/protected/report.pdf");
+    /// ```
+    pub fn x_accel_redirect<T>(uri: T) -> Result<Self, http::Error>
+    where
+        http::HeaderValue: TryFrom<T>,
+        <http::HeaderValue as TryFrom<T>>::Error: Into<http::Error>,
+    {
+        Ok(Response(
+            http::Response::builder()
+                .header("X-Accel-Redirect", uri)
+                .body(crate::Body::empty())?,
+        ))
+    }
+
+    /// Creates an empty response that has the front proxy (Apache, with
+    /// `mod_xsendfile`, or lighttpd) send the file at the given path in its
+    /// place, through the `X-Sendfile` header.  The path should be absolute,
+    /// as the proxy resolves it on its own.
+    ///
+    /// # Errors
+    /// This errors if the path is not a valid header value (e.g. if it has
+    /// a newline in it).
+    ///
+    /// # Examples
+    /// ```rust
+    /// # use under::*;
+    /// let response = Response::x_sendfile("/srv/files/report.pdf").unwrap();
+    /// assert_eq!(response.header("X-Sendfile").unwrap(), "/srv/files/report.pdf");
+    /// ```
+    pub fn x_sendfile<P: AsRef<std::path::Path>>(path: P) -> Result<Self, http::Error> {
+        let path = path.as_ref().as_os_str().as_encoded_bytes();
+        Ok(Response(
+            http::Response::builder()
+                .header("X-Sendfile", http::HeaderValue::from_bytes(path)?)
+                .body(crate::Body::empty())?,
+        ))
+    }
+
+    /// Creates a permanent redirect to the given location.
+    ///
+    /// # Errors
+    /// This attempts to convert the location into a
+    /// [`http::HeaderValue`]; however, the conversion may fail (for
+    /// reasons specified on [`http::HeaderValue::from_str`]).  It may also
+    /// fail to construct the underlying response.
+    ///
+    /// # Examples
+    /// ```rust
+    /// # use under::*;
+    /// let response = Response::permanent_redirect("/foo").unwrap();
+    /// assert_eq!(response.status(), http::StatusCode::PERMANENT_REDIRECT);
+    /// ```
+    pub fn permanent_redirect<T>(location: T) -> Result<Self, http::Error>
+    where
+        http::HeaderValue: TryFrom<T>,
+        <http::HeaderValue as TryFrom<T>>::Error: Into<http::Error>,
+    {
+        Ok(Response(
+            http::Response::builder()
+                .status(http::StatusCode::PERMANENT_REDIRECT)
+                .header(http::header::LOCATION, location)
+                .body(crate::Body::empty())?,
+        ))
+    }
+
+    /// Creates a temporary redirect to the given location.
+    ///
+    /// # Errors
+    /// This attempts to convert the location into a
+    /// [`http::HeaderValue`]; however, the conversion may fail (for
+    /// reasons specified on [`http::HeaderValue::from_str`]).  It may also
+    /// fail to construct the underlying response.
+    ///
+    /// # Examples
+    /// ```rust
+    /// # use under::*;
+    /// let response = Response::temporary_redirect("/foo").unwrap();
+    /// assert_eq!(response.status(), http::StatusCode::TEMPORARY_REDIRECT);
+    /// ```
+    pub fn temporary_redirect<T>(location: T) -> Result<Self, http::Error>
+    where
+        http::HeaderValue: TryFrom<T>,
+        <http::HeaderValue as TryFrom<T>>::Error: Into<http::Error>,
+    {
+        Ok(Response(
+            http::Response::builder()
+                .status(http::StatusCode::TEMPORARY_REDIRECT)
+                .header(http::header::LOCATION, location)
+                .body(crate::Body::empty())?,
+        ))
+    }
+
+    /// Creates an empty response with a status code of 201, with the location
+    /// of the created resource.
+    ///
+    /// # Errors
+    /// This attempts to convert the location into a
+    /// [`http::HeaderValue`]; however, the conversion may fail (for
+    /// reasons specified on [`http::HeaderValue::from_str`]).  It may also
+    /// fail to construct the underlying response.
+    ///
+    /// # Examples
+    /// ```rust
+    /// # use under::*;
+    /// let response = Response::created("/users/1").unwrap();
+    /// assert_eq!(response.status(), http::StatusCode::CREATED);
+    /// assert_eq!(response.header(http::header::LOCATION).unwrap(), "/users/1");
+    /// ```
+    pub fn created<T>(location: T) -> Result<Self, http::Error>
+    where
+        http::HeaderValue: TryFrom<T>,
+        <http::HeaderValue as TryFrom<T>>::Error: Into<http::Error>,
+    {
+        Ok(Response(
+            http::Response::builder()
+                .status(http::StatusCode::CREATED)
+                .header(http::header::LOCATION, location)
+                .body(crate::Body::empty())?,
+        ))
+    }
+
+    /// Creates an empty response with a status code of 202.  This is used to
+    /// signal that the request has been accepted for processing, but that
+    /// processing has not yet completed.
+    ///
+    /// # Examples
+    /// ```rust
+    /// # use under::*;
+    /// let response = Response::accepted();
+    /// assert_eq!(response.status(), http::StatusCode::ACCEPTED);
+    /// ```
+    pub fn accepted() -> Self {
+        Response::empty_status(http::StatusCode::ACCEPTED)
+    }
+
+    /// Creates an empty response with a status code of 204.  This is the same
+    /// as [`Response::empty_204`].
+    ///
+    /// # Examples
+    /// ```rust
+    /// # use under::*;
+    /// let response = Response::no_content();
+    /// assert_eq!(response.status(), http::StatusCode::NO_CONTENT);
+    /// ```
+    pub fn no_content() -> Self {
+        Response::empty_204()
+    }
+
+    /// Creates a response with an empty body and the given status.  This is
+    /// the same as [`Response::empty_status`], but accepts anything that can
+    /// be converted into a [`http::StatusCode`].
+    ///
+    /// # Examples
+    /// ```rust
+    /// # use under::*;
+    /// let response = Response::empty(http::StatusCode::IM_A_TEAPOT);
+    /// assert_eq!(response.status(), http::StatusCode::IM_A_TEAPOT);
+    /// ```
+    pub fn empty<S: Into<http::StatusCode>>(status: S) -> Self {
+        Response::empty_status(status.into())
+    }
+
+    /// Creates a response with an empty body and a set status.  The
+    /// Content-Type is not set.
+    ///
+    /// # Examples
+    /// ```rust
+    /// # use under::*;
+    /// let response = Response::empty_status(http::StatusCode::NOT_FOUND);
+    /// assert_eq!(response.status(), http::StatusCode::NOT_FOUND);
+    /// ```
+    #[allow(clippy::missing_panics_doc)]
+    pub fn empty_status(status: http::StatusCode) -> Self {
+        // This shouldn't panic, as the headers are garenteed to be valid.
+        Response(
+            http::Response::builder()
+                .status(status)
+                .body(crate::Body::empty())
+                .unwrap(),
+        )
+    }
+
+    /// Creates a response with the given text body.  The returned response
+    /// has a `Content-Type` of `text/plain; charset=utf-8`.
+    ///
+    /// # Examples
+    /// ```rust
+    /// # use under::*;
+    /// let response = Response::text("hello, world");
+    /// ```
+    #[allow(clippy::missing_panics_doc)]
+    pub fn text<V: Into<String>>(body: V) -> Self {
+        // This shouldn't panic, as the headers are garenteed to be valid.
+        Response(
+            http::Response::builder()
+                .header(http::header::CONTENT_TYPE, "text/plain; charset=utf-8")
+                .body(body.into().into())
+                .unwrap(),
+        )
+    }
+
+    /// Creates a response with the given status, and the given text body.
+    /// The returned response has a `Content-Type` of
+    /// `text/plain; charset=utf-8`.
+    ///
+    /// # Examples
+    /// ```rust
+    /// # use under::*;
+    /// # #[tokio::main] async fn main() -> Result<(), anyhow::Error> {
+    /// let mut response = Response::status_text(http::StatusCode::BAD_REQUEST, "missing name");
+    /// assert_eq!(response.status(), http::StatusCode::BAD_REQUEST);
+    /// assert_eq!(response.data(512).into_text().await?, "missing name");
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn status_text<S: Into<http::StatusCode>, V: Into<String>>(status: S, body: V) -> Self {
+        Response::text(body).with_status(status)
+    }
+
+    /// Creates a response with the given JSON body.  The returned response
+    /// has a `Content-Type` of `application/json; charset=utf-8`.
+    ///
+    /// # Errors
+    /// This errors if the underlying JSON serialization fails; and it will
+    /// return that exact error.
+    ///
+    /// # Examples
+    /// ```rust
+    /// # use under::*;
+    /// let response = Response::json(&serde_json::json!({ "hello": "world" }))?;
+    /// # Ok::<(), anyhow::Error>(())
+    /// ```
+    #[cfg(feature = "json")]
+    #[allow(clippy::missing_panics_doc)]
+    pub fn json<V: serde::Serialize>(body: &V) -> Result<Self, serde_json::Error> {
+        let value = serde_json::to_string(body)?;
+        // This shouldn't panic, as the value is a valid JSON string, and the
+        // headers are garenteed to be valid.
+        Ok(Response(
+            http::Response::builder()
+                .header(
+                    http::header::CONTENT_TYPE,
+                    "application/json; charset=utf-8",
+                )
+                .body(value.into())
+                .unwrap(),
+        ))
+    }
+
+    /// Creates a response with a JSON array body, written incrementally from
+    /// the given stream of items.  Each item is serialized as it is produced
+    /// by the stream, and written as an element of the array; so the array
+    /// is never buffered in full, and the body is always a well-formed JSON
+    /// array (once the stream ends).  This is useful for exporting a large
+    /// number of records.  The returned response has a `Content-Type` of
+    /// `application/json; charset=utf-8`.
+    ///
+    /// If an item fails to serialize, the body is aborted, and the client
+    /// sees an incomplete response.
+    ///
+    /// # Examples
+    /// ```rust
+    /// # use under::*;
+    /// # #[tokio::main] async fn main() -> Result<(), anyhow::Error> {
+    /// use futures::StreamExt;
+    /// let rows = futures::stream::iter(1..=3).map(|id| serde_json::json!({ "id": id }));
+    /// let mut response = Response::json_stream(rows);
+    /// let body = response.data(512).into_text().await?;
+    /// assert_eq!(body, r#"[{"id":1},{"id":2},{"id":3}]"#);
+    /// let mut response = Response::json_stream(futures::stream::empty::<u32>());
+    /// assert_eq!(response.data(512).into_text().await?, "[]");
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[cfg(feature = "json")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "json")))]
+    #[allow(clippy::missing_panics_doc)]
+    pub fn json_stream<S, V>(stream: S) -> Self
+    where
+        S: futures::Stream<Item = V> + Send + 'static,
+        V: serde::Serialize,
+    {
+        use futures::StreamExt;
+        let items = stream.enumerate().map(|(i, item)| {
+            let mut buffer = if i == 0 { vec![] } else { vec![b','] };
+            serde_json::to_writer(&mut buffer, &item)?;
+            Ok::<_, serde_json::Error>(buffer)
+        });
+        let body = futures::stream::once(async { Ok(b"[".to_vec()) })
+            .chain(items)
+            .chain(futures::stream::once(async { Ok(b"]".to_vec()) }));
+        // This shouldn't panic, as the headers are garenteed to be valid.
+        Response(
+            http::Response::builder()
+                .header(
+                    http::header::CONTENT_TYPE,
+                    "application/json; charset=utf-8",
+                )
+                .body(crate::Body::wrap_stream(body))
+                .unwrap(),
+        )
+    }
+
+    /// Creates a response with a streaming body, and a sender that writes to
+    /// it.  Data written to the sender is sent to the client as it is
+    /// written, and once all of the data has been written, trailers (for
+    /// example, a checksum or row count computed while streaming) may be sent
+    /// with [`crate::BodySender::send_trailers`].  The body ends when the
+    /// sender is dropped.  Trailers should be declared beforehand with
+    /// [`Response::with_trailers_declared`].
+    ///
+    /// Note that trailers are only sent to clients over HTTP/2; HTTP/1.1
+    /// responses are sent without them.
+    ///
+    /// # Examples
+    /// ```rust
+    /// # use under::*;
+    /// # #[tokio::main] async fn main() -> Result<(), anyhow::Error> {
+    /// let (response, mut sender) = Response::channel();
+    /// let mut response = response.with_trailers_declared(["Row-Count"])?;
+    /// tokio::spawn(async move {
+    ///     sender.send_data("a,b\n".into()).await?;
+    ///     let mut trailers = http::HeaderMap::new();
+    ///     trailers.insert("Row-Count", "1".parse()?);
+    ///     sender.send_trailers(trailers).await?;
+    ///     Ok::<_, anyhow::Error>(())
+    /// });
+    /// assert_eq!(response.header("Trailer").unwrap(), "row-count");
+    /// let (body, trailers) = response.data(512).into_bytes_with_trailers().await?;
+    /// assert_eq!(body, b"a,b\n");
+    /// assert_eq!(trailers.unwrap()["Row-Count"], "1");
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn channel() -> (Self, crate::BodySender) {
+        let (sender, body) = crate::Body::channel();
+        (Response(http::Response::new(body)), sender)
+    }
+
+    /// Declares the trailers that will be sent after the body of the
+    /// response, by setting the `Trailer` header to the given names.  This
+    /// replaces any previously declared trailers.
+    ///
+    /// # Errors
+    /// This errors if any of the given names is not a valid header name.
+    ///
+    /// # Examples
+    /// ```rust
+    /// # use under::*;
+    /// let response = Response::empty_200()
+    ///     .with_trailers_declared(["Content-MD5", "Row-Count"])
+    ///     .unwrap();
+    /// assert_eq!(response.header("Trailer").unwrap(), "content-md5, row-count");
+    /// ```
+    pub fn with_trailers_declared<I, K>(mut self, names: I) -> Result<Self, http::Error>
+    where
+        I: IntoIterator<Item = K>,
+        http::HeaderName: TryFrom<K>,
+        http::Error: From<<http::HeaderName as TryFrom<K>>::Error>,
+    {
+        let names = names
+            .into_iter()
+            .map(|name| Ok(http::HeaderName::try_from(name)?.as_str().to_owned()))
+            .collect::<Result<Vec<_>, http::Error>>()?;
+        let value = http::HeaderValue::try_from(names.join(", "))?;
+        self.0.headers_mut().insert(http::header::TRAILER, value);
+        Ok(self)
+    }
+
+    /// Creates a response with the given XML body.  The returned response
+    /// has a `Content-Type` of `application/xml; charset=utf-8`.
+    ///
+    /// # Errors
+    /// This errors if the underlying XML serialization fails; and it will
+    /// return that exact error.
+    ///
+    /// # Examples
+    /// ```rust
+    /// # use under::*;
+    /// #[derive(serde::Serialize)]
+    /// struct Greeting { hello: &'static str }
+    /// # #[tokio::main] async fn main() -> Result<(), anyhow::Error> {
+    /// let mut response = Response::xml(&Greeting { hello: "world" })?;
+    /// let body = response.data(512).into_text().await?;
+    /// assert_eq!(body, "<Greeting><hello>world</hello></Greeting>");
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[cfg(feature = "xml")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "xml")))]
+    #[allow(clippy::missing_panics_doc)]
+    pub fn xml<V: serde::Serialize>(body: &V) -> Result<Self, quick_xml::SeError> {
+        let value = quick_xml::se::to_string(body)?;
+        // This shouldn't panic, as the headers are garenteed to be valid.
+        Ok(Response(
+            http::Response::builder()
+                .header(http::header::CONTENT_TYPE, "application/xml; charset=utf-8")
+                .body(value.into())
+                .unwrap(),
+        ))
+    }
+
+    /// Sets the current responses's status code.
+    ///
+    /// # Examples
+    /// ```rust
+    /// # use under::*;
+    /// let mut response = Response::empty_404();
+    /// response.set_status(http::StatusCode::OK);
+    /// assert_eq!(response.status(), http::StatusCode::OK);
+    /// ```
+    pub fn set_status<S: Into<http::StatusCode>>(&mut self, status: S) {
+        *self.0.status_mut() = status.into();
+    }
+
+    /// Returns a response with the new status code.
+    ///
+    /// # Examples
+    /// ```rust
+    /// # use under::*;
+    /// let response = Response::empty_404();
+    /// let response = response.with_status(http::StatusCode::OK);
+    /// assert_eq!(response.status(), http::StatusCode::OK);
+    /// ```
+    pub fn with_status<S: Into<http::StatusCode>>(mut self, status: S) -> Self {
+        *self.0.status_mut() = status.into();
+        Response(self.0)
+    }
+
+    /// Returns a response that is downloaded as an attachment with the given
+    /// filename, by setting its `Content-Disposition` header.  The filename
+    /// is encoded so that non-ASCII names survive intact (see
+    /// [`ContentDisposition`]).
+    ///
+    /// # Examples
+    /// ```rust
+    /// # use under::*;
+    /// let response = Response::text("a,b\n").attachment("report \"q1\".csv");
+    /// assert_eq!(
+    ///     response.header("Content-Disposition").unwrap(),
+    ///     "attachment; filename=\"report \\\"q1\\\".csv\""
+    /// );
+    /// ```
+    pub fn attachment<S: Into<String>>(mut self, filename: S) -> Self {
+        let disposition = ContentDisposition::attachment(filename);
+        self.0.headers_mut().insert(
+            http::header::CONTENT_DISPOSITION,
+            disposition.to_header_value(),
+        );
+        self
+    }
+
+    /// Sets the `Cache-Control` header of the response, using a builder.
+    /// This replaces any `Cache-Control` header the response already has,
+    /// once the first directive is added.
+    ///
+    /// # Examples
+    /// ```rust
+    /// # use under::*;
+    /// let mut response = Response::text("hello");
+    /// response.cache_control().private().no_cache();
+    /// assert_eq!(response.header("Cache-Control").unwrap(), "private, no-cache");
+    /// ```
+    pub fn cache_control(&mut self) -> CacheControl<'_> {
+        CacheControl::new(self)
+    }
+
+    /// Sets the `Expires` header of the response to the given time.
+    ///
+    /// # Examples
+    /// ```rust
+    /// # use under::*;
+    /// use std::time::{Duration, SystemTime};
+    /// let mut response = Response::text("hello");
+    /// response.set_expires(SystemTime::UNIX_EPOCH + Duration::from_secs(784_111_777));
+    /// assert_eq!(
+    ///     response.header("Expires").unwrap(),
+    ///     "Sun, 06 Nov 1994 08:49:37 GMT"
+    /// );
+    /// ```
+    pub fn set_expires(&mut self, time: std::time::SystemTime) {
+        let date = self::cache::http_date(time);
+        if let Ok(date) = http::HeaderValue::try_from(date) {
+            self.0.headers_mut().insert(http::header::EXPIRES, date);
+        }
+    }
+
+    /// Returns a response with the `Expires` header set to the given time.
+    /// See [`Response::set_expires`].
+    pub fn with_expires(mut self, time: std::time::SystemTime) -> Self {
+        self.set_expires(time);
+        self
+    }
+
+    /// Creates a new response with the same head as this one (its status,
+    /// version, and headers), but with the given body.  Extensions are not
+    /// copied.  Since the body is [`bytes::Bytes`], which is reference
+    /// counted, this is a cheap way to reuse a response; see
+    /// [`ResponseTemplate`].
+    ///
+    /// # Examples
+    /// ```rust
+    /// # use under::*;
+    /// let response = Response::text("hello");
+    /// let copy = response.clone_with_body(bytes::Bytes::from_static(b"world"));
+    /// assert_eq!(copy.status(), response.status());
+    /// assert_eq!(copy.headers(), response.headers());
+    /// ```
+    pub fn clone_with_body(&self, body: bytes::Bytes) -> Response {
+        let mut response = http::Response::new(crate::Body::from(body));
+        *response.status_mut() = self.0.status();
+        *response.version_mut() = self.0.version();
+        response.headers_mut().clone_from(self.0.headers());
+        Response(response)
+    }
+
+    /// Buffers the body of this response, returning a [`CachedResponse`]
+    /// that can be cloned, and converted back into a response any number of
+    /// times.  Extensions are not kept.
+    ///
+    /// # Errors
+    /// Errors if the body could not be read.
+    ///
+    /// # Examples
+    /// ```rust
+    /// # use under::*;
+    /// # #[tokio::main] async fn main() -> Result<(), anyhow::Error> {
+    /// let cached = Response::text("hello").into_cached().await?;
+    /// let mut first: Response = cached.clone().into();
+    /// let mut second = cached.to_response();
+    /// assert_eq!(first.data(512).into_text().await?, "hello");
+    /// assert_eq!(second.data(512).into_text().await?, "hello");
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn into_cached(self) -> Result<CachedResponse, crate::UnderError> {
+        let (parts, body) = self.0.into_parts();
+        let body = http_body_util::BodyExt::collect(body)
+            .await
+            .map_err(crate::UnderError::ReadBody)?
+            .to_bytes();
+        Ok(CachedResponse {
+            status: parts.status,
+            version: parts.version,
+            headers: parts.headers,
+            body,
+        })
+    }
+
+    /// Returns state information provided by the
+    /// [`crate::middleware::StateMiddleware`] middleware.  This is a
+    /// shortcut to retrieving the [`crate::middleware::State`]
+    /// extension from the response.
+    ///
+    /// # Examples
+    /// ```rust
+    /// # use under::*;
+    /// use under::middleware::State;
+    /// let mut response = Response::empty_200();
+    /// response.extensions_mut().insert(State(123u32));
+    /// assert_eq!(response.state::<u32>(), Some(&123u32));
+    /// ```
+    pub fn state<T: Send + Sync + 'static>(&self) -> Option<&T> {
+        self.ext::<crate::middleware::State<T>>().map(|v| &v.0)
+    }
+
+    /// Retrieves a specific extension from the extensions map.  This is
+    /// the same as calling [`Self::extensions`].`get` wit the given
+    /// type parameter.
+    ///
+    /// # Examples
+    /// ```rust
+    /// # use under::*;
+    /// let mut response = Response::empty_200();
+    /// assert_eq!(response.ext::<u32>(), None);
+    /// ```
+    pub fn ext<T: Send + Sync + 'static>(&self) -> Option<&T> {
+        self.extensions().get::<T>()
+    }
+
+    /// Retrieves a mutable reference to the specific extension from the
+    /// extensions map.  This is the same as calling
+    /// [`Self::extensions_mut`].`get_mut` with the given type
+    /// parameter.
+    ///
+    /// # Examples
+    /// ```rust
+    /// # use under::*;
+    /// let mut response = Response::empty_200();
+    /// assert_eq!(response.ext_mut::<u32>(), None);
+    /// ```
+    pub fn ext_mut<T: Send + Sync + 'static>(&mut self) -> Option<&mut T> {
+        self.extensions_mut().get_mut::<T>()
+    }
+
+    /// Sets the value of the specific extension in the extensions map.
+    /// This is the same as calling [`Self::extensions_mut`].`insert`
+    /// with the given parameter.
+    ///
+    /// # Examples
+    /// ```rust
+    /// # use under::*;
+    /// let mut response = Response::empty_200();
+    /// response.set_ext(123u32);
+    /// assert_eq!(response.ext::<u32>(), Some(&123u32));
+    /// ```
+    pub fn set_ext<T: Clone + Send + Sync + 'static>(&mut self, value: T) -> &mut Self {
+        self.extensions_mut().insert(value);
+        self
+    }
+
+    /// Sets the value of the specific extension in the extensions map,
+    /// consuming `self`, and then returning the new value.  This is
+    /// the same as calling [`Self::set_ext`], but it consumes `self`.
+    ///
+    /// # Examples
+    /// ```rust
+    /// # use under::*;
+    /// let response = Response::empty_200();
+    /// let response = response.with_ext(123u32);
+    /// assert_eq!(response.ext::<u32>(), Some(&123u32));
+    /// ```
+    pub fn with_ext<T: Clone + Send + Sync + 'static>(mut self, value: T) -> Self {
+        self.set_ext(value);
+        self
+    }
+
+    /// Removes the specific extension from the extensions map.  This is
+    /// the same as calling [`Self::extensions_mut`].`remove` with the
+    /// given type parameter.
+    ///
+    /// # Examples
+    /// ```rust
+    /// # use under::*;
+    /// let mut response = Response::empty_200()
+    ///     .with_ext(123u32);
+    /// assert_eq!(response.ext::<u32>(), Some(&123u32));
+    /// response.remove_ext::<u32>();
+    /// assert_eq!(response.ext::<u32>(), None);
+    /// ```
+    pub fn remove_ext<T: Send + Sync + 'static>(&mut self) -> Option<T> {
+        self.extensions_mut().remove::<T>()
+    }
+
+    /// Removes the specific extension from the extensions map,
+    /// consuming `self`, and then returning the removed value.  This
+    /// is the same as calling [`Self::remove_ext`], but it consumes
+    /// `self`.
+    ///
+    /// # Examples
+    /// ```rust
+    /// # use under::*;
+    /// let response = Response::empty_200()
+    ///     .with_ext(123u32);
+    /// assert_eq!(response.ext::<u32>(), Some(&123u32));
+    /// let response = response.without_ext::<u32>();
+    /// assert_eq!(response.ext::<u32>(), None);
+    /// ```
+    pub fn without_ext<T: Send + Sync + 'static>(mut self) -> Self {
+        self.remove_ext::<T>();
+        self
+    }
+
+    forward! {
+        /// Returns the [`http::StatusCode`].
+        ///
+        /// # Examples
+        ///
+        /// ```rust
+        /// # use under::*;
+        /// let response = Response::default();
+        /// assert_eq!(response.status(), http::StatusCode::OK);
+        /// ```
+        pub fn status(&self) -> http::StatusCode;
+        /// Returns a reference to the associated extensions.
+        ///
+        /// # Examples
+        ///
+        /// ```rust
+        /// # use under::*;
+        /// let response = Response::default();
+        /// assert!(response.extensions().get::<i32>().is_none());
+        /// ```
+        pub fn extensions(&self) -> &http::Extensions;
+        /// Returns a mutable reference to the associated extensions.
+        ///
+        /// # Examples
+        ///
+        /// ```rust
+        /// # use under::*;
+        /// let mut response = Response::default();
+        /// response.extensions_mut().insert("hello");
+        /// assert_eq!(response.extensions().get(), Some(&"hello"));
+        /// ```
+        pub fn extensions_mut(&mut self) -> &mut http::Extensions;
+        /// Returns a reference to the associated header field map.
+        ///
+        /// # Examples
+        ///
+        /// ```rust
+        /// # use under::*;
+        /// let response = Response::default();
+        /// assert!(response.headers().is_empty());
+        /// ```
+        pub fn headers(&self) -> &http::HeaderMap<http::HeaderValue>;
+        /// Returns a mutable reference to the associated header field map.
+        ///
+        /// # Examples
+        ///
+        /// ```
+        /// # use under::*;
+        /// # use http::header::*;
+        /// let mut response = Response::default();
+        /// response.headers_mut().insert(HOST, HeaderValue::from_static("world"));
+        /// assert!(!response.headers().is_empty());
+        /// ```
+        pub fn headers_mut(&mut self) -> &mut http::HeaderMap<http::HeaderValue>;
+    }
+}
+
+impl crate::HttpEntity for Response {
+    #[inline]
+    fn body_mut(&mut self) -> &mut crate::Body {
+        self.0.body_mut()
+    }
+    #[inline]
+    fn headers(&self) -> &http::HeaderMap<http::HeaderValue> {
+        self.0.headers()
+    }
+    #[inline]
+    fn headers_mut(&mut self) -> &mut http::HeaderMap<http::HeaderValue> {
+        self.0.headers_mut()
+    }
+}
+
+impl Default for Response {
+    fn default() -> Self {
+        Response(
+            http::Response::builder()
+                .body(crate::Body::empty())
+                .unwrap(),
+        )
+    }
+}
+
+impl From<http::Response<crate::Body>> for Response {
+    fn from(hy: http::Response<crate::Body>) -> Self {
+        Response(hy)
+    }
+}
+
+impl From<Response> for http::Response<crate::Body> {
+    fn from(this: Response) -> Self {
+        this.0
+    }
+}
+
+impl std::borrow::Borrow<http::Response<crate::Body>> for Response {
+    fn borrow(&self) -> &http::Response<crate::Body> {
+        &self.0
+    }
+}
+
+impl std::borrow::BorrowMut<http::Response<crate::Body>> for Response {
+    fn borrow_mut(&mut self) -> &mut http::Response<crate::Body> {
+        &mut self.0
+    }
+}
+
+/// A prebuilt response, that can be cheaply turned into a [`Response`] any
+/// number of times.
+///
+/// This is a response head, and a body of [`bytes::Bytes`]; each response
+/// that is generated clones the head, and shares the body.  This is useful
+/// for responses that are the same for every request - such as an error
+/// page - to avoid constructing them per request.  It can be used as an
+/// endpoint (e.g. with [`crate::Router::fallback`]), and as the router's
+/// error response (see [`crate::Router::error_response`]).
+///
+/// # Examples
+/// ```rust
+/// # use under::*;
+/// # #[tokio::main] async fn main() -> Result<(), anyhow::Error> {
+/// let not_found = ResponseTemplate::new(
+///     Response::status_text(http::StatusCode::NOT_FOUND, ""),
+///     "<h1>Not Found</h1>",
+/// ).with_header(http::header::CONTENT_TYPE, "text/html; charset=utf-8")?;
+/// let mut http = under::http();
+/// http.fallback(not_found);
+/// http.prepare();
+/// let mut response = http.handle(Request::get("/missing")?).await?;
+/// assert_eq!(response.status(), http::StatusCode::NOT_FOUND);
+/// assert_eq!(response.data(512).into_text().await?, "<h1>Not Found</h1>");
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct ResponseTemplate {
+    head: Response,
+    body: bytes::Bytes,
+}
+
+impl ResponseTemplate {
+    /// Creates a template with the head of the given response, and the
+    /// given body.  The body of the given response is discarded.
+    pub fn new<B: Into<bytes::Bytes>>(head: Response, body: B) -> Self {
+        ResponseTemplate {
+            head: crate::HttpEntity::with_body(head, crate::Body::empty()),
+            body: body.into(),
+        }
+    }
+
+    /// Sets a header on the template's head.
+    ///
+    /// # Errors
+    /// Errors if the header value is invalid.
+    pub fn with_header<H, V>(mut self, key: H, value: V) -> Result<Self, http::Error>
+    where
+        H: http::header::IntoHeaderName,
+        V: TryInto<http::HeaderValue>,
+        http::Error: From<<V as TryInto<http::HeaderValue>>::Error>,
+    {
+        self.head = crate::HttpEntity::with_header(self.head, key, value)?;
+        Ok(self)
+    }
+
+    /// Generates a response from this template.
+    pub fn to_response(&self) -> Response {
+        self.head.clone_with_body(self.body.clone())
+    }
+}
+
+impl Clone for ResponseTemplate {
+    fn clone(&self) -> Self {
+        ResponseTemplate {
+            head: self.head.clone_with_body(bytes::Bytes::new()),
+            body: self.body.clone(),
+        }
+    }
+}
+
+#[async_trait]
+impl crate::Endpoint for ResponseTemplate {
+    async fn apply(
+        self: std::pin::Pin<&Self>,
+        _request: crate::Request,
+    ) -> Result<Response, anyhow::Error> {
+        Ok(self.to_response())
+    }
+}
+
+#[derive(Debug, Clone)]
+/// A response whose body has been buffered, so that it can be cloned.  This
+/// is created with [`Response::into_cached`], and is useful for caching
+/// responses, or for comparing them in tests.
+pub struct CachedResponse {
+    status: http::StatusCode,
+    version: http::Version,
+    headers: http::HeaderMap,
+    body: bytes::Bytes,
+}
+
+impl CachedResponse {
+    /// Returns the [`http::StatusCode`].
+    #[must_use]
+    pub fn status(&self) -> http::StatusCode {
+        self.status
+    }
+
+    /// Returns a reference to the headers.
+    #[must_use]
+    pub fn headers(&self) -> &http::HeaderMap {
+        &self.headers
+    }
+
+    /// Returns a reference to the buffered body.
+    #[must_use]
+    pub fn body(&self) -> &bytes::Bytes {
+        &self.body
+    }
+
+    /// Creates a response from this cached response.  The body is shared,
+    /// and not copied.
+    pub fn to_response(&self) -> Response {
+        self.clone().into()
+    }
+}
+
+impl From<CachedResponse> for Response {
+    fn from(cached: CachedResponse) -> Self {
+        let mut response = http::Response::new(crate::Body::from(cached.body));
+        *response.status_mut() = cached.status;
+        *response.version_mut() = cached.version;
+        *response.headers_mut() = cached.headers;
+        Response(response)
+    }
+}
+
+impl IntoResponse for CachedResponse {
+    fn into_response(self) -> Result<Response, anyhow::Error> {
+        Ok(self.into())
+    }
+}
+
+/// Converts the current type into a [`crate::Response`].
+///
+/// This assumes that the conversion into a response is fallible
+/// (as it often is).  This is used instead of `TryFrom` because
+/// `TryFrom<Result<T, E>>` is not implemented for `Result<T, E>`.
+///
+/// This uses `anyhow::Error` as the error type for a few reasons:
+///
+/// 1. [`anyhow::Error`] does not implement [`std::error::Error`].
+/// 2. [`std::convert::Infallible`]/[`!`] does not implement
+///    `Into<T>`/`From<T>`.
+/// 3. I can't figure out how to reconcile the two such that
+///    `IntoResponse` can be implemented for `Result<R, E>` where
+///    `R: IntoResponse`; especially if `R` is `Response`, as the
+///    `IntoResponse` implementation for that would have the error
+///    type be `Infallible`.
+pub trait IntoResponse {
+    /// Converts the current type into a response.
+    ///
+    /// # Errors
+    /// This can error if the conversion into a response fails.
+    /// This can fail if the current type is a `Result`, or if
+    /// e.g. the response fails to serialize.
+    fn into_response(self) -> Result<Response, anyhow::Error>;
+}
+
+impl IntoResponse for Response {
+    fn into_response(self) -> Result<Response, anyhow::Error> {
+        Ok(self)
+    }
+}
+
+impl<R, E> IntoResponse for Result<R, E>
+where
+    R: IntoResponse,
+    E: Into<anyhow::Error>,
+{
+    fn into_response(self) -> Result<Response, anyhow::Error> {
+        self.map_err(Into::into)
+            .and_then(IntoResponse::into_response)
+    }
+}
+
+impl IntoResponse for std::convert::Infallible {
+    fn into_response(self) -> Result<Response, anyhow::Error> {
+        match self {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn convert_response() {
+        let response = Response::empty_500();
+
+        assert!(Ok::<_, std::convert::Infallible>(response)
+            .into_response()
+            .is_ok());
+    }
+}
@@ -0,0 +1,95 @@
+use super::Response;
+use crate::range::{self, RangeError};
+use crate::{HttpEntity, Request};
+use std::io::SeekFrom;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncSeek, AsyncSeekExt};
+use tokio_util::io::ReaderStream;
+
+impl Response {
+    /// Creates a response that streams content of the given length from a
+    /// reader that can seek (e.g. a file, a blob in a database, or an object
+    /// in a store), honoring the `Range` header of the request.  The content
+    /// is taken to start at the beginning of the reader.
+    ///
+    /// A `GET` with a single satisfiable range receives a `206 Partial
+    /// Content` with just that range, read from where it starts; one with no
+    /// satisfiable ranges receives a `416 Range Not Satisfiable`.  Every
+    /// other request - including those with multiple ranges, an invalid
+    /// `Range` header, or an `If-Range` header (as the validator of the
+    /// content is not known here) - receives the whole content.  The
+    /// response always advertises `Accept-Ranges: bytes`.
+    ///
+    /// # Errors
+    /// This errors if the reader could not seek, or if the content type is
+    /// not a valid header value.
+    ///
+    /// # Examples
+    /// ```rust
+    /// # use under::*;
+    /// # #[tokio::main] async fn main() -> Result<(), anyhow::Error> {
+    /// let content = std::io::Cursor::new(b"hello, world".to_vec());
+    /// let request = Request::get("/blob")?.with_header("Range", "bytes=7-")?;
+    /// let mut response = Response::seekable_stream(&request, content, 12, "text/plain").await?;
+    /// assert_eq!(response.status(), http::StatusCode::PARTIAL_CONTENT);
+    /// assert_eq!(response.header("Content-Range").unwrap(), "bytes 7-11/12");
+    /// assert_eq!(response.data(512).into_text().await?, "world");
+    ///
+    /// let content = std::io::Cursor::new(b"hello, world".to_vec());
+    /// let request = Request::get("/blob")?.with_header("Range", "bytes=20-")?;
+    /// let response = Response::seekable_stream(&request, content, 12, "text/plain").await?;
+    /// assert_eq!(response.status(), http::StatusCode::RANGE_NOT_SATISFIABLE);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn seekable_stream<R>(
+        request: &Request,
+        mut reader: R,
+        len: u64,
+        content_type: &str,
+    ) -> Result<Response, anyhow::Error>
+    where
+        R: AsyncRead + AsyncSeek + Send + Unpin + 'static,
+    {
+        let ranges = request
+            .header(http::header::RANGE)
+            .filter(|_| request.method() == http::Method::GET)
+            .filter(|_| request.header(http::header::IF_RANGE).is_none())
+            .and_then(|header| header.to_str().ok())
+            .map(|header| range::parse(header, len));
+
+        let (response, range) = match ranges {
+            Some(Ok(ranges)) if ranges.len() == 1 => {
+                let range = ranges[0];
+                let response = Response::empty(http::StatusCode::PARTIAL_CONTENT)
+                    .with_header(http::header::CONTENT_RANGE, range.content_range(len))?;
+                (response, range)
+            }
+            Some(Err(error @ RangeError::Unsatisfiable)) => {
+                return Ok(error
+                    .to_response(len)
+                    .with_header(http::header::ACCEPT_RANGES, "bytes")?);
+            }
+            _ if len == 0 => {
+                return Ok(Response::empty_200()
+                    .with_header(http::header::CONTENT_TYPE, content_type)?
+                    .with_header(http::header::ACCEPT_RANGES, "bytes")?
+                    .with_header(http::header::CONTENT_LENGTH, 0)?);
+            }
+            _ => (
+                Response::empty_200(),
+                range::ByteRange {
+                    start: 0,
+                    end: len - 1,
+                },
+            ),
+        };
+
+        reader.seek(SeekFrom::Start(range.start)).await?;
+        let body = ReaderStream::new(reader.take(range.len()));
+        Ok(response
+            .with_header(http::header::CONTENT_TYPE, content_type)?
+            .with_header(http::header::ACCEPT_RANGES, "bytes")?
+            .with_header(http::header::CONTENT_LENGTH, range.len())?
+            .with_body(crate::Body::wrap_stream(body)))
+    }
+}
@@ -0,0 +1,118 @@
+//! Per-request random number generation.
+//!
+//! Handlers that generate tokens, ids, or anything else random should use
+//! the [`Rng`] of the request (see [`crate::Request::rng`]), rather than a
+//! global generator.  Every request handled by a router is given its own
+//! generator, which is seeded from the router's generator; so by seeding the
+//! router (see [`crate::Router::seed_rng`]), every request handled by it
+//! becomes deterministic, which is useful in tests.  It is gated behind the
+//! `rng` feature flag.
+
+use rand::{RngCore, SeedableRng};
+use std::sync::{Mutex, PoisonError};
+
+pub use rand::rngs::StdRng;
+
+/// The characters used by [`Rng::token`].
+const TOKEN_CHARS: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789";
+
+#[derive(Debug)]
+/// A random number generator.
+///
+/// This wraps a [`StdRng`], which is cryptographically secure, and so is
+/// suitable for generating tokens.  The generator is behind a lock, so that
+/// it can be used from a shared reference to the request; for anything not
+/// provided here, use [`Rng::with`] to access the generator directly.  A
+/// clone of a generator produces the same values as the original would
+/// have, from the point that it was cloned.
+///
+/// # Examples
+/// ```rust
+/// use under::Rng;
+/// let a = Rng::from_seed(42);
+/// let b = Rng::from_seed(42);
+/// assert_eq!(a.next_u64(), b.next_u64());
+/// assert_eq!(a.token(16).len(), 16);
+/// ```
+pub struct Rng(Mutex<StdRng>);
+
+impl Rng {
+    #[must_use]
+    /// Creates a generator seeded from the operating system's entropy.
+    pub fn from_entropy() -> Self {
+        Rng::from_rng(StdRng::from_entropy())
+    }
+
+    #[must_use]
+    /// Creates a generator deterministically seeded from the given seed.
+    /// Two generators created from the same seed produce the same values.
+    pub fn from_seed(seed: u64) -> Self {
+        Rng::from_rng(StdRng::seed_from_u64(seed))
+    }
+
+    fn from_rng(rng: StdRng) -> Self {
+        Rng(Mutex::new(rng))
+    }
+
+    /// Creates a new generator, seeded from this one.
+    pub(crate) fn fork(&self) -> Self {
+        self.with(|rng| {
+            let mut seed = <StdRng as SeedableRng>::Seed::default();
+            rng.fill_bytes(&mut seed);
+            Rng::from_rng(StdRng::from_seed(seed))
+        })
+    }
+
+    /// Runs the given closure with the underlying generator.  This allows
+    /// the generator to be used with anything from the `rand` crate.
+    ///
+    /// # Examples
+    /// ```rust
+    /// use rand::Rng as _;
+    /// let rng = under::Rng::from_seed(1);
+    /// let roll = rng.with(|rng| rng.gen_range(1..=6));
+    /// assert!((1..=6).contains(&roll));
+    /// ```
+    pub fn with<R, F: FnOnce(&mut StdRng) -> R>(&self, f: F) -> R {
+        f(&mut self.0.lock().unwrap_or_else(PoisonError::into_inner))
+    }
+
+    /// Generates a random `u32`.
+    pub fn next_u32(&self) -> u32 {
+        self.with(RngCore::next_u32)
+    }
+
+    /// Generates a random `u64`.
+    pub fn next_u64(&self) -> u64 {
+        self.with(RngCore::next_u64)
+    }
+
+    /// Fills the given buffer with random bytes.
+    pub fn fill_bytes(&self, dest: &mut [u8]) {
+        self.with(|rng| rng.fill_bytes(dest));
+    }
+
+    /// Generates a random alphanumeric string of the given length.
+    pub fn token(&self, len: usize) -> String {
+        self.with(|rng| {
+            (0..len)
+                .map(|_| {
+                    let index = rand::Rng::gen_range(rng, 0..TOKEN_CHARS.len());
+                    char::from(TOKEN_CHARS[index])
+                })
+                .collect()
+        })
+    }
+}
+
+impl Clone for Rng {
+    fn clone(&self) -> Self {
+        self.with(|rng| Rng::from_rng(rng.clone()))
+    }
+}
+
+impl Default for Rng {
+    fn default() -> Self {
+        Rng::from_entropy()
+    }
+}
@@ -0,0 +1,43 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+#[derive(Debug, Clone, Default)]
+/// A gauge of the connections the server currently has open.
+///
+/// This is retrieved with [`crate::Router::connections`], before the router
+/// starts listening, and can then be read at any time, e.g. to report it to
+/// a metrics system.  Connections that are kept alive between requests are
+/// counted, even while they are idle.
+///
+/// # Examples
+/// ```rust
+/// # use under::*;
+/// let mut http = under::http();
+/// let connections = http.connections();
+/// assert_eq!(connections.open(), 0);
+/// ```
+pub struct ConnectionGauge(Arc<AtomicUsize>);
+
+impl ConnectionGauge {
+    /// The number of connections that are currently open.
+    #[must_use]
+    pub fn open(&self) -> usize {
+        self.0.load(Ordering::Relaxed)
+    }
+
+    /// Counts a new connection, until the returned guard is dropped.
+    pub(super) fn track(&self) -> ConnectionGuard {
+        self.0.fetch_add(1, Ordering::Relaxed);
+        ConnectionGuard(self.0.clone())
+    }
+}
+
+/// Counts a connection as open for as long as it exists.
+#[derive(Debug)]
+pub(super) struct ConnectionGuard(Arc<AtomicUsize>);
+
+impl Drop for ConnectionGuard {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::Relaxed);
+    }
+}
@@ -0,0 +1,274 @@
+use std::borrow::Cow;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+/// How a particular kind of sequence in a request path is treated.  See
+/// [`PathDecoding`].
+pub enum DecodePolicy {
+    /// The request is rejected with an empty 400, before it is routed.
+    Reject,
+    /// The sequence is decoded before routing.
+    Decode,
+    /// The sequence is left as-is.
+    PassThrough,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+/// How the path of a request is decoded before it is routed, and before
+/// its fragments are extracted (see [`crate::Router::path_decoding`]).
+///
+/// The decoded path is only used for routing; [`crate::Request::uri`] always
+/// has the path as it was received.  The default, [`PathDecoding::raw`],
+/// leaves the path as-is, so that fragments are still percent-encoded.
+///
+/// # Examples
+/// ```rust
+/// use under::{DecodePolicy, PathDecoding};
+/// let decoding = PathDecoding::lenient().with_encoded_slash(DecodePolicy::Reject);
+/// ```
+pub struct PathDecoding {
+    percent: bool,
+    encoded_slash: DecodePolicy,
+    plus: DecodePolicy,
+    invalid_utf8: DecodePolicy,
+}
+
+impl PathDecoding {
+    /// Leaves the path as it was received; nothing is decoded, and nothing
+    /// is rejected.  This is the default.
+    #[must_use]
+    pub fn raw() -> Self {
+        PathDecoding {
+            percent: false,
+            encoded_slash: DecodePolicy::PassThrough,
+            plus: DecodePolicy::PassThrough,
+            invalid_utf8: DecodePolicy::PassThrough,
+        }
+    }
+
+    /// Decodes percent-encoded sequences, except for encoded slashes
+    /// (`%2F`), which are left as-is, so that they do not split a fragment
+    /// into two segments.  Sequences that do not decode to valid UTF-8 are
+    /// decoded lossily (as `U+FFFD`), and `+` is left as-is.
+    #[must_use]
+    pub fn lenient() -> Self {
+        PathDecoding {
+            percent: true,
+            encoded_slash: DecodePolicy::PassThrough,
+            plus: DecodePolicy::PassThrough,
+            invalid_utf8: DecodePolicy::Decode,
+        }
+    }
+
+    /// Decodes percent-encoded sequences, and rejects any request whose path
+    /// contains an encoded slash (`%2F`), or a sequence that does not decode
+    /// to valid UTF-8.  `+` is left as-is.
+    #[must_use]
+    pub fn strict() -> Self {
+        PathDecoding {
+            percent: true,
+            encoded_slash: DecodePolicy::Reject,
+            plus: DecodePolicy::PassThrough,
+            invalid_utf8: DecodePolicy::Reject,
+        }
+    }
+
+    /// Sets whether percent-encoded sequences (other than encoded slashes)
+    /// are decoded.
+    #[must_use]
+    pub fn with_percent_decoding(mut self, decode: bool) -> Self {
+        self.percent = decode;
+        self
+    }
+
+    /// Sets how encoded slashes (`%2F`) are treated.  Decoding them means
+    /// that they are routed as path separators.
+    #[must_use]
+    pub fn with_encoded_slash(mut self, policy: DecodePolicy) -> Self {
+        self.encoded_slash = policy;
+        self
+    }
+
+    /// Sets how `+` is treated.  Decoding it turns it into a space, as in a
+    /// form; in a path, `+` is otherwise an ordinary character.
+    #[must_use]
+    pub fn with_plus(mut self, policy: DecodePolicy) -> Self {
+        self.plus = policy;
+        self
+    }
+
+    /// Sets how percent-encoded sequences that are not valid UTF-8 are
+    /// treated.  Decoding them replaces the invalid bytes with `U+FFFD`.
+    /// This applies even if percent decoding is disabled.
+    #[must_use]
+    pub fn with_invalid_utf8(mut self, policy: DecodePolicy) -> Self {
+        self.invalid_utf8 = policy;
+        self
+    }
+
+    /// Whether or not percent-encoded sequences are decoded by this policy.
+    pub(crate) fn decodes_percent(self) -> bool {
+        self.percent
+    }
+
+    /// Decodes the given path, returning `None` if the request should be
+    /// rejected.
+    pub(crate) fn apply(self, path: &str) -> Option<Cow<'_, str>> {
+        if !path.contains(['%', '+']) || self == PathDecoding::raw() {
+            return Some(path.into());
+        }
+
+        let mut buffer = String::with_capacity(path.len());
+        let mut rest = path;
+        while let Some(index) = rest.find(['%', '+']) {
+            buffer.push_str(&rest[..index]);
+            rest = &rest[index..];
+
+            if let Some(after) = rest.strip_prefix('+') {
+                match self.plus {
+                    DecodePolicy::Reject => return None,
+                    DecodePolicy::Decode => buffer.push(' '),
+                    DecodePolicy::PassThrough => buffer.push('+'),
+                }
+                rest = after;
+                continue;
+            }
+
+            let (encoded, bytes) = percent_run(rest);
+            rest = &rest[encoded.len()..];
+            if bytes.is_empty() {
+                // A `%` that is not followed by two hex digits.
+                buffer.push('%');
+                rest = &rest[1..];
+            } else if bytes == [b'/'] {
+                match self.encoded_slash {
+                    DecodePolicy::Reject => return None,
+                    DecodePolicy::Decode => buffer.push('/'),
+                    DecodePolicy::PassThrough => buffer.push_str(encoded),
+                }
+            } else {
+                match (std::str::from_utf8(&bytes), self.invalid_utf8) {
+                    (Ok(decoded), _) if self.percent => buffer.push_str(decoded),
+                    (Ok(_), _) | (Err(_), DecodePolicy::PassThrough) => buffer.push_str(encoded),
+                    (Err(_), DecodePolicy::Reject) => return None,
+                    (Err(_), DecodePolicy::Decode) => {
+                        buffer.push_str(&String::from_utf8_lossy(&bytes));
+                    }
+                }
+            }
+        }
+        buffer.push_str(rest);
+
+        Some(buffer.into())
+    }
+}
+
+impl Default for PathDecoding {
+    fn default() -> Self {
+        PathDecoding::raw()
+    }
+}
+
+/// Decodes every percent-encoded sequence in the given string (including
+/// encoded slashes), replacing sequences that are not valid UTF-8 with
+/// `U+FFFD`.  `+` is left as-is, and a `%` that is not followed by two hex
+/// digits is left as a literal `%`.
+pub(crate) fn percent_decode(input: &str) -> Cow<'_, str> {
+    if !input.contains('%') {
+        return input.into();
+    }
+
+    let mut bytes = Vec::with_capacity(input.len());
+    let mut index = 0;
+    while index < input.len() {
+        if let Some(byte) = input.get(index..index + 3).and_then(hex_byte) {
+            bytes.push(byte);
+            index += 3;
+        } else {
+            bytes.push(input.as_bytes()[index]);
+            index += 1;
+        }
+    }
+
+    String::from_utf8_lossy(&bytes).into_owned().into()
+}
+
+/// Takes the longest run of percent-encoded bytes from the start of the
+/// given string, returning the encoded run, and the bytes it decodes to.
+/// A multi-byte character is always encoded as a single run, so each run
+/// can be checked for UTF-8 on its own.  An encoded slash is always a run of
+/// its own, so that it can be treated separately.
+fn percent_run(input: &str) -> (&str, Vec<u8>) {
+    let mut bytes = vec![];
+    let mut length = 0;
+    while let Some(byte) = input.get(length..length + 3).and_then(hex_byte) {
+        if byte == b'/' && !bytes.is_empty() {
+            break;
+        }
+        bytes.push(byte);
+        length += 3;
+        if byte == b'/' {
+            break;
+        }
+    }
+
+    (&input[..length], bytes)
+}
+
+fn hex_byte(sequence: &str) -> Option<u8> {
+    let digits = sequence.strip_prefix('%')?;
+    if !digits.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return None;
+    }
+    u8::from_str_radix(digits, 16).ok()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_percent_decode() {
+        assert_eq!(percent_decode("my%20file"), "my file");
+        assert_eq!(percent_decode("a%2Fb+c"), "a/b+c");
+        assert_eq!(percent_decode("caf%C3%A9%FF"), "café\u{FFFD}");
+        assert_eq!(percent_decode("100%"), "100%");
+        assert_eq!(percent_decode("%%41"), "%A");
+    }
+
+    #[test]
+    fn test_raw() {
+        let decoding = PathDecoding::raw();
+        assert_eq!(decoding.apply("/a%20b+c%2Fd").unwrap(), "/a%20b+c%2Fd");
+    }
+
+    #[test]
+    fn test_lenient() {
+        let decoding = PathDecoding::lenient();
+        assert_eq!(decoding.apply("/a%20b+c%2Fd").unwrap(), "/a b+c%2Fd");
+        assert_eq!(decoding.apply("/caf%C3%A9").unwrap(), "/café");
+        assert_eq!(decoding.apply("/%FF").unwrap(), "/\u{FFFD}");
+        assert_eq!(decoding.apply("/100%").unwrap(), "/100%");
+        assert_eq!(decoding.apply("/%zz").unwrap(), "/%zz");
+    }
+
+    #[test]
+    fn test_strict() {
+        let decoding = PathDecoding::strict();
+        assert_eq!(decoding.apply("/caf%C3%A9").unwrap(), "/café");
+        assert!(decoding.apply("/a%2Fb").is_none());
+        assert!(decoding.apply("/%C3").is_none());
+    }
+
+    #[test]
+    fn test_individual_policies() {
+        let decoding = PathDecoding::raw()
+            .with_plus(DecodePolicy::Decode)
+            .with_encoded_slash(DecodePolicy::Decode);
+        assert_eq!(decoding.apply("/a+b%2F%20").unwrap(), "/a b/%20");
+        let decoding = PathDecoding::raw().with_invalid_utf8(DecodePolicy::Reject);
+        assert_eq!(decoding.apply("/%C3%A9").unwrap(), "/%C3%A9");
+        assert!(decoding.apply("/%C3").is_none());
+        let decoding = PathDecoding::raw().with_plus(DecodePolicy::Reject);
+        assert!(decoding.apply("/a+b").is_none());
+    }
+}
@@ -0,0 +1,208 @@
+use super::{Pattern, Route};
+#[cfg(feature = "json")]
+use crate::HttpEntity;
+use crate::{Endpoint, Request, Response};
+use std::fmt::Write;
+use std::pin::Pin;
+use std::sync::Arc;
+
+/// The most routes that are reported as having matched only part of the
+/// path.
+const MAX_PARTIAL: usize = 5;
+
+/// The endpoint used for unmatched requests when
+/// [`super::Router::debug_unmatched`] is enabled.  This responds with a 404
+/// listing the routes that nearly matched the request.
+pub(super) struct Unmatched {
+    routes: Vec<Arc<Route>>,
+    path: String,
+}
+
+impl Unmatched {
+    pub(super) fn new(routes: &[Arc<Route>], path: &str) -> Self {
+        Unmatched {
+            routes: routes.to_vec(),
+            path: path.to_owned(),
+        }
+    }
+
+    fn near_misses(&self, request: &Request) -> Vec<NearMiss<'_>> {
+        let mut full = vec![];
+        let mut partial = vec![];
+        let mut depth = 1;
+        for route in self.routes.iter().rev() {
+            if route.error().is_some() {
+                continue;
+            }
+            let reason = if route.pattern.regex().is_match(&self.path) {
+                if !route.pattern.accepts(&self.path) {
+                    Reason::FragmentType
+                } else if !route.matches(request.method()) {
+                    Reason::Method
+                } else if !route.guards(request) {
+                    Reason::Guard
+                } else {
+                    continue;
+                }
+            } else {
+                match mismatch(&route.path, &self.path) {
+                    Some(reason @ Reason::Pattern { depth: d, .. }) if d >= depth => {
+                        if d > depth {
+                            depth = d;
+                            partial.clear();
+                        }
+                        reason
+                    }
+                    _ => continue,
+                }
+            };
+
+            if matches!(reason, Reason::Pattern { .. }) {
+                partial.push(NearMiss { route, reason });
+            } else {
+                full.push(NearMiss { route, reason });
+            }
+        }
+
+        partial.truncate(MAX_PARTIAL);
+        full.extend(partial);
+        full
+    }
+
+    fn text(&self, request: &Request, near_misses: &[NearMiss<'_>]) -> Response {
+        let mut body = format!(
+            "404 Not Found: no route matches {} {}\n",
+            request.method(),
+            self.path
+        );
+        if near_misses.is_empty() {
+            body.push_str("\nNo routes came close.\n");
+        } else {
+            body.push_str("\nNear misses:\n");
+        }
+        for miss in near_misses {
+            let method = miss.route.method().map_or("(all)", http::Method::as_str);
+            let _ = write!(body, "  {method} {}: ", miss.route.path);
+            let _ = match &miss.reason {
+                Reason::Method => writeln!(body, "the method does not match"),
+                Reason::Guard => writeln!(body, "a guard rejected the request"),
+                Reason::FragmentType => {
+                    writeln!(body, "a fragment does not parse as its type")
+                }
+                Reason::Pattern {
+                    position, expected, ..
+                } => writeln!(
+                    body,
+                    "matched {:?}, then expected {} at {:?}",
+                    &self.path[..*position],
+                    expected.map_or_else(|| "the end of the path".to_owned(), |e| format!("{e:?}")),
+                    &self.path[*position..],
+                ),
+            };
+        }
+
+        Response::text(body).with_status(http::StatusCode::NOT_FOUND)
+    }
+
+    #[cfg(feature = "json")]
+    fn json(&self, request: &Request, near_misses: &[NearMiss<'_>]) -> Response {
+        let near_misses = near_misses
+            .iter()
+            .map(|miss| {
+                let mut value = serde_json::json!({
+                    "route": miss.route.path,
+                    "method": miss.route.method().map(http::Method::as_str),
+                });
+                let reason = match &miss.reason {
+                    Reason::Method => "method",
+                    Reason::Guard => "guard",
+                    Reason::FragmentType => "fragment_type",
+                    Reason::Pattern {
+                        position, expected, ..
+                    } => {
+                        value["position"] = (*position).into();
+                        value["expected"] = (*expected).into();
+                        "pattern"
+                    }
+                };
+                value["reason"] = reason.into();
+                value
+            })
+            .collect::<Vec<_>>();
+        let body = serde_json::json!({
+            "error": "Not Found",
+            "method": request.method().as_str(),
+            "path": self.path,
+            "near_misses": near_misses,
+        });
+        Response::json(&body).map_or_else(
+            |_| self.text(request, &[]),
+            |r| r.with_status(http::StatusCode::NOT_FOUND),
+        )
+    }
+}
+
+#[async_trait]
+impl Endpoint for Unmatched {
+    async fn apply(self: Pin<&Self>, request: Request) -> Result<Response, anyhow::Error> {
+        let near_misses = self.near_misses(&request);
+        #[cfg(feature = "json")]
+        if request
+            .header(http::header::ACCEPT)
+            .and_then(|v| v.to_str().ok())
+            .is_some_and(|v| v.contains("application/json"))
+        {
+            return Ok(self.json(&request, &near_misses));
+        }
+        Ok(self.text(&request, &near_misses))
+    }
+}
+
+/// A route that nearly matched the request.
+struct NearMiss<'r> {
+    route: &'r Route,
+    reason: Reason<'r>,
+}
+
+/// Why a route did not match the request.
+enum Reason<'r> {
+    /// The path matches, but the method does not.
+    Method,
+    /// The path and method match, but a guard of the route rejected the
+    /// request.
+    Guard,
+    /// The path matches, except for a fragment that does not parse as its
+    /// type (e.g. `{id:u8}` for `256`).
+    FragmentType,
+    /// The first `depth` segments of the path match, but the rest do not.
+    Pattern {
+        depth: usize,
+        /// Where in the path the first segment that does not match starts.
+        position: usize,
+        /// The segment of the route that was expected there, or `None` if
+        /// the route ends there.
+        expected: Option<&'r str>,
+    },
+}
+
+/// Finds how many segments of the given path match the route, and where it
+/// stops matching.  This returns `None` if not even the first segment
+/// matches.
+fn mismatch<'r>(route: &'r str, path: &str) -> Option<Reason<'r>> {
+    let expected = route.split('/').skip(1).collect::<Vec<_>>();
+    let found = path.split('/').skip(1).collect::<Vec<_>>();
+    let depth = (1..=expected.len().min(found.len()))
+        .take_while(|&depth| {
+            let route = format!("/{}", expected[..depth].join("/"));
+            let path = format!("/{}", found[..depth].join("/"));
+            Pattern::new(&route).is_ok_and(|pattern| pattern.regex().is_match(&path))
+        })
+        .last()?;
+
+    let position = found[..depth].iter().map(|s| s.len() + 1).sum::<usize>() + 1;
+    Some(Reason::Pattern {
+        depth,
+        position: position.min(path.len()),
+        expected: expected.get(depth).copied(),
+    })
+}
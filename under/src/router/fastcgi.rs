@@ -0,0 +1,689 @@
+//! Serving the router over `FastCGI`, and CGI.
+//!
+//! Both pass the request to the application as a set of CGI parameters
+//! (`REQUEST_METHOD`, `HTTP_HOST`, and so on) and a body, and take the
+//! response as a CGI response (a `Status` header, the headers, a blank line,
+//! and the body).  With CGI, the parameters are the environment variables of
+//! the process, and the body is standard input; with `FastCGI`, both are sent
+//! (and the response returned) as records over a connection from the web
+//! server.
+
+use super::Router;
+use crate::{HttpEntity, Request, Response, UnderError};
+use futures::StreamExt;
+use std::sync::Arc;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+const VERSION: u8 = 1;
+
+const BEGIN_REQUEST: u8 = 1;
+const ABORT_REQUEST: u8 = 2;
+const END_REQUEST: u8 = 3;
+const PARAMS: u8 = 4;
+const STDIN: u8 = 5;
+const STDOUT: u8 = 6;
+const GET_VALUES: u8 = 9;
+const GET_VALUES_RESULT: u8 = 10;
+const UNKNOWN_TYPE: u8 = 11;
+
+const RESPONDER: u16 = 1;
+const KEEP_CONN: u8 = 1;
+
+const REQUEST_COMPLETE: u8 = 0;
+const CANT_MPX_CONN: u8 = 1;
+const UNKNOWN_ROLE: u8 = 3;
+
+/// The largest content a single record can hold.
+const MAX_CONTENT: usize = u16::MAX as usize;
+
+impl Router {
+    /// Serves the router over `FastCGI`, on the specified address.  This is
+    /// for running behind a web server that speaks `FastCGI`, rather than
+    /// HTTP, to the application (e.g. nginx, with `fastcgi_pass`).
+    ///
+    /// The router is prepared, and then handles requests as it would when
+    /// listening for HTTP (see [`Router::serve`]).  One request is handled
+    /// at a time on each connection from the web server; the web server is
+    /// told that requests cannot be multiplexed.  When the router is told
+    /// to terminate (see [`Router::termination_signal`]), it stops accepting
+    /// connections.
+    ///
+    /// # Errors
+    /// This can fail if the socket address is invalid, if the socket is
+    /// already in use, or if the routes are invalid.
+    ///
+    /// # Examples
+    /// ```rust,no_run
+    /// # use under::*;
+    /// # #[tokio::main] async fn main() -> Result<(), anyhow::Error> {
+    /// let mut http = under::http();
+    /// http.at("/").get(|_| async { Response::text("hello, world!") });
+    /// // with e.g. `fastcgi_pass 127.0.0.1:9000;` in nginx.
+    /// http.listen_fastcgi("127.0.0.1:9000").await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[cfg_attr(docsrs, doc(cfg(feature = "fastcgi")))]
+    pub async fn listen_fastcgi(self, address: &str) -> Result<(), UnderError> {
+        self.listen_fastcgi_on(crate::Listener::bind(address)?)
+            .await
+    }
+
+    /// Serves the router over `FastCGI`, on the given listener.  This behaves
+    /// the same as [`Router::listen_fastcgi`].
+    ///
+    /// # Errors
+    /// This can fail if the listener cannot be used, or if the routes are
+    /// invalid.
+    #[cfg_attr(docsrs, doc(cfg(feature = "fastcgi")))]
+    pub async fn listen_fastcgi_on(mut self, listener: crate::Listener) -> Result<(), UnderError> {
+        self.try_prepare().map_err(UnderError::InvalidRoutes)?;
+        match listener.local_addr() {
+            Ok(address) => log::info!("listen_fastcgi({address})"),
+            Err(_) => log::info!("listen_fastcgi(?)"),
+        }
+        let listener = listener.into_tokio()?;
+        let terminate = self.terminate.take();
+        accept(Arc::new(self), terminate, || listener.accept()).await
+    }
+
+    /// Serves the router over `FastCGI`, on a unix socket at the given path.
+    /// This behaves the same as [`Router::listen_fastcgi`].
+    ///
+    /// # Errors
+    /// This can fail if the socket could not be bound (e.g. because the path
+    /// already exists), or if the routes are invalid.
+    ///
+    /// # Examples
+    /// ```rust,no_run
+    /// # use under::*;
+    /// # #[tokio::main] async fn main() -> Result<(), anyhow::Error> {
+    /// let mut http = under::http();
+    /// http.at("/").get(|_| async { Response::text("hello, world!") });
+    /// // with e.g. `fastcgi_pass unix:/run/app.sock;` in nginx.
+    /// http.listen_fastcgi_unix("/run/app.sock").await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[cfg_attr(docsrs, doc(cfg(feature = "fastcgi")))]
+    #[cfg(unix)]
+    pub async fn listen_fastcgi_unix<P: AsRef<std::path::Path>>(
+        mut self,
+        path: P,
+    ) -> Result<(), UnderError> {
+        self.try_prepare().map_err(UnderError::InvalidRoutes)?;
+        let listener =
+            tokio::net::UnixListener::bind(path.as_ref()).map_err(UnderError::Listener)?;
+        log::info!("listen_fastcgi({})", path.as_ref().display());
+        let terminate = self.terminate.take();
+        accept(Arc::new(self), terminate, || listener.accept()).await
+    }
+
+    /// Handles a single request as a CGI program: the request is read from
+    /// the environment variables and standard input of the process, and the
+    /// response is written to standard output.  This is for web servers
+    /// that start a new process for each request (e.g. Apache, with
+    /// `mod_cgi`).
+    ///
+    /// # Errors
+    /// This can fail if the routes are invalid, if standard input could not
+    /// be read, or if the response could not be written to standard output.
+    ///
+    /// # Examples
+    /// ```rust,no_run
+    /// # use under::*;
+    /// # #[tokio::main] async fn main() -> Result<(), anyhow::Error> {
+    /// let mut http = under::http();
+    /// http.at("/").get(|_| async { Response::text("hello, world!") });
+    /// http.serve_cgi().await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[cfg_attr(docsrs, doc(cfg(feature = "fastcgi")))]
+    pub async fn serve_cgi(mut self) -> Result<(), UnderError> {
+        use std::io::{Read, Write};
+
+        self.try_prepare().map_err(UnderError::InvalidRoutes)?;
+        let params = std::env::vars_os()
+            .map(|(name, value)| {
+                let value = value
+                    .into_string()
+                    .unwrap_or_else(|value| value.to_string_lossy().into_owned());
+                (name.to_string_lossy().into_owned(), value.into_bytes())
+            })
+            .collect::<Vec<_>>();
+
+        let length = param(&params, "CONTENT_LENGTH")
+            .and_then(|v| std::str::from_utf8(v).ok())
+            .and_then(|v| v.trim().parse::<u64>().ok())
+            .unwrap_or(0);
+        let mut body = vec![];
+        std::io::stdin()
+            .take(length)
+            .read_to_end(&mut body)
+            .map_err(UnderError::Cgi)?;
+
+        let request = into_request(&params, body).map_err(|error| {
+            UnderError::Cgi(std::io::Error::new(std::io::ErrorKind::InvalidData, error))
+        })?;
+        let mut response = self.serve(request).await;
+
+        let mut stdout = std::io::stdout().lock();
+        stdout
+            .write_all(&response_head(&response))
+            .map_err(UnderError::Cgi)?;
+        let mut body = response.take_body();
+        while let Some(chunk) = body.next().await {
+            stdout
+                .write_all(&chunk.map_err(UnderError::Cgi)?)
+                .map_err(UnderError::Cgi)?;
+        }
+        stdout.flush().map_err(UnderError::Cgi)
+    }
+}
+
+/// Accepts connections until the termination signal is sent, serving
+/// `FastCGI` on each of them.
+async fn accept<A, F, S, T>(
+    router: Arc<Router>,
+    terminate: Option<tokio::sync::watch::Receiver<bool>>,
+    mut accept: A,
+) -> Result<(), UnderError>
+where
+    A: FnMut() -> F,
+    F: std::future::Future<Output = std::io::Result<(S, T)>>,
+    S: AsyncRead + AsyncWrite + Send + 'static,
+{
+    let termination = super::service::termination(terminate);
+    tokio::pin!(termination);
+    loop {
+        tokio::select! {
+            () = &mut termination => return Ok(()),
+            accepted = accept() => match accepted {
+                Ok((stream, _)) => {
+                    let router = router.clone();
+                    tokio::spawn(async move {
+                        if let Err(error) = serve_connection(&router, stream).await {
+                            log::debug!("fastcgi connection failed: {error}");
+                        }
+                    });
+                }
+                Err(error) => log::error!("could not accept a fastcgi connection: {error}"),
+            },
+        }
+    }
+}
+
+/// A single record of the `FastCGI` protocol.
+struct Record {
+    kind: u8,
+    id: u16,
+    content: Vec<u8>,
+}
+
+/// Serves `FastCGI` requests on the given connection, until the web server
+/// closes it (or asks for it to be closed).
+async fn serve_connection<S>(router: &Router, stream: S) -> std::io::Result<()>
+where
+    S: AsyncRead + AsyncWrite,
+{
+    let (mut reader, mut writer) = tokio::io::split(stream);
+    while let Some(record) = read_record(&mut reader).await? {
+        match record.kind {
+            BEGIN_REQUEST if record.content.len() >= 3 => {
+                let role = u16::from_be_bytes([record.content[0], record.content[1]]);
+                let keep_conn = record.content[2] & KEEP_CONN != 0;
+                if role == RESPONDER {
+                    serve_request(router, &mut reader, &mut writer, record.id).await?;
+                } else {
+                    end_request(&mut writer, record.id, UNKNOWN_ROLE).await?;
+                }
+                if !keep_conn {
+                    break;
+                }
+            }
+            GET_VALUES if record.id == 0 => {
+                let mut values = vec![];
+                for (name, _) in decode_params(&record.content) {
+                    if name == "FCGI_MPXS_CONNS" {
+                        encode_param(&mut values, &name, b"0");
+                    }
+                }
+                write_record(&mut writer, GET_VALUES_RESULT, 0, &values).await?;
+            }
+            kind if record.id == 0 => {
+                write_record(&mut writer, UNKNOWN_TYPE, 0, &[kind, 0, 0, 0, 0, 0, 0, 0]).await?;
+            }
+            // Records for requests that have already ended (e.g. a late
+            // abort) are ignored.
+            _ => {}
+        }
+    }
+    writer.shutdown().await
+}
+
+/// Serves the request with the given id, whose `BEGIN_REQUEST` record has
+/// just been read.
+async fn serve_request<R, W>(
+    router: &Router,
+    reader: &mut R,
+    writer: &mut W,
+    id: u16,
+) -> std::io::Result<()>
+where
+    R: AsyncRead + Unpin,
+    W: AsyncWrite + Unpin,
+{
+    let mut params = vec![];
+    loop {
+        let Some(record) = read_record(reader).await? else {
+            return Ok(());
+        };
+        match record.kind {
+            PARAMS if record.id == id && record.content.is_empty() => break,
+            PARAMS if record.id == id => params.extend_from_slice(&record.content),
+            ABORT_REQUEST if record.id == id => {
+                return end_request(writer, id, REQUEST_COMPLETE).await;
+            }
+            BEGIN_REQUEST => end_request(writer, record.id, CANT_MPX_CONN).await?,
+            _ => {}
+        }
+    }
+
+    let params = decode_params(&params);
+    let (mut sender, body) = crate::Body::channel();
+    let request = match into_request(&params, body) {
+        Ok(request) => request,
+        Err(error) => {
+            log::debug!("invalid fastcgi request: {error}");
+            let response = Response::empty(http::StatusCode::BAD_REQUEST);
+            write_record(writer, STDOUT, id, &response_head(&response)).await?;
+            write_record(writer, STDOUT, id, &[]).await?;
+            return end_request(writer, id, REQUEST_COMPLETE).await;
+        }
+    };
+
+    // The body is sent to the router while it handles the request; if the
+    // router drops the body without reading all of it, the rest is still
+    // read (and discarded), so that the connection can be reused.
+    let read_body = async move {
+        let mut sender = Some(&mut sender);
+        while let Some(record) = read_record(reader).await? {
+            match record.kind {
+                STDIN if record.id == id && record.content.is_empty() => break,
+                STDIN if record.id == id => {
+                    if let Some(body) = sender.as_deref_mut() {
+                        if body.send_data(record.content.into()).await.is_err() {
+                            sender = None;
+                        }
+                    }
+                }
+                ABORT_REQUEST if record.id == id => break,
+                _ => {}
+            }
+        }
+        Ok::<_, std::io::Error>(())
+    };
+    let respond = async {
+        let mut response = router.serve(request).await;
+        let mut head = Some(response_head(&response));
+        let mut body = response.take_body();
+        while let Some(chunk) = body.next().await {
+            let chunk = match chunk {
+                Ok(chunk) => chunk,
+                Err(error) => {
+                    log::error!("error writing the response body: {error}");
+                    break;
+                }
+            };
+            let mut data = head.take().unwrap_or_default();
+            data.extend_from_slice(&chunk);
+            for content in data.chunks(MAX_CONTENT) {
+                write_record(writer, STDOUT, id, content).await?;
+            }
+        }
+        if let Some(head) = head {
+            write_record(writer, STDOUT, id, &head).await?;
+        }
+        write_record(writer, STDOUT, id, &[]).await?;
+        end_request(writer, id, REQUEST_COMPLETE).await
+    };
+
+    let (read, respond) = futures::join!(read_body, respond);
+    read.and(respond)
+}
+
+/// Builds a request from the given CGI parameters, and body.
+fn into_request<B: Into<crate::Body>>(
+    params: &[(String, Vec<u8>)],
+    body: B,
+) -> Result<Request, anyhow::Error> {
+    let text = |name: &str| param(params, name).and_then(|v| std::str::from_utf8(v).ok());
+
+    let method = text("REQUEST_METHOD").unwrap_or("GET");
+    let method = http::Method::from_bytes(method.as_bytes())?;
+    let target = match text("REQUEST_URI") {
+        Some(uri) if !uri.is_empty() => uri.to_owned(),
+        _ => {
+            let path = format!(
+                "{}{}",
+                text("SCRIPT_NAME").unwrap_or(""),
+                text("PATH_INFO").unwrap_or("")
+            );
+            let path = if path.is_empty() {
+                "/".to_owned()
+            } else {
+                path
+            };
+            match text("QUERY_STRING") {
+                Some(query) if !query.is_empty() => format!("{path}?{query}"),
+                _ => path,
+            }
+        }
+    };
+    let https = matches!(text("HTTPS"), Some(v) if v.eq_ignore_ascii_case("on") || v == "1")
+        || text("REQUEST_SCHEME") == Some("https");
+    let scheme = if https { "https" } else { "http" };
+    let uri = text("HTTP_HOST")
+        .or_else(|| text("SERVER_NAME"))
+        .and_then(|host| {
+            format!("{scheme}://{host}{target}")
+                .parse::<http::Uri>()
+                .ok()
+        })
+        .map_or_else(|| target.parse::<http::Uri>(), Ok)?;
+
+    let mut request = Request::from_method(uri, method)?;
+    for (name, value) in params {
+        let name = match name.as_str() {
+            "CONTENT_TYPE" | "CONTENT_LENGTH" if value.is_empty() => continue,
+            "CONTENT_TYPE" => "content-type".to_owned(),
+            "CONTENT_LENGTH" => "content-length".to_owned(),
+            name => match name.strip_prefix("HTTP_") {
+                Some(name) => name.to_ascii_lowercase().replace('_', "-"),
+                None => continue,
+            },
+        };
+        request.headers_mut().append(
+            http::HeaderName::from_bytes(name.as_bytes())?,
+            http::HeaderValue::from_bytes(value)?,
+        );
+    }
+
+    let address = text("REMOTE_ADDR").and_then(|ip| ip.parse::<std::net::IpAddr>().ok());
+    if let Some(ip) = address {
+        let port = text("REMOTE_PORT")
+            .and_then(|p| p.parse().ok())
+            .unwrap_or(0);
+        request.set_ext(crate::middleware::PeerAddress((ip, port).into()));
+    }
+
+    request.set_body(body);
+    Ok(request)
+}
+
+/// Finds the value of the parameter with the given name.
+fn param<'p>(params: &'p [(String, Vec<u8>)], name: &str) -> Option<&'p [u8]> {
+    params
+        .iter()
+        .find(|(n, _)| n == name)
+        .map(|(_, v)| v.as_slice())
+}
+
+/// The head of a CGI response: the status, and the headers, followed by a
+/// blank line.
+fn response_head(response: &Response) -> Vec<u8> {
+    let status = response.status();
+    let mut head = format!(
+        "Status: {} {}\r\n",
+        status.as_str(),
+        status.canonical_reason().unwrap_or("")
+    )
+    .into_bytes();
+    for (name, value) in response.headers() {
+        head.extend_from_slice(name.as_str().as_bytes());
+        head.extend_from_slice(b": ");
+        head.extend_from_slice(value.as_bytes());
+        head.extend_from_slice(b"\r\n");
+    }
+    head.extend_from_slice(b"\r\n");
+    head
+}
+
+/// Reads a single record, or `None` if the connection was closed before
+/// it.
+async fn read_record<R: AsyncRead + Unpin>(reader: &mut R) -> std::io::Result<Option<Record>> {
+    let mut header = [0u8; 8];
+    match reader.read_exact(&mut header).await {
+        Ok(_) => {}
+        Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(e) => return Err(e),
+    }
+    let id = u16::from_be_bytes([header[2], header[3]]);
+    let length = usize::from(u16::from_be_bytes([header[4], header[5]]));
+    let padding = usize::from(header[6]);
+    let mut content = vec![0u8; length + padding];
+    reader.read_exact(&mut content).await?;
+    content.truncate(length);
+    Ok(Some(Record {
+        kind: header[1],
+        id,
+        content,
+    }))
+}
+
+/// Writes a single record, whose content must be no longer than
+/// [`MAX_CONTENT`].
+async fn write_record<W: AsyncWrite + Unpin>(
+    writer: &mut W,
+    kind: u8,
+    id: u16,
+    content: &[u8],
+) -> std::io::Result<()> {
+    let length = u16::try_from(content.len())
+        .map_err(|_| std::io::Error::new(std::io::ErrorKind::InvalidInput, "record too long"))?;
+    let [id_hi, id_lo] = id.to_be_bytes();
+    let [length_hi, length_lo] = length.to_be_bytes();
+    writer
+        .write_all(&[VERSION, kind, id_hi, id_lo, length_hi, length_lo, 0, 0])
+        .await?;
+    writer.write_all(content).await
+}
+
+/// Ends the request with the given id, with the given protocol status.
+async fn end_request<W: AsyncWrite + Unpin>(
+    writer: &mut W,
+    id: u16,
+    status: u8,
+) -> std::io::Result<()> {
+    write_record(writer, END_REQUEST, id, &[0, 0, 0, 0, status, 0, 0, 0]).await?;
+    writer.flush().await
+}
+
+/// Decodes the name-value pairs of a `PARAMS` (or `GET_VALUES`) stream.
+/// Decoding stops at the first malformed pair.
+fn decode_params(mut data: &[u8]) -> Vec<(String, Vec<u8>)> {
+    fn length(data: &mut &[u8]) -> Option<usize> {
+        let first = *data.first()?;
+        if first & 0x80 == 0 {
+            *data = &data[1..];
+            Some(usize::from(first))
+        } else {
+            let bytes: [u8; 4] = data.get(..4)?.try_into().ok()?;
+            *data = &data[4..];
+            usize::try_from(u32::from_be_bytes(bytes) & 0x7fff_ffff).ok()
+        }
+    }
+
+    let mut params = vec![];
+    while !data.is_empty() {
+        let (Some(name_length), Some(value_length)) = (length(&mut data), length(&mut data)) else {
+            break;
+        };
+        let Some(name) = data.get(..name_length) else {
+            break;
+        };
+        let Some(value) = data.get(name_length..name_length + value_length) else {
+            break;
+        };
+        params.push((String::from_utf8_lossy(name).into_owned(), value.to_vec()));
+        data = &data[name_length + value_length..];
+    }
+    params
+}
+
+/// Encodes a name-value pair, appending it to the given buffer.
+fn encode_param(buf: &mut Vec<u8>, name: &str, value: &[u8]) {
+    for length in [name.len(), value.len()] {
+        match u8::try_from(length) {
+            Ok(length) if length < 0x80 => buf.push(length),
+            _ => {
+                let length = u32::try_from(length).unwrap_or(u32::MAX) | 0x8000_0000;
+                buf.extend_from_slice(&length.to_be_bytes());
+            }
+        }
+    }
+    buf.extend_from_slice(name.as_bytes());
+    buf.extend_from_slice(value);
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn record(kind: u8, id: u16, content: &[u8]) -> Vec<u8> {
+        let mut data = vec![VERSION, kind];
+        data.extend_from_slice(&id.to_be_bytes());
+        data.extend_from_slice(&u16::try_from(content.len()).unwrap().to_be_bytes());
+        data.extend_from_slice(&[0, 0]);
+        data.extend_from_slice(content);
+        data
+    }
+
+    fn request(id: u16, keep_conn: bool, params: &[(&str, &str)], body: &[u8]) -> Vec<u8> {
+        let flags = if keep_conn { KEEP_CONN } else { 0 };
+        let mut data = record(BEGIN_REQUEST, id, &[0, 1, flags, 0, 0, 0, 0, 0]);
+        let mut encoded = vec![];
+        for (name, value) in params {
+            encode_param(&mut encoded, name, value.as_bytes());
+        }
+        data.extend(record(PARAMS, id, &encoded));
+        data.extend(record(PARAMS, id, &[]));
+        if !body.is_empty() {
+            data.extend(record(STDIN, id, body));
+        }
+        data.extend(record(STDIN, id, &[]));
+        data
+    }
+
+    /// Sends the given bytes over a connection, and returns the records of
+    /// the response.
+    async fn exchange(router: &Router, input: Vec<u8>) -> Vec<Record> {
+        let (mut client, server) = tokio::io::duplex(1 << 20);
+        client.write_all(&input).await.unwrap();
+        client.shutdown().await.unwrap();
+        serve_connection(router, server).await.unwrap();
+        let mut records = vec![];
+        while let Some(record) = read_record(&mut client).await.unwrap() {
+            records.push(record);
+        }
+        records
+    }
+
+    fn stdout(records: &[Record], id: u16) -> String {
+        let data = records
+            .iter()
+            .filter(|r| r.kind == STDOUT && r.id == id)
+            .flat_map(|r| r.content.iter().copied())
+            .collect::<Vec<_>>();
+        String::from_utf8(data).unwrap()
+    }
+
+    fn router() -> Router {
+        let mut http = crate::http();
+        http.at("/echo/{name}")
+            .post(|mut request: Request| async move {
+                let name = request.fragment_str("name").unwrap().to_owned();
+                let body = request.data(512).into_text().await?;
+                let agent = request.header("User-Agent").unwrap().to_str()?.to_owned();
+                let address = request.peer_addr().unwrap();
+                let query = request.uri().query().unwrap_or_default().to_owned();
+                Ok::<_, anyhow::Error>(Response::text(format!(
+                    "{name} {body} {agent} {address} {query} {}",
+                    request.scheme()
+                )))
+            })
+            .get(crate::endpoints::simple(Response::empty_204));
+        http.prepare();
+        http
+    }
+
+    #[tokio::test]
+    async fn test_request() {
+        let router = router();
+        let params = [
+            ("REQUEST_METHOD", "POST"),
+            ("REQUEST_URI", "/echo/alice?x=1"),
+            ("HTTP_HOST", "example.com"),
+            ("HTTPS", "on"),
+            ("HTTP_USER_AGENT", "test"),
+            ("CONTENT_LENGTH", "5"),
+            ("CONTENT_TYPE", "text/plain"),
+            ("REMOTE_ADDR", "192.0.2.1"),
+            ("REMOTE_PORT", "4000"),
+        ];
+        let records = exchange(&router, request(1, false, &params, b"hello")).await;
+        let output = stdout(&records, 1);
+        assert!(output.starts_with("Status: 200 OK\r\n"), "{output}");
+        assert!(output.ends_with("\r\n\r\nalice hello test 192.0.2.1:4000 x=1 https"));
+        let end = records.last().unwrap();
+        assert_eq!(end.kind, END_REQUEST);
+        assert_eq!(end.content[4], REQUEST_COMPLETE);
+    }
+
+    #[tokio::test]
+    async fn test_keep_conn() {
+        let router = router();
+        let params = [("REQUEST_METHOD", "GET"), ("SCRIPT_NAME", "/echo/bob")];
+        let mut input = request(1, true, &params, &[]);
+        input.extend(request(2, false, &params, &[]));
+        let records = exchange(&router, input).await;
+        assert!(stdout(&records, 1).starts_with("Status: 204 No Content\r\n"));
+        assert!(stdout(&records, 2).starts_with("Status: 204 No Content\r\n"));
+        let ends = records.iter().filter(|r| r.kind == END_REQUEST).count();
+        assert_eq!(ends, 2);
+    }
+
+    #[tokio::test]
+    async fn test_management() {
+        let router = router();
+        let mut values = vec![];
+        encode_param(&mut values, "FCGI_MPXS_CONNS", b"");
+        let mut input = record(GET_VALUES, 0, &values);
+        input.extend(record(99, 0, &[]));
+        input.extend(record(BEGIN_REQUEST, 3, &[0, 2, 0, 0, 0, 0, 0, 0]));
+        let records = exchange(&router, input).await;
+        assert_eq!(records[0].kind, GET_VALUES_RESULT);
+        assert_eq!(
+            decode_params(&records[0].content),
+            vec![("FCGI_MPXS_CONNS".to_owned(), b"0".to_vec())]
+        );
+        assert_eq!(records[1].kind, UNKNOWN_TYPE);
+        assert_eq!(records[1].content[0], 99);
+        assert_eq!(records[2].kind, END_REQUEST);
+        assert_eq!(records[2].content[4], UNKNOWN_ROLE);
+    }
+
+    #[test]
+    fn test_params() {
+        let long = "x".repeat(300);
+        let mut data = vec![];
+        encode_param(&mut data, "SHORT", b"value");
+        encode_param(&mut data, "LONG", long.as_bytes());
+        let params = decode_params(&data);
+        assert_eq!(params[0], ("SHORT".to_owned(), b"value".to_vec()));
+        assert_eq!(params[1], ("LONG".to_owned(), long.into_bytes()));
+        assert!(decode_params(&[0x80, 0, 0]).is_empty());
+        assert!(decode_params(&[5, 0, b'a']).is_empty());
+    }
+}
@@ -0,0 +1,207 @@
+use super::route::Guard;
+use super::{Path, Router};
+use crate::{Endpoint, Middleware, Request, Response};
+use std::any::TypeId;
+use std::borrow::Cow;
+use std::pin::Pin;
+use std::sync::Arc;
+
+/// A builder for a group of routes that share configuration.
+///
+/// This is created by [`Router::group`].  The configuration - middleware,
+/// guards, tags, and a fallback - is collected on the builder, and then
+/// applied to every route declared in [`Group::routes`].  Unlike
+/// [`Router::with`], the group's middleware only runs for requests that are
+/// routed to one of its routes; it runs after the router's middleware.
+///
+/// # Examples
+/// ```rust
+/// # use under::*;
+/// use under::middleware::StateMiddleware;
+/// # #[tokio::main] async fn main() -> Result<(), anyhow::Error> {
+/// let mut http = under::http();
+/// http.group("/api/v1")
+///     .with(StateMiddleware::new(1u32))
+///     .guard(|request| request.header("X-API-Key").is_some())
+///     .tag("api")
+///     .fallback(under::endpoints::status(http::StatusCode::NOT_FOUND))
+///     .routes(|api| {
+///         api.at("/version").get(|request: Request| async move {
+///             Response::text(format!("v{}", request.state::<u32>().unwrap()))
+///         });
+///         api.at("/tags").get(|request: Request| async move {
+///             Response::text(request.route_tags().join(","))
+///         });
+///     });
+/// http.prepare();
+///
+/// let request = Request::get("/api/v1/version")?.with_header("X-API-Key", "k")?;
+/// let mut response = http.handle(request).await?;
+/// assert_eq!(response.data(512).into_text().await?, "v1");
+/// let request = Request::get("/api/v1/tags")?.with_header("X-API-Key", "k")?;
+/// let mut response = http.handle(request).await?;
+/// assert_eq!(response.data(512).into_text().await?, "api");
+/// let request = Request::get("/api/v1/missing")?.with_header("X-API-Key", "k")?;
+/// let response = http.handle(request).await?;
+/// assert_eq!(response.status(), http::StatusCode::NOT_FOUND);
+/// // Without the key, none of the group's routes accept the request.
+/// let response = http.handle(Request::get("/api/v1/version")?).await?;
+/// assert_eq!(response.status(), http::StatusCode::INTERNAL_SERVER_ERROR);
+/// # Ok(())
+/// # }
+/// ```
+pub struct Group<'a> {
+    router: &'a mut Router,
+    prefix: String,
+    middleware: Vec<Pin<Box<dyn Middleware>>>,
+    guards: Vec<Guard>,
+    tags: Vec<Cow<'static, str>>,
+    skips: Vec<TypeId>,
+    fallback: Option<Pin<Box<dyn Endpoint>>>,
+}
+
+impl<'a> Group<'a> {
+    pub(super) fn new(router: &'a mut Router, prefix: String) -> Self {
+        Group {
+            router,
+            prefix,
+            middleware: vec![],
+            guards: vec![],
+            tags: vec![],
+            skips: vec![],
+            fallback: None,
+        }
+    }
+
+    /// Appends middleware to the group.  This operates very similarly to
+    /// [`Router::with`], except that it only runs for the routes of the
+    /// group.
+    pub fn with<M: Middleware>(&mut self, middleware: M) -> &mut Self {
+        self.middleware.push(Box::pin(middleware));
+        self
+    }
+
+    /// Adds a guard to the group.  A route in the group only matches a
+    /// request if all of the group's guards accept it; otherwise, routing
+    /// continues as if the route did not exist (so an earlier route, or the
+    /// router's fallback, may respond instead).  This is useful for, e.g.,
+    /// routes that should only exist for a particular host.
+    ///
+    /// Guards are checked while routing, before any middleware has run.
+    /// Note that [`Router::route_label`] does not check guards.
+    pub fn guard<F>(&mut self, guard: F) -> &mut Self
+    where
+        F: Fn(&Request) -> bool + Send + Sync + 'static,
+    {
+        self.guards.push(Arc::new(guard));
+        self
+    }
+
+    /// Tags the routes of the group.  Tags don't affect routing; they are
+    /// available to middleware and endpoints through
+    /// [`Request::route_tags`], so that e.g. a middleware can treat every
+    /// route tagged `public` differently.
+    pub fn tag<T: Into<Cow<'static, str>>>(&mut self, tag: T) -> &mut Self {
+        self.tags.push(tag.into());
+        self
+    }
+
+    /// Skips the given middleware for the routes of the group, as if
+    /// [`Request::skip`] were called for every request routed to one of
+    /// them.  This is useful for, e.g., disabling a middleware of the router
+    /// for a group of streaming routes.
+    ///
+    /// # Examples
+    /// ```rust
+    /// # use under::*;
+    /// use under::middleware::ServerTiming;
+    /// # #[tokio::main] async fn main() -> Result<(), anyhow::Error> {
+    /// let mut http = under::http();
+    /// http.with(ServerTiming::new());
+    /// http.group("/events")
+    ///     .skip::<ServerTiming>()
+    ///     .routes(|events| {
+    ///         events.get(under::endpoints::simple(Response::empty_204));
+    ///     });
+    /// http.prepare();
+    /// let response = http.handle(Request::get("/events")?).await?;
+    /// assert!(response.header("Server-Timing").is_none());
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn skip<M: Middleware>(&mut self) -> &mut Self {
+        self.skips.push(TypeId::of::<M>());
+        self
+    }
+
+    /// Sets a fallback for the group.  This responds to any request under the
+    /// group's prefix that no route in the group matches, instead of the
+    /// router's fallback.  It has the group's guards, middleware, and tags,
+    /// like any other route of the group.
+    pub fn fallback<E: Endpoint>(&mut self, endpoint: E) -> &mut Self {
+        self.fallback = Some(Box::pin(endpoint));
+        self
+    }
+
+    /// Declares the routes of the group, applying the group's configuration
+    /// to each of them.  The given path is at the group's prefix.
+    ///
+    /// This leaves the builder in its default state afterwards (with the
+    /// same prefix), like [`crate::endpoints::ScopeEndpointBuilder::then`].
+    pub fn routes<F: FnOnce(&mut Path<'_>)>(&mut self, build: F) -> &mut Self {
+        let routes = self.router.routes_mut();
+        let start = routes.len();
+        let mut path = Path::new(self.prefix.clone(), &mut *routes);
+        if let Some(fallback) = self.fallback.take() {
+            // The fallback is added first, so that every other route in the
+            // group takes precedence over it.
+            let fallback = Arc::new(fallback);
+            path.all(Shared(fallback.clone()));
+            path.at("/{rest:path}").all(Shared(fallback));
+        }
+        build(&mut path);
+
+        let middleware: Arc<[Pin<Box<dyn Middleware>>]> =
+            std::mem::take(&mut self.middleware).into();
+        let guards = std::mem::take(&mut self.guards);
+        let tags = std::mem::take(&mut self.tags);
+        let skips = std::mem::take(&mut self.skips);
+        let added = routes.drain(start..).collect::<Vec<_>>();
+        for route in added {
+            // The routes were only just created, and so nothing else has a
+            // reference to them yet.
+            let route = match Arc::try_unwrap(route) {
+                Ok(route) => Arc::new(route.layer(&middleware, &guards, &tags, &skips)),
+                Err(route) => route,
+            };
+            routes.push(route);
+        }
+        self
+    }
+}
+
+impl std::fmt::Debug for Group<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Group")
+            .field("prefix", &self.prefix)
+            .field("middleware", &self.middleware)
+            .field("guards", &self.guards.len())
+            .field("tags", &self.tags)
+            .field("fallback", &self.fallback)
+            .finish_non_exhaustive()
+    }
+}
+
+/// An endpoint that is shared between more than one route.
+struct Shared(Arc<Pin<Box<dyn Endpoint>>>);
+
+#[async_trait]
+impl Endpoint for Shared {
+    async fn apply(self: Pin<&Self>, request: Request) -> Result<Response, anyhow::Error> {
+        self.0.as_ref().as_ref().apply(request).await
+    }
+
+    fn describe(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.0.describe(f)
+    }
+}
@@ -0,0 +1,56 @@
+use crate::{Request, RequestInfo, Response};
+use std::sync::Arc;
+use std::time::Duration;
+
+pub(super) type RequestHook = Box<dyn Fn(&Request) + Send + Sync>;
+pub(super) type ResponseHook = Box<dyn Fn(&RequestInfo, &Response) + Send + Sync>;
+pub(super) type CompleteHook = Arc<dyn Fn(&Completion) + Send + Sync>;
+
+#[derive(Debug)]
+/// Information on a request that has been completely handled.
+///
+/// This is given to the hooks added with
+/// [`crate::Router::on_request_complete`], after the body of the response
+/// has been written (or dropped).
+pub struct Completion {
+    pub(super) info: RequestInfo,
+    pub(super) status: http::StatusCode,
+    pub(super) duration: Duration,
+    pub(super) bytes: u64,
+    pub(super) complete: bool,
+}
+
+impl Completion {
+    /// Information on the request.
+    #[must_use]
+    pub fn info(&self) -> &RequestInfo {
+        &self.info
+    }
+
+    /// The status of the response.
+    #[must_use]
+    pub fn status(&self) -> http::StatusCode {
+        self.status
+    }
+
+    /// How long the request took, from when it was received, to when the
+    /// body of the response was written.
+    #[must_use]
+    pub fn duration(&self) -> Duration {
+        self.duration
+    }
+
+    /// The number of bytes written for the body of the response.
+    #[must_use]
+    pub fn bytes(&self) -> u64 {
+        self.bytes
+    }
+
+    /// Whether or not the body of the response was written in full.  If
+    /// this is `false`, the body was dropped before then (e.g. because the
+    /// client disconnected).
+    #[must_use]
+    pub fn is_complete(&self) -> bool {
+        self.complete
+    }
+}
@@ -0,0 +1,53 @@
+use crate::Response;
+use std::borrow::Cow;
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Default)]
+#[non_exhaustive]
+/// Which media types a `415 Unsupported Media Type` response says would have
+/// been accepted (see [`crate::Router::media_type_hint`]).
+///
+/// When an extractor rejects the content type of a request (with
+/// [`crate::json::JsonRejection::UnsupportedMediaType`], or
+/// [`crate::UnderError::UnsupportedMediaType`]), the router responds with a
+/// 415, listing the accepted types in the `Accept-Post` header for a `POST`,
+/// the `Accept-Patch` header for a `PATCH`, or the `Accept` header
+/// otherwise.
+pub enum MediaTypeHint {
+    /// Lists the types that the extractor that rejected the request
+    /// accepts: `application/json` for [`crate::json::Json`], or, for
+    /// [`crate::UnderError::UnsupportedMediaType`], the types that the error
+    /// carries (e.g. every type that [`crate::HttpEntity::as_sniff_form`]
+    /// can parse with the enabled features).  Errors that carry no types,
+    /// such as those from [`crate::Request::expect_content_type`], get no
+    /// hint.  This is the default.
+    #[default]
+    Auto,
+    /// Lists the given types, whichever extractor rejected the request.
+    Types(Vec<Cow<'static, str>>),
+    /// Lists nothing; the 415 is sent without a hint.
+    Off,
+}
+
+impl MediaTypeHint {
+    /// Adds the hint to the 415 response to a request with the given method,
+    /// where the extractor that rejected it accepts the given types.
+    pub(super) fn apply(&self, response: &mut Response, method: &http::Method, accepted: &[&str]) {
+        let types = match self {
+            MediaTypeHint::Auto => accepted.join(", "),
+            MediaTypeHint::Types(types) => types.join(", "),
+            MediaTypeHint::Off => return,
+        };
+        let Ok(value) = http::HeaderValue::try_from(types) else {
+            return;
+        };
+        if value.is_empty() {
+            return;
+        }
+        let name = match *method {
+            http::Method::POST => http::HeaderName::from_static("accept-post"),
+            http::Method::PATCH => http::HeaderName::from_static("accept-patch"),
+            _ => http::header::ACCEPT,
+        };
+        response.headers_mut().insert(name, value);
+    }
+}
@@ -1,13 +1,32 @@
+mod connections;
+mod decoding;
+mod diagnostics;
+#[cfg(feature = "fastcgi")]
+mod fastcgi;
+mod group;
+mod lifecycle;
+mod media_type;
 mod pattern;
 mod route;
 mod service;
+mod stats;
+mod write_timeout;
 
+pub use self::connections::ConnectionGauge;
+pub(crate) use self::decoding::percent_decode;
+pub use self::decoding::{DecodePolicy, PathDecoding};
+pub use self::group::Group;
+pub use self::lifecycle::Completion;
+use self::lifecycle::{CompleteHook, RequestHook, ResponseHook};
+pub use self::media_type::MediaTypeHint;
 pub(crate) use self::pattern::Pattern;
-pub use self::route::Path;
 pub(crate) use self::route::Route;
+pub use self::route::{Path, RouteInfo};
+pub use self::stats::RouterStats;
 use crate::endpoint::Endpoint;
 use crate::middleware::Middleware;
-use crate::{Request, Response};
+use crate::{Request, RequestInfo, Response};
+use std::borrow::Cow;
 use std::pin::Pin;
 use std::sync::Arc;
 use tokio::sync::watch;
@@ -36,31 +55,97 @@ use tokio::sync::watch;
 ///
 /// Internally, the router uses a regular expression matcher to convert the
 /// given paths (e.g. `/user/{id}`) into a regular expression
-/// (`^/user/(?P<id>[^/]+)`).  It does this segment-by-segment in the path, and
+/// (`\A/user/(?P<id>(?:[^/]+))\z`).  It does this segment-by-segment in the path, and
 /// is rather strict about what the names of a placeholder component can be
-/// (only alphabetical).  This is compiled into a `RegexSet`, which, when run
+/// (only letters, digits, and underscores, not starting with a digit).  This is compiled into a `RegexSet`, which, when run
 /// against a given path, will return a list of routes that the path matches.
 /// Because we don't have to fool around with matching every route, the timing
 /// is `O(n)`, with `n` being the length of the input path.  After the
 /// `RegexSet` match, we again match against the route to collect the pattern
 /// matchers (e.g. `{some}` and `{value:path}`), before returning both.  This
 /// information is included as a part of the request.
+#[allow(clippy::struct_excessive_bools)]
 pub struct Router {
-    regex: regex::RegexSet,
+    /// The compiled patterns of the routes.  This is reset whenever the
+    /// routes may change, and compiled again once it is next needed.
+    regex: std::sync::OnceLock<Result<regex::RegexSet, crate::RouterBuildError>>,
     routes: Vec<Arc<Route>>,
     middleware: Vec<Pin<Box<dyn Middleware>>>,
+    /// The names of each middleware, if any.  This always has the same
+    /// length as `middleware`.
+    middleware_names: Vec<Option<Cow<'static, str>>>,
     fallback: Option<Pin<Box<dyn Endpoint>>>,
+    error_response: Option<crate::ResponseTemplate>,
     terminate: Option<watch::Receiver<bool>>,
+    profile: bool,
+    response_maps: Vec<ResponseMap>,
+    error_hooks: Vec<ErrorHook>,
+    error_format: Option<ErrorFormat>,
+    expose_error_details: bool,
+    request_hooks: Vec<RequestHook>,
+    response_hooks: Vec<ResponseHook>,
+    complete_hooks: Arc<Vec<CompleteHook>>,
+    strip_head_body: bool,
+    expect_continue: bool,
+    body_timeout: Option<std::time::Duration>,
+    write_timeout: Option<std::time::Duration>,
+    idle_timeout: Option<std::time::Duration>,
+    max_requests_per_connection: Option<usize>,
+    connections: ConnectionGauge,
+    stats: RouterStats,
+    shards: Option<usize>,
+    path_decoding: PathDecoding,
+    media_type_hint: MediaTypeHint,
+    max_path_length: Option<usize>,
+    debug_unmatched: bool,
+    redactor: crate::redact::Redactor,
+    #[cfg(feature = "serde")]
+    query_config: Option<crate::QueryConfig>,
+    #[cfg(feature = "rng")]
+    rng: crate::Rng,
 }
 
+type ResponseMap = Box<dyn Fn(&RequestInfo, Response) -> Response + Send + Sync>;
+type ErrorHook = Box<dyn Fn(&anyhow::Error, &RequestInfo) + Send + Sync>;
+type ErrorFormat = Box<dyn Fn(&anyhow::Error) -> String + Send + Sync>;
+
 impl Default for Router {
     fn default() -> Self {
+        let connections = ConnectionGauge::default();
         Router {
-            regex: regex::RegexSet::empty(),
+            regex: std::sync::OnceLock::new(),
             middleware: vec![],
+            middleware_names: vec![],
             routes: vec![],
             fallback: None,
+            error_response: None,
             terminate: None,
+            profile: false,
+            response_maps: vec![],
+            error_hooks: vec![],
+            error_format: None,
+            expose_error_details: true,
+            request_hooks: vec![],
+            response_hooks: vec![],
+            complete_hooks: Arc::new(vec![]),
+            strip_head_body: true,
+            expect_continue: true,
+            body_timeout: None,
+            write_timeout: None,
+            idle_timeout: None,
+            max_requests_per_connection: None,
+            stats: RouterStats::new(connections.clone()),
+            connections,
+            shards: None,
+            path_decoding: PathDecoding::raw(),
+            media_type_hint: MediaTypeHint::Auto,
+            max_path_length: None,
+            debug_unmatched: false,
+            redactor: crate::redact::Redactor::default(),
+            #[cfg(feature = "serde")]
+            query_config: None,
+            #[cfg(feature = "rng")]
+            rng: crate::Rng::from_entropy(),
         }
     }
 }
@@ -68,80 +153,1101 @@ impl Default for Router {
 impl Router {
     /// Prepares the router, constructing the routes.
     ///
-    /// This is automatically called when listening using [`Router::listen`].
-    /// However, you may want to use the router before that point for e.g.
-    /// testing, and so this must be called before any requests are routed
-    /// (or, if any routes are changed).  If this is not called, you will
-    /// receive only 500 errors.
-    #[allow(clippy::missing_panics_doc)]
+    /// This does not need to be called: the routes are constructed
+    /// automatically the first time a request is routed after they have
+    /// changed (and when listening using [`Router::listen`]).  Calling this
+    /// ahead of time moves the cost of constructing them out of the first
+    /// request.
+    ///
+    /// # Panics
+    /// Panics if any of the routes are invalid; see [`Router::try_prepare`]
+    /// for a version that does not.
+    ///
+    /// # Examples
+    /// ```rust
+    /// # use under::*;
+    /// # #[tokio::main] async fn main() -> Result<(), anyhow::Error> {
+    /// let mut http = under::http();
+    /// http.at("/a").get(under::endpoints::simple(Response::empty_204));
+    /// let response = http.handle(Request::get("/a")?).await?;
+    /// assert_eq!(response.status(), http::StatusCode::NO_CONTENT);
+    /// http.at("/b").get(under::endpoints::simple(Response::empty_204));
+    /// let response = http.handle(Request::get("/b")?).await?;
+    /// assert_eq!(response.status(), http::StatusCode::NO_CONTENT);
+    /// # Ok(())
+    /// # }
+    /// ```
     pub fn prepare(&mut self) {
-        let patterns = self
-            .routes
+        if let Err(error) = self.try_prepare() {
+            panic!("{error}");
+        }
+    }
+
+    /// Prepares the router, constructing the routes (see
+    /// [`Router::prepare`]), and reporting any routes that are invalid.
+    /// Until they are fixed, [`Router::handle`] errors with
+    /// [`crate::UnderError::InvalidRoutes`], and [`Router::listen`] refuses
+    /// to start.
+    ///
+    /// # Errors
+    /// Errors if any of the routes are invalid, e.g. if their path uses an
+    /// unknown fragment type.
+    ///
+    /// # Examples
+    /// ```rust
+    /// # use under::*;
+    /// let mut http = under::http();
+    /// http.at("/users/{id:number}").get(under::endpoints::simple(Response::empty_204));
+    /// let error = http.try_prepare().unwrap_err();
+    /// assert!(matches!(error, RouterBuildError::UnknownFragmentType { .. }));
+    /// assert_eq!(
+    ///     error.to_string(),
+    ///     "unknown fragment type \"number\" in route:\n    /users/{id:number}\n           ^^^^^^^^^^^"
+    /// );
+    /// ```
+    pub fn try_prepare(&mut self) -> Result<(), crate::RouterBuildError> {
+        let regex = compile(&self.routes);
+        let result = regex.as_ref().map(|_| ()).map_err(Clone::clone);
+        self.regex = std::sync::OnceLock::from(regex);
+        result
+    }
+
+    /// Information about each route of the router, in the order they were
+    /// added; this includes how the path of each is matched (see
+    /// [`RouteInfo::pattern`]).
+    ///
+    /// # Examples
+    /// ```rust
+    /// # use under::*;
+    /// let mut http = under::http();
+    /// http.at("/users/{id:uint}/{}").get(under::endpoints::simple(Response::empty_204));
+    /// let route = http.route_info().next().unwrap();
+    /// assert_eq!(route.path(), "/users/{id:uint}/{}");
+    /// assert_eq!(route.method(), Some(&http::Method::GET));
+    /// assert_eq!(route.pattern(), r"\A/users/(?P<id>(?:\d+))/((?:[^/]+))\z");
+    /// assert_eq!(route.fragments(), 2);
+    /// ```
+    pub fn route_info(&self) -> impl Iterator<Item = RouteInfo<'_>> + '_ {
+        self.routes.iter().map(|route| RouteInfo::new(route))
+    }
+
+    pub(crate) fn routes(&self) -> &[Arc<Route>] {
+        &self.routes[..]
+    }
+
+    /// The routes of the router, to be changed.  This resets the compiled
+    /// patterns, since they may no longer match the routes.
+    pub(super) fn routes_mut(&mut self) -> &mut Vec<Arc<Route>> {
+        self.regex.take();
+        &mut self.routes
+    }
+
+    fn regex(&self) -> Result<&regex::RegexSet, &crate::RouterBuildError> {
+        self.regex.get_or_init(|| compile(&self.routes)).as_ref()
+    }
+
+    /// Creates a [`Path`] at the provided prefix.  See [`Path::at`] for more.
+    pub fn at<P: AsRef<str>>(&mut self, prefix: P) -> Path<'_> {
+        Path::new(join_paths("", prefix.as_ref()), self.routes_mut())
+    }
+
+    /// Creates a [`Path`] at the provided prefix, and executes the provided
+    /// closure with it.  See [`Path::under`] for more.
+    pub fn under<P: AsRef<str>, F: FnOnce(&mut Path<'_>)>(
+        &mut self,
+        prefix: P,
+        build: F,
+    ) -> &mut Self {
+        let mut path = Path::new(join_paths("", prefix.as_ref()), self.routes_mut());
+        build(&mut path);
+        self
+    }
+
+    /// Mounts the given controller at the provided prefix.  See
+    /// [`crate::Controller`] for more.
+    pub fn controller<P: AsRef<str>, C: crate::Controller>(
+        &mut self,
+        prefix: P,
+        controller: C,
+    ) -> &mut Self {
+        let mut path = Path::new(join_paths("", prefix.as_ref()), self.routes_mut());
+        path.controller(controller);
+        self
+    }
+
+    /// Creates a builder for a group of routes at the provided prefix, which
+    /// share middleware, guards, tags, and a fallback.  See [`Group`] for
+    /// more.
+    pub fn group<P: AsRef<str>>(&mut self, prefix: P) -> Group<'_> {
+        Group::new(self, join_paths("", prefix.as_ref()))
+    }
+
+    /// Creates a builder for mounting well-known routes (such as a health
+    /// check, or `security.txt`) onto the router.  See
+    /// [`crate::well_known::WellKnown`] for more.
+    pub fn well_known(&mut self) -> crate::well_known::WellKnown<'_> {
+        crate::well_known::WellKnown::new(self)
+    }
+
+    /// Appends middleware to the router.  Each middleware is executed in the
+    /// order that it is appended to the router (i.e., the first middleware
+    /// inserted executes first).
+    ///
+    /// # Examples
+    /// ```rust
+    /// let mut http = under::http();
+    /// http.with(under::middleware::TraceMiddleware::new())
+    ///     .with(under::middleware::StateMiddleware::new(123u32));
+    /// ```
+    pub fn with<M: Middleware>(&mut self, middleware: M) -> &mut Self {
+        self.middleware.push(Box::pin(middleware));
+        self.middleware_names.push(None);
+        self
+    }
+
+    /// Appends middleware to the router, under the given name.  This acts
+    /// the same as [`Router::with`], except that the middleware can later be
+    /// replaced or removed using [`Router::replace_middleware`] and
+    /// [`Router::remove_middleware`].  If there is already middleware with the
+    /// given name, it is replaced in place (keeping its position in the
+    /// stack), instead of being appended.
+    ///
+    /// This allows e.g. frameworks built on top of this router to provide a
+    /// default middleware stack that applications can then customize.
+    ///
+    /// # Examples
+    /// ```rust
+    /// let mut http = under::http();
+    /// http.with_named("trace", under::middleware::TraceMiddleware::new())
+    ///     .with(under::middleware::StateMiddleware::new(123u32));
+    /// ```
+    pub fn with_named<M: Middleware>(
+        &mut self,
+        name: impl Into<Cow<'static, str>>,
+        middleware: M,
+    ) -> &mut Self {
+        let name = name.into();
+        if let Some(index) = self.middleware_index(&name) {
+            self.middleware[index] = Box::pin(middleware);
+        } else {
+            self.middleware.push(Box::pin(middleware));
+            self.middleware_names.push(Some(name));
+        }
+        self
+    }
+
+    /// Replaces the middleware with the given name, returning the previous
+    /// middleware.  The new middleware keeps the position of the old one in
+    /// the stack.  If there is no middleware with the given name, nothing is
+    /// replaced (and the new middleware is dropped), and `None` is returned.
+    ///
+    /// # Examples
+    /// ```rust
+    /// # use under::*;
+    /// use under::middleware::StateMiddleware;
+    /// # #[tokio::main] async fn main() -> Result<(), anyhow::Error> {
+    /// let mut http = under::http();
+    /// http.with_named("state", StateMiddleware::new(1u32))
+    ///     .at("/").get(|req: Request| async move {
+    ///         Response::text(format!("{}", req.state::<u32>().unwrap()))
+    ///     });
+    /// assert!(http.replace_middleware("state", StateMiddleware::new(2u32)).is_some());
+    /// assert!(http.replace_middleware("other", StateMiddleware::new(3u32)).is_none());
+    /// http.prepare();
+    /// let mut response = http.handle(Request::get("/")?).await?;
+    /// assert_eq!(response.data(512).into_text().await?, "2");
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn replace_middleware<M: Middleware>(
+        &mut self,
+        name: &str,
+        middleware: M,
+    ) -> Option<Pin<Box<dyn Middleware>>> {
+        let index = self.middleware_index(name)?;
+        Some(std::mem::replace(
+            &mut self.middleware[index],
+            Box::pin(middleware),
+        ))
+    }
+
+    /// Removes the middleware with the given name from the stack, returning
+    /// it.  If there is no middleware with the given name, `None` is
+    /// returned.
+    ///
+    /// # Examples
+    /// ```rust
+    /// let mut http = under::http();
+    /// http.with_named("trace", under::middleware::TraceMiddleware::new());
+    /// assert!(http.remove_middleware("trace").is_some());
+    /// assert!(http.remove_middleware("trace").is_none());
+    /// ```
+    pub fn remove_middleware(&mut self, name: &str) -> Option<Pin<Box<dyn Middleware>>> {
+        let index = self.middleware_index(name)?;
+        self.middleware_names.remove(index);
+        Some(self.middleware.remove(index))
+    }
+
+    fn middleware_index(&self, name: &str) -> Option<usize> {
+        self.middleware_names
             .iter()
-            .map(|route| route.pattern.regex().as_str());
-        // This shouldn't panic, because the patterns were already validated
-        // (e.g. if any of them were invalid, we would have already panicked).
-        let set = regex::RegexSet::new(patterns).unwrap();
-        self.regex = set;
+            .position(|n| n.as_deref() == Some(name))
+    }
+
+    /// Sets a fallback endpoint.  If there exists no other endpoint in the
+    /// router that could potentially respond to the request, it will first
+    /// attempt to execute this fallback endpoint, before instead returning
+    /// an empty 500 error (or the [`Router::error_response`]).  Without a
+    /// fallback, a request whose path only matches routes for other methods
+    /// gets an empty 405, with an `Allow` header listing those methods (see
+    /// [`crate::UnderError::MethodNotAllowed`]).  For a fixed
+    /// response, such as a 404 page, a [`crate::ResponseTemplate`] can be
+    /// used as the endpoint.
+    ///
+    /// # Examples
+    /// ```rust
+    /// # use under::*;
+    /// # #[tokio::main] async fn main() -> Result<(), anyhow::Error> {
+    /// let mut http = under::http();
+    /// http.at("/foo").get(under::endpoints::simple(Response::empty_204));
+    /// http.fallback(under::endpoints::simple(Response::empty_404));
+    /// http.prepare();
+    /// let response = http.handle(Request::get("/foo")?).await?;
+    /// assert_eq!(response.status(), http::StatusCode::NO_CONTENT);
+    /// let response = http.handle(Request::get("/bar")?).await?;
+    /// assert_eq!(response.status(), http::StatusCode::NOT_FOUND);
+    /// # Ok(())
+    /// # }
+    pub fn fallback<E: Endpoint>(&mut self, endpoint: E) -> &mut Self {
+        self.fallback = Some(Box::pin(endpoint));
+        self
+    }
+
+    /// Sets a chain of fallback endpoints, which are tried in order.  Each
+    /// may decline the request (by responding with [`Response::decline`]),
+    /// passing it on to the next; the response of the first that does not
+    /// decline is used.  If every one of them declines, the response is an
+    /// empty 404.  This replaces any fallback set with [`Router::fallback`].
+    ///
+    /// This is useful for, e.g., serving static files if they exist, then
+    /// the index page of a single-page application for the paths it
+    /// handles, and then a 404 for everything else.
+    ///
+    /// # Examples
+    /// ```rust
+    /// # use under::*;
+    /// use under::endpoints::EndpointExt;
+    /// # #[tokio::main] async fn main() -> Result<(), anyhow::Error> {
+    /// let assets = |request: Request| async move {
+    ///     if request.uri().path() == "/app.js" {
+    ///         Response::text("console.log(1)")
+    ///     } else {
+    ///         Response::decline(request)
+    ///     }
+    /// };
+    /// let index = |request: Request| async move {
+    ///     if request.uri().path().starts_with("/app/") {
+    ///         Response::text("<html></html>")
+    ///     } else {
+    ///         Response::decline(request)
+    ///     }
+    /// };
+    /// let mut http = under::http();
+    /// http.fallback_chain([
+    ///     assets.boxed(),
+    ///     index.boxed(),
+    ///     under::endpoints::simple(Response::empty_404).boxed(),
+    /// ]);
+    /// http.prepare();
+    /// let mut response = http.handle(Request::get("/app.js")?).await?;
+    /// assert_eq!(response.data(512).into_text().await?, "console.log(1)");
+    /// let mut response = http.handle(Request::get("/app/settings")?).await?;
+    /// assert_eq!(response.data(512).into_text().await?, "<html></html>");
+    /// let response = http.handle(Request::get("/missing")?).await?;
+    /// assert_eq!(response.status(), http::StatusCode::NOT_FOUND);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn fallback_chain<I>(&mut self, chain: I) -> &mut Self
+    where
+        I: IntoIterator<Item = crate::endpoints::BoxedEndpoint>,
+    {
+        let chain = crate::endpoints::FallbackChain::new(chain.into_iter().collect());
+        self.fallback(chain)
+    }
+
+    /// Sets the response used in place of the default, empty 500: both for
+    /// requests that no endpoint (nor the fallback) could respond to, and,
+    /// when listening (or with [`Router::serve`]), for requests that errored.
+    /// The response is prebuilt, so it is not constructed per request.
+    ///
+    /// # Examples
+    /// ```rust
+    /// # use under::*;
+    /// # #[tokio::main] async fn main() -> Result<(), anyhow::Error> {
+    /// let mut http = under::http();
+    /// http.at("/").get(|_| async { Err::<Response, _>(anyhow::anyhow!("oops")) });
+    /// http.error_response(ResponseTemplate::new(
+    ///     Response::empty_500(),
+    ///     "something went wrong",
+    /// ));
+    /// http.prepare();
+    /// let mut response = http.serve(Request::get("/")?).await;
+    /// assert_eq!(response.status(), http::StatusCode::INTERNAL_SERVER_ERROR);
+    /// assert_eq!(response.data(512).into_text().await?, "something went wrong");
+    /// let mut response = http.handle(Request::get("/missing")?).await?;
+    /// assert_eq!(response.data(512).into_text().await?, "something went wrong");
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn error_response(&mut self, template: crate::ResponseTemplate) -> &mut Self {
+        self.error_response = Some(template);
+        self
+    }
+
+    /// Adds a hook that post-processes every response generated by the router.
+    /// The hook is given information on the request that generated the
+    /// response, as well as the response itself, and returns the (possibly
+    /// modified) response.
+    ///
+    /// This runs after the whole middleware stack has completed, for every
+    /// response - including those generated by the fallback endpoint, or the
+    /// default 500 endpoint.  This is useful for global concerns, like adding a
+    /// header to every response, that don't need a full middleware.  Hooks are
+    /// run in the order that they are added.  If the middleware stack errors,
+    /// and the error is not turned into a response, the hooks are run on the
+    /// 500 that [`Router::serve`] sends in its place ([`Router::handle`] still
+    /// returns the error as-is).
+    ///
+    /// # Examples
+    /// ```rust
+    /// # use under::*;
+    /// # #[tokio::main] async fn main() -> Result<(), anyhow::Error> {
+    /// let mut http = under::http();
+    /// http.at("/").get(under::endpoints::simple(Response::empty_204));
+    /// http.map_response(|info, response| {
+    ///     let path = info.uri().path().to_string();
+    ///     response.with_header("X-Path", path).unwrap()
+    /// });
+    /// http.prepare();
+    /// let response = http.handle(Request::get("/")?).await?;
+    /// assert_eq!(response.header("X-Path").unwrap(), "/");
+    /// let response = http.handle(Request::get("/missing")?).await?;
+    /// assert_eq!(response.status(), http::StatusCode::INTERNAL_SERVER_ERROR);
+    /// assert_eq!(response.header("X-Path").unwrap(), "/missing");
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn map_response<F>(&mut self, map: F) -> &mut Self
+    where
+        F: Fn(&RequestInfo, Response) -> Response + Send + Sync + 'static,
+    {
+        self.response_maps.push(Box::new(map));
+        self
+    }
+
+    /// Adds a hook that is called whenever the middleware stack (i.e., any
+    /// middleware, or the endpoint) returns an error.  The hook is given the
+    /// error, as well as information on the request that caused it.  This is
+    /// useful for reporting errors to an external service (e.g. Sentry)
+    /// without having to wrap every endpoint.  Hooks are run in the order
+    /// that they are added.
+    ///
+    /// The hooks are run before the router does anything with the error:
+    /// before errors that reject the request (e.g. a
+    /// [`crate::json::JsonRejection`], or an [`crate::UnderError`] with a 4xx
+    /// status) are turned into their response, and before any other error is
+    /// returned from the router; when listening (see [`Router::listen`]),
+    /// that error is then turned into an empty 500 response (or the
+    /// [`Router::error_response`]).
+    ///
+    /// # Examples
+    /// ```rust
+    /// # use under::*;
+    /// use std::sync::atomic::{AtomicUsize, Ordering};
+    /// use std::sync::Arc;
+    /// # #[tokio::main] async fn main() -> Result<(), anyhow::Error> {
+    /// let errors = Arc::new(AtomicUsize::new(0));
+    /// let counter = errors.clone();
+    /// let mut http = under::http();
+    /// http.at("/").get(|_| async { Err::<Response, _>(anyhow::anyhow!("oops")) });
+    /// http.on_error(move |error, info| {
+    ///     assert_eq!(error.to_string(), "oops");
+    ///     assert_eq!(info.uri(), "/");
+    ///     counter.fetch_add(1, Ordering::SeqCst);
+    /// });
+    /// http.prepare();
+    /// assert!(http.handle(Request::get("/")?).await.is_err());
+    /// assert_eq!(errors.load(Ordering::SeqCst), 1);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn on_error<F>(&mut self, hook: F) -> &mut Self
+    where
+        F: Fn(&anyhow::Error, &RequestInfo) + Send + Sync + 'static,
+    {
+        self.error_hooks.push(Box::new(hook));
+        self
+    }
+
+    /// Sets how errors are formatted when they are logged, before an empty
+    /// 500 (or the [`Router::error_response`]) is sent in their place (see
+    /// [`Router::serve`]).  By default, the error is logged along with each
+    /// of its causes (including any context added with
+    /// [`anyhow::Context`]), one per line, followed by its backtrace, if one
+    /// was captured (see [`anyhow::Error::backtrace`] for how to enable
+    /// them).
+    ///
+    /// # Examples
+    /// ```rust
+    /// # use under::*;
+    /// use anyhow::Context;
+    /// use std::sync::{Arc, Mutex};
+    /// # #[tokio::main] async fn main() -> Result<(), anyhow::Error> {
+    /// let formatted = Arc::new(Mutex::new(Vec::new()));
+    /// let sink = formatted.clone();
+    /// let mut http = under::http();
+    /// http.at("/").get(|_| async {
+    ///     Err::<Response, _>(anyhow::anyhow!("connection refused"))
+    ///         .context("could not load the user")
+    /// });
+    /// http.error_format(move |error| {
+    ///     let chain = error.chain().map(ToString::to_string).collect::<Vec<_>>();
+    ///     sink.lock().unwrap().push(chain.join(" <- "));
+    ///     format!("{error:#}")
+    /// });
+    /// http.prepare();
+    /// let response = http.serve(Request::get("/")?).await;
+    /// assert_eq!(response.status(), http::StatusCode::INTERNAL_SERVER_ERROR);
+    /// assert_eq!(
+    ///     formatted.lock().unwrap()[0],
+    ///     "could not load the user <- connection refused"
+    /// );
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn error_format<F>(&mut self, format: F) -> &mut Self
+    where
+        F: Fn(&anyhow::Error) -> String + Send + Sync + 'static,
+    {
+        self.error_format = Some(Box::new(format));
+        self
+    }
+
+    /// Adds a hook that is called whenever the server receives a request,
+    /// before it is routed.  The hook is given a reference to the request;
+    /// this is useful for e.g. audit logging, or counting requests.  Hooks
+    /// are run in the order that they are added.
+    ///
+    /// Like the other lifecycle hooks ([`Router::on_response`],
+    /// [`Router::on_request_complete`]), this is run by the server; i.e.,
+    /// when listening (see [`Router::listen`]), or when using
+    /// [`Router::serve`], but not [`Router::handle`], or when the router is
+    /// used as an endpoint of another router.
+    ///
+    /// # Examples
+    /// ```rust
+    /// # use under::*;
+    /// use std::sync::atomic::{AtomicUsize, Ordering};
+    /// use std::sync::Arc;
+    /// # #[tokio::main] async fn main() -> Result<(), anyhow::Error> {
+    /// let requests = Arc::new(AtomicUsize::new(0));
+    /// let counter = requests.clone();
+    /// let mut http = under::http();
+    /// http.on_request(move |_| { counter.fetch_add(1, Ordering::SeqCst); });
+    /// http.prepare();
+    /// http.serve(Request::get("/")?).await;
+    /// assert_eq!(requests.load(Ordering::SeqCst), 1);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn on_request<F>(&mut self, hook: F) -> &mut Self
+    where
+        F: Fn(&Request) + Send + Sync + 'static,
+    {
+        self.request_hooks.push(Box::new(hook));
+        self
+    }
+
+    /// Adds a hook that is called whenever the server has generated a
+    /// response, before the response is sent.  The hook is given information
+    /// on the request, as well as a reference to the response.  If the
+    /// router errored, this is the (empty) 500 response generated by the
+    /// server.
+    ///
+    /// # Examples
+    /// ```rust
+    /// # use under::*;
+    /// # #[tokio::main] async fn main() -> Result<(), anyhow::Error> {
+    /// let mut http = under::http();
+    /// http.on_response(|info, response| {
+    ///     log::info!("{} {}: {}", info.method(), info.uri(), response.status());
+    /// });
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn on_response<F>(&mut self, hook: F) -> &mut Self
+    where
+        F: Fn(&RequestInfo, &Response) + Send + Sync + 'static,
+    {
+        self.response_hooks.push(Box::new(hook));
+        self
+    }
+
+    /// Adds a hook that is called once the server has completely handled a
+    /// request - i.e., once the body of the response has been written, or
+    /// dropped (e.g. if the client disconnected).  The hook is given a
+    /// [`Completion`], containing information on the request, the status of
+    /// the response, the number of bytes written, and how long it all took.
+    ///
+    /// # Examples
+    /// ```rust
+    /// # use under::*;
+    /// use std::sync::{Arc, Mutex};
+    /// # #[tokio::main] async fn main() -> Result<(), anyhow::Error> {
+    /// let sizes = Arc::new(Mutex::new(vec![]));
+    /// let recorded = sizes.clone();
+    /// let mut http = under::http();
+    /// http.on_request_complete(move |completion| {
+    ///     recorded.lock().unwrap().push(completion.bytes());
+    /// });
+    /// http.at("/").get(under::endpoints::simple(|| Response::text("hello")));
+    /// http.prepare();
+    /// let mut response = http.serve(Request::get("/")?).await;
+    /// response.data(512).into_text().await?;
+    /// assert_eq!(*sizes.lock().unwrap(), vec![5]);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn on_request_complete<F>(&mut self, hook: F) -> &mut Self
+    where
+        F: Fn(&Completion) + Send + Sync + 'static,
+    {
+        // The bodies of responses that are still alive share the hooks; they
+        // keep the hooks they were served with, and only the router's copy
+        // gets the new one.
+        Arc::make_mut(&mut self.complete_hooks).push(Arc::new(hook));
+        self
+    }
+
+    /// Enables (or disables) profiling for every request handled by the
+    /// router.  When enabled, a [`crate::middleware::Timings`] extension is
+    /// inserted into each request, and each middleware (and the endpoint)
+    /// records how long it took into it.  The timings are then placed into
+    /// the extensions of the resulting response.  This is disabled by
+    /// default, as it does incur a (small) cost on every request.
+    ///
+    /// # Examples
+    /// ```rust
+    /// # use under::*;
+    /// use under::middleware::Timings;
+    /// # #[tokio::main] async fn main() -> Result<(), anyhow::Error> {
+    /// let mut http = under::http();
+    /// http.profile(true);
+    /// http.at("/").get(under::endpoints::simple(Response::empty_204));
+    /// http.prepare();
+    /// let response = http.handle(Request::get("/")?).await?;
+    /// assert!(response.ext::<Timings>().is_some());
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn profile(&mut self, enabled: bool) -> &mut Self {
+        self.profile = enabled;
+        self
+    }
+
+    /// Whether or not the responses generated for rejected requests (i.e.
+    /// when an endpoint returns a [`crate::json::JsonRejection`] as its error)
+    /// include the details of why the request was rejected, such as the
+    /// path, line, and column of a deserialization error.  This defaults to
+    /// `true`; you may want to disable it in production, so as not to leak
+    /// the internal structure of your types.
+    ///
+    /// # Examples
+    /// ```rust
+    /// # use under::*;
+    /// use under::json::Json;
+    /// # #[tokio::main] async fn main() -> Result<(), anyhow::Error> {
+    /// let mut http = under::http();
+    /// http.expose_error_details(false);
+    /// http.at("/").post(|mut request: Request| async move {
+    ///     let value: u32 = request.require_json(512).await?;
+    ///     Ok::<_, anyhow::Error>(Json(value))
+    /// });
+    /// http.prepare();
+    /// let request = Request::post("/")?
+    ///     .with_header("Content-Type", "application/json")?
+    ///     .with_body("\"hello\"");
+    /// let mut response = http.handle(request).await?;
+    /// assert_eq!(response.status(), http::StatusCode::UNPROCESSABLE_ENTITY);
+    /// let body = response.data(512).into_text().await?;
+    /// assert_eq!(body, r#"{"error":"Unprocessable Entity"}"#);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn expose_error_details(&mut self, expose: bool) -> &mut Self {
+        self.expose_error_details = expose;
+        self
+    }
+
+    /// Seeds the random number generator of the router.  Every request
+    /// handled by the router is given its own generator (see
+    /// [`Request::rng`]), which is seeded from the router's; so, once seeded,
+    /// the same sequence of requests produces the same random values.  By
+    /// default, the router is seeded from the operating system's entropy.
+    ///
+    /// # Examples
+    /// ```rust
+    /// # use under::*;
+    /// # #[tokio::main] async fn main() -> Result<(), anyhow::Error> {
+    /// let mut http = under::http();
+    /// http.at("/").get(|request: Request| async move {
+    ///     Response::text(request.rng().unwrap().token(8))
+    /// });
+    /// http.prepare();
+    /// http.seed_rng(42);
+    /// let first = http.handle(Request::get("/")?).await?.data(512).into_text().await?;
+    /// http.seed_rng(42);
+    /// let second = http.handle(Request::get("/")?).await?.data(512).into_text().await?;
+    /// assert_eq!(first, second);
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[cfg(feature = "rng")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "rng")))]
+    pub fn seed_rng(&mut self, seed: u64) -> &mut Self {
+        self.rng = crate::Rng::from_seed(seed);
+        self
+    }
+
+    /// Whether or not the server strips the body of responses to `HEAD`
+    /// requests.  A response to a `HEAD` request must not have a body; so,
+    /// by default, if an endpoint responds to one with a body anyway, the
+    /// body is dropped (while keeping the `Content-Length` it would have
+    /// had).  This allows `HEAD` requests to be routed to the same endpoint
+    /// as `GET` requests.  Like the lifecycle hooks, this only applies to
+    /// requests handled by the server (see [`Router::serve`]).
+    ///
+    /// # Examples
+    /// ```rust
+    /// # use under::*;
+    /// # #[tokio::main] async fn main() -> Result<(), anyhow::Error> {
+    /// let mut http = under::http();
+    /// http.at("/").all(under::endpoints::simple(|| Response::text("hello")));
+    /// http.prepare();
+    /// let mut response = http.serve(Request::head("/")?).await;
+    /// assert_eq!(response.header(http::header::CONTENT_LENGTH).unwrap(), "5");
+    /// assert_eq!(response.data(512).into_text().await?, "");
+    /// http.strip_head_body(false);
+    /// let mut response = http.serve(Request::head("/")?).await;
+    /// assert_eq!(response.data(512).into_text().await?, "hello");
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn strip_head_body(&mut self, strip: bool) -> &mut Self {
+        self.strip_head_body = strip;
+        self
+    }
+
+    /// Whether or not the server accepts requests that expect a
+    /// `100 Continue` (see [`Request::expects_continue`]).  When accepted
+    /// (the default), the interim `100 Continue` response is sent
+    /// automatically once the endpoint starts reading the body of the
+    /// request; so an endpoint can gatekeep a large upload (e.g. by checking
+    /// authorization) simply by responding before reading the body, and the
+    /// client never sends the payload.  When not accepted, such requests are
+    /// rejected with an empty 417 before they are routed.
+    ///
+    /// Requests with any other expectation are always rejected with a 417,
+    /// as they cannot be met.  Like the lifecycle hooks, this only applies to
+    /// requests handled by the server (see [`Router::serve`]).
+    ///
+    /// # Examples
+    /// ```rust
+    /// # use under::*;
+    /// # #[tokio::main] async fn main() -> Result<(), anyhow::Error> {
+    /// let mut http = under::http();
+    /// http.at("/upload").post(under::endpoints::simple(Response::empty_204));
+    /// http.expect_continue(false);
+    /// http.prepare();
+    /// let request = Request::post("/upload")?.with_header("Expect", "100-continue")?;
+    /// let response = http.serve(request).await;
+    /// assert_eq!(response.status(), http::StatusCode::EXPECTATION_FAILED);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn expect_continue(&mut self, accept: bool) -> &mut Self {
+        self.expect_continue = accept;
+        self
+    }
+
+    /// Sets the default timeout for reading the body of each request handled
+    /// by the router (see [`crate::DataStream::with_timeout`]).  If reading
+    /// the body times out, and the resulting error is returned from the
+    /// endpoint, the router responds with an empty 408.  By default, there
+    /// is no timeout.
+    ///
+    /// # Examples
+    /// ```rust
+    /// # use under::*;
+    /// use std::time::Duration;
+    /// # #[tokio::main] async fn main() -> Result<(), anyhow::Error> {
+    /// let mut http = under::http();
+    /// http.body_timeout(Duration::from_millis(10));
+    /// http.at("/").post(|mut request: Request| async move {
+    ///     let body = request.data(512).into_text().await?;
+    ///     Ok::<_, anyhow::Error>(Response::text(body))
+    /// });
+    /// http.prepare();
+    /// let (_sender, body) = Body::channel();
+    /// let response = http.handle(Request::post("/")?.with_body(body)).await?;
+    /// assert_eq!(response.status(), http::StatusCode::REQUEST_TIMEOUT);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn body_timeout(&mut self, timeout: std::time::Duration) -> &mut Self {
+        self.body_timeout = Some(timeout);
+        self
+    }
+
+    /// Sets the maximum amount of time that writing the response to a
+    /// connection may stall for, before the connection is closed.  A write
+    /// stalls when the client is not reading the response; so this keeps a
+    /// slow (or malicious) client, reading a byte at a time, from holding a
+    /// connection - and the resources of the handler producing its response -
+    /// forever.  Any progress resets the timeout, so large responses to
+    /// clients that are reading them are unaffected.  By default, there is no
+    /// timeout.
+    ///
+    /// This only applies when listening (see [`Router::listen`]).
+    ///
+    /// # Examples
+    /// ```rust
+    /// # use under::*;
+    /// use std::time::Duration;
+    /// let mut http = under::http();
+    /// http.write_timeout(Duration::from_secs(30));
+    /// ```
+    pub fn write_timeout(&mut self, timeout: std::time::Duration) -> &mut Self {
+        self.write_timeout = Some(timeout);
+        self
+    }
+
+    /// Sets the maximum amount of time that a connection may wait for the
+    /// next request - i.e., how long a kept-alive connection may stay idle,
+    /// and how long a client may take to send the headers of a request -
+    /// before the connection is closed.  By default, idle connections are
+    /// kept open until the client closes them.
+    ///
+    /// This only applies to HTTP/1 connections, when listening (see
+    /// [`Router::listen`]).
+    ///
+    /// # Examples
+    /// ```rust
+    /// # use under::*;
+    /// use std::time::Duration;
+    /// let mut http = under::http();
+    /// http.idle_timeout(Duration::from_secs(60));
+    /// ```
+    pub fn idle_timeout(&mut self, timeout: std::time::Duration) -> &mut Self {
+        self.idle_timeout = Some(timeout);
+        self
+    }
+
+    /// Sets the maximum number of requests that may be made over a single
+    /// connection.  The response to the last request has a
+    /// `Connection: close` header, and the connection is closed once it has
+    /// been sent; the client then has to open a new connection.  This keeps
+    /// long-lived connections from pinning a client to a single server
+    /// (e.g. behind a load balancer).  By default, there is no limit.
+    ///
+    /// This only applies to HTTP/1 connections, when listening (see
+    /// [`Router::listen`]).
+    ///
+    /// # Examples
+    /// ```rust
+    /// # use under::*;
+    /// let mut http = under::http();
+    /// http.max_requests_per_connection(1000);
+    /// ```
+    pub fn max_requests_per_connection(&mut self, requests: usize) -> &mut Self {
+        self.max_requests_per_connection = Some(requests);
+        self
+    }
+
+    /// Returns a gauge of the connections that are currently open, while
+    /// listening (see [`ConnectionGauge`]).  This must be retrieved before
+    /// calling [`Router::listen`], as that consumes the router.
+    pub fn connections(&self) -> ConnectionGauge {
+        self.connections.clone()
+    }
+
+    /// Returns the statistics of the requests and connections the router is
+    /// handling (see [`RouterStats`]).  Like [`Router::connections`], this
+    /// must be retrieved before calling [`Router::listen`].
+    pub fn stats(&self) -> RouterStats {
+        self.stats.clone()
+    }
+
+    /// Drains the router, without shutting it down: this returns a future
+    /// that marks the router as draining (see [`RouterStats::is_draining`]),
+    /// and then resolves once no requests are in flight, or once the
+    /// timeout passes, to whether the requests finished in time.  While
+    /// draining, the router keeps handling requests, but closes HTTP/1
+    /// connections after each response, so that clients move to other
+    /// instances.
+    ///
+    /// This is meant for orchestration hooks that run before the server is
+    /// told to shut down (e.g. a Kubernetes `preStop` hook), so that the
+    /// server is taken out of rotation, and has finished what it was doing,
+    /// by the time it is.  As the router is consumed by [`Router::listen`],
+    /// drain it through its [`Router::stats`] instead, once it is listening
+    /// (see [`RouterStats::drain`]); the future can also be created
+    /// beforehand, as nothing happens until it is first polled.
+    ///
+    /// # Examples
+    /// ```rust
+    /// # use under::*;
+    /// use std::time::Duration;
+    /// # #[tokio::main] async fn main() -> Result<(), anyhow::Error> {
+    /// let mut http = under::http();
+    /// let stats = http.stats();
+    /// http.at("/slow").get(|_| async {
+    ///     tokio::time::sleep(Duration::from_millis(50)).await;
+    ///     Response::empty_204()
+    /// });
+    /// http.prepare();
+    /// let http = std::sync::Arc::new(http);
+    /// let request = tokio::spawn({
+    ///     let http = http.clone();
+    ///     async move { http.serve(Request::get("/slow").unwrap()).await }
+    /// });
+    /// tokio::time::sleep(Duration::from_millis(10)).await;
+    /// assert_eq!(stats.in_flight(), 1);
+    /// assert!(http.drain(Duration::from_secs(5)).await);
+    /// assert!(stats.is_draining());
+    /// assert_eq!(stats.in_flight(), 0);
+    /// assert_eq!(request.await?.status(), http::StatusCode::NO_CONTENT);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn drain(
+        &self,
+        timeout: std::time::Duration,
+    ) -> impl std::future::Future<Output = bool> + Send {
+        self.stats.drain(timeout)
     }
 
-    pub(crate) fn routes(&self) -> &[Arc<Route>] {
-        &self.routes[..]
+    /// Runs the server on the given number of shards, instead of on the
+    /// runtime that [`Router::listen`] is called from.  Each shard is a
+    /// thread with its own single-threaded runtime, which accepts
+    /// connections on its own socket (bound with `SO_REUSEPORT`, where that
+    /// is available), and handles them to completion; connections, and the
+    /// tasks handling them, never move between threads.  On large machines,
+    /// this can make better use of the CPU caches than a single
+    /// multi-threaded runtime.
+    ///
+    /// The runtime that [`Router::listen`] is called from only waits for the
+    /// shards to stop, so it may as well be single-threaded.  Every shard
+    /// stops when the router is terminated (see
+    /// [`Router::termination_signal`]).  By default, the server is not
+    /// sharded.
+    ///
+    /// # Panics
+    /// Panics if `shards` is zero.
+    ///
+    /// # Examples
+    /// ```rust,no_run
+    /// # use under::*;
+    /// # #[tokio::main(flavor = "current_thread")]
+    /// # async fn main() -> Result<(), anyhow::Error> {
+    /// let mut http = under::http();
+    /// http.at("/").get(|_| async { Response::text("hello, world!") });
+    /// let shards = std::thread::available_parallelism().map_or(1, |n| n.get());
+    /// http.shards(shards);
+    /// http.listen("0.0.0.0:8080").await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn shards(&mut self, shards: usize) -> &mut Self {
+        assert!(shards > 0, "a server cannot run on zero shards");
+        self.shards = Some(shards);
+        self
     }
 
-    /// Creates a [`Path`] at the provided prefix.  See [`Path::at`] for more.
-    pub fn at<P: AsRef<str>>(&mut self, prefix: P) -> Path<'_> {
-        Path::new(join_paths("", prefix.as_ref()), &mut self.routes)
+    /// Sets how the path of each request is decoded before it is routed, and
+    /// before its fragments are extracted (see [`PathDecoding`]).  This
+    /// decides how percent-encoded sequences, encoded slashes (`%2F`), `+`,
+    /// and sequences that are not valid UTF-8 are treated; requests that are
+    /// rejected by it receive an empty 400, before any middleware is run.
+    /// By default, the path is used as it was received, so fragments are
+    /// still percent-encoded.
+    ///
+    /// # Examples
+    /// ```rust
+    /// # use under::*;
+    /// # #[tokio::main] async fn main() -> Result<(), anyhow::Error> {
+    /// let mut http = under::http();
+    /// http.at("/users/{name}").get(|request: Request| async move {
+    ///     Response::text(request.fragment_str("name").unwrap().to_owned())
+    /// });
+    /// http.path_decoding(PathDecoding::strict());
+    /// http.prepare();
+    /// let mut response = http.handle(Request::get("/users/j%C3%B6rg%20b")?).await?;
+    /// assert_eq!(response.data(512).into_text().await?, "jörg b");
+    /// let response = http.handle(Request::get("/users/a%2Fb")?).await?;
+    /// assert_eq!(response.status(), http::StatusCode::BAD_REQUEST);
+    ///
+    /// http.path_decoding(PathDecoding::raw());
+    /// let mut response = http.handle(Request::get("/users/a%2Fb")?).await?;
+    /// assert_eq!(response.data(512).into_text().await?, "a%2Fb");
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn path_decoding(&mut self, decoding: PathDecoding) -> &mut Self {
+        self.path_decoding = decoding;
+        self
     }
 
-    /// Creates a [`Path`] at the provided prefix, and executes the provided
-    /// closure with it.  See [`Path::under`] for more.
-    pub fn under<P: AsRef<str>, F: FnOnce(&mut Path<'_>)>(
-        &mut self,
-        prefix: P,
-        build: F,
-    ) -> &mut Self {
-        let mut path = Path::new(join_paths("", prefix.as_ref()), &mut self.routes);
-        build(&mut path);
+    /// Sets the longest path (in bytes, before it is decoded) that the
+    /// router will route.  Requests with a longer path are answered with a
+    /// `414 URI Too Long`, without being matched against any route.  Matching
+    /// is linear in the length of the path, but a route with many fragments
+    /// (or repeated fragments) still has to capture each of them, so this
+    /// bounds the work a single pathological path can cause.  By default,
+    /// there is no limit beyond that of the server.
+    ///
+    /// # Examples
+    /// ```rust
+    /// # use under::*;
+    /// # #[tokio::main] async fn main() -> Result<(), anyhow::Error> {
+    /// let mut http = under::http();
+    /// http.at("/files/{path:path}").get(under::endpoints::simple(Response::empty_204));
+    /// http.max_path_length(64);
+    /// let response = http.handle(Request::get("/files/a/b")?).await?;
+    /// assert_eq!(response.status(), http::StatusCode::NO_CONTENT);
+    /// let path = format!("/files/{}", "a/".repeat(64));
+    /// let response = http.handle(Request::get(&path[..])?).await?;
+    /// assert_eq!(response.status(), http::StatusCode::URI_TOO_LONG);
+    /// assert_eq!(http.route_label(&http::Method::GET, &path), Router::UNMATCHED);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn max_path_length(&mut self, length: usize) -> &mut Self {
+        self.max_path_length = Some(length);
         self
     }
 
-    /// Appends middleware to the router.  Each middleware is executed in the
-    /// order that it is appended to the router (i.e., the first middleware
-    /// inserted executes first).
+    /// Whether or not the given (raw) path is longer than the router routes
+    /// (see [`Router::max_path_length`]).
+    fn path_too_long(&self, path: &str) -> bool {
+        self.max_path_length.is_some_and(|max| path.len() > max)
+    }
+
+    /// Enables (or disables) diagnostics for unmatched requests.  When
+    /// enabled, a request that does not match any route receives a 404
+    /// listing the routes that nearly matched it - those whose path matched,
+    /// but whose method (or guards, or fragment types) did not, and those
+    /// that matched the most segments of the path, along with where they
+    /// stopped matching.  The listing is JSON if the request accepts
+    /// `application/json` (and the `json` feature is enabled), or plain text
+    /// otherwise.  This takes the place of the fallback endpoint (see
+    /// [`Router::fallback`]).
+    ///
+    /// This is meant for development; it exposes the routes of the router to
+    /// anyone who asks.  It is disabled by default.
     ///
     /// # Examples
     /// ```rust
+    /// # use under::*;
+    /// # #[tokio::main] async fn main() -> Result<(), anyhow::Error> {
     /// let mut http = under::http();
-    /// http.with(under::middleware::TraceMiddleware::new())
-    ///     .with(under::middleware::StateMiddleware::new(123u32));
+    /// http.at("/users/{id:uint}").get(under::endpoints::simple(Response::empty_204));
+    /// http.debug_unmatched(true);
+    /// let mut response = http.handle(Request::post("/users/1")?).await?;
+    /// assert_eq!(response.status(), http::StatusCode::NOT_FOUND);
+    /// let body = response.data(512).into_text().await?;
+    /// assert!(body.contains("GET /users/{id:uint}: the method does not match"));
+    /// let mut response = http.handle(Request::get("/users/me")?).await?;
+    /// let body = response.data(512).into_text().await?;
+    /// assert!(body.contains(r#"matched "/users/", then expected "{id:uint}" at "me""#));
+    /// # Ok(())
+    /// # }
     /// ```
-    pub fn with<M: Middleware>(&mut self, middleware: M) -> &mut Self {
-        self.middleware.push(Box::pin(middleware));
+    pub fn debug_unmatched(&mut self, enabled: bool) -> &mut Self {
+        self.debug_unmatched = enabled;
         self
     }
 
-    /// Sets a fallback endpoint.  If there exists no other endpoint in the
-    /// router that could potentially respond to the request, it will first
-    /// attempt to execute this fallback endpoint, before instead returning
-    /// an empty 500 error.
+    /// Sets which media types the router lists as accepted, when an
+    /// extractor rejects the content type of a request, and the router
+    /// responds with a 415 (see [`MediaTypeHint`]).  This is
+    /// [`MediaTypeHint::Auto`] by default.
     ///
     /// # Examples
     /// ```rust
     /// # use under::*;
     /// # #[tokio::main] async fn main() -> Result<(), anyhow::Error> {
+    /// #[derive(serde::Deserialize)]
+    /// struct User { name: String }
+    ///
     /// let mut http = under::http();
-    /// http.at("/foo").get(under::endpoints::simple(Response::empty_204));
-    /// http.fallback(under::endpoints::simple(Response::empty_404));
+    /// http.at("/users").post(|mut request: Request| async move {
+    ///     let user: User = request.as_sniff(512).await?;
+    ///     Ok::<_, anyhow::Error>(Response::text(user.name))
+    /// });
     /// http.prepare();
-    /// let response = http.handle(Request::get("/foo")?).await?;
-    /// assert_eq!(response.status(), http::StatusCode::NO_CONTENT);
-    /// let response = http.handle(Request::get("/bar")?).await?;
-    /// assert_eq!(response.status(), http::StatusCode::NOT_FOUND);
+    /// let request = Request::post("/users")?
+    ///     .with_header("Content-Type", "text/plain")?
+    ///     .with_body("alice");
+    /// let response = http.handle(request).await?;
+    /// assert_eq!(response.status(), http::StatusCode::UNSUPPORTED_MEDIA_TYPE);
+    /// assert!(response.header("Accept-Post").unwrap().to_str()?.contains("application/json"));
+    ///
+    /// http.media_type_hint(MediaTypeHint::Types(vec!["application/json".into()]));
+    /// let request = Request::post("/users")?.with_body("alice");
+    /// let response = http.handle(request).await?;
+    /// assert_eq!(response.header("Accept-Post").unwrap(), "application/json");
     /// # Ok(())
     /// # }
-    pub fn fallback<E: Endpoint>(&mut self, endpoint: E) -> &mut Self {
-        self.fallback = Some(Box::pin(endpoint));
+    /// ```
+    pub fn media_type_hint(&mut self, hint: MediaTypeHint) -> &mut Self {
+        self.media_type_hint = hint;
+        self
+    }
+
+    /// Sets the redactor for what the router itself logs about requests:
+    /// the URI of each request, when it is routed (at the `trace` level),
+    /// and alongside the error, when handling it fails (see
+    /// [`Router::serve`]).  This is [`crate::redact::Redactor::default`] by
+    /// default.  The middleware that log requests (e.g.
+    /// [`crate::middleware::AccessLog`]) have their own redactors.
+    ///
+    /// The [`Router::on_error`] hooks are given the request as it is, so
+    /// that they can inspect it; if they report it elsewhere, they should
+    /// redact it themselves, e.g. with the same redactor.
+    ///
+    /// # Examples
+    /// ```rust
+    /// # use under::*;
+    /// use under::redact::Redactor;
+    /// let mut http = under::http();
+    /// http.redactor(Redactor::default().with_query_param("signature"));
+    /// ```
+    pub fn redactor(&mut self, redactor: crate::redact::Redactor) -> &mut Self {
+        self.redactor = redactor;
+        self
+    }
+
+    /// Sets how the query strings of the requests the router handles are
+    /// parsed into types, by [`Request::query`] and
+    /// [`Request::require_query`].  By default, this is
+    /// [`crate::QueryConfig::default`].  See [`crate::QueryConfig`] for an
+    /// example.
+    #[cfg(feature = "serde")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "serde")))]
+    pub fn query_config(&mut self, config: crate::QueryConfig) -> &mut Self {
+        self.query_config = Some(config);
         self
     }
 
@@ -165,74 +1271,421 @@ impl Router {
     /// calling [`crate::Endpoint::apply`].
     ///
     /// # Errors
-    /// This will error if any middleware or endpoint errors.  Note that this
-    /// does not error if the router was not prepared before calling this
-    /// method.
+    /// This will error if any middleware or endpoint errors.
     pub async fn handle(&self, request: Request) -> Result<Response, anyhow::Error> {
         Pin::new(self).apply(request).await
     }
 
-    pub(crate) fn lookup(&self, path: &str, method: &http::Method) -> Option<Arc<Route>> {
-        self.regex
+    /// Handles an HTTP event given to an AWS Lambda function, returning the
+    /// response to it.  See [`crate::lambda`] for more.
+    ///
+    /// # Errors
+    /// This will error if the event is not an HTTP event, if it could not be
+    /// converted into a request, or if any middleware or endpoint errors.
+    #[cfg(feature = "lambda")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "lambda")))]
+    pub async fn handle_lambda(
+        &self,
+        event: serde_json::Value,
+    ) -> Result<serde_json::Value, anyhow::Error> {
+        let event: crate::lambda::LambdaRequest = serde_json::from_value(event)?;
+        let format = event.format();
+        let response = self.handle(event.into_request()?).await?;
+        let response = crate::lambda::LambdaResponse::from_response(response, format).await?;
+        Ok(serde_json::to_value(response)?)
+    }
+
+    /// The label [`Router::route_label`] gives to requests that do not match
+    /// any route.
+    pub const UNMATCHED: &'static str = "unmatched";
+
+    /// Prints the routes of the router to standard output, as a table (see
+    /// the [`std::fmt::Display`] implementation of the router).
+    ///
+    /// # Examples
+    /// ```rust
+    /// # use under::*;
+    /// let mut http = under::http();
+    /// http.with(under::middleware::TraceMiddleware::new());
+    /// http.at("/").get(under::endpoints::simple(Response::empty_204));
+    /// http.at("/users/{id:uint}").all(under::endpoints::simple(Response::empty_404));
+    /// http.print_routes();
+    /// let table = http.to_string();
+    /// let mut lines = table.lines();
+    /// assert!(lines.next().unwrap().starts_with("METHOD  PATTERN           ENDPOINT"));
+    /// assert!(lines.next().unwrap().starts_with("GET     /                 "));
+    /// assert!(lines.next().unwrap().starts_with("(all)   /users/{id:uint}  "));
+    /// assert!(table.lines().skip(1).all(|line| line.ends_with("  1")));
+    /// ```
+    pub fn print_routes(&self) {
+        print!("{self}");
+    }
+
+    /// Maps the given method and path to the pattern of the route it is
+    /// routed to (e.g. `/users/{id}`), or to [`Router::UNMATCHED`] if it does
+    /// not match any route.  The path is decoded as it would be for routing
+    /// (see [`Router::path_decoding`]).
+    ///
+    /// This is meant for labelling metrics: labelling them with the path of
+    /// the request would create a new label for every distinct path (e.g.
+    /// every user ID, or every path a scanner tries), whereas there are only
+    /// as many patterns as there are routes.  From within a middleware or
+    /// endpoint, the pattern is also available from
+    /// [`Request::matched_path`].  The guards of a route (see
+    /// [`Group::guard`]) are not checked, as there is no request to check
+    /// them against.
+    ///
+    /// # Examples
+    /// ```rust
+    /// # use under::*;
+    /// let mut http = under::http();
+    /// http.at("/users/{id:uint}").get(under::endpoints::simple(Response::empty_204));
+    /// http.prepare();
+    /// assert_eq!(http.route_label(&http::Method::GET, "/users/1"), "/users/{id:uint}");
+    /// assert_eq!(http.route_label(&http::Method::GET, "/users/2"), "/users/{id:uint}");
+    /// assert_eq!(http.route_label(&http::Method::POST, "/users/1"), Router::UNMATCHED);
+    /// assert_eq!(http.route_label(&http::Method::GET, "/wp-admin"), Router::UNMATCHED);
+    /// ```
+    #[must_use]
+    pub fn route_label(&self, method: &http::Method, path: &str) -> &str {
+        if self.path_too_long(path) {
+            return Self::UNMATCHED;
+        }
+        self.path_decoding
+            .apply(path)
+            .and_then(|path| self.lookup(&path, method))
+            .map_or(Self::UNMATCHED, |route| &route.path[..])
+    }
+
+    pub(crate) fn lookup(&self, path: &str, method: &http::Method) -> Option<&Arc<Route>> {
+        self.find(path, |r| r.matches(method))
+    }
+
+    /// Finds the last route that matches the given path, and is accepted by
+    /// the given predicate.
+    fn find<F: Fn(&Route) -> bool>(&self, path: &str, accept: F) -> Option<&Arc<Route>> {
+        let regex = self.regex().ok()?;
+        regex
             .matches(path)
             .into_iter()
             .map(|i| &self.routes[i])
-            .filter(|r| r.matches(method))
-            .next_back()
-            .cloned()
+            .rfind(|r| accept(r) && r.pattern.accepts(path))
     }
 
-    fn fallback_endpoint(&self) -> Option<Pin<&dyn Endpoint>> {
-        self.fallback.as_ref().map(Pin::as_ref)
+    /// The methods of the routes that match the path, if none of them
+    /// accept the given method.  If a route for any method (or for the
+    /// given method, whose guards rejected the request) matches the path,
+    /// the method is allowed, and this returns `None`.
+    fn allowed_methods(&self, path: &str, method: &http::Method) -> Option<Vec<http::Method>> {
+        let regex = self.regex().ok()?;
+        let mut allowed: Vec<http::Method> = vec![];
+        for route in regex.matches(path).into_iter().map(|i| &self.routes[i]) {
+            if !route.pattern.accepts(path) {
+                continue;
+            }
+            match route.method() {
+                Some(other) if other != method => {
+                    if !allowed.contains(other) {
+                        allowed.push(other.clone());
+                    }
+                }
+                _ => return None,
+            }
+        }
+        (!allowed.is_empty()).then_some(allowed)
     }
-}
 
-#[async_trait]
-impl crate::Endpoint for Router {
-    async fn apply(self: Pin<&Self>, mut request: Request) -> Result<Response, anyhow::Error> {
-        let route = self.lookup(request.uri().path(), request.method());
-        if let Some(route) = route.clone() {
-            // This should most always be a `Some`, because the route's path
-            // would 100% match the uri's path.
-            if let Some(fragment) =
-                crate::request::fragment::Fragment::new(request.uri().path(), &route)
-            {
-                request.extensions_mut().insert(fragment);
+    /// Converts errors that represent a rejected request into a response,
+    /// passing every other error through.
+    fn reject(
+        &self,
+        error: anyhow::Error,
+        method: &http::Method,
+    ) -> Result<Response, anyhow::Error> {
+        // Errors are only looked at by reference, so that those that are
+        // passed through keep their context, and their backtrace.
+        #[cfg(feature = "json")]
+        if let Some(rejection) = error.downcast_ref::<crate::json::JsonRejection>() {
+            let mut response = rejection.to_response(self.expose_error_details);
+            if let crate::json::JsonRejection::UnsupportedMediaType(_) = rejection {
+                self.media_type_hint
+                    .apply(&mut response, method, &["application/json"]);
+            }
+            return Ok(response);
+        }
+
+        #[cfg(feature = "json")]
+        if let Some(validation) = error.downcast_ref::<crate::validation::Validation>() {
+            return Ok(validation.to_response());
+        }
+
+        #[cfg(all(feature = "json", feature = "from_form"))]
+        if let Some(crate::UnderError::FormDeserialization(error)) =
+            error.downcast_ref::<crate::UnderError>()
+        {
+            return Ok(crate::validation::Validation::from(error).to_response());
+        }
+
+        if let Some(denied) = error.downcast_ref::<crate::authz::Denied>() {
+            return Ok(denied.to_response());
+        }
+
+        match error.downcast_ref::<crate::UnderError>() {
+            Some(under @ crate::UnderError::UnsupportedMediaType(_, accepted)) => {
+                let mut response = under.to_response();
+                self.media_type_hint.apply(&mut response, method, accepted);
+                Ok(response)
+            }
+            Some(under) if under.status() != http::StatusCode::INTERNAL_SERVER_ERROR => {
+                Ok(under.to_response())
+            }
+            _ => Err(error),
+        }
+    }
+
+    /// Routes the request, and runs it through the middleware stack and its
+    /// endpoint.  Every response or error of the router comes through here,
+    /// so that [`Router::apply`] can run its hooks on all of them.
+    async fn dispatch(&self, mut request: Request) -> Result<Response, anyhow::Error> {
+        if let Err(error) = self.regex() {
+            return Err(crate::UnderError::InvalidRoutes(error.clone()).into());
+        }
+        if self.path_too_long(request.uri().path()) {
+            return Ok(Response::empty_status(http::StatusCode::URI_TOO_LONG));
+        }
+        let Some(path) = self
+            .path_decoding
+            .apply(request.uri().path())
+            .map(std::borrow::Cow::into_owned)
+        else {
+            return Ok(Response::empty_400());
+        };
+        // The route is borrowed from the router, rather than cloned, so that
+        // the only clone of it is the one that the fragments hold.
+        let route = self.find(&path, |r| r.matches(request.method()) && r.guards(&request));
+        let unmatched = (route.is_none() && self.debug_unmatched)
+            .then(|| diagnostics::Unmatched::new(&self.routes, &path));
+        let not_allowed = (route.is_none() && self.fallback.is_none())
+            .then(|| self.allowed_methods(&path, request.method()))
+            .flatten()
+            .map(NotAllowed);
+        if let Some(route) = route {
+            if !route.skips().is_empty() {
+                request.skip_types(route.skips());
             }
-            request.extensions_mut().insert(route);
+            let decoded = self.path_decoding.decodes_percent();
+            let fragment = crate::request::fragment::Fragment::new(path, route.clone(), decoded);
+            request.extensions_mut().insert(fragment);
         }
 
         let endpoint = {
-            let route_endpoint = || route.as_ref().map(|e| e.endpoint().as_ref());
+            let route_endpoint = || route.map(|e| e.endpoint().as_ref());
+            let unmatched_endpoint = || {
+                let unmatched: Pin<&diagnostics::Unmatched> = Pin::new(unmatched.as_ref()?);
+                Some(unmatched as Pin<&dyn Endpoint>)
+            };
+            let not_allowed_endpoint = || {
+                let not_allowed: Pin<&NotAllowed> = Pin::new(not_allowed.as_ref()?);
+                Some(not_allowed as Pin<&dyn Endpoint>)
+            };
             let fallback_endpoint = || self.fallback_endpoint();
             route_endpoint()
+                .or_else(unmatched_endpoint)
+                .or_else(not_allowed_endpoint)
                 .or_else(fallback_endpoint)
                 .unwrap_or_else(default_endpoint)
         };
-        log::trace!("{} {} --> {:?}", request.method(), request.uri(), endpoint);
+        log::trace!(
+            "{} {} --> {:?}",
+            request.method(),
+            self.redactor.redact_uri(&request.uri().to_string()),
+            endpoint
+        );
+        #[cfg(feature = "rng")]
+        if request.ext::<crate::Rng>().is_none() {
+            request.set_ext(self.rng.fork());
+        }
+        if let Some(timeout) = self.body_timeout {
+            request.set_ext(crate::request::BodyTimeout(timeout));
+        }
+        #[cfg(feature = "serde")]
+        if let Some(config) = self.query_config {
+            request.set_ext(config);
+        }
         let next = crate::middleware::Next::new(&self.middleware[..], endpoint);
         next.apply(request).await
     }
+
+    /// Runs the [`Router::map_response`] hooks on a response of the router.
+    fn map_response_with(&self, info: Option<&RequestInfo>, response: Response) -> Response {
+        match info {
+            Some(info) => self
+                .response_maps
+                .iter()
+                .fold(response, |response, map| map(info, response)),
+            None => response,
+        }
+    }
+
+    /// Information on the request, if any of the hooks need it.
+    fn hook_info(&self, request: &Request) -> Option<RequestInfo> {
+        if self.response_maps.is_empty() && self.error_hooks.is_empty() {
+            None
+        } else {
+            Some(RequestInfo::from(request))
+        }
+    }
+
+    fn fallback_endpoint(&self) -> Option<Pin<&dyn Endpoint>> {
+        self.fallback.as_ref().map(Pin::as_ref).or_else(|| {
+            let template: Pin<&crate::ResponseTemplate> = Pin::new(self.error_response.as_ref()?);
+            Some(template)
+        })
+    }
+
+    /// The response used when the router errors.
+    pub(crate) fn server_error(&self) -> Response {
+        self.error_response
+            .as_ref()
+            .map_or_else(Response::empty_500, crate::ResponseTemplate::to_response)
+    }
+}
+
+#[async_trait]
+impl crate::Endpoint for Router {
+    async fn apply(self: Pin<&Self>, mut request: Request) -> Result<Response, anyhow::Error> {
+        let timings = if self.profile {
+            let timings = crate::middleware::Timings::default();
+            request.extensions_mut().insert(timings.clone());
+            Some(timings)
+        } else {
+            None
+        };
+        let info = self.hook_info(&request);
+        let method = request.method().clone();
+        // The hooks see every error, including those that are rejected
+        // (turned into a response) here.
+        let mut response = self.dispatch(request).await.or_else(|error| {
+            if let Some(info) = &info {
+                self.error_hooks.iter().for_each(|hook| hook(&error, info));
+            }
+            self.reject(error, &method)
+        })?;
+        if let Some(timings) = timings {
+            response.extensions_mut().insert(timings);
+        }
+        Ok(self.map_response_with(info.as_ref(), response))
+    }
 }
 
+/// Displays the routes of the router as a table, in the order they were
+/// added: the method, pattern, and endpoint of each, and how much middleware
+/// runs for it (both the router's and the route's own).  See
+/// [`Router::print_routes`].
+impl std::fmt::Display for Router {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let rows = self
+            .routes
+            .iter()
+            .map(|route| {
+                [
+                    route
+                        .method()
+                        .map_or("(all)", http::Method::as_str)
+                        .to_owned(),
+                    route.path.clone(),
+                    format!("{:?}", route.endpoint()),
+                    (self.middleware.len() + route.middleware()).to_string(),
+                ]
+            })
+            .collect::<Vec<_>>();
+        let header = ["METHOD", "PATTERN", "ENDPOINT", "MIDDLEWARE"];
+        let mut widths = header.map(str::len);
+        for row in &rows {
+            for (width, cell) in widths.iter_mut().zip(row) {
+                *width = (*width).max(cell.chars().count());
+            }
+        }
+
+        let header = header.map(str::to_owned);
+        for row in std::iter::once(&header).chain(&rows) {
+            let [method, pattern, endpoint, middleware] = row;
+            writeln!(
+                f,
+                "{method:<0$}  {pattern:<1$}  {endpoint:<2$}  {middleware}",
+                widths[0], widths[1], widths[2]
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Shows the middleware stack of the router, in the order it runs, along with
+/// the names of any named middleware (see [`Router::with_named`]).
 impl std::fmt::Debug for Router {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        struct Stack<'a>(&'a Router);
+        impl std::fmt::Debug for Stack<'_> {
+            fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+                let names = self.0.middleware_names.iter();
+                let entries = self.0.middleware.iter().zip(names).map(|(m, name)| {
+                    let m: &dyn Middleware = &**m;
+                    match name {
+                        Some(name) => format!("{name}: {}", crate::middleware::Describe(m)),
+                        None => crate::middleware::Describe(m).to_string(),
+                    }
+                });
+                f.debug_list().entries(entries.map(DisplayDebug)).finish()
+            }
+        }
+
         f.debug_struct("Router")
             .field("regex", &self.regex)
+            .field("middleware", &Stack(self))
             .field("routes", &self.routes)
-            .finish()
+            .finish_non_exhaustive()
+    }
+}
+
+/// Debugs a string without quoting it.
+struct DisplayDebug(String);
+
+impl std::fmt::Debug for DisplayDebug {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+/// Compiles the patterns of the given routes into a set.
+fn compile(routes: &[Arc<Route>]) -> Result<regex::RegexSet, crate::RouterBuildError> {
+    if let Some(error) = routes.iter().find_map(|route| route.error()) {
+        return Err(error.clone());
     }
+    let patterns = routes.iter().map(|route| route.pattern.regex().as_str());
+    regex::RegexSet::new(patterns).map_err(crate::RouterBuildError::Compile)
 }
 
-lazy_static::lazy_static! {
-    static ref DEFAULT_ENDPOINT: crate::endpoints::SyncEndpoint<fn(Request) -> Response> = crate::endpoints::SyncEndpoint::new(|_| Response::empty_500());
-    static ref DEFAULT_ENDPOINT_PIN: Pin<&'static (dyn Endpoint + Unpin + 'static)> = Pin::new(&*DEFAULT_ENDPOINT);
+/// The endpoint used for requests whose path only matches routes for other
+/// methods, when the router has no fallback.  This errors with
+/// [`crate::UnderError::MethodNotAllowed`], which the router turns into a
+/// 405 listing the methods of those routes.
+struct NotAllowed(Vec<http::Method>);
+
+#[async_trait]
+impl Endpoint for NotAllowed {
+    async fn apply(self: Pin<&Self>, _: Request) -> Result<Response, anyhow::Error> {
+        Err(crate::UnderError::MethodNotAllowed(self.0.clone()).into())
+    }
 }
 
+static DEFAULT_ENDPOINT: crate::endpoints::SyncEndpoint<fn(Request) -> Response> =
+    crate::endpoints::SyncEndpoint::new(|_| Response::empty_500());
+
 // 'r can be anything _up to and including_ 'static, and this makes it play
 // nice with unwrap_or_else.
 pub(crate) fn default_endpoint<'r>() -> Pin<&'r dyn Endpoint> {
-    *DEFAULT_ENDPOINT_PIN
+    let endpoint: Pin<&'static crate::endpoints::SyncEndpoint<_>> = Pin::new(&DEFAULT_ENDPOINT);
+    endpoint
 }
 
 // Base *MUST* be either `""` or start with `"/"`.
@@ -328,10 +1781,268 @@ mod test {
         assert!(result.is_none());
     }
 
+    #[test]
+    fn test_invalid_routes() {
+        let mut router = Router::default();
+        router.at("/{id}/{id:uint}").get(simple_endpoint);
+        let error = router.try_prepare().unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "duplicate fragment \"id\" in route:\n    /{id}/{id:uint}\n          ^^^^^^^^^"
+        );
+        assert!(router.lookup("/1/1", &http::Method::GET).is_none());
+    }
+
+    #[test]
+    fn test_fragment_names() {
+        let mut router = Router::default();
+        router.at("/users/{user_id2}").get(simple_endpoint);
+        router.prepare();
+        let route = router.lookup("/users/1", &http::Method::GET).unwrap();
+        let keys = route.pattern.match_keys();
+        assert_eq!(keys[1].as_deref(), Some("user_id2"));
+
+        let mut router = Router::default();
+        router.at("/users/{user-id}").get(simple_endpoint);
+        assert!(matches!(
+            router.try_prepare(),
+            Err(crate::RouterBuildError::InvalidFragment { span, .. }) if span == (7..16)
+        ));
+    }
+
+    #[test]
+    fn test_optional_segments() {
+        let mut router = Router::default();
+        router.at("/users/{id}/{detail?}").get(simple_endpoint);
+        router.at("/sum/{terms:int+2}").get(simple_endpoint);
+        router.at("/files/{parts*}").get(simple_endpoint);
+        router.prepare();
+        let matches = |path| router.lookup(path, &http::Method::GET).map(|r| &r.path[..]);
+        assert_eq!(matches("/users/1"), Some("/users/{id}/{detail?}"));
+        assert_eq!(matches("/users/1/posts"), Some("/users/{id}/{detail?}"));
+        assert_eq!(matches("/users/1/posts/2"), None);
+        assert_eq!(matches("/sum/1/-2/3"), Some("/sum/{terms:int+2}"));
+        assert_eq!(matches("/sum/1"), None);
+        assert_eq!(matches("/sum/1/a"), None);
+        assert_eq!(matches("/files"), Some("/files/{parts*}"));
+        assert_eq!(matches("/files/a/b"), Some("/files/{parts*}"));
+
+        let mut router = Router::default();
+        router.at("/{name:oext?}").get(simple_endpoint);
+        assert!(router.try_prepare().is_err());
+    }
+
+    #[test]
+    fn test_typed_fragments() {
+        let mut router = Router::default();
+        router.at("/small/{id:u8}").get(simple_endpoint);
+        router.at("/on/{when:date}").get(simple_endpoint);
+        router.at("/flags/{flags:bool+}").get(simple_endpoint);
+        router.at("/scale/{by:f64}").get(simple_endpoint);
+        router.prepare();
+        let matches = |path| router.lookup(path, &http::Method::GET).is_some();
+        assert!(matches("/small/255"));
+        assert!(!matches("/small/256"));
+        assert!(matches("/on/2024-02-29"));
+        assert!(!matches("/on/2023-02-29"));
+        assert!(!matches("/on/2023-13-01"));
+        assert!(matches("/flags/true/false"));
+        assert!(!matches("/flags/true/yes"));
+        assert!(matches("/scale/-1.5e3"));
+        assert!(!matches("/scale/1.5.3"));
+    }
+
+    #[test]
+    fn test_pattern_audit() {
+        let mut router = Router::default();
+        let types = [
+            "", ":str", ":int", ":uint", ":u8", ":f64", ":bool", ":date", ":uuid", ":path",
+        ];
+        for kind in types {
+            for modifier in ["", "?", "*", "+", "+2"] {
+                router
+                    .at(format!("/a/{{{kind}{modifier}}}/{{b}}{{c:oext}}"))
+                    .get(simple_endpoint);
+            }
+        }
+        router.prepare();
+        for route in router.route_info() {
+            assert_eq!(route.fragments(), 3, "{}", route.pattern());
+            assert!(route.pattern().starts_with("\\A"));
+            assert!(route.pattern().ends_with("\\z"));
+        }
+
+        let router = simple_router();
+        assert!(router.lookup("/alpha", &http::Method::GET).is_some());
+        assert!(router.lookup("/alpha\n", &http::Method::GET).is_none());
+        assert!(router.lookup("\n/alpha", &http::Method::GET).is_none());
+    }
+
     #[test]
     fn test_correct_method() {
         let router = simple_router();
         let result = router.lookup("/alpha", &http::Method::POST);
         assert!(result.is_none());
     }
+
+    #[tokio::test]
+    async fn test_unrejected_errors_keep_context() {
+        use anyhow::Context;
+        let mut router = Router::default();
+        router.at("/").get(|_| async {
+            let reset = std::io::Error::new(std::io::ErrorKind::ConnectionReset, "reset");
+            Err::<Response, _>(UnderError::ReadBody(reset)).context("loading the user")
+        });
+        router.prepare();
+        let error = router.handle(Request::get("/").unwrap()).await.unwrap_err();
+        let chain = error.chain().map(ToString::to_string).collect::<Vec<_>>();
+        assert_eq!(
+            chain,
+            [
+                "loading the user",
+                "could not read the body of a request or response",
+                "reset"
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_error_hooks_see_rejected_errors() {
+        use std::sync::{Arc, Mutex};
+        let errors = Arc::new(Mutex::new(vec![]));
+        let seen = errors.clone();
+        let mut router = Router::default();
+        router.at("/").get(|_| async {
+            Err::<Response, _>(UnderError::BadRequest(anyhow::anyhow!("no name")))
+        });
+        router.on_error(move |error, info| {
+            seen.lock().unwrap().push(format!("{} {error}", info.uri()));
+        });
+        router.prepare();
+        let response = router.handle(Request::get("/").unwrap()).await.unwrap();
+        assert_eq!(response.status(), http::StatusCode::BAD_REQUEST);
+        assert_eq!(*errors.lock().unwrap(), ["/ the request was malformed"]);
+    }
+
+    #[cfg(all(feature = "serde", feature = "from_form", feature = "multipart"))]
+    #[tokio::test]
+    async fn test_media_type_hints_come_from_the_extractor() {
+        use crate::HttpEntity;
+        type Form = std::collections::HashMap<String, Vec<String>>;
+        let mut router = Router::default();
+        router.at("/form").post(|mut request: Request| async move {
+            request.as_sniff_form::<Form>(512).await?;
+            Ok::<_, anyhow::Error>(Response::empty_204())
+        });
+        router.at("/text").post(|request: Request| async move {
+            request.expect_content_type("text/plain")?;
+            Ok::<_, anyhow::Error>(Response::empty_204())
+        });
+        router.prepare();
+
+        let request = Request::post("/form").unwrap().with_body("alice");
+        let response = router.handle(request).await.unwrap();
+        assert_eq!(response.status(), http::StatusCode::UNSUPPORTED_MEDIA_TYPE);
+        let hint = response.header("Accept-Post").unwrap().to_str().unwrap();
+        assert!(hint.contains("application/x-www-form-urlencoded"));
+        assert!(hint.contains("multipart/form-data"));
+
+        let request = Request::post("/text").unwrap().with_body("alice");
+        let response = router.handle(request).await.unwrap();
+        assert_eq!(response.status(), http::StatusCode::UNSUPPORTED_MEDIA_TYPE);
+        assert!(response.header("Accept-Post").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_method_not_allowed() {
+        use crate::HttpEntity;
+        let mut router = Router::default();
+        router
+            .at("/users")
+            .get(simple_endpoint)
+            .post(simple_endpoint);
+        router.at("/users/{id}").get(simple_endpoint);
+        router.prepare();
+        let response = router.handle(Request::delete("/users").unwrap()).await;
+        let response = response.unwrap();
+        assert_eq!(response.status(), http::StatusCode::METHOD_NOT_ALLOWED);
+        assert_eq!(response.header("Allow").unwrap(), "GET, POST");
+        let response = router.handle(Request::delete("/missing").unwrap()).await;
+        let response = response.unwrap();
+        assert_eq!(response.status(), http::StatusCode::INTERNAL_SERVER_ERROR);
+
+        router.fallback(crate::endpoints::simple(Response::empty_404));
+        let response = router.handle(Request::delete("/users").unwrap()).await;
+        assert_eq!(response.unwrap().status(), http::StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_complete_hooks_added_while_serving() {
+        let mut router = Router::default();
+        router
+            .at("/")
+            .get(|_| async { Ok::<_, anyhow::Error>(Response::text("hello")) });
+        router.on_request_complete(|_| {});
+        router.prepare();
+        let response = router.serve(Request::get("/").unwrap()).await;
+        router.on_request_complete(|_| {});
+        drop(response);
+    }
+
+    #[tokio::test]
+    async fn test_complete_hooks_keep_trailers() {
+        use crate::HttpEntity;
+        let mut router = Router::default();
+        router.at("/").get(|_| async {
+            let (response, mut sender) = Response::channel();
+            tokio::spawn(async move {
+                sender.send_data("hello".into()).await?;
+                let mut trailers = http::HeaderMap::new();
+                trailers.insert("Row-Count", http::HeaderValue::from_static("1"));
+                sender.send_trailers(trailers).await
+            });
+            Ok::<_, anyhow::Error>(response)
+        });
+        let completed = Arc::new(std::sync::Mutex::new(None));
+        let recorded = completed.clone();
+        router.on_request_complete(move |completion| {
+            *recorded.lock().unwrap() = Some((completion.bytes(), completion.is_complete()));
+        });
+        router.prepare();
+        let mut response = router.serve(Request::get("/").unwrap()).await;
+        let (body, trailers) = response.data(512).into_bytes_with_trailers().await.unwrap();
+        assert_eq!(body, b"hello");
+        assert_eq!(trailers.unwrap()["Row-Count"], "1");
+        drop(response);
+        assert_eq!(*completed.lock().unwrap(), Some((5, true)));
+    }
+
+    #[tokio::test]
+    async fn test_response_maps_see_server_errors() {
+        use crate::HttpEntity;
+        let mut router = Router::default();
+        router
+            .at("/")
+            .get(|_| async { Err::<Response, _>(anyhow::anyhow!("oops")) });
+        router.map_response(|_, response: Response| response.with_header("X-Mapped", "1").unwrap());
+        router.prepare();
+        let response = router.serve(Request::get("/").unwrap()).await;
+        assert_eq!(response.status(), http::StatusCode::INTERNAL_SERVER_ERROR);
+        assert_eq!(response.header("X-Mapped").unwrap(), "1");
+    }
+
+    #[tokio::test]
+    async fn test_response_maps_see_rejected_paths() {
+        use crate::HttpEntity;
+        let mut router = Router::default();
+        router.max_path_length(4);
+        router.map_response(|_, response: Response| response.with_header("X-Mapped", "1").unwrap());
+        router.prepare();
+        let response = router
+            .handle(Request::get("/alpha").unwrap())
+            .await
+            .unwrap();
+        assert_eq!(response.status(), http::StatusCode::URI_TOO_LONG);
+        assert_eq!(response.header("X-Mapped").unwrap(), "1");
+    }
 }
@@ -1,5 +1,6 @@
+use crate::RouterBuildError;
 use std::fmt::Write;
-use std::sync::Arc;
+use std::sync::{Arc, LazyLock};
 
 #[derive(Clone, Debug)]
 /// The pattern actually used to match against the path.  This contains both
@@ -8,17 +9,69 @@ use std::sync::Arc;
 pub(crate) struct Pattern {
     regex: regex::Regex,
     match_keys: Arc<[Option<Arc<str>>]>,
+    /// The checks for the fragments whose type cannot be fully checked by
+    /// the regular expression (e.g. that a `u8` is at most 255), by capture
+    /// index.
+    validators: Arc<[(usize, Validator)]>,
 }
 
+/// Checks that a single segment of a fragment can be parsed as its type.
+type Validator = fn(&str) -> bool;
+
 impl Pattern {
-    pub(crate) fn new(prefix: &str) -> Self {
-        let regex = regex::Regex::new(&regex_pattern(prefix)).unwrap();
+    pub(crate) fn new(prefix: &str) -> Result<Self, RouterBuildError> {
+        let (pattern, validators) = regex_pattern(prefix)?;
+        let regex = regex::RegexBuilder::new(&pattern)
+            .size_limit(SIZE_LIMIT)
+            .build()
+            .map_err(|source| RouterBuildError::InvalidPattern {
+                path: prefix.to_owned(),
+                source,
+            })?;
+        // Fragments are selected by index, so every group other than those
+        // of the fragments must be non-capturing.
+        debug_assert_eq!(
+            regex.captures_len(),
+            PATTERN.find_iter(prefix).count() + 1,
+            "pattern {pattern:?} of {prefix:?} has a capture group that is not a fragment"
+        );
+        Ok(Self::from_regex(regex, validators.into()))
+    }
+
+    /// A pattern that never matches any path.  This stands in for the
+    /// pattern of a route that is invalid, which can never be routed to.
+    pub(crate) fn never() -> Self {
+        Self::from_regex(regex::Regex::new("[^\\s\\S]").unwrap(), Arc::new([]))
+    }
+
+    fn from_regex(regex: regex::Regex, validators: Arc<[(usize, Validator)]>) -> Self {
         let match_keys = regex
             .capture_names()
             .map(|v| v.map(Arc::from))
             .collect::<Arc<[_]>>();
 
-        Pattern { regex, match_keys }
+        Pattern {
+            regex,
+            match_keys,
+            validators,
+        }
+    }
+
+    /// Whether or not every typed fragment of the given path (which must
+    /// already match the regular expression) parses as its type.  Repeated
+    /// fragments are checked segment by segment.
+    pub(crate) fn accepts(&self, path: &str) -> bool {
+        if self.validators.is_empty() {
+            return true;
+        }
+        let Some(captures) = self.regex.captures(path) else {
+            return false;
+        };
+        self.validators.iter().all(|&(index, validate)| {
+            captures
+                .get(index)
+                .map_or(true, |value| value.as_str().split('/').all(validate))
+        })
     }
 
     /// Get a reference to the pattern's regex.
@@ -32,34 +85,206 @@ impl Pattern {
     }
 }
 
-lazy_static::lazy_static! {
-    static ref PATTERN: regex::Regex = regex::Regex::new("\\{(?P<name>[a-zA-Z]+)?(?::(?P<pattern>[a-zA-Z]+))?\\}").unwrap();
+/// The most memory the compiled regular expression of a single route may
+/// use.  This is far more than any reasonable route needs, but stops a route
+/// with many repeated fragments from growing without bound.
+const SIZE_LIMIT: usize = 1 << 20;
+
+/// Anything that looks like a fragment; each is then checked against
+/// [`FRAGMENT`].
+static PATTERN: LazyLock<regex::Regex> =
+    LazyLock::new(|| regex::Regex::new("\\{[^{}]*\\}").unwrap());
+
+static FRAGMENT: LazyLock<regex::Regex> = LazyLock::new(|| {
+    regex::Regex::new(concat!(
+        "^\\{(?P<name>[a-zA-Z_][a-zA-Z0-9_]*)?",
+        "(?::(?P<pattern>[a-zA-Z][a-zA-Z0-9]*))?",
+        "(?P<modifier>[?*]|\\+(?P<depth>[1-9][0-9]*)?)?\\}$"
+    ))
+    .unwrap()
+});
+
+/// How many times a fragment may be repeated.
+#[derive(Clone, Copy)]
+enum Repeat {
+    /// Exactly once.
+    Once,
+    /// Never, or once (`?`).
+    Optional,
+    /// At least the given number of segments (`*` or `+`).
+    Segments(usize),
 }
 
-fn regex_pattern(path: &str) -> String {
+/// The regular expression for the given path, along with the checks for
+/// its fragments.  The expression is anchored to the start and end of the
+/// whole path (`\A` and `\z`, rather than `^` and `$`, so that it does not
+/// depend on any flags).
+fn regex_pattern(path: &str) -> Result<(String, Vec<(usize, Validator)>), RouterBuildError> {
     let mut start = 0;
-    let mut buffer = String::with_capacity(path.len() + 2);
-    buffer.push('^');
+    let mut buffer = String::with_capacity(path.len() + 4);
+    let mut names = vec![];
+    let mut validators = vec![];
+    buffer.push_str("\\A");
 
-    for matches in PATTERN.find_iter(path) {
-        buffer.push_str(&regex::escape(&path[start..matches.start()]));
-        start = matches.end();
-        let capture = PATTERN.captures(matches.as_str()).unwrap();
+    // Every fragment has exactly one capture group, after the implicit
+    // group for the whole match.
+    for (index, matches) in PATTERN.find_iter(path).enumerate() {
+        let invalid = || RouterBuildError::InvalidFragment {
+            path: path.to_owned(),
+            span: matches.range(),
+        };
+        let capture = FRAGMENT.captures(matches.as_str()).ok_or_else(invalid)?;
         let name = capture.name("name").map(|m| m.as_str());
         let pattern = capture.name("pattern").map(|m| m.as_str());
-        push_pattern(&mut buffer, name, pattern);
+        let repeat = match capture.name("modifier").map(|m| m.as_str()) {
+            None => Repeat::Once,
+            Some("?") => Repeat::Optional,
+            Some("*") => Repeat::Segments(0),
+            Some(_) => Repeat::Segments(
+                capture
+                    .name("depth")
+                    .map_or(Ok(1), |depth| depth.as_str().parse())
+                    .map_err(|_| invalid())?,
+            ),
+        };
+        let Some(kind) = FragmentType::find(pattern) else {
+            return Err(RouterBuildError::UnknownFragmentType {
+                path: path.to_owned(),
+                span: matches.range(),
+                kind: pattern.unwrap_or_default().to_owned(),
+            });
+        };
+        if kind.extension && !matches!(repeat, Repeat::Once) {
+            return Err(invalid());
+        }
+
+        // A fragment that may be left out takes the slash before it with it,
+        // so that e.g. `/users/{id}/{detail?}` matches `/users/1`.
+        let literal = &path[start..matches.start()];
+        let leading = matches!(repeat, Repeat::Optional | Repeat::Segments(0));
+        let (literal, slash) = match literal.strip_suffix('/') {
+            Some(literal) if leading => (literal, true),
+            _ => (literal, false),
+        };
+        buffer.push_str(&regex::escape(literal));
+        start = matches.end();
+
+        if let Some(name) = name {
+            if names.contains(&name) {
+                return Err(RouterBuildError::DuplicateFragment {
+                    path: path.to_owned(),
+                    span: matches.range(),
+                    name: name.to_owned(),
+                });
+            }
+            names.push(name);
+        }
+        push_pattern(&mut buffer, name, &kind, repeat, slash);
+        if let Some(validate) = kind.validate {
+            validators.push((index + 1, validate));
+        }
     }
 
     buffer.push_str(&regex::escape(&path[start..]));
 
-    buffer.push('$');
-    buffer
+    buffer.push_str("\\z");
+    Ok((buffer, validators))
 }
 
 static UUID_PATTERN: &str =
     "[a-fA-F0-9]{8}-[a-fA-F0-9]{4}-4[a-fA-F0-9]{3}-[89aAbB][a-fA-F0-9]{3}-[a-fA-F0-9]{12}";
 
-fn push_pattern(buffer: &mut String, name: Option<&str>, pattern: Option<&str>) {
+/// The type of a fragment: what a single segment of it matches, and how it is
+/// checked.
+struct FragmentType {
+    /// The regular expression for a single segment of the fragment.
+    body: &'static str,
+    /// The check for a segment, if the regular expression alone is not
+    /// enough.
+    validate: Option<Validator>,
+    /// Whether this is an (optional) extension, that includes its `.`.
+    extension: bool,
+}
+
+impl FragmentType {
+    /// Finds the type with the given name.
+    fn find(name: Option<&str>) -> Option<Self> {
+        const UINT: &str = "\\d+";
+        const INT: &str = "[+-]?\\d+";
+        const FLOAT: &str = "[+-]?(?:\\d+(?:\\.\\d*)?|\\.\\d+)(?:[eE][+-]?\\d+)?";
+
+        fn parses<T: std::str::FromStr>(value: &str) -> bool {
+            value.parse::<T>().is_ok()
+        }
+
+        let (body, validate): (_, Option<Validator>) = match name {
+            Some("oext") => {
+                return Some(FragmentType {
+                    body: "[^/]+",
+                    validate: None,
+                    extension: true,
+                })
+            }
+            Some("int") => (INT, None),
+            Some("uint") => (UINT, None),
+            Some("u8") => (UINT, Some(parses::<u8>)),
+            Some("u16") => (UINT, Some(parses::<u16>)),
+            Some("u32") => (UINT, Some(parses::<u32>)),
+            Some("u64") => (UINT, Some(parses::<u64>)),
+            Some("u128") => (UINT, Some(parses::<u128>)),
+            Some("usize") => (UINT, Some(parses::<usize>)),
+            Some("i8") => (INT, Some(parses::<i8>)),
+            Some("i16") => (INT, Some(parses::<i16>)),
+            Some("i32") => (INT, Some(parses::<i32>)),
+            Some("i64") => (INT, Some(parses::<i64>)),
+            Some("i128") => (INT, Some(parses::<i128>)),
+            Some("isize") => (INT, Some(parses::<isize>)),
+            Some("f32" | "f64") => (FLOAT, None),
+            Some("bool") => ("true|false", None),
+            Some("date") => ("\\d{4}-\\d{2}-\\d{2}", Some(is_date)),
+            Some("path") => (".+", None),
+            Some("uuid") => (UUID_PATTERN, None),
+            Some("str" | "s" | "string") | None => ("[^/]+", None),
+            Some(_) => return None,
+        };
+
+        Some(FragmentType {
+            body,
+            validate,
+            extension: false,
+        })
+    }
+}
+
+/// Whether the given `YYYY-MM-DD` string is a date that exists.
+fn is_date(value: &str) -> bool {
+    let mut parts = value.splitn(3, '-').map(str::parse::<u32>);
+    let (Some(Ok(year)), Some(Ok(month)), Some(Ok(day))) =
+        (parts.next(), parts.next(), parts.next())
+    else {
+        return false;
+    };
+    let leap = year % 4 == 0 && (year % 100 != 0 || year % 400 == 0);
+    let days = match month {
+        1 | 3 | 5 | 7 | 8 | 10 | 12 => 31,
+        4 | 6 | 9 | 11 => 30,
+        2 if leap => 29,
+        2 => 28,
+        _ => return false,
+    };
+    (1..=days).contains(&day)
+}
+
+/// Pushes the regular expression for the given fragment.  If `slash` is set,
+/// the fragment is preceded by a slash, which is only matched if the
+/// fragment is.
+fn push_pattern(
+    buffer: &mut String,
+    name: Option<&str>,
+    kind: &FragmentType,
+    repeat: Repeat,
+    slash: bool,
+) {
     struct NamePattern<'n>(Option<&'n str>);
     impl std::fmt::Display for NamePattern<'_> {
         fn fmt(&self, fmt: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
@@ -71,14 +296,21 @@ fn push_pattern(buffer: &mut String, name: Option<&str>, pattern: Option<&str>)
         }
     }
     let name = NamePattern(name);
-    match pattern {
-        Some("oext") => write!(buffer, "(?:\\.({name}[^/]+))?"),
-        Some("int") => write!(buffer, "({name}[+-]?\\d+)"),
-        Some("uint") => write!(buffer, "({name}\\d+)"),
-        Some("path") => write!(buffer, "({name}.+)"),
-        Some("uuid") => write!(buffer, "({name}{UUID_PATTERN})"),
-        Some("str" | "s" | "string") | None => write!(buffer, "({name}[^/]+)"),
-        Some(v) => panic!("unknown path pattern type {v:?}"),
+    let body = kind.body;
+    if kind.extension {
+        write!(buffer, "(?:\\.({name}{body}))?").unwrap();
+        return;
+    }
+    let slash = if slash { "/" } else { "" };
+    match repeat {
+        Repeat::Once => write!(buffer, "({name}(?:{body}))"),
+        Repeat::Optional => write!(buffer, "(?:{slash}({name}(?:{body})))?"),
+        Repeat::Segments(0) => write!(buffer, "(?:{slash}({name}(?:{body})(?:/(?:{body}))*))?"),
+        Repeat::Segments(depth) => write!(
+            buffer,
+            "({name}(?:{body})(?:/(?:{body})){{{},}})",
+            depth - 1
+        ),
     }
     .unwrap();
 }
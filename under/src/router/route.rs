@@ -1,13 +1,28 @@
 use super::Pattern;
-use crate::Endpoint;
+use crate::{Endpoint, Middleware, Request, Response, RouterBuildError};
+use std::any::TypeId;
+use std::borrow::Cow;
 use std::pin::Pin;
 use std::sync::Arc;
 
+pub(super) type Guard = Arc<dyn Fn(&Request) -> bool + Send + Sync>;
+type RequestMap = Arc<dyn Fn(Request) -> Request + Send + Sync>;
+type ResponseMap = Arc<dyn Fn(Response) -> Response + Send + Sync>;
+
 pub(crate) struct Route {
     pub(crate) path: String,
     pub(crate) pattern: Pattern,
     method: Option<http::Method>,
     endpoint: Pin<Box<dyn Endpoint>>,
+    guards: Vec<Guard>,
+    tags: Vec<Cow<'static, str>>,
+    /// The middleware skipped for requests to the route (see
+    /// [`super::Group::skip`]).
+    skips: Vec<TypeId>,
+    /// Why the path of the route is invalid, if it is.
+    error: Option<RouterBuildError>,
+    /// How much middleware the route has of its own (from its groups).
+    middleware: usize,
 }
 
 impl Route {
@@ -21,9 +36,58 @@ impl Route {
         &self.endpoint
     }
 
+    /// The tags of the route (see [`super::Group::tag`]).
+    pub(crate) fn tags(&self) -> &[Cow<'static, str>] {
+        &self.tags
+    }
+
+    /// The middleware skipped for requests to the route.
+    pub(crate) fn skips(&self) -> &[TypeId] {
+        &self.skips
+    }
+
+    /// How much middleware the route has of its own, not including the
+    /// router's.
+    pub(crate) fn middleware(&self) -> usize {
+        self.middleware
+    }
+
+    pub(crate) fn error(&self) -> Option<&RouterBuildError> {
+        self.error.as_ref()
+    }
+
     pub(crate) fn matches(&self, method: &http::Method) -> bool {
         self.method.is_none() || self.method.as_ref() == Some(method)
     }
+
+    /// Whether or not every guard of the route accepts the request (see
+    /// [`super::Group::guard`]).
+    pub(crate) fn guards(&self, request: &Request) -> bool {
+        self.guards.iter().all(|guard| guard(request))
+    }
+
+    /// Applies the configuration of a group to this route.  The group's
+    /// middleware runs before the endpoint (and before any middleware that the
+    /// endpoint itself has).
+    pub(super) fn layer(
+        mut self,
+        middleware: &Arc<[Pin<Box<dyn Middleware>>]>,
+        guards: &[Guard],
+        tags: &[Cow<'static, str>],
+        skips: &[TypeId],
+    ) -> Self {
+        self.middleware += middleware.len();
+        if !middleware.is_empty() {
+            self.endpoint = Box::pin(Layered {
+                middleware: middleware.clone(),
+                endpoint: self.endpoint,
+            });
+        }
+        self.guards.extend_from_slice(guards);
+        self.tags.extend_from_slice(tags);
+        self.skips.extend_from_slice(skips);
+        self
+    }
 }
 
 impl std::fmt::Debug for Route {
@@ -32,6 +96,66 @@ impl std::fmt::Debug for Route {
             .field("path", &self.path)
             .field("method", &self.method)
             .field("endpoint", &self.endpoint)
+            .field("tags", &self.tags)
+            .finish_non_exhaustive()
+    }
+}
+
+#[derive(Clone, Copy)]
+/// Information about a route of a router, for inspecting how its path is
+/// matched.  See [`crate::Router::route_info`].
+pub struct RouteInfo<'r> {
+    route: &'r Route,
+}
+
+impl<'r> RouteInfo<'r> {
+    pub(super) fn new(route: &'r Route) -> Self {
+        RouteInfo { route }
+    }
+
+    /// The path of the route, as it was given (e.g. `/users/{id:uint}`).
+    #[must_use]
+    pub fn path(&self) -> &'r str {
+        &self.route.path
+    }
+
+    /// The method of the route, or `None` if the route accepts every
+    /// method.
+    #[must_use]
+    pub fn method(&self) -> Option<&'r http::Method> {
+        self.route.method()
+    }
+
+    /// The tags of the route (see [`super::Group::tag`]).
+    #[must_use]
+    pub fn tags(&self) -> &'r [Cow<'static, str>] {
+        self.route.tags()
+    }
+
+    /// The regular expression that the path of the route is compiled to,
+    /// which the (decoded) path of a request is matched against.  Each
+    /// fragment has exactly one capture group, in order, so that e.g. the
+    /// second fragment is always the second group; every other group is
+    /// non-capturing.  For an invalid route, this is a pattern that never
+    /// matches.
+    #[must_use]
+    pub fn pattern(&self) -> &'r str {
+        self.route.pattern.regex().as_str()
+    }
+
+    /// The number of fragments of the route.
+    #[must_use]
+    pub fn fragments(&self) -> usize {
+        self.route.pattern.match_keys().len().saturating_sub(1)
+    }
+}
+
+impl std::fmt::Debug for RouteInfo<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("RouteInfo")
+            .field("path", &self.path())
+            .field("method", &self.method())
+            .field("pattern", &self.pattern())
             .finish_non_exhaustive()
     }
 }
@@ -52,9 +176,10 @@ impl std::fmt::Debug for Route {
 /// {[name][:<type>]}
 /// ```
 ///
-/// Where `[name]` is the (optional) text-based name for the fragment, and
+/// Where `[name]` is the (optional) name for the fragment (letters, digits,
+/// and underscores, not starting with a digit; e.g. `user_id`), and
 /// `<type>` is the (optional) type of the fragment (defaulting to string).
-/// There are currently six fragment types:
+/// There are currently these fragment types:
 ///
 /// - `oext`: matches an (optional) extension; e.g. `.jpeg`.  This can be used
 ///   to allow the front-end to optionally specify the expected content-type
@@ -67,11 +192,35 @@ impl std::fmt::Debug for Route {
 /// - `path`: matches anything, including path segments (`/`).  This is similar
 ///   to the `**` glob.
 /// - `uuid`: matches an [RFC 4122] UUID.
+/// - `u8`, `u16`, `u32`, `u64`, `u128`, `usize`, `i8`, `i16`, `i32`, `i64`,
+///   `i128`, `isize`: matches an integer that fits in the given type; e.g.
+///   `{id:u8}` does not match `256`.  So, [`crate::Request::fragment`] with
+///   that type never fails for a request routed to the route.
+/// - `f32`, `f64`: matches a decimal number (optionally with an exponent).
+/// - `bool`: matches `true` or `false`.
+/// - `date`: matches a date in the form `YYYY-MM-DD` that exists; e.g.
+///   `2024-02-29`, but not `2023-02-29`.
 /// - none / `str` / `s` / `string`: matches any characters excluding a path
 ///   segment (`/`).
 ///
-/// Note that using an invalid type will currently cause it to panic.  Non-named
-/// fragments (e.g. `{}`) must be indexed using numbers, 1-indexed.
+/// The type (or the name, if there is no type) may be followed by a
+/// modifier, changing how many times the fragment matches:
+///
+/// - `?`: the fragment is optional; e.g. `/users/{id}/{detail?}` matches
+///   both `/users/1` and `/users/1/posts`.
+/// - `+`: the fragment matches one or more segments of its type, separated
+///   by `/`; e.g. `/sum/{terms:int+}` matches `/sum/1/-2/3`, with the
+///   fragment being `1/-2/3`.  Adding a number requires at least that many
+///   segments; e.g. `{terms:int+2}`.
+/// - `*`: the same as `+`, except that the fragment may also be left out.
+///
+/// If a fragment that may be left out (`?` or `*`) directly follows a `/`,
+/// the `/` is left out with it.  The `oext` type cannot have a modifier (it
+/// is already optional).
+///
+/// Using an invalid type (or naming two fragments the same) makes the route
+/// invalid; this is reported when the router is prepared (see
+/// [`crate::Router::try_prepare`]).  Non-named fragments (e.g. `{}`) must be indexed using numbers, 1-indexed.
 ///
 /// [RFC 4122]: https://datatracker.ietf.org/doc/html/rfc4122
 ///
@@ -116,11 +265,12 @@ impl std::fmt::Debug for Route {
 /// # Ok(())
 /// # }
 /// ```
-#[derive(Debug)]
 pub struct Path<'a> {
     pub(super) prefix: String,
     pub(super) builder: &'a mut Vec<Arc<Route>>,
-    pub(super) pattern: Option<Pattern>,
+    pub(super) pattern: Option<Result<Pattern, RouterBuildError>>,
+    request_maps: Vec<RequestMap>,
+    response_maps: Vec<ResponseMap>,
 }
 
 macro_rules! method {
@@ -139,6 +289,8 @@ impl<'a> Path<'a> {
             prefix: prefix.into(),
             builder,
             pattern: None,
+            request_maps: vec![],
+            response_maps: vec![],
         }
     }
 
@@ -166,7 +318,10 @@ impl<'a> Path<'a> {
     /// # }
     /// ```
     pub fn at<P: AsRef<str>>(&mut self, path: P) -> Path<'_> {
-        Path::new(super::join_paths(&self.prefix, path.as_ref()), self.builder)
+        let mut child = Path::new(super::join_paths(&self.prefix, path.as_ref()), self.builder);
+        child.request_maps.clone_from(&self.request_maps);
+        child.response_maps.clone_from(&self.response_maps);
+        child
     }
 
     /// This appends to the prefix, creating a new [`Path`] from the
@@ -204,6 +359,13 @@ impl<'a> Path<'a> {
         self
     }
 
+    /// Mounts the given controller at the current prefix, declaring each of
+    /// its routes (see [`crate::Controller::routes`]).
+    pub fn controller<C: crate::Controller>(&mut self, controller: C) -> &mut Self {
+        Arc::new(controller).routes(self);
+        self
+    }
+
     /// Creates an endpoint responding to any method at the current prefix.
     ///
     /// # Examples
@@ -223,14 +385,7 @@ impl<'a> Path<'a> {
     /// # }
     /// ```
     pub fn all<E: Endpoint>(&mut self, endpoint: E) -> &mut Self {
-        let pattern = self.create_pattern();
-        self.builder.push(Arc::new(Route {
-            path: self.prefix.clone(),
-            pattern,
-            method: None,
-            endpoint: Box::pin(endpoint),
-        }));
-        self
+        self.push(None, endpoint)
     }
 
     /// Creates an endpoint of the specified method at the current prefix.
@@ -250,14 +405,140 @@ impl<'a> Path<'a> {
     /// # }
     /// ```
     pub fn method<E: Endpoint>(&mut self, method: http::Method, endpoint: E) -> &mut Self {
-        let pattern = self.create_pattern();
+        self.push(Some(method), endpoint)
+    }
 
-        self.builder.push(Arc::new(Route {
-            path: self.prefix.clone(),
-            pattern,
-            method: Some(method),
-            endpoint: Box::pin(endpoint),
-        }));
+    /// Creates an endpoint of the specified method at the current prefix,
+    /// whose handler is given the state along with each request (see
+    /// [`crate::endpoints::with_state`]).
+    pub fn method_with_state<S, F, Fut, Res>(
+        &mut self,
+        method: http::Method,
+        state: S,
+        handler: F,
+    ) -> &mut Self
+    where
+        S: Clone + Send + Sync + 'static,
+        F: Fn(Request, S) -> Fut + Send + Sync + 'static,
+        Fut: std::future::Future<Output = Res> + Send + 'static,
+        Res: crate::IntoResponse + Send + 'static,
+    {
+        self.method(method, crate::endpoints::with_state(state, handler))
+    }
+
+    /// Creates a GET endpoint at the current prefix, whose handler is given
+    /// the state along with each request.  The state is cloned for each
+    /// request (see [`crate::endpoints::with_state`]).
+    ///
+    /// # Examples
+    /// ```rust
+    /// # #[tokio::main] async fn main() -> Result<(), anyhow::Error> {
+    /// # use under::*;
+    /// use std::sync::Arc;
+    ///
+    /// struct Greeter {
+    ///     greeting: String,
+    /// }
+    ///
+    /// async fn greet(request: Request, greeter: Arc<Greeter>) -> Response {
+    ///     let name = request.fragment_str("name").unwrap();
+    ///     Response::text(format!("{}, {name}!", greeter.greeting))
+    /// }
+    ///
+    /// let greeter = Arc::new(Greeter { greeting: "Hello".to_owned() });
+    /// let mut http = under::http();
+    /// http.at("/greet/{name}").get_with_state(greeter, greet);
+    /// http.prepare();
+    /// let mut response = http.handle(Request::get("/greet/alice")?).await?;
+    /// assert_eq!(response.data(512).into_text().await?, "Hello, alice!");
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn get_with_state<S, F, Fut, Res>(&mut self, state: S, handler: F) -> &mut Self
+    where
+        S: Clone + Send + Sync + 'static,
+        F: Fn(Request, S) -> Fut + Send + Sync + 'static,
+        Fut: std::future::Future<Output = Res> + Send + 'static,
+        Res: crate::IntoResponse + Send + 'static,
+    {
+        self.method_with_state(http::Method::GET, state, handler)
+    }
+
+    /// Creates a POST endpoint at the current prefix, whose handler is
+    /// given the state along with each request (see
+    /// [`Path::get_with_state`]).
+    pub fn post_with_state<S, F, Fut, Res>(&mut self, state: S, handler: F) -> &mut Self
+    where
+        S: Clone + Send + Sync + 'static,
+        F: Fn(Request, S) -> Fut + Send + Sync + 'static,
+        Fut: std::future::Future<Output = Res> + Send + 'static,
+        Res: crate::IntoResponse + Send + 'static,
+    {
+        self.method_with_state(http::Method::POST, state, handler)
+    }
+
+    /// Transforms each request before it is passed to the endpoints of this
+    /// path - e.g., to rewrite a header, or insert a default.  This applies
+    /// to every endpoint that is added to this path (or any path created
+    /// from it, with [`Path::at`] or [`Path::under`]) _after_ this is
+    /// called; it runs after all of the router's middleware.  Transformers
+    /// are run in the order they are added.
+    ///
+    /// # Examples
+    /// ```rust
+    /// # use under::*;
+    /// # #[tokio::main] async fn main() -> Result<(), anyhow::Error> {
+    /// let mut http = under::http();
+    /// http.at("/legacy")
+    ///     .map_request(|request| {
+    ///         if request.header("Accept").is_some() {
+    ///             request
+    ///         } else {
+    ///             request.with_header("Accept", "text/plain").unwrap()
+    ///         }
+    ///     })
+    ///     .get(|request: Request| async move {
+    ///         let accept = request.header("Accept").unwrap().to_str()?.to_owned();
+    ///         Ok::<_, anyhow::Error>(Response::text(accept))
+    ///     });
+    /// http.prepare();
+    /// let mut response = http.handle(Request::get("/legacy")?).await?;
+    /// assert_eq!(response.data(512).into_text().await?, "text/plain");
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn map_request<F>(&mut self, map: F) -> &mut Self
+    where
+        F: Fn(Request) -> Request + Send + Sync + 'static,
+    {
+        self.request_maps.push(Arc::new(map));
+        self
+    }
+
+    /// Transforms each response generated by the endpoints of this path,
+    /// before it is passed back through the router's middleware.  This
+    /// applies to endpoints in the same way as [`Path::map_request`]; it is
+    /// not run if the endpoint errors.
+    ///
+    /// # Examples
+    /// ```rust
+    /// # use under::*;
+    /// # #[tokio::main] async fn main() -> Result<(), anyhow::Error> {
+    /// let mut http = under::http();
+    /// http.at("/reports/{id}")
+    ///     .map_response(|response| response.with_header("Cache-Control", "no-store").unwrap())
+    ///     .get(under::endpoints::simple(Response::empty_204));
+    /// http.prepare();
+    /// let response = http.handle(Request::get("/reports/1")?).await?;
+    /// assert_eq!(response.header("Cache-Control").unwrap(), "no-store");
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn map_response<F>(&mut self, map: F) -> &mut Self
+    where
+        F: Fn(Response) -> Response + Send + Sync + 'static,
+    {
+        self.response_maps.push(Arc::new(map));
         self
     }
 
@@ -408,7 +689,37 @@ impl<'a> Path<'a> {
         pub fn patch = http::Method::PATCH;
     ];
 
-    fn create_pattern(&mut self) -> Pattern {
+    fn push<E: Endpoint>(&mut self, method: Option<http::Method>, endpoint: E) -> &mut Self {
+        let (pattern, error) = match self.create_pattern() {
+            Ok(pattern) => (pattern, None),
+            Err(error) => (Pattern::never(), Some(error)),
+        };
+        let endpoint: Pin<Box<dyn Endpoint>> =
+            if self.request_maps.is_empty() && self.response_maps.is_empty() {
+                Box::pin(endpoint)
+            } else {
+                Box::pin(Transformed {
+                    endpoint: Box::pin(endpoint),
+                    request_maps: self.request_maps.clone(),
+                    response_maps: self.response_maps.clone(),
+                })
+            };
+
+        self.builder.push(Arc::new(Route {
+            path: self.prefix.clone(),
+            pattern,
+            method,
+            endpoint,
+            guards: vec![],
+            tags: vec![],
+            skips: vec![],
+            error,
+            middleware: 0,
+        }));
+        self
+    }
+
+    fn create_pattern(&mut self) -> Result<Pattern, RouterBuildError> {
         if let Some(pattern) = self.pattern.clone() {
             pattern
         } else {
@@ -418,3 +729,54 @@ impl<'a> Path<'a> {
         }
     }
 }
+
+impl std::fmt::Debug for Path<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Path")
+            .field("prefix", &self.prefix)
+            .field("builder", &self.builder)
+            .field("pattern", &self.pattern)
+            .field("request_maps", &self.request_maps.len())
+            .field("response_maps", &self.response_maps.len())
+            .finish()
+    }
+}
+
+/// An endpoint with the transformers of its path applied to it (see
+/// [`Path::map_request`]).
+struct Transformed {
+    endpoint: Pin<Box<dyn Endpoint>>,
+    request_maps: Vec<RequestMap>,
+    response_maps: Vec<ResponseMap>,
+}
+
+#[async_trait]
+impl Endpoint for Transformed {
+    async fn apply(self: Pin<&Self>, request: Request) -> Result<Response, anyhow::Error> {
+        let request = self.request_maps.iter().fold(request, |r, map| map(r));
+        let response = self.endpoint.as_ref().apply(request).await?;
+        Ok(self.response_maps.iter().fold(response, |r, map| map(r)))
+    }
+
+    fn describe(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.endpoint.describe(f)
+    }
+}
+
+/// An endpoint with the middleware of its group applied to it.
+struct Layered {
+    middleware: Arc<[Pin<Box<dyn Middleware>>]>,
+    endpoint: Pin<Box<dyn Endpoint>>,
+}
+
+#[async_trait]
+impl Endpoint for Layered {
+    async fn apply(self: Pin<&Self>, request: Request) -> Result<Response, anyhow::Error> {
+        let next = crate::middleware::Next::new(&self.middleware, self.endpoint.as_ref());
+        next.apply(request).await
+    }
+
+    fn describe(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.endpoint.describe(f)
+    }
+}
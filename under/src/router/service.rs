@@ -1,10 +1,16 @@
-use super::Router;
-use crate::Endpoint;
-use crate::UnderError;
+use super::connections::ConnectionGuard;
+use super::write_timeout::WriteTimeout;
+use super::{Completion, Router};
+use crate::middleware::BodyCounter;
+use crate::{HttpEntity, Request, RequestInfo, Response, UnderError};
+use hyper_util::rt::{TokioExecutor, TokioIo, TokioTimer};
+use hyper_util::server::conn::auto;
+use hyper_util::server::graceful::GracefulShutdown;
 use std::future::Future;
-use std::net::SocketAddr;
 use std::pin::Pin;
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::Arc;
+use tokio::sync::watch;
 
 impl Router {
     /// Creates a listen server on the specified address.
@@ -25,80 +31,389 @@ impl Router {
     /// # Ok(())
     /// # }
     /// ```
-    pub async fn listen(mut self, address: &str) -> Result<(), UnderError> {
-        let address: SocketAddr = address
-            .parse()
-            .map_err(|_| UnderError::InvalidAddress(address.to_owned()))?;
-        self.prepare();
+    pub async fn listen(self, address: &str) -> Result<(), UnderError> {
+        #[cfg(all(unix, not(any(target_os = "solaris", target_os = "illumos"))))]
+        if let Some(shards) = self.shards {
+            let listeners = (0..shards)
+                .map(|_| crate::Listener::bind_reuse_port(address))
+                .collect::<Result<Vec<_>, _>>()?;
+            return self.listen_sharded(listeners).await;
+        }
+
+        self.listen_on(crate::Listener::bind(address)?).await
+    }
+
+    /// Starts listening for incoming connections on the given listener.
+    /// This behaves the same as [`Router::listen`], but allows the listener
+    /// to be created in other ways - e.g., to take over the listener of a
+    /// previous process, for a zero-downtime restart (see
+    /// [`crate::Listener`]).  If the server is sharded (see
+    /// [`Router::shards`]), every shard accepts connections from the same
+    /// listener.
+    ///
+    /// # Errors
+    /// This can fail if the listener cannot be used by the server.
+    ///
+    /// # Examples
+    /// ```rust,no_run
+    /// # use under::*;
+    /// # #[tokio::main] async fn main() -> Result<(), anyhow::Error> {
+    /// let mut http = under::http();
+    /// http.at("/").get(|_| async { Response::text("hello, world!") });
+    /// http.listen_on(Listener::bind_reuse_port("0.0.0.0:8080")?).await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn listen_on(mut self, listener: crate::Listener) -> Result<(), UnderError> {
+        if let Some(shards) = self.shards {
+            let mut listeners = (1..shards)
+                .map(|_| listener.try_clone())
+                .collect::<Result<Vec<_>, _>>()?;
+            listeners.push(listener);
+            return self.listen_sharded(listeners).await;
+        }
 
-        log::info!("listen({})", address);
+        self.start(&listener)?;
+        let termination = termination(self.terminate.take());
+        accept(Arc::pin(self), listener, termination).await
+    }
+
+    /// Runs a shard of the server for each listener, each on its own thread
+    /// and runtime, and waits for all of them to stop.  The first error from
+    /// any shard is returned.
+    async fn listen_sharded(mut self, listeners: Vec<crate::Listener>) -> Result<(), UnderError> {
+        if let Some(listener) = listeners.first() {
+            self.start(listener)?;
+        }
+        let terminate = self.terminate.take();
+        let this = Arc::pin(self);
+
+        let mut shards = Vec::with_capacity(listeners.len());
+        for (index, listener) in listeners.into_iter().enumerate() {
+            let (tx, rx) = tokio::sync::oneshot::channel();
+            let router = this.clone();
+            let terminate = terminate.clone();
+            std::thread::Builder::new()
+                .name(format!("under-shard-{index}"))
+                .spawn(move || {
+                    let result = tokio::runtime::Builder::new_current_thread()
+                        .enable_all()
+                        .build()
+                        .map_err(UnderError::Shard)
+                        .and_then(|runtime| {
+                            runtime.block_on(accept(router, listener, termination(terminate)))
+                        });
+                    tx.send(result).ok();
+                })
+                .map_err(UnderError::Shard)?;
+            shards.push(rx);
+        }
+
+        let mut result = Ok(());
+        for shard in futures::future::join_all(shards).await {
+            let shard = shard.unwrap_or_else(|_| {
+                Err(UnderError::Shard(std::io::Error::other(
+                    "the shard panicked",
+                )))
+            });
+            if let Err(error) = shard {
+                log::error!("shard stopped: {error:?}");
+                if result.is_ok() {
+                    result = Err(error);
+                }
+            }
+        }
+        result
+    }
+
+    /// Prepares the router for listening on the given listener, logging the
+    /// routes.  This errors if any of the routes are invalid.
+    fn start(&mut self, listener: &crate::Listener) -> Result<(), UnderError> {
+        self.try_prepare().map_err(UnderError::InvalidRoutes)?;
+        self.stats.start();
+
+        match listener.local_addr() {
+            Ok(address) => log::info!("listen({address})"),
+            Err(_) => log::info!("listen(?)"),
+        }
 
         if log::log_enabled!(log::Level::Trace) {
             for route in self.routes() {
                 log::trace!(
                     "route: {} {} ({:?})",
-                    route.method().map_or("(all)", hyper::Method::as_str),
+                    route.method().map_or("(all)", http::Method::as_str),
                     route.path,
                     route.pattern.regex()
                 );
             }
         }
 
-        let termination = self.terminate.take();
-        let termination = async {
-            match termination {
-                Some(mut tx) => loop {
-                    if *tx.borrow() {
-                        break;
-                    }
-                    match tx.changed().await {
-                        Ok(_) => continue,
-                        Err(_) => futures::future::pending().await,
-                    }
-                },
-                None => futures::future::pending().await,
+        Ok(())
+    }
+
+    /// Handles a request the same way that the server would, had the request
+    /// been received while listening (see [`Router::listen`]).  Unlike
+    /// [`Router::handle`], this runs the lifecycle hooks (e.g.
+    /// [`Router::on_request`]), and does not error; instead, if the router
+    /// errors, the error is logged (along with the method and URI of the
+    /// request, redacted with the [`Router::redactor`], and formatted with
+    /// the [`Router::error_format`]), and an empty 500
+    /// (or the [`Router::error_response`]) is returned.  This is useful for testing
+    /// the router as a whole.
+    ///
+    /// # Examples
+    /// ```rust
+    /// # use under::*;
+    /// # #[tokio::main] async fn main() -> Result<(), anyhow::Error> {
+    /// let mut http = under::http();
+    /// http.at("/").get(|_| async { Err::<Response, _>(anyhow::anyhow!("oops")) });
+    /// http.prepare();
+    /// let response = http.serve(Request::get("/")?).await;
+    /// assert_eq!(response.status(), http::StatusCode::INTERNAL_SERVER_ERROR);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn serve(&self, request: Request) -> Response {
+        let start = std::time::Instant::now();
+        let _request = self.stats.track();
+        let is_head = request.method() == http::Method::HEAD;
+        self.request_hooks.iter().for_each(|hook| hook(&request));
+        let expectation_failed = match request.header(http::header::EXPECT) {
+            Some(_) if request.expects_continue() => !self.expect_continue,
+            Some(_) => true,
+            None => false,
+        };
+        let info = if self.response_hooks.is_empty() && self.complete_hooks.is_empty() {
+            None
+        } else {
+            Some(RequestInfo::from(&request))
+        };
+
+        let mut response = if expectation_failed {
+            Response::empty(http::StatusCode::EXPECTATION_FAILED)
+        } else {
+            let method = request.method().clone();
+            let uri = request.uri().clone();
+            let map_info = (!self.response_maps.is_empty()).then(|| RequestInfo::from(&request));
+            match self.handle(request).await {
+                Ok(response) => response,
+                Err(error) => {
+                    let uri = self.redactor.redact_uri(&uri.to_string()).into_owned();
+                    let error = match &self.error_format {
+                        Some(format) => format(&error),
+                        None => format_error(&error),
+                    };
+                    log::error!("error handling request {method} {uri}: {error}");
+                    self.map_response_with(map_info.as_ref(), self.server_error())
+                }
+            }
+        };
+
+        if is_head && self.strip_head_body {
+            strip_body(&mut response);
+        }
+
+        let Some(info) = info else {
+            return response;
+        };
+
+        self.response_hooks
+            .iter()
+            .for_each(|hook| hook(&info, &response));
+
+        if !self.complete_hooks.is_empty() {
+            let hooks = self.complete_hooks.clone();
+            let status = response.status();
+            let on_complete = move |counter: &BodyCounter| {
+                let completion = Completion {
+                    info,
+                    status,
+                    duration: start.elapsed(),
+                    bytes: counter.bytes(),
+                    complete: counter.is_complete(),
+                };
+                hooks.iter().for_each(|hook| hook(&completion));
+            };
+            crate::middleware::count_body(&mut response, Some(Box::new(on_complete)));
+        }
+
+        response
+    }
+}
+
+/// Resolves once the given termination signal is sent (see
+/// [`Router::termination_signal`]); or never, if there is none.
+pub(super) async fn termination(terminate: Option<watch::Receiver<bool>>) {
+    match terminate {
+        Some(mut tx) => loop {
+            if *tx.borrow() {
+                break;
             }
+            match tx.changed().await {
+                Ok(()) => {}
+                Err(_) => futures::future::pending().await,
+            }
+        },
+        None => futures::future::pending().await,
+    }
+}
+
+/// Accepts connections on the given listener, on the current runtime, until
+/// the termination future resolves.  Once it does, the connections that are
+/// still open are closed gracefully - each finishes the requests it is
+/// handling - before this returns.
+async fn accept(
+    this: Pin<Arc<Router>>,
+    listener: crate::Listener,
+    termination: impl Future<Output = ()>,
+) -> Result<(), UnderError> {
+    let listener = listener.into_tokio()?;
+    let idle_timeout = this.idle_timeout;
+    let mut builder = auto::Builder::new(TokioExecutor::new());
+    if let Some(timeout) = idle_timeout {
+        builder
+            .http1()
+            .timer(TokioTimer::new())
+            .header_read_timeout(timeout);
+    }
+    let builder = Arc::new(builder);
+
+    let graceful = GracefulShutdown::new();
+    let mut termination = std::pin::pin!(termination);
+    loop {
+        let (stream, address) = tokio::select! {
+            accepted = listener.accept() => match accepted {
+                Ok(accepted) => accepted,
+                Err(error) => {
+                    accept_error(error).await;
+                    continue;
+                }
+            },
+            () = &mut termination => break,
         };
 
-        let this = Arc::pin(self);
+        let service = RouterService {
+            connection: this.connections.track(),
+            router: this.clone(),
+            address,
+            requests: AtomicUsize::new(0),
+        };
+        let write_timeout = this.write_timeout;
+        let builder = builder.clone();
+        let watcher = graceful.watcher();
+        tokio::spawn(async move {
+            // hyper only starts timing out the headers of the first request
+            // once it knows the version of HTTP the connection uses, which
+            // takes the first bytes of the request.
+            if let Some(timeout) = idle_timeout {
+                if tokio::time::timeout(timeout, stream.readable())
+                    .await
+                    .is_err()
+                {
+                    return;
+                }
+            }
+
+            let io = TokioIo::new(WriteTimeout::new(stream, write_timeout));
+            let connection = builder.serve_connection_with_upgrades(io, service);
+            if let Err(error) = watcher.watch(connection).await {
+                log::debug!("connection from {address} failed: {error}");
+            }
+        });
+    }
 
-        hyper::server::Server::bind(&address)
-            .serve(hyper::service::make_service_fn(
-                |v: &hyper::server::conn::AddrStream| {
-                    let router = this.clone();
-                    let service = RouterService(router, v.remote_addr());
-                    async move { Ok::<_, std::convert::Infallible>(service) }
-                },
-            ))
-            .with_graceful_shutdown(termination)
-            .await
-            .map_err(UnderError::HyperServer)?;
+    this.stats.set_draining();
+    graceful.shutdown().await;
+    Ok(())
+}
 
-        Ok(())
+/// Handles an error accepting a connection.  Errors that are specific to the
+/// connection (e.g. it was reset before it was accepted) are ignored; others
+/// (e.g. running out of file descriptors) are logged, and accepting pauses
+/// for a second, so that the error does not repeat in a busy loop.
+async fn accept_error(error: std::io::Error) {
+    use std::io::ErrorKind;
+    if matches!(
+        error.kind(),
+        ErrorKind::ConnectionRefused | ErrorKind::ConnectionAborted | ErrorKind::ConnectionReset
+    ) {
+        return;
     }
+
+    log::error!("could not accept a connection: {error}");
+    tokio::time::sleep(std::time::Duration::from_secs(1)).await;
 }
 
-#[derive(Clone)]
-struct RouterService(Pin<Arc<Router>>, std::net::SocketAddr);
+/// Drops the body of the given response, keeping the `Content-Length` that
+/// it would have had, if it is known.
+fn strip_body(response: &mut Response) {
+    if response.header(http::header::CONTENT_LENGTH).is_none() {
+        let borrowed: &http::Response<crate::Body> = std::borrow::Borrow::borrow(&*response);
+        if let Some(length) = http_body::Body::size_hint(borrowed.body()).exact() {
+            response
+                .headers_mut()
+                .insert(http::header::CONTENT_LENGTH, length.into());
+        }
+    }
 
-type RouterFuture<R, E> = Pin<Box<dyn Future<Output = Result<R, E>> + Send + 'static>>;
+    response.set_body(crate::Body::empty());
+}
 
-impl tower::Service<hyper::Request<hyper::Body>> for RouterService {
-    type Response = hyper::Response<hyper::Body>;
-    type Error = anyhow::Error;
-    type Future = RouterFuture<Self::Response, Self::Error>;
+/// The service for a single connection.
+struct RouterService {
+    router: Pin<Arc<Router>>,
+    address: std::net::SocketAddr,
+    /// The number of requests made over this connection so far.
+    requests: AtomicUsize,
+    /// Keeps the connection counted as open, for as long as it exists.
+    #[allow(dead_code)]
+    connection: ConnectionGuard,
+}
 
-    fn poll_ready(
-        &mut self,
-        _cx: &mut std::task::Context<'_>,
-    ) -> std::task::Poll<Result<(), Self::Error>> {
-        std::task::Poll::Ready(Ok(()))
+/// Formats the error with each of its causes on its own line, followed by
+/// its backtrace, if one was captured.
+fn format_error(error: &anyhow::Error) -> String {
+    use std::fmt::Write as _;
+    let mut formatted = error.to_string();
+    for cause in error.chain().skip(1) {
+        // Writing to a string never fails.
+        let _ = write!(formatted, "\n    caused by: {cause}");
+    }
+    let backtrace = error.backtrace();
+    if backtrace.status() == std::backtrace::BacktraceStatus::Captured {
+        let _ = write!(formatted, "\nbacktrace:\n{backtrace}");
     }
+    formatted
+}
+
+type RouterFuture<R, E> = Pin<Box<dyn Future<Output = Result<R, E>> + Send + 'static>>;
+
+impl hyper::service::Service<http::Request<hyper::body::Incoming>> for RouterService {
+    type Response = http::Response<crate::Body>;
+    type Error = std::convert::Infallible;
+    type Future = RouterFuture<Self::Response, Self::Error>;
 
-    fn call(&mut self, mut request: hyper::Request<hyper::Body>) -> Self::Future {
-        let this = (self.0).clone();
-        let addr = crate::middleware::PeerAddress(self.1);
+    fn call(&self, mut request: http::Request<hyper::body::Incoming>) -> Self::Future {
+        let this = self.router.clone();
+        let addr = crate::middleware::PeerAddress(self.address);
         request.extensions_mut().insert(addr);
-        Box::pin(async move { this.as_ref().apply(request.into()).await.map(Into::into) })
+        let requests = self.requests.fetch_add(1, Ordering::Relaxed) + 1;
+        let close = request.version() < http::Version::HTTP_2
+            && (this.stats.is_draining()
+                || this
+                    .max_requests_per_connection
+                    .is_some_and(|max| requests >= max));
+        Box::pin(async move {
+            let mut response = this
+                .serve(request.map(crate::Body::from_incoming).into())
+                .await;
+            if close {
+                response.headers_mut().insert(
+                    http::header::CONNECTION,
+                    http::HeaderValue::from_static("close"),
+                );
+            }
+            Ok(response.into())
+        })
     }
 }
@@ -0,0 +1,188 @@
+use super::ConnectionGauge;
+use crate::{Request, Response};
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, OnceLock};
+use std::time::{Duration, Instant};
+
+#[derive(Debug, Default)]
+struct Counters {
+    in_flight: AtomicUsize,
+    requests: AtomicU64,
+    started: OnceLock<Instant>,
+    draining: AtomicBool,
+    /// Notified whenever the last request in flight finishes.
+    idle: tokio::sync::Notify,
+}
+
+#[derive(Debug, Clone, Default)]
+/// Statistics about the requests and connections the server is handling.
+///
+/// This is retrieved with [`crate::Router::stats`], before the router starts
+/// listening, and can then be read at any time, e.g. from a readiness probe,
+/// or to report to a metrics system.  It is also an endpoint, which responds
+/// with the statistics, for mounting at a debugging path (e.g.
+/// `/debug/under`); that path should not be exposed publicly.
+///
+/// Requests are counted from when the router starts handling them, until
+/// their response is ready (the body of the response may still be being
+/// sent); this includes requests handled with [`crate::Router::serve`].
+///
+/// # Examples
+/// ```rust
+/// # use under::*;
+/// # #[tokio::main] async fn main() -> Result<(), anyhow::Error> {
+/// let mut http = under::http();
+/// let stats = http.stats();
+/// http.at("/").get(under::endpoints::simple(Response::empty_204));
+/// http.at("/debug/under").get(stats.clone());
+/// http.prepare();
+/// http.serve(Request::get("/")?).await;
+/// assert_eq!(stats.requests(), 1);
+/// assert_eq!(stats.in_flight(), 0);
+/// assert!(!stats.is_draining());
+///
+/// let mut response = http.serve(Request::get("/debug/under")?).await;
+/// let body = response.data(512).into_json::<serde_json::Value>().await?;
+/// assert_eq!(body["requests"], 2);
+/// assert_eq!(body["in_flight"], 1);
+/// # Ok(())
+/// # }
+/// ```
+pub struct RouterStats {
+    counters: Arc<Counters>,
+    connections: ConnectionGauge,
+}
+
+impl RouterStats {
+    pub(super) fn new(connections: ConnectionGauge) -> Self {
+        RouterStats {
+            counters: Arc::default(),
+            connections,
+        }
+    }
+
+    /// The number of connections that are currently open (see
+    /// [`ConnectionGauge::open`]).
+    #[must_use]
+    pub fn connections(&self) -> usize {
+        self.connections.open()
+    }
+
+    /// The number of requests that are currently being handled.
+    #[must_use]
+    pub fn in_flight(&self) -> usize {
+        self.counters.in_flight.load(Ordering::Relaxed)
+    }
+
+    /// The number of requests that have been handled (or are being handled)
+    /// in total.
+    #[must_use]
+    pub fn requests(&self) -> u64 {
+        self.counters.requests.load(Ordering::Relaxed)
+    }
+
+    /// How long the router has been listening for, or `None` if it has not
+    /// started listening.
+    #[must_use]
+    pub fn uptime(&self) -> Option<Duration> {
+        self.counters.started.get().map(Instant::elapsed)
+    }
+
+    /// Whether the server is draining: either because it has been told to
+    /// shut down (see [`crate::Router::termination_signal`]), and is now
+    /// waiting for the requests it has to finish, while accepting no new
+    /// connections; or because [`RouterStats::drain`] was called.
+    #[must_use]
+    pub fn is_draining(&self) -> bool {
+        self.counters.draining.load(Ordering::Relaxed)
+    }
+
+    /// Records that the router has started listening.
+    pub(super) fn start(&self) {
+        self.counters.started.get_or_init(Instant::now);
+    }
+
+    /// Marks the server as draining, and waits until no requests are in
+    /// flight, or the timeout passes.  This resolves to whether the
+    /// requests finished in time.  See [`crate::Router::drain`].
+    pub fn drain(&self, timeout: Duration) -> impl std::future::Future<Output = bool> + Send {
+        let counters = self.counters.clone();
+        async move {
+            counters.draining.store(true, Ordering::Relaxed);
+            let idle = async {
+                loop {
+                    let notified = counters.idle.notified();
+                    tokio::pin!(notified);
+                    notified.as_mut().enable();
+                    if counters.in_flight.load(Ordering::Acquire) == 0 {
+                        break;
+                    }
+                    notified.await;
+                }
+            };
+            tokio::time::timeout(timeout, idle).await.is_ok()
+        }
+    }
+
+    /// Records that the server has started shutting down.
+    pub(super) fn set_draining(&self) {
+        self.counters.draining.store(true, Ordering::Relaxed);
+    }
+
+    /// Counts a request as being handled, until the returned guard is
+    /// dropped.
+    pub(super) fn track(&self) -> RequestGuard {
+        self.counters.requests.fetch_add(1, Ordering::Relaxed);
+        self.counters.in_flight.fetch_add(1, Ordering::AcqRel);
+        RequestGuard(self.counters.clone())
+    }
+
+    fn to_response(&self) -> Result<Response, anyhow::Error> {
+        let uptime = self.uptime().map(|uptime| uptime.as_secs_f64());
+        #[cfg(feature = "json")]
+        {
+            Ok(Response::json(&serde_json::json!({
+                "connections": self.connections(),
+                "in_flight": self.in_flight(),
+                "requests": self.requests(),
+                "uptime": uptime,
+                "draining": self.is_draining(),
+            }))?)
+        }
+        #[cfg(not(feature = "json"))]
+        {
+            let uptime = uptime.map_or_else(|| "-".to_owned(), |uptime| format!("{uptime:.3}"));
+            Ok(Response::text(format!(
+                "connections: {}\nin_flight: {}\nrequests: {}\nuptime: {uptime}\ndraining: {}\n",
+                self.connections(),
+                self.in_flight(),
+                self.requests(),
+                self.is_draining(),
+            )))
+        }
+    }
+}
+
+#[async_trait]
+impl crate::Endpoint for RouterStats {
+    async fn apply(self: Pin<&Self>, _: Request) -> Result<Response, anyhow::Error> {
+        self.to_response()
+    }
+
+    fn describe(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("stats")
+    }
+}
+
+/// Counts a request as being handled for as long as it exists.
+#[derive(Debug)]
+pub(super) struct RequestGuard(Arc<Counters>);
+
+impl Drop for RequestGuard {
+    fn drop(&mut self) {
+        if self.0.in_flight.fetch_sub(1, Ordering::AcqRel) == 1 {
+            self.0.idle.notify_waiters();
+        }
+    }
+}
@@ -3,13 +3,23 @@
 //! This adds some wrappers around using the `async-sse` crate with this
 //! HTTP library, making it easier to handle SSE connections.  It is gated
 //! behind the `sse` feature flag for those who do not want to use it.
+//!
+//! The [`Sender`] given by [`endpoint`] and [`upgrade`] waits for the client
+//! to read each event before the next can be sent.  For producers that
+//! should not wait on a slow client (e.g. one feeding many connections),
+//! [`buffered`] and [`upgrade_buffered`] give a [`BufferedSender`] instead,
+//! which buffers a bounded number of events, and decides what to do when
+//! the buffer is full with an [`Overflow`] policy.
 
 use crate::{HttpEntity, Request, Response};
 pub use async_sse::Sender;
 use futures::StreamExt;
+use std::collections::VecDeque;
 use std::future::Future;
 use std::pin::Pin;
-use std::sync::Arc;
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::Duration;
+use tokio::sync::Notify;
 use tokio_util::compat::FuturesAsyncReadCompatExt;
 
 /// Creates an endpoint that can handle SSE connections.  This directly
@@ -73,7 +83,7 @@ where
     F: FnOnce(Request, Sender) -> Fut + Send + Sync + 'static,
     Fut: Future<Output = crate::Result<()>> + Send + 'static,
 {
-    Ok(handle_sse(request, handle))
+    Ok(handle_sse(request, None, handle))
 }
 
 /// Performs a heartbeat on an SSE connection.  This allows the server to
@@ -130,11 +140,54 @@ pub async fn stream_heartbeat<I, S: futures::Stream<Item = I> + Unpin>(
 ///
 /// This is created by [`endpoint`], and implements the [`crate::Endpoint`]
 /// trait.
-pub struct SseEndpoint<F>(Arc<F>);
+pub struct SseEndpoint<F> {
+    handle: Arc<F>,
+    heartbeat: Option<Duration>,
+}
 
 impl<F> SseEndpoint<F> {
     fn new(f: F) -> Self {
-        SseEndpoint(Arc::new(f))
+        SseEndpoint {
+            handle: Arc::new(f),
+            heartbeat: None,
+        }
+    }
+
+    /// Sends a keepalive comment (`:`) to the client whenever no data has
+    /// been sent for the given interval, so that the handler does not need
+    /// to (see [`stream_heartbeat`]).  Comments never interrupt an event,
+    /// and are ignored by clients.  The heartbeat also means that a
+    /// disconnected client is noticed, even while the handler is idle; once
+    /// the client disconnects, the handler is cancelled.
+    ///
+    /// # Examples
+    /// ```rust
+    /// # use under::*;
+    /// use std::time::Duration;
+    /// use under::sse::Sender;
+    ///
+    /// async fn handle(_: Request, sender: Sender) -> Result<(), anyhow::Error> {
+    ///     tokio::time::sleep(Duration::from_millis(50)).await;
+    ///     sender.send(None, "hello", None).await?;
+    ///     Ok(())
+    /// }
+    ///
+    /// # #[tokio::main] async fn main() -> Result<(), anyhow::Error> {
+    /// let mut http = under::http();
+    /// http.at("/sse").get(under::sse::endpoint(handle)
+    ///     .with_heartbeat(Duration::from_millis(20)));
+    /// http.prepare();
+    /// let mut response = http.handle(Request::get("/sse")?).await?;
+    /// let body = response.data(512).into_text().await?;
+    /// assert!(body.starts_with(":\n:\n"));
+    /// assert!(body.ends_with("data:hello\n\n"));
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[must_use]
+    pub fn with_heartbeat(mut self, interval: Duration) -> Self {
+        self.heartbeat = Some(interval);
+        self
     }
 }
 
@@ -145,21 +198,22 @@ where
     Fut: Future<Output = crate::Result<()>> + Send + 'static,
 {
     async fn apply(self: Pin<&Self>, request: Request) -> Result<Response, anyhow::Error> {
-        let h = self.0.clone();
+        let h = self.handle.clone();
         // we need this for lifetime extension.  If we pass in `h` directly,
         // `h` would be bound to the lifetime of this function.
         #[allow(clippy::redundant_closure)]
-        Ok(handle_sse(request, move |r, s| h(r, s)))
+        Ok(handle_sse(request, self.heartbeat, move |r, s| h(r, s)))
     }
 
     fn describe(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        f.debug_tuple("SseEndpoint")
-            .field(&std::any::type_name::<F>())
+        f.debug_struct("SseEndpoint")
+            .field("handle", &std::any::type_name::<F>())
+            .field("heartbeat", &self.heartbeat)
             .finish()
     }
 }
 
-fn handle_sse<F, Fut>(request: Request, handle: F) -> crate::Response
+fn handle_sse<F, Fut>(request: Request, heartbeat: Option<Duration>, handle: F) -> crate::Response
 where
     F: FnOnce(Request, Sender) -> Fut + Send + Sync + 'static,
     Fut: Future<Output = crate::Result<()>> + Send + 'static,
@@ -167,16 +221,458 @@ where
     let (sender, encoder) = async_sse::encode();
 
     let stream = tokio_util::io::ReaderStream::new(encoder.compat());
-    let response = Response::empty_200()
+    let task = tokio::task::spawn(async move {
+        handle(request, sender).await.ok();
+    });
+
+    event_stream(stream, heartbeat, task)
+}
+
+/// Creates the response for an SSE connection, with the given stream as
+/// the body.  If there is a heartbeat, the stream is wrapped to send it, and
+/// the task is cancelled once the stream is dropped.
+fn event_stream<S>(
+    stream: S,
+    heartbeat: Option<Duration>,
+    task: tokio::task::JoinHandle<()>,
+) -> crate::Response
+where
+    S: futures::Stream<Item = std::io::Result<bytes::Bytes>> + Send + 'static,
+{
+    let body = match heartbeat {
+        Some(interval) => crate::Body::wrap_stream(Keepalive::new(stream, interval, task)),
+        None => crate::Body::wrap_stream(stream),
+    };
+
+    Response::empty_200()
         .with_header("Cache-Control", "no-cache")
         .expect("Cache-Control is a valid header")
         .with_header("Content-Type", "text/event-stream")
         .expect("Content-Type is a valid header")
-        .with_body(hyper::Body::wrap_stream(stream));
+        .with_body(body)
+}
+
+/// An event stream that sends a keepalive comment whenever the inner
+/// stream has been idle for the interval.  Comments are only sent at the
+/// start of a line, so that they never split a line of an event (the inner
+/// stream may split a long line across chunks).
+struct Keepalive {
+    inner: Pin<Box<dyn futures::Stream<Item = std::io::Result<bytes::Bytes>> + Send>>,
+    interval: tokio::time::Interval,
+    line_start: bool,
+    task: tokio::task::JoinHandle<()>,
+}
+
+impl Keepalive {
+    fn new<S>(stream: S, period: Duration, task: tokio::task::JoinHandle<()>) -> Self
+    where
+        S: futures::Stream<Item = std::io::Result<bytes::Bytes>> + Send + 'static,
+    {
+        let mut interval = tokio::time::interval_at(tokio::time::Instant::now() + period, period);
+        interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        Keepalive {
+            inner: Box::pin(stream),
+            interval,
+            line_start: true,
+            task,
+        }
+    }
+}
+
+impl futures::Stream for Keepalive {
+    type Item = std::io::Result<bytes::Bytes>;
+
+    fn poll_next(
+        mut self: Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<Option<Self::Item>> {
+        use std::task::Poll;
+
+        match self.inner.as_mut().poll_next(cx) {
+            Poll::Ready(Some(Ok(chunk))) => {
+                if !chunk.is_empty() {
+                    self.line_start = chunk.ends_with(b"\n");
+                }
+                self.interval.reset();
+                return Poll::Ready(Some(Ok(chunk)));
+            }
+            Poll::Ready(other) => return Poll::Ready(other),
+            Poll::Pending => {}
+        }
+
+        match self.interval.poll_tick(cx) {
+            Poll::Ready(_) if self.line_start => {
+                Poll::Ready(Some(Ok(bytes::Bytes::from_static(b":\n"))))
+            }
+            _ => Poll::Pending,
+        }
+    }
+}
+
+impl Drop for Keepalive {
+    fn drop(&mut self) {
+        // The client has disconnected (or the stream has finished, in which
+        // case the task has, too).
+        self.task.abort();
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+/// What a [`BufferedSender`] does with an event when its buffer is full.
+pub enum Overflow {
+    /// The oldest buffered event is dropped, to make room for the new one.
+    /// This suits streams where only the latest state matters.
+    DropOldest,
+    /// The new event is dropped.
+    DropNewest,
+    /// Sending waits until there is room in the buffer.  This slows the
+    /// producer down to the pace of the client.
+    Backpressure,
+}
+
+/// Creates an endpoint that can handle SSE connections, buffering up to
+/// `capacity` events for each connection (at least one).  This is like
+/// [`endpoint`], but the handler is given a [`BufferedSender`], which
+/// handles a full buffer according to the given [`Overflow`] policy.
+///
+/// # Examples
+/// ```rust
+/// # use under::*;
+/// use under::sse::{BufferedSender, Overflow};
+///
+/// async fn handle(_: Request, sender: BufferedSender) -> Result<(), anyhow::Error> {
+///     for i in 0..10 {
+///         sender.send(None, &i.to_string(), None).await?;
+///     }
+///     Ok(())
+/// }
+///
+/// # #[tokio::main] async fn main() -> Result<(), anyhow::Error> {
+/// let mut http = under::http();
+/// http.at("/sse").get(under::sse::buffered(4, Overflow::DropOldest, handle));
+/// http.prepare();
+/// let mut response = http.handle(Request::get("/sse")?).await?;
+/// // Give the handler time to overflow the buffer, before reading it.
+/// tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+/// let body = response.data(512).into_text().await?;
+/// assert_eq!(body, "data:6\n\ndata:7\n\ndata:8\n\ndata:9\n\n");
+/// # Ok(())
+/// # }
+/// ```
+pub fn buffered<F, Fut>(capacity: usize, overflow: Overflow, handle: F) -> BufferedSseEndpoint<F>
+where
+    F: Fn(Request, BufferedSender) -> Fut + Send + Sync + 'static,
+    Fut: Future<Output = crate::Result<()>> + Send + 'static,
+{
+    BufferedSseEndpoint {
+        handle: Arc::new(handle),
+        capacity,
+        overflow,
+        heartbeat: None,
+    }
+}
 
-    tokio::task::spawn(async move {
+/// Upgrades a request to SSE, buffering up to `capacity` events (at least
+/// one).  This is like [`upgrade`], but the handler is given a
+/// [`BufferedSender`]; see [`buffered`].
+#[allow(clippy::missing_errors_doc)]
+pub fn upgrade_buffered<F, Fut>(
+    request: Request,
+    capacity: usize,
+    overflow: Overflow,
+    handle: F,
+) -> Result<Response, anyhow::Error>
+where
+    F: FnOnce(Request, BufferedSender) -> Fut + Send + Sync + 'static,
+    Fut: Future<Output = crate::Result<()>> + Send + 'static,
+{
+    Ok(handle_buffered_sse(
+        request,
+        (capacity, overflow),
+        None,
+        handle,
+    ))
+}
+
+#[derive(Debug, Clone)]
+/// An instance of a buffered SSE endpoint.
+///
+/// This is created by [`buffered`], and implements the [`crate::Endpoint`]
+/// trait.
+pub struct BufferedSseEndpoint<F> {
+    handle: Arc<F>,
+    capacity: usize,
+    overflow: Overflow,
+    heartbeat: Option<Duration>,
+}
+
+impl<F> BufferedSseEndpoint<F> {
+    /// Sends a keepalive comment whenever no data has been sent for the
+    /// given interval, and cancels the handler once the client disconnects.
+    /// See [`SseEndpoint::with_heartbeat`].
+    #[must_use]
+    pub fn with_heartbeat(mut self, interval: Duration) -> Self {
+        self.heartbeat = Some(interval);
+        self
+    }
+}
+
+#[async_trait]
+impl<F, Fut> crate::Endpoint for BufferedSseEndpoint<F>
+where
+    F: Fn(Request, BufferedSender) -> Fut + Send + Sync + 'static,
+    Fut: Future<Output = crate::Result<()>> + Send + 'static,
+{
+    async fn apply(self: Pin<&Self>, request: Request) -> Result<Response, anyhow::Error> {
+        let h = self.handle.clone();
+        #[allow(clippy::redundant_closure)]
+        let buffer = (self.capacity, self.overflow);
+        Ok(handle_buffered_sse(
+            request,
+            buffer,
+            self.heartbeat,
+            move |r, s| h(r, s),
+        ))
+    }
+
+    fn describe(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("BufferedSseEndpoint")
+            .field("handle", &std::any::type_name::<F>())
+            .field("capacity", &self.capacity)
+            .field("overflow", &self.overflow)
+            .field("heartbeat", &self.heartbeat)
+            .finish()
+    }
+}
+
+fn handle_buffered_sse<F, Fut>(
+    request: Request,
+    (capacity, overflow): (usize, Overflow),
+    heartbeat: Option<Duration>,
+    handle: F,
+) -> crate::Response
+where
+    F: FnOnce(Request, BufferedSender) -> Fut + Send + Sync + 'static,
+    Fut: Future<Output = crate::Result<()>> + Send + 'static,
+{
+    let shared = Arc::new(Buffer {
+        state: Mutex::new(BufferState {
+            events: VecDeque::with_capacity(capacity.max(1)),
+            senders: 1,
+            closed: false,
+            dropped: 0,
+        }),
+        capacity: capacity.max(1),
+        overflow,
+        readable: Notify::new(),
+        writable: Notify::new(),
+    });
+
+    let stream = futures::stream::unfold(Reader(shared.clone()), |reader| async move {
+        let event = reader.next().await?;
+        Some((Ok(event.into()), reader))
+    });
+
+    let sender = BufferedSender(shared);
+    let task = tokio::task::spawn(async move {
         handle(request, sender).await.ok();
     });
 
-    response
+    event_stream(stream, heartbeat, task)
+}
+
+/// The sending side of a buffered SSE connection.
+///
+/// This is created by [`buffered`] and [`upgrade_buffered`].  Each event is
+/// buffered as a whole, so dropping events never leaves a partial event in
+/// the stream.  Clones of the sender send to the same connection; the
+/// connection is closed once every clone has been dropped.
+pub struct BufferedSender(Arc<Buffer>);
+
+impl BufferedSender {
+    /// Sends a new message over SSE.  This has the same arguments as
+    /// [`Sender::send`].  With [`Overflow::Backpressure`], this waits until
+    /// there is room in the buffer; otherwise, this never waits.
+    ///
+    /// # Errors
+    /// Errors if the client has disconnected.
+    pub async fn send(
+        &self,
+        name: impl Into<Option<&str>>,
+        data: &str,
+        id: Option<&str>,
+    ) -> std::io::Result<()> {
+        let mut event = String::new();
+        if let Some(name) = name.into() {
+            event.push_str("event:");
+            event.push_str(name);
+            event.push('\n');
+        }
+        if let Some(id) = id {
+            event.push_str("id:");
+            event.push_str(id);
+            event.push('\n');
+        }
+        for line in data.lines() {
+            event.push_str("data:");
+            event.push_str(line);
+            event.push('\n');
+        }
+        event.push('\n');
+
+        self.push(event.into_bytes()).await
+    }
+
+    /// Sends a new "retry" message over SSE.  This has the same arguments as
+    /// [`Sender::send_retry`]; the duration is sent in milliseconds, as the
+    /// SSE specification requires.
+    ///
+    /// # Errors
+    /// Errors if the client has disconnected.
+    pub async fn send_retry(
+        &self,
+        duration: std::time::Duration,
+        id: Option<&str>,
+    ) -> std::io::Result<()> {
+        let mut event = String::new();
+        if let Some(id) = id {
+            event.push_str("id:");
+            event.push_str(id);
+            event.push('\n');
+        }
+        event.push_str("retry:");
+        event.push_str(&duration.as_millis().to_string());
+        event.push_str("\n\n");
+
+        self.push(event.into_bytes()).await
+    }
+
+    /// The number of events that have been dropped because the buffer was
+    /// full.
+    #[must_use]
+    pub fn dropped(&self) -> u64 {
+        self.0.lock().dropped
+    }
+
+    /// Whether the client has disconnected; if it has, every send errors.
+    #[must_use]
+    pub fn is_closed(&self) -> bool {
+        self.0.lock().closed
+    }
+
+    async fn push(&self, event: Vec<u8>) -> std::io::Result<()> {
+        let buffer = &self.0;
+        loop {
+            let writable = buffer.writable.notified();
+            tokio::pin!(writable);
+            {
+                let mut state = buffer.lock();
+                if state.closed {
+                    return Err(std::io::Error::new(
+                        std::io::ErrorKind::ConnectionAborted,
+                        "sse disconnected",
+                    ));
+                }
+
+                if state.events.len() < buffer.capacity {
+                    state.events.push_back(event);
+                    buffer.readable.notify_one();
+                    return Ok(());
+                }
+
+                match buffer.overflow {
+                    Overflow::DropNewest => {
+                        state.dropped += 1;
+                        return Ok(());
+                    }
+                    Overflow::DropOldest => {
+                        state.events.pop_front();
+                        state.events.push_back(event);
+                        state.dropped += 1;
+                        buffer.readable.notify_one();
+                        return Ok(());
+                    }
+                    // Register for the wakeup before releasing the lock, so
+                    // that a read in between is not missed.
+                    Overflow::Backpressure => writable.as_mut().enable(),
+                };
+            }
+            writable.await;
+        }
+    }
+}
+
+impl Clone for BufferedSender {
+    fn clone(&self) -> Self {
+        self.0.lock().senders += 1;
+        BufferedSender(self.0.clone())
+    }
+}
+
+impl Drop for BufferedSender {
+    fn drop(&mut self) {
+        let mut state = self.0.lock();
+        state.senders -= 1;
+        if state.senders == 0 {
+            self.0.readable.notify_one();
+        }
+    }
+}
+
+impl std::fmt::Debug for BufferedSender {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("BufferedSender")
+            .field("capacity", &self.0.capacity)
+            .field("overflow", &self.0.overflow)
+            .finish_non_exhaustive()
+    }
+}
+
+struct Buffer {
+    state: Mutex<BufferState>,
+    capacity: usize,
+    overflow: Overflow,
+    readable: Notify,
+    writable: Notify,
+}
+
+struct BufferState {
+    events: VecDeque<Vec<u8>>,
+    senders: usize,
+    closed: bool,
+    dropped: u64,
+}
+
+impl Buffer {
+    fn lock(&self) -> std::sync::MutexGuard<'_, BufferState> {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+/// The receiving side of the buffer, which is the body of the response.
+/// Once it is dropped (i.e. the client disconnected), the buffer is closed.
+struct Reader(Arc<Buffer>);
+
+impl Reader {
+    async fn next(&self) -> Option<Vec<u8>> {
+        loop {
+            {
+                let mut state = self.0.lock();
+                if let Some(event) = state.events.pop_front() {
+                    self.0.writable.notify_waiters();
+                    return Some(event);
+                } else if state.senders == 0 {
+                    return None;
+                }
+            }
+            self.0.readable.notified().await;
+        }
+    }
+}
+
+impl Drop for Reader {
+    fn drop(&mut self) {
+        self.0.lock().closed = true;
+        self.0.writable.notify_waiters();
+    }
 }
@@ -0,0 +1,70 @@
+use crate::Request;
+use hyper_util::rt::TokioIo;
+use std::borrow::BorrowMut;
+use std::future::Future;
+use std::io;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+
+/// A connection that has been upgraded from HTTP to another protocol.
+///
+/// This is given to the handler of [`crate::endpoints::tunnel`] (and used by
+/// WebSocket connections), once the response that upgraded the connection
+/// has been sent.  It is the raw, bidirectional connection to the client,
+/// and implements [`AsyncRead`] and [`AsyncWrite`].
+pub struct Upgraded(TokioIo<hyper::upgrade::Upgraded>);
+
+impl std::fmt::Debug for Upgraded {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Upgraded").finish_non_exhaustive()
+    }
+}
+
+/// Returns a future that resolves to the upgraded connection of the given
+/// request, once its response has been sent.  This fails if the connection
+/// does not support upgrading (e.g. the request did not come from a server).
+pub(crate) fn on(request: &mut Request) -> impl Future<Output = hyper::Result<Upgraded>> {
+    let upgrade = hyper::upgrade::on(BorrowMut::<http::Request<crate::Body>>::borrow_mut(request));
+    async move { upgrade.await.map(|io| Upgraded(TokioIo::new(io))) }
+}
+
+impl AsyncRead for Upgraded {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.0).poll_read(cx, buf)
+    }
+}
+
+impl AsyncWrite for Upgraded {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.0).poll_write(cx, buf)
+    }
+
+    fn poll_write_vectored(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        bufs: &[io::IoSlice<'_>],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.0).poll_write_vectored(cx, bufs)
+    }
+
+    fn is_write_vectored(&self) -> bool {
+        self.0.is_write_vectored()
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.0).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.0).poll_shutdown(cx)
+    }
+}
@@ -0,0 +1,369 @@
+//! Request validation.
+//!
+//! Extracting a request - its query string, form, or JSON body - can fail in
+//! more than one place, and clients are best served by learning about every
+//! invalid field at once, rather than one per request.  [`Validation`]
+//! collects field errors from each extractor (and from the application's own
+//! checks), and, if it is returned from an endpoint, the router turns it into
+//! a single 422 response, with the messages for each field:
+//!
+//! ```json
+//! {"error":"Unprocessable Entity","fields":{"age":["is required"]}}
+//! ```
+//!
+//! # Examples
+//! ```rust
+//! # use under::*;
+//! use under::validation::Validation;
+//!
+//! #[derive(serde::Deserialize)]
+//! struct Page { page: u32 }
+//!
+//! #[derive(serde::Deserialize)]
+//! struct Comment { body: String }
+//!
+//! async fn comment(mut request: Request) -> Result<Response, anyhow::Error> {
+//!     let mut validation = Validation::new();
+//!     let _page = validation.collect(request.require_query::<Page>())?;
+//!     let comment = validation.collect(request.require_json::<Comment>(4096).await)?;
+//!     if let Some(comment) = &comment {
+//!         validation.check(!comment.body.is_empty(), "body", "must not be empty");
+//!     }
+//!     validation.finish()?;
+//!     Ok(Response::empty_204())
+//! }
+//!
+//! # #[tokio::main] async fn main() -> Result<(), anyhow::Error> {
+//! let mut http = under::http();
+//! http.at("/comments").post(comment);
+//! http.prepare();
+//! let request = Request::post("/comments?page=first")?
+//!     .with_header("Content-Type", "application/json")?
+//!     .with_body(r#"{"body": ""}"#);
+//! let mut response = http.handle(request).await?;
+//! assert_eq!(response.status(), http::StatusCode::UNPROCESSABLE_ENTITY);
+//! let body = response.data(512).into_json::<serde_json::Value>().await?;
+//! assert_eq!(body["fields"]["page"][0], "invalid digit found in string");
+//! assert_eq!(body["fields"]["body"][0], "must not be empty");
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::Response;
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, serde::Serialize)]
+/// A single invalid field.
+pub struct FieldError {
+    /// The path to the field, e.g. `user.name` or `items[0]`.  This is empty
+    /// if the error is not for any particular field.
+    pub field: String,
+    /// The message describing why the field is invalid, e.g. `is required`.
+    pub message: String,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, thiserror::Error)]
+#[error("{} invalid field(s)", errors.len())]
+/// A collection of field errors.
+///
+/// If this is returned (as an error) from an endpoint or middleware, the
+/// router converts it into a 422 response (see [`Validation::to_response`]).
+pub struct Validation {
+    errors: Vec<FieldError>,
+}
+
+impl Validation {
+    /// Creates an empty validation, with no errors.
+    #[must_use]
+    pub fn new() -> Self {
+        Validation::default()
+    }
+
+    /// Records an error for the given field.
+    pub fn add<F: Into<String>, M: Into<String>>(&mut self, field: F, message: M) -> &mut Self {
+        self.errors.push(FieldError {
+            field: field.into(),
+            message: message.into(),
+        });
+        self
+    }
+
+    /// Records an error for the given field, if `valid` is `false`.
+    pub fn check<F: Into<String>, M: Into<String>>(
+        &mut self,
+        valid: bool,
+        field: F,
+        message: M,
+    ) -> &mut Self {
+        if !valid {
+            self.add(field, message);
+        }
+        self
+    }
+
+    /// Records the field errors of a failed extraction, returning the
+    /// extracted value if it succeeded.  If the extraction failed for a
+    /// reason other than invalid fields (e.g. the body had the wrong content
+    /// type, or could not be read), that error is returned instead, so that
+    /// it can be passed back up with `?`.
+    ///
+    /// # Errors
+    /// Errors if the extraction failed, and its error could not be converted
+    /// into field errors (see [`IntoValidation`]).
+    pub fn collect<T, E: IntoValidation>(&mut self, result: Result<T, E>) -> Result<Option<T>, E> {
+        match result {
+            Ok(value) => Ok(Some(value)),
+            Err(error) => {
+                self.errors.extend(error.into_validation()?.errors);
+                Ok(None)
+            }
+        }
+    }
+
+    /// Whether or not any errors have been recorded.
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.errors.is_empty()
+    }
+
+    /// The errors that have been recorded, in the order they were recorded.
+    #[must_use]
+    pub fn errors(&self) -> &[FieldError] {
+        &self.errors
+    }
+
+    /// Completes the validation.
+    ///
+    /// # Errors
+    /// Returns `self` as the error if any errors have been recorded.
+    pub fn finish(self) -> Result<(), Validation> {
+        if self.is_empty() {
+            Ok(())
+        } else {
+            Err(self)
+        }
+    }
+
+    /// Generates the response for this validation: a 422, with a JSON body
+    /// mapping each field to its messages.  Errors that are not for any
+    /// particular field are listed under the empty field name.
+    ///
+    /// # Examples
+    /// ```rust
+    /// # use under::*;
+    /// use under::validation::Validation;
+    /// # #[tokio::main] async fn main() -> Result<(), anyhow::Error> {
+    /// let mut validation = Validation::new();
+    /// validation.add("name", "is required").add("name", "is too short");
+    /// let mut response = validation.to_response();
+    /// assert_eq!(response.status(), http::StatusCode::UNPROCESSABLE_ENTITY);
+    /// let body = response.data(512).into_text().await?;
+    /// assert_eq!(
+    ///     body,
+    ///     r#"{"error":"Unprocessable Entity","fields":{"name":["is required","is too short"]}}"#
+    /// );
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn to_response(&self) -> Response {
+        let status = http::StatusCode::UNPROCESSABLE_ENTITY;
+        let mut fields = serde_json::Map::new();
+        for error in &self.errors {
+            let messages = fields
+                .entry(error.field.clone())
+                .or_insert_with(|| serde_json::Value::Array(vec![]));
+            if let serde_json::Value::Array(messages) = messages {
+                messages.push(error.message.clone().into());
+            }
+        }
+
+        let body = serde_json::json!({
+            "error": status.canonical_reason().unwrap_or("error"),
+            "fields": fields,
+        });
+        Response::json(&body).map_or_else(|_| Response::empty(status), |r| r.with_status(status))
+    }
+
+    /// Records an error from a deserializer, at the given path.  Missing
+    /// fields are reported against the field itself, rather than its parent.
+    fn add_deserialize(&mut self, path: &str, message: &str) {
+        let path = if path == "." { "" } else { path };
+        // serde_json appends the position to its messages; that is not
+        // useful for a single field.
+        let message = message
+            .rsplit_once(" at line ")
+            .map_or(message, |(message, _)| message);
+        let missing = message
+            .strip_prefix("missing field `")
+            .and_then(|m| m.strip_suffix('`'));
+
+        match (missing, path) {
+            (Some(field), "") => self.add(field, "is required"),
+            (Some(field), path) => self.add(format!("{path}.{field}"), "is required"),
+            (None, path) => self.add(path, message),
+        };
+    }
+}
+
+impl FromIterator<FieldError> for Validation {
+    fn from_iter<I: IntoIterator<Item = FieldError>>(iter: I) -> Self {
+        Validation {
+            errors: iter.into_iter().collect(),
+        }
+    }
+}
+
+/// Errors that may describe invalid fields.
+///
+/// This is used by [`Validation::collect`], and is implemented for the
+/// errors of the extractors: [`crate::from_form::FromFormError`],
+/// [`crate::json::JsonRejection`], and [`crate::UnderError`] (for
+/// [`crate::DataStream::into_form`]).  Each of these, when returned from an
+/// endpoint, is also turned into a 422 (if it describes invalid fields).
+///
+/// # Examples
+/// ```rust
+/// # use under::*;
+/// #[derive(FromForm)]
+/// struct Login { username: String, password: String }
+///
+/// # #[tokio::main] async fn main() -> Result<(), anyhow::Error> {
+/// let mut http = under::http();
+/// http.at("/login").post(|mut request: Request| async move {
+///     let _login: Login = request.data(512).into_form().await?;
+///     Ok::<_, anyhow::Error>(Response::empty_204())
+/// });
+/// http.prepare();
+/// let request = Request::post("/login")?
+///     .with_header("Content-Type", "application/x-www-form-urlencoded")?
+///     .with_body("username=sergio");
+/// let mut response = http.handle(request).await?;
+/// assert_eq!(response.status(), http::StatusCode::UNPROCESSABLE_ENTITY);
+/// let body = response.data(512).into_json::<serde_json::Value>().await?;
+/// assert_eq!(body["fields"]["password"][0], "is required");
+/// # Ok(())
+/// # }
+/// ```
+pub trait IntoValidation: Sized {
+    /// Converts this error into field errors; or, if it is not about the
+    /// fields of the request, returns it back.
+    ///
+    /// # Errors
+    /// Returns `self` if it does not describe invalid fields.
+    fn into_validation(self) -> Result<Validation, Self>;
+}
+
+impl IntoValidation for Validation {
+    fn into_validation(self) -> Result<Validation, Self> {
+        Ok(self)
+    }
+}
+
+#[cfg(feature = "from_form")]
+#[cfg_attr(docsrs, doc(cfg(feature = "from_form")))]
+impl IntoValidation for crate::from_form::FromFormError {
+    fn into_validation(self) -> Result<Validation, Self> {
+        Ok(self.into())
+    }
+}
+
+#[cfg(feature = "from_form")]
+#[cfg_attr(docsrs, doc(cfg(feature = "from_form")))]
+impl From<crate::from_form::FromFormError> for Validation {
+    fn from(error: crate::from_form::FromFormError) -> Self {
+        Validation::from(&error)
+    }
+}
+
+#[cfg(feature = "from_form")]
+#[cfg_attr(docsrs, doc(cfg(feature = "from_form")))]
+impl From<&crate::from_form::FromFormError> for Validation {
+    fn from(error: &crate::from_form::FromFormError) -> Self {
+        use crate::from_form::FromFormError;
+
+        fn add(validation: &mut Validation, error: &FromFormError) {
+            match error {
+                FromFormError::MissingField(field) => {
+                    validation.add(*field, "is required");
+                }
+                FromFormError::InvalidFormat(field, _, error) => {
+                    validation.add(*field, error.to_string());
+                }
+                FromFormError::Invalid(field, message) => {
+                    validation.add(*field, message.as_str());
+                }
+                FromFormError::Multiple(errors) => {
+                    for error in errors {
+                        add(validation, error);
+                    }
+                }
+            }
+        }
+
+        let mut validation = Validation::new();
+        add(&mut validation, error);
+        validation
+    }
+}
+
+impl IntoValidation for crate::json::JsonRejection {
+    /// Only rejections for bodies that are valid JSON, but do not match the
+    /// expected type (i.e., those that generate a 422), are converted.
+    fn into_validation(self) -> Result<Validation, Self> {
+        match self {
+            crate::json::JsonRejection::Invalid { path, error } if error.is_data() => {
+                let mut validation = Validation::new();
+                validation.add_deserialize(path.as_deref().unwrap_or(""), &error.to_string());
+                Ok(validation)
+            }
+            rejection => Err(rejection),
+        }
+    }
+}
+
+impl IntoValidation for crate::UnderError {
+    /// Only [`crate::UnderError::FormDeserialization`] is converted.
+    fn into_validation(self) -> Result<Validation, Self> {
+        match self {
+            #[cfg(feature = "from_form")]
+            crate::UnderError::FormDeserialization(error) => Ok(error.into()),
+            error => Err(error),
+        }
+    }
+}
+
+/// A value that records where deserializing it failed, instead of failing.
+/// `serde_qs` does not expose its deserializer, so the path has to be tracked
+/// from the inside.
+enum Tracked<T> {
+    Ok(T),
+    Invalid(String, String),
+}
+
+impl<'de, T: serde::Deserialize<'de>> serde::Deserialize<'de> for Tracked<T> {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        match serde_path_to_error::deserialize(deserializer) {
+            Ok(value) => Ok(Tracked::Ok(value)),
+            Err(error) => Ok(Tracked::Invalid(
+                error.path().to_string(),
+                error.into_inner().to_string(),
+            )),
+        }
+    }
+}
+
+/// Deserializes the given query string, recording any errors against the
+/// field they are for.  A missing query string is treated as empty.
+pub(crate) fn query<T: serde::de::DeserializeOwned>(
+    query: Option<&str>,
+    config: crate::QueryConfig,
+) -> Result<T, Validation> {
+    let mut validation = Validation::new();
+    match config.parse::<Tracked<T>>(query.unwrap_or("")) {
+        Ok(Tracked::Ok(value)) => return Ok(value),
+        Ok(Tracked::Invalid(path, message)) => validation.add_deserialize(&path, &message),
+        Err(error) => {
+            validation.add("", error.to_string());
+        }
+    }
+    Err(validation)
+}
@@ -0,0 +1,117 @@
+//! Well-known routes.
+//!
+//! Most HTTP applications end up with the same handful of boilerplate
+//! routes: a health check, a `security.txt`, a `robots.txt`, and somewhere
+//! for an ACME client to place its challenges.  This provides helpers for mounting
+//! these onto a router; see [`crate::Router::well_known`].
+
+use crate::endpoints::DocumentEndpoint;
+use crate::{Endpoint, Response, Router};
+
+const TEXT: &str = "text/plain; charset=utf-8";
+
+#[derive(Debug)]
+/// A builder for mounting well-known routes onto a router.
+///
+/// This is created by [`crate::Router::well_known`].  Each method mounts a
+/// single route onto the router, and returns the builder, so that they can
+/// be chained.
+///
+/// # Examples
+/// ```rust
+/// # use under::*;
+/// # #[tokio::main] async fn main() -> Result<(), anyhow::Error> {
+/// let mut http = under::http();
+/// http.well_known()
+///     .health()
+///     .security_txt("Contact: mailto:security@example.com\n")
+///     .robots_txt("User-agent: *\nDisallow: /admin\n");
+/// http.prepare();
+/// let response = http.handle(Request::get("/.well-known/health")?).await?;
+/// assert_eq!(response.status(), http::StatusCode::OK);
+/// let mut response = http.handle(Request::get("/.well-known/security.txt")?).await?;
+/// let body = response.data(512).into_text().await?;
+/// assert_eq!(body, "Contact: mailto:security@example.com\n");
+/// let response = http.handle(Request::get("/robots.txt")?).await?;
+/// assert_eq!(response.status(), http::StatusCode::OK);
+/// # Ok(())
+/// # }
+/// ```
+pub struct WellKnown<'a> {
+    router: &'a mut Router,
+}
+
+impl<'a> WellKnown<'a> {
+    pub(crate) fn new(router: &'a mut Router) -> Self {
+        WellKnown { router }
+    }
+
+    /// Mounts a health check at `GET /.well-known/health`, which always
+    /// responds with a 200, with a body of `ok`.  For a health check that
+    /// actually checks something, see [`WellKnown::health_check`].
+    pub fn health(&mut self) -> &mut Self {
+        self.health_check(crate::endpoints::simple(|| Response::text("ok")))
+    }
+
+    /// Mounts the given endpoint as the health check, at
+    /// `GET /.well-known/health`.
+    ///
+    /// # Examples
+    /// ```rust
+    /// # use under::*;
+    /// # #[tokio::main] async fn main() -> Result<(), anyhow::Error> {
+    /// let mut http = under::http();
+    /// http.well_known()
+    ///     .health_check(under::endpoints::simple(Response::empty_503));
+    /// http.prepare();
+    /// let response = http.handle(Request::get("/.well-known/health")?).await?;
+    /// assert_eq!(response.status(), http::StatusCode::SERVICE_UNAVAILABLE);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn health_check<E: Endpoint>(&mut self, endpoint: E) -> &mut Self {
+        self.router.at("/.well-known/health").get(endpoint);
+        self
+    }
+
+    /// Mounts the given contents as the [`security.txt`] of the site, at
+    /// `GET /.well-known/security.txt`.  The contents are served as-is, with
+    /// an `ETag`.
+    ///
+    /// [`security.txt`]: https://securitytxt.org/
+    pub fn security_txt<S: Into<String>>(&mut self, contents: S) -> &mut Self {
+        let endpoint = DocumentEndpoint::new(contents.into(), TEXT);
+        self.router.at("/.well-known/security.txt").get(endpoint);
+        self
+    }
+
+    /// Mounts the given contents as the `robots.txt` of the site, at
+    /// `GET /robots.txt`.  Note that, unlike the other routes, this is not
+    /// under `/.well-known/`, as crawlers do not look for it there.  The
+    /// contents are served as-is, with an `ETag`.
+    pub fn robots_txt<S: Into<String>>(&mut self, contents: S) -> &mut Self {
+        let endpoint = DocumentEndpoint::new(contents.into(), TEXT);
+        self.router.at("/robots.txt").get(endpoint);
+        self
+    }
+
+    /// Serves ACME (e.g. Let's Encrypt) HTTP-01 challenges from the given
+    /// directory, at `GET /.well-known/acme-challenge/{token}`.  The ACME
+    /// client should be configured to write its challenge files into this
+    /// directory (this is sometimes called "webroot" mode).  This uses
+    /// [`crate::endpoints::dir`], so the same restrictions on the paths
+    /// apply.
+    ///
+    /// # Examples
+    /// ```rust
+    /// # use under::*;
+    /// let mut http = under::http();
+    /// http.well_known().acme_challenge("/var/www/acme");
+    /// ```
+    pub fn acme_challenge<P: Into<std::path::PathBuf>>(&mut self, path: P) -> &mut Self {
+        self.router
+            .at("/.well-known/acme-challenge/{:path}")
+            .get(crate::endpoints::dir(path));
+        self
+    }
+}
@@ -0,0 +1,614 @@
+//! WebSocket connections.
+//!
+//! This adds support for upgrading HTTP requests to WebSocket connections,
+//! using the `tokio-tungstenite` crate.  The connection is wrapped in a
+//! [`WebSocket`], which takes care of the framing hygiene most applications
+//! would otherwise have to re-implement: it answers pings, sends its own
+//! pings to keep the connection alive (and to detect dead clients), and has
+//! helpers for sending and receiving JSON messages.  Connections can also
+//! join named rooms of a [`Hub`], so that messages can be broadcast to every
+//! client in a room.  It is gated behind the `websocket` feature flag for
+//! those who do not want to use it.
+
+use crate::{HttpEntity, Request, Response, Upgraded};
+use futures::{SinkExt, StreamExt};
+use std::collections::HashMap;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::Duration;
+use tokio::sync::mpsc;
+use tokio_tungstenite::tungstenite::handshake::derive_accept_key;
+use tokio_tungstenite::tungstenite::protocol::frame::coding::CloseCode;
+use tokio_tungstenite::tungstenite::protocol::{CloseFrame, Role};
+pub use tokio_tungstenite::tungstenite::{Error, Message};
+use tokio_tungstenite::WebSocketStream;
+
+/// The default interval between keepalive pings.
+const DEFAULT_PING_INTERVAL: Duration = Duration::from_secs(30);
+/// The number of broadcast messages that can be queued for a connection
+/// before further broadcasts to it are dropped.
+const BROADCAST_CAPACITY: usize = 64;
+
+/// Creates an endpoint that can handle WebSocket connections.  This upgrades
+/// every valid WebSocket handshake, before calling the handler function with
+/// the current request and the connection; requests that are not WebSocket
+/// handshakes receive a 400.
+///
+/// # Examples
+/// ```rust,no_run
+/// # use under::*;
+/// use under::ws::WebSocket;
+///
+/// async fn echo(_: Request, mut ws: WebSocket) -> Result<(), anyhow::Error> {
+///     while let Some(message) = ws.recv().await {
+///         ws.send(message?).await?;
+///     }
+///     Ok(())
+/// }
+///
+/// let mut http = under::http();
+/// http.at("/ws").get(under::ws::endpoint(echo));
+/// ```
+pub fn endpoint<F, Fut>(handle: F) -> WsEndpoint<F>
+where
+    F: Fn(Request, WebSocket) -> Fut + Send + Sync + 'static,
+    Fut: Future<Output = crate::Result<()>> + Send + 'static,
+{
+    WsEndpoint::new(handle)
+}
+
+/// Upgrades a request to a WebSocket connection.  This allows you to check
+/// beforehand if a request should be upgraded (e.g. with [`is_upgrade`]),
+/// instead of [`endpoint`], which upgrades every handshake.  The connection
+/// pings the client every 30 seconds.
+///
+/// # Errors
+/// This returns an error if the request is not a valid WebSocket handshake.
+///
+/// # Examples
+/// ```rust,no_run
+/// # use under::*;
+/// use under::ws::WebSocket;
+///
+/// async fn chat(request: Request, mut ws: WebSocket) -> Result<(), anyhow::Error> {
+///     ws.send_text("hello, world!").await?;
+///     Ok(())
+/// }
+///
+/// async fn handle(request: Request) -> Result<Response, anyhow::Error> {
+///     if under::ws::is_upgrade(&request) {
+///         under::ws::upgrade(request, chat)
+///     } else {
+///         Ok(Response::text("try connecting with a WebSocket"))
+///     }
+/// }
+///
+/// let mut http = under::http();
+/// http.at("/chat").get(handle);
+/// ```
+pub fn upgrade<F, Fut>(request: Request, handle: F) -> Result<Response, anyhow::Error>
+where
+    F: FnOnce(Request, WebSocket) -> Fut + Send + 'static,
+    Fut: Future<Output = crate::Result<()>> + Send + 'static,
+{
+    handle_ws(request, Some(DEFAULT_PING_INTERVAL), handle)
+        .ok_or_else(|| anyhow::anyhow!("the request is not a WebSocket handshake"))
+}
+
+/// Checks whether the given request is a WebSocket handshake - i.e., that it
+/// is a `GET` request, asking to upgrade to `websocket` with version 13 of
+/// the protocol, and that it has a `Sec-WebSocket-Key`.
+///
+/// # Examples
+/// ```rust
+/// # use under::*;
+/// let request = Request::get("/ws").unwrap()
+///     .with_header("Connection", "keep-alive, Upgrade").unwrap()
+///     .with_header("Upgrade", "websocket").unwrap()
+///     .with_header("Sec-WebSocket-Version", "13").unwrap()
+///     .with_header("Sec-WebSocket-Key", "dGhlIHNhbXBsZSBub25jZQ==").unwrap();
+/// assert!(under::ws::is_upgrade(&request));
+/// assert!(!under::ws::is_upgrade(&Request::get("/ws").unwrap()));
+/// ```
+#[must_use]
+pub fn is_upgrade(request: &Request) -> bool {
+    let contains = |name: http::header::HeaderName, token: &str| {
+        request.headers().get_all(name).iter().any(|value| {
+            value.to_str().is_ok_and(|value| {
+                value
+                    .split(',')
+                    .any(|part| part.trim().eq_ignore_ascii_case(token))
+            })
+        })
+    };
+
+    request.method() == http::Method::GET
+        && contains(http::header::CONNECTION, "upgrade")
+        && contains(http::header::UPGRADE, "websocket")
+        && request
+            .header(http::header::SEC_WEBSOCKET_VERSION)
+            .is_some_and(|v| v == "13")
+        && request.header(http::header::SEC_WEBSOCKET_KEY).is_some()
+}
+
+#[derive(Debug, Clone)]
+/// An instance of a WebSocket endpoint.
+///
+/// This is created by [`endpoint`], and implements the [`crate::Endpoint`]
+/// trait.
+///
+/// # Examples
+/// ```rust
+/// # use under::*;
+/// # async fn echo(_: Request, _: under::ws::WebSocket) -> Result<(), anyhow::Error> { Ok(()) }
+/// # #[tokio::main] async fn main() -> Result<(), anyhow::Error> {
+/// let mut http = under::http();
+/// http.at("/ws").get(under::ws::endpoint(echo));
+/// http.prepare();
+/// let request = Request::get("/ws")?
+///     .with_header("Connection", "Upgrade")?
+///     .with_header("Upgrade", "websocket")?
+///     .with_header("Sec-WebSocket-Version", "13")?
+///     .with_header("Sec-WebSocket-Key", "dGhlIHNhbXBsZSBub25jZQ==")?;
+/// let response = http.handle(request).await?;
+/// assert_eq!(response.status(), http::StatusCode::SWITCHING_PROTOCOLS);
+/// assert_eq!(
+///     response.header("Sec-WebSocket-Accept").unwrap(),
+///     "s3pPLMBiTxaQ9kYGzzhZRbK+xOo="
+/// );
+/// let response = http.handle(Request::get("/ws")?).await?;
+/// assert_eq!(response.status(), http::StatusCode::BAD_REQUEST);
+/// # Ok(())
+/// # }
+/// ```
+pub struct WsEndpoint<F> {
+    handle: Arc<F>,
+    ping_interval: Option<Duration>,
+}
+
+impl<F> WsEndpoint<F> {
+    fn new(handle: F) -> Self {
+        WsEndpoint {
+            handle: Arc::new(handle),
+            ping_interval: Some(DEFAULT_PING_INTERVAL),
+        }
+    }
+
+    /// Sets the interval between the keepalive pings sent to the client.  If
+    /// the client does not send anything back within an interval of a ping,
+    /// the connection is considered dead, and [`WebSocket::recv`] returns
+    /// `None`.  Passing `None` disables the keepalive pings.  This defaults
+    /// to 30 seconds.
+    ///
+    /// # Examples
+    /// ```rust,no_run
+    /// # use under::*;
+    /// use std::time::Duration;
+    /// # async fn echo(_: Request, _: under::ws::WebSocket) -> Result<(), anyhow::Error> { Ok(()) }
+    /// let mut http = under::http();
+    /// http.at("/ws").get(under::ws::endpoint(echo).with_ping_interval(Some(Duration::from_secs(5))));
+    /// ```
+    #[must_use]
+    pub fn with_ping_interval(mut self, interval: Option<Duration>) -> Self {
+        self.ping_interval = interval;
+        self
+    }
+}
+
+#[async_trait]
+impl<F, Fut> crate::Endpoint for WsEndpoint<F>
+where
+    F: Fn(Request, WebSocket) -> Fut + Send + Sync + 'static,
+    Fut: Future<Output = crate::Result<()>> + Send + 'static,
+{
+    async fn apply(self: Pin<&Self>, request: Request) -> Result<Response, anyhow::Error> {
+        let h = self.handle.clone();
+        // we need this for lifetime extension.  If we pass in `h` directly,
+        // `h` would be bound to the lifetime of this function.
+        #[allow(clippy::redundant_closure)]
+        let response = handle_ws(request, self.ping_interval, move |r, s| h(r, s));
+        Ok(response.unwrap_or_else(Response::empty_400))
+    }
+
+    fn describe(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("WsEndpoint")
+            .field(&std::any::type_name::<F>())
+            .finish()
+    }
+}
+
+fn handle_ws<F, Fut>(
+    mut request: Request,
+    ping_interval: Option<Duration>,
+    handle: F,
+) -> Option<Response>
+where
+    F: FnOnce(Request, WebSocket) -> Fut + Send + 'static,
+    Fut: Future<Output = crate::Result<()>> + Send + 'static,
+{
+    if !is_upgrade(&request) {
+        return None;
+    }
+
+    let key = request.header(http::header::SEC_WEBSOCKET_KEY)?;
+    let accept = derive_accept_key(key.as_bytes());
+    let upgrade = crate::upgrade::on(&mut request);
+    let response = Response::empty(http::StatusCode::SWITCHING_PROTOCOLS)
+        .with_header(http::header::CONNECTION, "Upgrade")
+        .expect("Connection is a valid header")
+        .with_header(http::header::UPGRADE, "websocket")
+        .expect("Upgrade is a valid header")
+        .with_header(http::header::SEC_WEBSOCKET_ACCEPT, accept)
+        .expect("Sec-WebSocket-Accept is a valid header");
+
+    let path = request.uri().path().to_owned();
+    tokio::task::spawn(async move {
+        match upgrade.await {
+            Ok(upgraded) => {
+                let stream = WebSocketStream::from_raw_socket(upgraded, Role::Server, None).await;
+                if let Err(error) = handle(request, WebSocket::new(stream, ping_interval)).await {
+                    log::error!("error handling WebSocket on {path}: {error:?}");
+                }
+            }
+            Err(error) => log::warn!("could not upgrade to a WebSocket: {error}"),
+        }
+    });
+
+    Some(response)
+}
+
+/// A WebSocket connection.
+///
+/// This is given to the handlers of [`endpoint`] and [`upgrade`].  Pings from
+/// the client are answered automatically; and, unless disabled, the
+/// connection pings the client periodically, considering the connection
+/// dead if the client goes quiet for a full interval afterwards (see
+/// [`WsEndpoint::with_ping_interval`]).  Pings and pongs are never returned
+/// from [`WebSocket::recv`].
+pub struct WebSocket {
+    stream: WebSocketStream<Upgraded>,
+    ping: Option<tokio::time::Interval>,
+    awaiting_pong: bool,
+    broadcasts: (mpsc::Sender<Message>, mpsc::Receiver<Message>),
+}
+
+impl WebSocket {
+    fn new(stream: WebSocketStream<Upgraded>, ping_interval: Option<Duration>) -> Self {
+        let ping = ping_interval.map(|period| {
+            let mut interval =
+                tokio::time::interval_at(tokio::time::Instant::now() + period, period);
+            interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+            interval
+        });
+
+        WebSocket {
+            stream,
+            ping,
+            awaiting_pong: false,
+            broadcasts: mpsc::channel(BROADCAST_CAPACITY),
+        }
+    }
+
+    /// Joins the given room of the hub, so that messages broadcast to the
+    /// room are sent to this client.  Broadcasts are sent while waiting for
+    /// the next message from the client, in [`WebSocket::recv`].  Joining a
+    /// room that this connection is already a part of does nothing.  The
+    /// connection leaves all of its rooms when it is dropped.  Up to 64
+    /// broadcasts are queued for the connection; if it falls further behind
+    /// than that, further broadcasts to it are dropped until it catches up.
+    pub fn join(&self, hub: &Hub, room: &str) {
+        hub.join(room, &self.broadcasts.0);
+    }
+
+    /// Leaves the given room of the hub.
+    pub fn leave(&self, hub: &Hub, room: &str) {
+        hub.leave(room, &self.broadcasts.0);
+    }
+
+    /// Sends a message to the client.
+    ///
+    /// # Errors
+    /// This returns an error if the message could not be sent; this implies
+    /// an issue with the underlying connection.
+    pub async fn send(&mut self, message: Message) -> Result<(), Error> {
+        self.stream.send(message).await
+    }
+
+    /// Sends a text message to the client.
+    ///
+    /// # Errors
+    /// This errors for the same reasons as [`WebSocket::send`].
+    pub async fn send_text<S: Into<String>>(&mut self, text: S) -> Result<(), Error> {
+        self.send(Message::Text(text.into())).await
+    }
+
+    /// Serializes the given value as JSON, and sends it to the client as a
+    /// text message.
+    ///
+    /// # Errors
+    /// This errors if the value could not be serialized, or for the same
+    /// reasons as [`WebSocket::send`].
+    ///
+    /// # Examples
+    /// ```rust,no_run
+    /// # use under::*;
+    /// use under::ws::WebSocket;
+    /// async fn greet(_: Request, mut ws: WebSocket) -> Result<(), anyhow::Error> {
+    ///     ws.send_json(&serde_json::json!({ "hello": "world" })).await?;
+    ///     Ok(())
+    /// }
+    /// ```
+    pub async fn send_json<T: serde::Serialize>(&mut self, value: &T) -> Result<(), anyhow::Error> {
+        let text = serde_json::to_string(value)?;
+        self.send_text(text).await?;
+        Ok(())
+    }
+
+    /// Receives the next message from the client.  While waiting, this also
+    /// sends any messages broadcast to the rooms this connection has joined.
+    /// This returns `None` once the connection has been closed (by either
+    /// side), or if the client stopped responding to the keepalive pings.
+    ///
+    /// # Errors
+    /// This returns an error (in the `Some` case) if the next message could
+    /// not be read from the connection, or if a broadcast message could not
+    /// be sent.
+    pub async fn recv(&mut self) -> Option<Result<Message, Error>> {
+        loop {
+            let message = tokio::select! {
+                message = self.stream.next() => message,
+                Some(broadcast) = self.broadcasts.1.recv() => {
+                    if let Err(error) = self.stream.send(broadcast).await {
+                        return Some(Err(error));
+                    }
+                    continue;
+                }
+                () = tick(&mut self.ping) => {
+                    if self.awaiting_pong {
+                        log::debug!("closing unresponsive WebSocket");
+                        return None;
+                    }
+
+                    self.awaiting_pong = true;
+                    if let Err(error) = self.stream.send(Message::Ping(vec![])).await {
+                        return Some(Err(error));
+                    }
+                    continue;
+                }
+            };
+
+            // Anything from the client shows that it is still there.
+            self.awaiting_pong = false;
+            match message? {
+                Ok(Message::Ping(_) | Message::Pong(_) | Message::Frame(_)) => {}
+                Ok(Message::Close(_)) | Err(Error::ConnectionClosed) => return None,
+                other => return Some(other),
+            }
+        }
+    }
+
+    /// Receives the next text (or binary) message from the client, and
+    /// deserializes it from JSON.  This returns `None` under the same
+    /// circumstances as [`WebSocket::recv`].
+    ///
+    /// # Errors
+    /// This errors if the message could not be read, or if it could not be
+    /// deserialized into `T`.
+    ///
+    /// # Examples
+    /// ```rust,no_run
+    /// # use under::*;
+    /// use under::ws::WebSocket;
+    ///
+    /// #[derive(serde::Deserialize, serde::Serialize)]
+    /// struct Chat { text: String }
+    ///
+    /// async fn chat(_: Request, mut ws: WebSocket) -> Result<(), anyhow::Error> {
+    ///     while let Some(chat) = ws.recv_json::<Chat>().await? {
+    ///         ws.send_json(&chat).await?;
+    ///     }
+    ///     Ok(())
+    /// }
+    /// ```
+    pub async fn recv_json<T: serde::de::DeserializeOwned>(
+        &mut self,
+    ) -> Result<Option<T>, anyhow::Error> {
+        match self.recv().await.transpose()? {
+            Some(Message::Text(text)) => Ok(Some(serde_json::from_str(&text)?)),
+            Some(Message::Binary(bytes)) => Ok(Some(serde_json::from_slice(&bytes)?)),
+            Some(_) => Err(anyhow::anyhow!("received an unexpected message")),
+            None => Ok(None),
+        }
+    }
+
+    /// Closes the connection with the given [close code] and reason.  Any
+    /// messages received from the client afterwards are discarded.
+    ///
+    /// [close code]: https://www.rfc-editor.org/rfc/rfc6455#section-7.4.1
+    ///
+    /// # Errors
+    /// This returns an error if the close frame could not be sent.
+    ///
+    /// # Examples
+    /// ```rust,no_run
+    /// # use under::*;
+    /// use under::ws::WebSocket;
+    /// async fn reject(_: Request, ws: WebSocket) -> Result<(), anyhow::Error> {
+    ///     ws.close_with(1008, "not allowed").await?;
+    ///     Ok(())
+    /// }
+    /// ```
+    pub async fn close_with<S: Into<String>>(mut self, code: u16, reason: S) -> Result<(), Error> {
+        let frame = CloseFrame {
+            code: CloseCode::from(code),
+            reason: reason.into().into(),
+        };
+
+        match self.stream.close(Some(frame)).await {
+            Ok(()) | Err(Error::ConnectionClosed) => Ok(()),
+            Err(error) => Err(error),
+        }
+    }
+}
+
+impl std::fmt::Debug for WebSocket {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("WebSocket")
+            .field(
+                "ping",
+                &self.ping.as_ref().map(tokio::time::Interval::period),
+            )
+            .field("awaiting_pong", &self.awaiting_pong)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Waits for the next tick of the keepalive interval; or forever, if there is
+/// no interval.
+async fn tick(ping: &mut Option<tokio::time::Interval>) {
+    match ping {
+        Some(ping) => {
+            ping.tick().await;
+        }
+        None => std::future::pending().await,
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+/// A set of named rooms of WebSocket connections.
+///
+/// Connections join (and leave) rooms with [`WebSocket::join`] (and
+/// [`WebSocket::leave`]), and messages broadcast to a room with
+/// [`Hub::broadcast`] are sent to every connection in it.  Connections that
+/// have been closed are pruned from their rooms on the next broadcast, and
+/// rooms without any connections are removed.
+///
+/// A hub is cheap to clone - all clones share the same rooms - so it can be
+/// provided to every handler with the [`crate::middleware::StateMiddleware`],
+/// so that any handler can publish to it, even ones that are not WebSocket
+/// handlers.
+///
+/// # Examples
+/// ```rust,no_run
+/// # use under::*;
+/// use under::ws::{Hub, Message, WebSocket};
+///
+/// async fn chat(request: Request, mut ws: WebSocket) -> Result<(), anyhow::Error> {
+///     let hub = request.state::<Hub>().unwrap();
+///     let room = request.fragment_str("room").unwrap();
+///     ws.join(hub, room);
+///     while let Some(message) = ws.recv().await {
+///         hub.broadcast(room, &message?);
+///     }
+///     Ok(())
+/// }
+///
+/// async fn announce(request: Request) -> Result<Response, anyhow::Error> {
+///     let hub = request.state::<Hub>().unwrap();
+///     let room = request.fragment_str("room").unwrap();
+///     let count = hub.broadcast(room, &Message::Text("announcement!".into()));
+///     Ok(Response::text(format!("sent to {count} clients")))
+/// }
+///
+/// let mut http = under::http();
+/// http.with(under::middleware::StateMiddleware::new(Hub::new()));
+/// http.at("/rooms/{room}").get(under::ws::endpoint(chat));
+/// http.at("/rooms/{room}/announce").post(announce);
+/// ```
+pub struct Hub {
+    rooms: Arc<Mutex<HashMap<String, Vec<mpsc::Sender<Message>>>>>,
+}
+
+impl Hub {
+    /// Creates a new, empty hub.  This is provided as an alternative to
+    /// `Default`.
+    #[must_use]
+    pub fn new() -> Self {
+        Hub::default()
+    }
+
+    fn join(&self, room: &str, client: &mpsc::Sender<Message>) {
+        let mut rooms = self.rooms.lock().unwrap_or_else(PoisonError::into_inner);
+        let members = rooms.entry(room.to_owned()).or_default();
+        if !members.iter().any(|member| member.same_channel(client)) {
+            members.push(client.clone());
+        }
+    }
+
+    fn leave(&self, room: &str, client: &mpsc::Sender<Message>) {
+        let mut rooms = self.rooms.lock().unwrap_or_else(PoisonError::into_inner);
+        if let Some(members) = rooms.get_mut(room) {
+            members.retain(|member| !member.same_channel(client));
+            if members.is_empty() {
+                rooms.remove(room);
+            }
+        }
+    }
+
+    /// Broadcasts the given message to every connection in the given room,
+    /// returning the number of connections it was sent to.  Any connection
+    /// that has been closed is removed from the room; a connection whose
+    /// queue of broadcasts is full (see [`WebSocket::join`]) does not
+    /// receive the message, and is not counted.
+    ///
+    /// # Examples
+    /// ```rust
+    /// use under::ws::{Hub, Message};
+    /// let hub = Hub::new();
+    /// assert_eq!(hub.broadcast("lobby", &Message::Text("hello".into())), 0);
+    /// assert_eq!(hub.members("lobby"), 0);
+    /// assert!(hub.rooms().is_empty());
+    /// ```
+    pub fn broadcast(&self, room: &str, message: &Message) -> usize {
+        let mut rooms = self.rooms.lock().unwrap_or_else(PoisonError::into_inner);
+        let Some(members) = rooms.get_mut(room) else {
+            return 0;
+        };
+
+        let mut count = 0;
+        members.retain(|member| match member.try_send(message.clone()) {
+            Ok(()) => {
+                count += 1;
+                true
+            }
+            Err(mpsc::error::TrySendError::Full(_)) => true,
+            Err(mpsc::error::TrySendError::Closed(_)) => false,
+        });
+        if members.is_empty() {
+            rooms.remove(room);
+        }
+        count
+    }
+
+    /// Serializes the given value as JSON, and broadcasts it to every
+    /// connection in the given room as a text message.  See
+    /// [`Hub::broadcast`].
+    ///
+    /// # Errors
+    /// This errors if the value could not be serialized.
+    pub fn broadcast_json<T: serde::Serialize>(
+        &self,
+        room: &str,
+        value: &T,
+    ) -> Result<usize, serde_json::Error> {
+        Ok(self.broadcast(room, &Message::Text(serde_json::to_string(value)?)))
+    }
+
+    /// Returns the number of open connections in the given room.
+    #[must_use]
+    pub fn members(&self, room: &str) -> usize {
+        let rooms = self.rooms.lock().unwrap_or_else(PoisonError::into_inner);
+        rooms.get(room).map_or(0, |members| {
+            members.iter().filter(|member| !member.is_closed()).count()
+        })
+    }
+
+    /// Returns the names of all of the rooms.  Rooms are removed once all of
+    /// their connections have left, or have been pruned by a broadcast.
+    #[must_use]
+    pub fn rooms(&self) -> Vec<String> {
+        let rooms = self.rooms.lock().unwrap_or_else(PoisonError::into_inner);
+        rooms.keys().cloned().collect()
+    }
+}
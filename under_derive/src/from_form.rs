@@ -19,81 +19,129 @@ pub(super) fn from_form(s: syn::ItemStruct) -> Result<proc_macro2::TokenStream,
         } else {
             quote::quote_spanned!(Span::mixed_site()=> let mut #variable_name: Option<#field_ty> = None;)
         }
-    });
+    }).collect::<Vec<_>>();
 
-    let field_check = fields.iter().map(|f| {
-        let variable_name = &f.variable_name;
+    let text_check = |f: &FormFieldMeta| {
         let struct_name_s = ident_lit(&f.struct_name.to_string(), f.struct_name.span());
-        let form_key = &f.form_key;
         let field_ty = &f.r#type;
 
-        let raw_ty = quote::quote_spanned!(Span::mixed_site()=> ::std::any::type_name::<#field_ty>());
+        let raw_ty =
+            quote::quote_spanned!(Span::mixed_site()=> ::std::any::type_name::<#field_ty>());
 
-        let acceptable_form_keys = f.aliases.iter().map(|a| {
-            quote::quote_spanned!(Span::mixed_site()=> #a)
-        }).chain(std::iter::once(quote::quote_spanned!(Span::mixed_site()=> #form_key)));
+        let acceptable_form_keys = f.acceptable_form_keys();
 
-        match f.parse_with.as_ref() {
-            Some(parse_with) if f.multiple => {
-                quote::quote_spanned! {Span::mixed_site()=>
-                    #(#acceptable_form_keys)|* => {
-                        let value = #parse_with(__value.as_ref())
-                            .map_err(|e| ::under::from_form::FromFormError::InvalidFormat(#struct_name_s, #raw_ty, e.into()))?;
-                        <#field_ty as ::under::from_form::FromFormMultiple>::push(&mut #variable_name, value);
-                    }
-                }
-            }
+        let parse = match f.parse_with.as_ref() {
             Some(parse_with) => {
-                quote::quote_spanned! {Span::mixed_site()=>
-                    #(#acceptable_form_keys)|* => {
-                        #variable_name = Some(#parse_with(__value.as_ref())
-                            .map_err(|e| ::under::from_form::FromFormError::InvalidFormat(#struct_name_s, #raw_ty, e.into()))?);
-                    }
+                quote::quote_spanned!(Span::mixed_site()=> #parse_with(__value.as_ref()))
+            }
+            None if f.multiple => quote::quote_spanned! {Span::mixed_site()=>
+                <<#field_ty as ::under::from_form::FromFormMultiple>::Item as ::under::from_form::FromFormValue>::from_form_value(__value.as_ref())
+            },
+            None => quote::quote_spanned! {Span::mixed_site()=>
+                <#field_ty as ::under::from_form::FromFormValue>::from_form_value(__value.as_ref())
+            },
+        };
+
+        let store = f.store();
+
+        quote::quote_spanned! {Span::mixed_site()=>
+            #(#acceptable_form_keys)|* => {
+                match #parse.map_err(|e| ::under::from_form::FromFormError::InvalidFormat(#struct_name_s, #raw_ty, e.into())) {
+                    Ok(value) => { #store; }
+                    Err(error) => __errors.push(error),
                 }
             }
-            None if f.multiple => {
-                quote::quote_spanned! {Span::mixed_site()=>
-                    #(#acceptable_form_keys)|* => {
-                        let value = <<#field_ty as ::under::from_form::FromFormMultiple>::Item as ::under::from_form::FromFormValue>::from_form_value(__value.as_ref())
-                            .map_err(|e| ::under::from_form::FromFormError::InvalidFormat(#struct_name_s, #raw_ty, e.into()))?;
-                        <#field_ty as ::under::from_form::FromFormMultiple>::push(&mut #variable_name, value);
-                    }
+        }
+    };
+    let field_check = fields
+        .iter()
+        .filter(|f| !f.file)
+        .map(text_check)
+        .collect::<Vec<_>>();
+    // Array keys (`tags[]`, or `tags[0]`) fill in the `multiple` fields of
+    // the same name, if no field has that exact key.
+    let array_check = fields
+        .iter()
+        .filter(|f| !f.file && f.multiple)
+        .map(text_check)
+        .collect::<Vec<_>>();
+
+    // Files can only come from a multipart form; an empty file input counts
+    // as missing.
+    let file_check = |f: &FormFieldMeta| {
+        let acceptable_form_keys = f.acceptable_form_keys();
+        let store = f.store();
+        quote::quote_spanned! {Span::mixed_site()=>
+            #(#acceptable_form_keys)|* => {
+                if let Some(value) = ::under::multipart::UploadedFile::from_part(__part) {
+                    #store;
                 }
             }
-            None => {
+        }
+    };
+    let file_array_check = fields
+        .iter()
+        .filter(|f| f.file && f.multiple)
+        .map(file_check)
+        .collect::<Vec<_>>();
+    let file_check = fields
+        .iter()
+        .filter(|f| f.file)
+        .map(file_check)
+        .collect::<Vec<_>>();
+
+    // Each of these rebinds the field's variable to an `Option` of its final
+    // value, recording an error if a required field is missing (and could
+    // not be parsed - so that it is not reported twice).
+    let final_values = fields.iter().map(|f| {
+        let variable_name = &f.variable_name;
+        if f.multiple || f.optional {
+            return quote::quote_spanned!(Span::mixed_site()=> let #variable_name = Some(#variable_name););
+        } else if f.checkbox {
+            // An unchecked checkbox is left out of the form entirely.
+            return quote::quote_spanned!(Span::mixed_site()=> let #variable_name = Some(#variable_name.unwrap_or(false)););
+        }
+
+        match f.default {
+            FormFieldDefaultValue::Yes => {
+                quote::quote_spanned!(Span::mixed_site()=> let #variable_name = Some(#variable_name.unwrap_or_default());)
+            }
+            FormFieldDefaultValue::Custom(ref v) => {
+                quote::quote_spanned!(Span::mixed_site()=> let #variable_name = Some(#variable_name.unwrap_or_else(#v));)
+            }
+            FormFieldDefaultValue::No => {
+                let struct_name_s = ident_lit(&f.struct_name.to_string(), f.struct_name.span());
                 quote::quote_spanned! {Span::mixed_site()=>
-                    #(#acceptable_form_keys)|* => {
-                        #variable_name = Some(<#field_ty as ::under::from_form::FromFormValue>::from_form_value(__value.as_ref())
-                            .map_err(|e| ::under::from_form::FromFormError::InvalidFormat(#struct_name_s, #raw_ty, e.into()))?);
+                    if #variable_name.is_none() && __errors.iter().all(|e| e.field() != Some(#struct_name_s)) {
+                        __errors.push(::under::from_form::FromFormError::MissingField(#struct_name_s));
                     }
                 }
             }
         }
-    });
+    }).collect::<Vec<_>>();
+
+    let validations = fields.iter().filter_map(|f| {
+        let validate = f.validate.as_ref()?;
+        let variable_name = &f.variable_name;
+        let struct_name_s = ident_lit(&f.struct_name.to_string(), f.struct_name.span());
+        Some(quote::quote_spanned! {Span::mixed_site()=>
+            if let Some(value) = &#variable_name {
+                if let Err(e) = #validate(value) {
+                    __errors.push(::under::from_form::FromFormError::Invalid(#struct_name_s, e.to_string()));
+                }
+            }
+        })
+    }).collect::<Vec<_>>();
+
+    let variable_names = fields.iter().map(|f| &f.variable_name).collect::<Vec<_>>();
 
     let final_assignment = fields.iter().map(|f| {
         let variable_name = &f.variable_name;
-        let prefix = if f.field.ident.is_some() {
+        if f.field.ident.is_some() {
             let n = &f.field.ident;
-            quote::quote_spanned!(Span::mixed_site()=> #n: )
-        } else {
-            quote::quote_spanned!(Span::mixed_site()=> )
-        };
-        if f.multiple || f.optional {
-            quote::quote_spanned!(Span::mixed_site()=> #prefix #variable_name)
+            quote::quote_spanned!(Span::mixed_site()=> #n: #variable_name)
         } else {
-            match f.default {
-                FormFieldDefaultValue::Yes => {
-                    quote::quote_spanned!(Span::mixed_site()=> #prefix #variable_name.unwrap_or_default())
-                }
-                FormFieldDefaultValue::Custom(ref v) => {
-                    quote::quote_spanned!(Span::mixed_site()=> #prefix #variable_name.unwrap_or_else(#v))
-                }
-                FormFieldDefaultValue::No => {
-                    let struct_name_s = ident_lit(&f.struct_name.to_string(), f.struct_name.span());
-                    quote::quote_spanned!(Span::mixed_site()=> #prefix #variable_name.ok_or_else(|| ::under::from_form::FromFormError::MissingField(#struct_name_s))?)
-                }
-            }
+            quote::quote_spanned!(Span::mixed_site()=> #variable_name)
         }
     });
 
@@ -111,6 +159,43 @@ pub(super) fn from_form(s: syn::ItemStruct) -> Result<proc_macro2::TokenStream,
         }
     };
 
+    // The text of a multipart form is parsed the same way as any other form,
+    // so this is only needed if there are files to read.
+    let field_dispatch = dispatch(&field_check, &array_check);
+
+    let from_multipart = if file_check.is_empty() {
+        quote::quote!()
+    } else {
+        let file_dispatch = dispatch(&file_check, &file_array_check);
+        quote::quote_spanned! {Span::mixed_site()=>
+            fn from_multipart(__multipart: &::under::multipart::Multipart) -> Result<Self, ::under::from_form::FromFormError> {
+                #( #field_definitions )*
+                #[allow(unused_mut)]
+                let mut __errors: Vec<::under::from_form::FromFormError> = Vec::new();
+
+                for __part in __multipart.parts() {
+                    let __key = match __part.name() {
+                        Some(key) => key,
+                        None => continue,
+                    };
+                    if __part.is_file() {
+                        #file_dispatch
+                    } else if let Some(__value) = __part.text() {
+                        #field_dispatch
+                    }
+                }
+
+                #( #final_values )*
+                #( #validations )*
+
+                match (#( #variable_names, )*) {
+                    (#( Some(#variable_names), )*) if __errors.is_empty() => Ok(#struct_composition),
+                    _ => Err(::under::from_form::FromFormError::aggregate(__errors)),
+                }
+            }
+        }
+    };
+
     Ok(quote::quote_spanned! {Span::mixed_site()=>
         #[automatically_derived]
         impl ::under::from_form::FromForm for #name {
@@ -121,16 +206,24 @@ pub(super) fn from_form(s: syn::ItemStruct) -> Result<proc_macro2::TokenStream,
                 V: AsRef<str> + 'f,
             {
                 #( #field_definitions )*
+                #[allow(unused_mut)]
+                let mut __errors: Vec<::under::from_form::FromFormError> = Vec::new();
 
                 for (__key, __value) in __form {
-                    match __key.as_ref() {
-                        #( #field_check )*
-                        _ => {}
-                    }
+                    let __key = __key.as_ref();
+                    #field_dispatch
                 }
 
-                Ok(#struct_composition)
+                #( #final_values )*
+                #( #validations )*
+
+                match (#( #variable_names, )*) {
+                    (#( Some(#variable_names), )*) if __errors.is_empty() => Ok(#struct_composition),
+                    _ => Err(::under::from_form::FromFormError::aggregate(__errors)),
+                }
             }
+
+            #from_multipart
         }
     })
 }
@@ -187,8 +280,11 @@ struct FormFieldMeta<'f> {
     aliases: Vec<String>,
     multiple: bool,
     optional: bool,
+    file: bool,
+    checkbox: bool,
     default: FormFieldDefaultValue,
     parse_with: Option<syn::ExprPath>,
+    validate: Option<syn::ExprPath>,
 }
 
 #[derive(Debug)]
@@ -214,8 +310,11 @@ impl<'f> FormFieldMeta<'f> {
         let mut aliases = vec![];
         let mut multiple = false;
         let mut optional = false;
+        let mut file = false;
+        let mut checkbox = false;
         let mut default = FormFieldDefaultValue::No;
         let mut parse_with = None;
+        let mut validate = None;
 
         let attrs = field
             .attrs
@@ -284,6 +383,22 @@ impl<'f> FormFieldMeta<'f> {
                             return Err(syn::Error::new_spanned(nv.lit, "expected bool"));
                         }
                     }
+                    syn::NestedMeta::Meta(syn::Meta::NameValue(nv)) if nv.path.is_ident("file") => {
+                        if let syn::Lit::Bool(b) = nv.lit {
+                            file = b.value;
+                        } else {
+                            return Err(syn::Error::new_spanned(nv.lit, "expected bool"));
+                        }
+                    }
+                    syn::NestedMeta::Meta(syn::Meta::NameValue(nv))
+                        if nv.path.is_ident("checkbox") =>
+                    {
+                        if let syn::Lit::Bool(b) = nv.lit {
+                            checkbox = b.value;
+                        } else {
+                            return Err(syn::Error::new_spanned(nv.lit, "expected bool"));
+                        }
+                    }
                     syn::NestedMeta::Meta(syn::Meta::NameValue(nv))
                         if nv.path.is_ident("parse_with") =>
                     {
@@ -293,6 +408,15 @@ impl<'f> FormFieldMeta<'f> {
                             return Err(syn::Error::new_spanned(nv.lit, "expected string"));
                         }
                     }
+                    syn::NestedMeta::Meta(syn::Meta::NameValue(nv))
+                        if nv.path.is_ident("validate") =>
+                    {
+                        if let syn::Lit::Str(s) = nv.lit {
+                            validate = Some(syn::parse_str(&s.value())?);
+                        } else {
+                            return Err(syn::Error::new_spanned(nv.lit, "expected string"));
+                        }
+                    }
                     syn::NestedMeta::Meta(syn::Meta::Path(p)) if p.is_ident("multiple") => {
                         multiple = true;
                     }
@@ -302,6 +426,12 @@ impl<'f> FormFieldMeta<'f> {
                     syn::NestedMeta::Meta(syn::Meta::Path(p)) if p.is_ident("default") => {
                         default = FormFieldDefaultValue::Yes;
                     }
+                    syn::NestedMeta::Meta(syn::Meta::Path(p)) if p.is_ident("file") => {
+                        file = true;
+                    }
+                    syn::NestedMeta::Meta(syn::Meta::Path(p)) if p.is_ident("checkbox") => {
+                        checkbox = true;
+                    }
 
                     v => return Err(syn::Error::new_spanned(v, "expected key-value")),
                 }
@@ -337,6 +467,21 @@ impl<'f> FormFieldMeta<'f> {
                 field,
                 "cannot have both `multiple` and `default`",
             ));
+        } else if file && default.has_value() {
+            return Err(syn::Error::new_spanned(
+                field,
+                "cannot have both `file` and `default`",
+            ));
+        } else if file && parse_with.is_some() {
+            return Err(syn::Error::new_spanned(
+                field,
+                "cannot have both `file` and `parse_with`",
+            ));
+        } else if checkbox && (optional || multiple || file || default.has_value()) {
+            return Err(syn::Error::new_spanned(
+                field,
+                "cannot have `checkbox` with `optional`, `multiple`, `file`, or `default`",
+            ));
         }
 
         let ty = match &field.ty {
@@ -371,12 +516,70 @@ impl<'f> FormFieldMeta<'f> {
             aliases,
             multiple,
             optional,
+            file,
+            checkbox,
             parse_with,
+            validate,
             default,
         })
     }
 }
 
+/// Matches `__key` against the arms of the fields with that exact key, and
+/// then, if it is an array key, the arms of the fields with its name.
+fn dispatch(
+    exact: &[proc_macro2::TokenStream],
+    array: &[proc_macro2::TokenStream],
+) -> proc_macro2::TokenStream {
+    if array.is_empty() {
+        return quote::quote_spanned! {Span::mixed_site()=>
+            match __key {
+                #( #exact )*
+                _ => {}
+            }
+        };
+    }
+
+    quote::quote_spanned! {Span::mixed_site()=>
+        match __key {
+            #( #exact )*
+            __key => {
+                if let Some(__key) = ::under::from_form::array_key(__key) {
+                    match __key {
+                        #( #array )*
+                        _ => {}
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl FormFieldMeta<'_> {
+    /// The keys of the form that fill in this field: its aliases, and its
+    /// name.
+    fn acceptable_form_keys(&self) -> impl Iterator<Item = proc_macro2::TokenStream> + '_ {
+        self.aliases
+            .iter()
+            .map(|a| quote::quote_spanned!(Span::mixed_site()=> #a))
+            .chain(
+                std::iter::once(&self.form_key)
+                    .map(|form_key| quote::quote_spanned!(Span::mixed_site()=> #form_key)),
+            )
+    }
+
+    /// Stores a parsed `value` in the field's variable.
+    fn store(&self) -> proc_macro2::TokenStream {
+        let variable_name = &self.variable_name;
+        let field_ty = &self.r#type;
+        if self.multiple {
+            quote::quote_spanned!(Span::mixed_site()=> <#field_ty as ::under::from_form::FromFormMultiple>::push(&mut #variable_name, value))
+        } else {
+            quote::quote_spanned!(Span::mixed_site()=> #variable_name = Some(value))
+        }
+    }
+}
+
 fn is_named(fields: &syn::Fields) -> bool {
     matches!(fields, syn::Fields::Named(_))
 }
@@ -30,8 +30,8 @@ mod from_form;
 ///   the field is optional; if it is not present in the form, the default value
 ///   is used (through `Default::default`).
 /// - `default = "value"` - similar to above, but it uses the function
-///    specified by `value` (as a path) to get the default value.  This is
-///    incompatible with `optional` and `multiple`.
+///   specified by `value` (as a path) to get the default value.  This is
+///   incompatible with `optional` and `multiple`.
 /// - `optional` - this may only be specified on a field.  If it is specified,
 ///   the field is optional; if it is not present in the form, the field is
 ///   skipped.  It is expected that the type of this field is `Option<T>`.
@@ -41,12 +41,39 @@ mod from_form;
 /// - `multiple` - this may only be specified on a field.  If it is specified,
 ///   the field is a multiple field; it is expected that the type of this field
 ///   implements `FromFieldMultiple` instead of `FromFieldValue`.  It pushes
-///   the inner value every time the key is encountered.  This is incompatible
+///   the inner value every time the key is encountered, including as an
+///   array key (`key[]` or `key[0]`, as sent by PHP- and Rails-style form
+///   libraries; see `under::from_form::array_key`).  This is incompatible
 ///   with `default` and `optional`.
 /// - `parse_with = "value"` - this may only be specified on a field.  If it is
 ///   specified, the field is parsed with the given function.  The function must
 ///   be a path to a function that takes a `&str` and returns a `Result<T, E>`,
 ///   where `T` is the type of the field and `E` is the error type.
+/// - `validate = "value"` - this may only be specified on a field.  If it is
+///   specified, the field is checked with the given function once it has
+///   been parsed.  The function must be a path to a function that takes a
+///   `&T` and returns a `Result<(), E>`, where `T` is the type of the field
+///   and `E` implements `Display`; the `Display` of the error is used as the
+///   message of `FromFormError::Invalid`.
+/// - `checkbox` - this may only be specified on a field.  If it is
+///   specified, the field is a checkbox: its type is expected to be `bool`,
+///   and if it is not present in the form, it is `false` (as browsers leave
+///   unchecked checkboxes out of the form entirely).  If the key is given
+///   more than once (e.g. by a hidden input before the checkbox), the last
+///   value wins.  This is incompatible with `default`, `optional`,
+///   `multiple`, and `file`.
+/// - `file` - this may only be specified on a field.  If it is specified,
+///   the field is filled in from a file of a `multipart/form-data` form
+///   (through `FromForm::from_multipart`), and its type is expected to be
+///   `under::multipart::UploadedFile` (or, with `optional` or `multiple`, an
+///   `Option` or collection of it).  Text fields of the same name are
+///   ignored, and a form that is not multipart never has any files.  This is
+///   incompatible with `default` and `parse_with`, and requires the
+///   `multipart` feature of `under`.
+///
+/// Every field is parsed (and validated) before any errors are returned, so
+/// that a form with more than one invalid field reports all of them (as
+/// `FromFormError::Multiple`).
 pub fn derive_from_form(item: TokenStream) -> TokenStream {
     let input = syn::parse_macro_input!(item as syn::ItemStruct);
     proc_macro::TokenStream::from(